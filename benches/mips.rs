@@ -0,0 +1,167 @@
+// Millions of instructions per second on a few loop-heavy programs, run
+// without caches, with the cache of decoded instructions, and a basic block
+// at a time. Each program loops forever, and is stopped after a fixed
+// number of steps.
+use rv64_emulator::{Builder, StopReason};
+use std::time::Instant;
+
+const STEPS: u64 = 5_000_000;
+
+const PROGRAMS: &[(&str, &str)] = &[
+    (
+        "count",
+        "
+        start:
+            li a0, 0
+            li a1, 1000000
+        1:
+            addi a0, a0, 1
+            bne a0, a1, 1b
+            j start
+        ",
+    ),
+    (
+        "sieve",
+        "
+        .equ SIZE, 8192
+        start:
+            la s0, flags
+            li s1, SIZE
+            # Mark every number as prime
+            li t0, 0
+            li t1, 1
+        1:
+            add t2, s0, t0
+            sb t1, (t2)
+            addi t0, t0, 1
+            blt t0, s1, 1b
+            # Strike out the multiples of each prime
+            li t0, 2
+        2:
+            mul t1, t0, t0
+            bge t1, s1, start
+            add t2, s0, t0
+            lbu t2, (t2)
+            beqz t2, 4f
+        3:
+            add t2, s0, t1
+            sb zero, (t2)
+            add t1, t1, t0
+            blt t1, s1, 3b
+        4:
+            addi t0, t0, 1
+            j 2b
+        .data
+        flags:
+            .zero SIZE
+        ",
+    ),
+    (
+        "matmul",
+        "
+        # 16 by 16 matrices of dwords
+        .equ N, 16
+        start:
+            la s0, a
+            la s1, b
+            la s2, c
+            li s3, N
+            li t0, 0
+        1:
+            li t1, 0
+        2:
+            li t2, 0
+            li t3, 0
+        3:
+            # t3 += a[t0][t2] * b[t2][t1]
+            mul t4, t0, s3
+            add t4, t4, t2
+            slli t4, t4, 3
+            add t4, t4, s0
+            ld t4, (t4)
+            mul t5, t2, s3
+            add t5, t5, t1
+            slli t5, t5, 3
+            add t5, t5, s1
+            ld t5, (t5)
+            mul t4, t4, t5
+            add t3, t3, t4
+            addi t2, t2, 1
+            blt t2, s3, 3b
+            mul t4, t0, s3
+            add t4, t4, t1
+            slli t4, t4, 3
+            add t4, t4, s2
+            sd t3, (t4)
+            addi t1, t1, 1
+            blt t1, s3, 2b
+            addi t0, t0, 1
+            blt t0, s3, 1b
+            j start
+        .data
+        .align 3
+        a:
+            .zero 2048
+        b:
+            .zero 2048
+        c:
+            .zero 2048
+        ",
+    ),
+    (
+        "memcpy",
+        "
+        .equ SIZE, 4096
+        start:
+            la a0, destination
+            la a1, source
+            li a2, SIZE
+        1:
+            ld t0, (a1)
+            sd t0, (a0)
+            addi a0, a0, 8
+            addi a1, a1, 8
+            addi a2, a2, -8
+            bnez a2, 1b
+            j start
+        .data
+        .align 3
+        source:
+            .zero SIZE
+        destination:
+            .zero SIZE
+        ",
+    ),
+];
+
+fn mips(source: &str, decode_cache: bool, block_cache: bool) -> f64 {
+    let mut emulator = Builder::new()
+        .memory_size(0x10_0000)
+        .decode_cache(decode_cache)
+        .block_cache(block_cache)
+        .assembly(source)
+        .unwrap();
+    let start = Instant::now();
+    assert_eq!(emulator.run_until(STEPS).unwrap(), StopReason::Limit);
+    STEPS as f64 / start.elapsed().as_secs_f64() / 1e6
+}
+
+fn main() {
+    println!(
+        "{:8} {:>10} {:>10} {:>10} {:>8}",
+        "program", "uncached", "decoded", "blocks", "speedup"
+    );
+    for &(name, source) in PROGRAMS {
+        let uncached = mips(source, false, false);
+        let decoded = mips(source, true, false);
+        let blocks = mips(source, true, true);
+        println!(
+            "{:8} {:>10.2} {:>10.2} {:>10.2} {:>7.2}x",
+            name,
+            uncached,
+            decoded,
+            blocks,
+            blocks / uncached
+        );
+    }
+}
@@ -0,0 +1,8 @@
+// An emulator of a 64 bit RISC-V hart and the machine around it, for other
+// crates to embed. An Emulator is made by giving a Builder the program to
+// load, then run with step, run_until or run while its registers, memory
+// and CSRs are inspected.
+pub mod riscv;
+pub use riscv::builder::Builder;
+pub use riscv::cpu::{AbiRegister, FRegister, Register};
+pub use riscv::{Emulator, EmulatorError, StopReason};
@@ -1,25 +1,116 @@
-mod riscv;
+use rv64_emulator::riscv::{gdb, uart};
+use rv64_emulator::{Builder, EmulatorError};
 use std::env;
 use std::fs::File;
 use std::io::Read;
 use std::path::Path;
+use std::process;
+
+// Exit statuses for failures of the emulator rather than the guest, from
+// sysexits.h
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+const EX_IOERR: i32 = 74;
+
+fn fail(status: i32, message: &str) -> ! {
+    eprintln!("rv64_emulator: {}", message);
+    process::exit(status);
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: rv64_emulator [--trace] [--exit-outside-text] [--gdb <port>] [--user [-E NAME=value]...] <filename> [args]...");
+    process::exit(EX_USAGE);
+}
+
+fn exit_status(error: &EmulatorError) -> i32 {
+    match error {
+        EmulatorError::Elf(_)
+        | EmulatorError::Assembler(_)
+        | EmulatorError::Bus(_)
+        | EmulatorError::Config(_)
+        | EmulatorError::Csr(_)
+        | EmulatorError::Register(_) => EX_DATAERR,
+        EmulatorError::Halted { .. } => EX_SOFTWARE,
+        EmulatorError::Gdb(_) => EX_IOERR,
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        panic!(" Usage: rv64_emulator <filename>");
+    let mut args = env::args().skip(1);
+    // --user runs a Linux executable on emulated system calls, with the
+    // environment given by -E and the arguments after the filename
+    let mut user = false;
+    let mut environment = Vec::new();
+    // --gdb waits for GDB to connect on a port of localhost and debug the
+    // program
+    let mut gdb = None;
+    // --trace prints each instruction to stderr as it runs
+    let mut trace = false;
+    // --exit-outside-text ends a bare metal program with status 0 once it
+    // runs off the end of its code, for programs that don't write the
+    // finisher
+    let mut exit_outside_text = false;
+    let filename = loop {
+        match args.next() {
+            Some(flag) if flag == "--user" => user = true,
+            Some(flag) if flag == "--trace" => trace = true,
+            Some(flag) if flag == "--exit-outside-text" => exit_outside_text = true,
+            Some(flag) if flag == "--gdb" => {
+                gdb = Some(
+                    args.next()
+                        .and_then(|port| port.parse::<u16>().ok())
+                        .unwrap_or_else(|| usage()),
+                )
+            }
+            Some(flag) if flag == "-E" => environment.push(args.next().unwrap_or_else(|| usage())),
+            Some(filename) => break filename,
+            None => usage(),
+        }
+    };
+    let guest_args: Vec<String> = args.collect();
+    if !user && (!guest_args.is_empty() || !environment.is_empty()) {
+        usage();
     }
-    let path = Path::new(&args[1]);
+    let path = Path::new(&filename);
     let display = path.display();
 
     let mut file = match File::open(path) {
-        Err(why) => panic!("couldn't open {}: {}", display, why),
+        Err(why) => fail(EX_NOINPUT, &format!("couldn't open {}: {}", display, why)),
         Ok(file) => file,
     };
-    let mut encoded_instructions = Vec::new();
-    match file.read_to_end(&mut encoded_instructions) {
-        Err(why) => panic!("couldn't read {}: {}", display, why),
-        Ok(bytes_read) => bytes_read,
+    let mut image = Vec::new();
+    if let Err(why) = file.read_to_end(&mut image) {
+        fail(EX_IOERR, &format!("couldn't read {}: {}", display, why));
+    }
+    let builder = Builder::new()
+        .trace(trace)
+        .exit_outside_text(exit_outside_text);
+    let emulator = if user {
+        let argv: Vec<String> = Some(filename.clone())
+            .into_iter()
+            .chain(guest_args)
+            .collect();
+        builder.user(&image, &argv, &environment)
+    } else if path.extension().is_some_and(|extension| extension == "s") {
+        // Assembly source, which is assembled to run in system mode
+        match String::from_utf8(image) {
+            Err(why) => fail(EX_DATAERR, &format!("couldn't read {}: {}", display, why)),
+            Ok(source) => builder.uart(uart::Uart::console()).assembly(&source),
+        }
+    } else {
+        builder.uart(uart::Uart::console()).system(&image)
     };
-    riscv::emulate(encoded_instructions);
+    let status = emulator.and_then(|mut emulator| match gdb {
+        Some(port) => gdb::serve(&mut emulator, port),
+        None => emulator.run(),
+    });
+    match status {
+        Err(why) => fail(
+            exit_status(&why),
+            &format!("emulation of {} failed: {}", display, why),
+        ),
+        Ok(status) => process::exit(status),
+    }
 }
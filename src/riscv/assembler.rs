@@ -0,0 +1,2131 @@
+// A small assembler, so that programs and tests in particular can be
+// written as assembly rather than machine code. It reads the syntax GNU as
+// and the disassembler use: labels, the usual pseudo-instructions, %hi and
+// %lo, and directives for data, alignment, constants and sections.
+// Sections are laid out one after another in the order they first appear,
+// making a flat image to load at a base address. Instructions are never
+// compressed.
+use crate::riscv::cpu::{self, FRegister, Register};
+use crate::riscv::csr;
+use crate::riscv::disassembler::{DYNAMIC, FENCE_IORW, FENCE_RW, FENCE_TSO, ROUNDING_MODES};
+use crate::riscv::elf::{Symbol, SymbolTable};
+use crate::riscv::encoder::encode;
+use crate::riscv::instruction::Instruction;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+// Limits on what the source can ask for, so that it can't make the
+// assembler allocate more memory than the host has. Alignment is capped at a
+// page as in GNU as.
+const MAX_ALIGNMENT: u64 = 4096;
+const MAX_SIZE: u64 = 64 << 20;
+
+#[derive(Debug, PartialEq)]
+pub struct AssemblerError {
+    // Line of the source, counted from 1
+    pub line: usize,
+    pub message: String,
+}
+impl fmt::Display for AssemblerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+impl std::error::Error for AssemblerError {}
+
+// An assembled program and the addresses of its labels
+pub struct Image {
+    pub bytes: Vec<u8>,
+    pub symbols: SymbolTable,
+}
+
+// What a statement puts into its section
+enum Contents<'a> {
+    Bytes(Vec<u8>),
+    // Values of expressions, each `width` bytes wide
+    Data {
+        width: usize,
+        expressions: Vec<&'a str>,
+    },
+    Instruction {
+        mnemonic: &'a str,
+        operands: Vec<&'a str>,
+        size: u64,
+    },
+}
+
+struct Statement<'a> {
+    line: usize,
+    section: usize,
+    offset: u64,
+    contents: Contents<'a>,
+}
+
+struct Section<'a> {
+    name: &'a str,
+    size: u64,
+    // The largest alignment asked for in the section, which its start is
+    // aligned to
+    alignment: u64,
+}
+
+fn too_large() -> String {
+    format!("the image is larger than {} bytes", MAX_SIZE)
+}
+
+fn is_symbol_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'
+}
+
+fn register(name: &str) -> Option<Register> {
+    let number = match name {
+        "fp" => 8,
+        _ => match name
+            .strip_prefix('x')
+            .and_then(|number| number.parse().ok())
+        {
+            Some(number) => number,
+            None => cpu::ABI_NAMES.iter().position(|&abi| abi == name)?,
+        },
+    };
+    Register::try_from(number).ok()
+}
+fn float_register(name: &str) -> Option<FRegister> {
+    let number = match name
+        .strip_prefix('f')
+        .and_then(|number| number.parse().ok())
+    {
+        Some(number) => number,
+        None => cpu::FLOAT_ABI_NAMES.iter().position(|&abi| abi == name)?,
+    };
+    FRegister::try_from(number).ok()
+}
+
+// Drop a comment, which starts with # outside of a string or character
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(open), c) if c == open => quote = None,
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => (),
+        }
+    }
+    line
+}
+
+// Split a label off the start of a statement
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let end = text.find(|c| !is_symbol_char(c))?;
+    let rest = text[end..].strip_prefix(':')?;
+    let label = &text[..end];
+    let numeric = label.bytes().all(|b| b.is_ascii_digit());
+    if end == 0 || (label.starts_with(|c: char| c.is_ascii_digit()) && !numeric) {
+        return None;
+    }
+    Some((label, rest))
+}
+
+// Split a reference like 1b or 1f to a numeric label into its number and
+// whether it looks backwards
+fn local_reference(token: &str) -> Option<(&str, bool)> {
+    let (number, backward) = match token.strip_suffix('b') {
+        Some(number) => (number, true),
+        None => (token.strip_suffix('f')?, false),
+    };
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some((number, backward))
+}
+
+// Split operands at commas outside of parentheses and strings
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut operands = Vec::new();
+    let (mut depth, mut quoted, mut escaped) = (0, false, false);
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth -= 1,
+            ',' if !quoted && depth == 0 => {
+                operands.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    let last = text[start..].trim();
+    if !last.is_empty() || !operands.is_empty() {
+        operands.push(last);
+    }
+    operands
+}
+
+// Take one character of a string or character literal, undoing escapes
+fn unescape(chars: &mut std::str::Chars) -> Result<u8, String> {
+    let c = match chars.next() {
+        Some('\\') => match chars.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => c,
+            c => return Err(format!("unknown escape \\{}", c.unwrap_or(' '))),
+        },
+        Some(c) => c,
+        None => return Err("unterminated literal".to_string()),
+    };
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(format!("{} is not ASCII", c))
+    }
+}
+fn string(text: &str) -> Result<Vec<u8>, String> {
+    let inner = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .ok_or_else(|| format!("expected a string, found {}", text))?;
+    let mut chars = inner.chars();
+    let mut bytes = Vec::new();
+    while !chars.as_str().is_empty() {
+        bytes.push(unescape(&mut chars)?);
+    }
+    Ok(bytes)
+}
+
+fn parse_number(text: &str) -> Result<i64, String> {
+    let lower = text.to_ascii_lowercase();
+    let (digits, radix) = if let Some(digits) = lower.strip_prefix("0x") {
+        (digits, 16)
+    } else if let Some(digits) = lower.strip_prefix("0b") {
+        (digits, 2)
+    } else {
+        (lower.as_str(), 10)
+    };
+    // Values up to 2^64 are taken as two's complement
+    u64::from_str_radix(digits, radix)
+        .map(|value| value as i64)
+        .map_err(|_| format!("invalid number {}", text))
+}
+
+// Sign extend the low 12 bits
+fn low12(value: i64) -> i64 {
+    value << 52 >> 52
+}
+
+// Expressions are numbers, character literals and symbols, with `.` for
+// the address of the statement, combined with + and -, unary - and ~,
+// parentheses, and %hi and %lo for the halves of a lui/addi pair
+struct Expression<'a> {
+    text: &'a str,
+    address: u64,
+    symbols: &'a dyn Fn(&str) -> Option<i64>,
+}
+impl Expression<'_> {
+    fn eat(&mut self, prefix: &str) -> bool {
+        self.text = self.text.trim_start();
+        match self.text.strip_prefix(prefix) {
+            Some(rest) => {
+                self.text = rest;
+                true
+            }
+            None => false,
+        }
+    }
+    fn expect(&mut self, prefix: &str) -> Result<(), String> {
+        if self.eat(prefix) {
+            Ok(())
+        } else {
+            Err(format!("expected {}", prefix))
+        }
+    }
+    // The rest of a parenthesized expression
+    fn group(&mut self) -> Result<i64, String> {
+        let value = self.sum()?;
+        self.expect(")")?;
+        Ok(value)
+    }
+    fn sum(&mut self) -> Result<i64, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat("+") {
+                value = value.wrapping_add(self.term()?);
+            } else if self.eat("-") {
+                value = value.wrapping_sub(self.term()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+    fn term(&mut self) -> Result<i64, String> {
+        if self.eat("-") {
+            return Ok(self.term()?.wrapping_neg());
+        }
+        if self.eat("~") {
+            return Ok(!self.term()?);
+        }
+        if self.eat("%hi(") {
+            return Ok((self.group()?.wrapping_add(0x800) >> 12) & 0xfffff);
+        }
+        if self.eat("%lo(") {
+            return Ok(low12(self.group()?));
+        }
+        if self.eat("(") {
+            return self.group();
+        }
+        if self.eat("'") {
+            let mut chars = self.text.chars();
+            let value = unescape(&mut chars)?;
+            self.text = chars.as_str();
+            self.expect("'")?;
+            return Ok(i64::from(value));
+        }
+        let end = self
+            .text
+            .find(|c| !is_symbol_char(c))
+            .unwrap_or(self.text.len());
+        let (token, rest) = self.text.split_at(end);
+        self.text = rest;
+        match token {
+            "" => Err("expected a value".to_string()),
+            "." => Ok(self.address as i64),
+            _ if local_reference(token).is_some() => {
+                (self.symbols)(token).ok_or_else(|| format!("undefined local label {}", token))
+            }
+            _ if token.starts_with(|c: char| c.is_ascii_digit()) => parse_number(token),
+            _ => (self.symbols)(token).ok_or_else(|| format!("undefined symbol {}", token)),
+        }
+    }
+}
+fn evaluate(
+    text: &str,
+    address: u64,
+    symbols: &dyn Fn(&str) -> Option<i64>,
+) -> Result<i64, String> {
+    let mut expression = Expression {
+        text,
+        address,
+        symbols,
+    };
+    let value = expression.sum()?;
+    match expression.text.trim() {
+        "" => Ok(value),
+        rest => Err(format!("unexpected {} in expression", rest)),
+    }
+}
+
+// The instructions that put `value` in `rd`, the same sequence GNU as and
+// LLVM pick: lui and addiw for 32-bit values, and for wider ones the upper
+// bits loaded the same way, shifted into place, with the low 12 added
+fn load_immediate(rd: Register, value: i64) -> Vec<Instruction> {
+    let low = low12(value) as i32;
+    if value == i64::from(value as i32) {
+        let high = ((value.wrapping_add(0x800) >> 12) & 0xfffff) as i32;
+        return match (high, low) {
+            (0, imm) => vec![Instruction::Addi {
+                rd,
+                rs1: Register::X0,
+                imm,
+            }],
+            (imm, 0) => vec![Instruction::Lui { rd, imm }],
+            (imm, low) => vec![
+                Instruction::Lui { rd, imm },
+                Instruction::Addiw {
+                    rd,
+                    rs1: rd,
+                    imm: low,
+                },
+            ],
+        };
+    }
+    let upper = value.wrapping_add(0x800) >> 12;
+    let shift = 12 + upper.trailing_zeros();
+    let mut sequence = load_immediate(rd, value.wrapping_add(0x800) >> shift);
+    sequence.push(Instruction::Slli {
+        rd,
+        rs1: rd,
+        shamt: shift,
+    });
+    if low != 0 {
+        sequence.push(Instruction::Addi {
+            rd,
+            rs1: rd,
+            imm: low,
+        });
+    }
+    sequence
+}
+
+// The auipc immediate and low 12 bits that add up to `offset`
+fn pc_relative(offset: i64) -> Result<(i32, i32), String> {
+    if !(-(1 << 31) - 0x800..(1 << 31) - 0x800).contains(&offset) {
+        return Err(format!("{:#x} is out of reach of auipc", offset));
+    }
+    Ok((
+        ((offset + 0x800) >> 12) as i32 & 0xfffff,
+        low12(offset) as i32,
+    ))
+}
+
+// The operands of an instruction, and what the symbols in them are
+struct Operands<'a> {
+    operands: &'a [&'a str],
+    address: u64,
+    symbols: &'a dyn Fn(&str) -> Option<i64>,
+    // How many operands have been looked at, to catch extra ones
+    used: Cell<usize>,
+}
+impl<'a> Operands<'a> {
+    fn len(&self) -> usize {
+        self.operands.len()
+    }
+    fn get(&self, index: usize) -> Result<&'a str, String> {
+        self.used.set(self.used.get().max(index + 1));
+        self.operands
+            .get(index)
+            .copied()
+            .ok_or_else(|| format!("missing operand {}", index + 1))
+    }
+    fn x(&self, index: usize) -> Result<Register, String> {
+        let operand = self.get(index)?;
+        register(operand).ok_or_else(|| format!("expected an integer register, found {}", operand))
+    }
+    fn f(&self, index: usize) -> Result<FRegister, String> {
+        let operand = self.get(index)?;
+        float_register(operand)
+            .ok_or_else(|| format!("expected a floating point register, found {}", operand))
+    }
+    // An integer register that may be left out, for x0
+    fn optional_x(&self, index: usize) -> Result<Register, String> {
+        if index < self.len() {
+            self.x(index)
+        } else {
+            Ok(Register::X0)
+        }
+    }
+    fn value(&self, index: usize) -> Result<i64, String> {
+        evaluate(self.get(index)?, self.address, self.symbols)
+    }
+    fn signed(&self, index: usize, bits: u32) -> Result<i32, String> {
+        let value = self.value(index)?;
+        let limit = 1 << (bits - 1);
+        if (-limit..limit).contains(&value) {
+            Ok(value as i32)
+        } else {
+            Err(format!("{} doesn't fit in {} signed bits", value, bits))
+        }
+    }
+    fn unsigned(&self, index: usize, bits: u32) -> Result<u32, String> {
+        let value = self.value(index)?;
+        if (0..1 << bits).contains(&value) {
+            Ok(value as u32)
+        } else {
+            Err(format!("{} doesn't fit in {} unsigned bits", value, bits))
+        }
+    }
+    // The 20-bit immediate of lui and auipc, given either sign extended or
+    // as the unsigned field
+    fn upper(&self, index: usize) -> Result<i32, String> {
+        let value = self.value(index)?;
+        if (-(1 << 19)..1 << 20).contains(&value) {
+            Ok(value as i32 & 0xfffff)
+        } else {
+            Err(format!("{} doesn't fit in 20 bits", value))
+        }
+    }
+    // The offset of a jump or branch target from the instruction
+    fn target(&self, index: usize, bits: u32) -> Result<i32, String> {
+        let offset = self.value(index)?.wrapping_sub(self.address as i64);
+        let limit = 1 << (bits - 1);
+        if offset & 1 != 0 {
+            Err(format!("target {} is misaligned", self.get(index)?))
+        } else if (-limit..limit).contains(&offset) {
+            Ok(offset as i32)
+        } else {
+            Err(format!("target {} is out of reach", self.get(index)?))
+        }
+    }
+    // An offset(register) memory operand
+    fn memory(&self, index: usize) -> Result<(i32, Register), String> {
+        let operand = self.get(index)?;
+        let error = || format!("expected offset(register), found {}", operand);
+        let inner = operand.strip_suffix(')').ok_or_else(error)?;
+        let open = inner.rfind('(').ok_or_else(error)?;
+        let base = register(inner[open + 1..].trim()).ok_or_else(error)?;
+        let offset = match inner[..open].trim() {
+            "" => 0,
+            offset => evaluate(offset, self.address, self.symbols)?,
+        };
+        if (-2048..2048).contains(&offset) {
+            Ok((offset as i32, base))
+        } else {
+            Err(format!("offset {} doesn't fit in 12 signed bits", offset))
+        }
+    }
+    // The address register of an atomic, written (register)
+    fn address(&self, index: usize) -> Result<Register, String> {
+        match self.memory(index)? {
+            (0, base) => Ok(base),
+            _ => Err("atomics take no offset".to_string()),
+        }
+    }
+    fn csr(&self, index: usize) -> Result<u32, String> {
+        let operand = self.get(index)?;
+        match (0..0x1000).find(|&address| csr::name(address).as_deref() == Some(operand)) {
+            Some(address) => Ok(address as u32),
+            None => self.unsigned(index, 12),
+        }
+    }
+    // An optional rounding mode, dynamic when left out
+    fn rounding(&self, index: usize) -> Result<u32, String> {
+        if index >= self.len() {
+            return Ok(DYNAMIC);
+        }
+        let operand = self.get(index)?;
+        ROUNDING_MODES
+            .iter()
+            .position(|&mode| !mode.is_empty() && mode == operand)
+            .map(|mode| mode as u32)
+            .ok_or_else(|| format!("unknown rounding mode {}", operand))
+    }
+    // Predecessor or successor set of a fence, such as rw
+    fn fence_set(&self, index: usize) -> Result<u32, String> {
+        let operand = self.get(index)?;
+        operand.chars().try_fold(0, |set, c| match "iorw".find(c) {
+            Some(bit) => Ok(set | 0b1000 >> bit),
+            None => Err(format!("invalid fence set {}", operand)),
+        })
+    }
+}
+
+// The instructions a statement assembles to
+fn expand(mnemonic: &str, o: &Operands) -> Result<Vec<Instruction>, String> {
+    let (zero, ra) = (Register::X0, Register::X1);
+    let instructions = match mnemonic {
+        "nop" => vec![Instruction::Addi {
+            rd: zero,
+            rs1: zero,
+            imm: 0,
+        }],
+        "li" => load_immediate(o.x(0)?, o.value(1)?),
+        "la" | "lla" => {
+            let rd = o.x(0)?;
+            let (high, low) = pc_relative(o.value(1)?.wrapping_sub(o.address as i64))?;
+            vec![
+                Instruction::Auipc { rd, imm: high },
+                Instruction::Addi {
+                    rd,
+                    rs1: rd,
+                    imm: low,
+                },
+            ]
+        }
+        // Calls through ra, and tail calls through t1
+        "call" | "tail" => {
+            let (rd, scratch) = match mnemonic {
+                "call" => (ra, ra),
+                _ => (zero, Register::X6),
+            };
+            let (high, low) = pc_relative(o.value(0)?.wrapping_sub(o.address as i64))?;
+            vec![
+                Instruction::Auipc {
+                    rd: scratch,
+                    imm: high,
+                },
+                Instruction::Jalr {
+                    rd,
+                    rs1: scratch,
+                    imm: low,
+                },
+            ]
+        }
+        "mv" => vec![Instruction::Addi {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: 0,
+        }],
+        "not" => vec![Instruction::Xori {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: -1,
+        }],
+        "neg" => vec![Instruction::Sub {
+            rd: o.x(0)?,
+            rs1: zero,
+            rs2: o.x(1)?,
+        }],
+        "negw" => vec![Instruction::Subw {
+            rd: o.x(0)?,
+            rs1: zero,
+            rs2: o.x(1)?,
+        }],
+        "sext.w" => vec![Instruction::Addiw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: 0,
+        }],
+        "seqz" => vec![Instruction::Sltiu {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: 1,
+        }],
+        "snez" => vec![Instruction::Sltu {
+            rd: o.x(0)?,
+            rs1: zero,
+            rs2: o.x(1)?,
+        }],
+        "sltz" => vec![Instruction::Slt {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: zero,
+        }],
+        "sgtz" => vec![Instruction::Slt {
+            rd: o.x(0)?,
+            rs1: zero,
+            rs2: o.x(1)?,
+        }],
+        "beqz" => vec![Instruction::Beq {
+            rs1: o.x(0)?,
+            rs2: zero,
+            imm: o.target(1, 13)?,
+        }],
+        "bnez" => vec![Instruction::Bne {
+            rs1: o.x(0)?,
+            rs2: zero,
+            imm: o.target(1, 13)?,
+        }],
+        "bltz" => vec![Instruction::Blt {
+            rs1: o.x(0)?,
+            rs2: zero,
+            imm: o.target(1, 13)?,
+        }],
+        "bgez" => vec![Instruction::Bge {
+            rs1: o.x(0)?,
+            rs2: zero,
+            imm: o.target(1, 13)?,
+        }],
+        "blez" => vec![Instruction::Bge {
+            rs1: zero,
+            rs2: o.x(0)?,
+            imm: o.target(1, 13)?,
+        }],
+        "bgtz" => vec![Instruction::Blt {
+            rs1: zero,
+            rs2: o.x(0)?,
+            imm: o.target(1, 13)?,
+        }],
+        // Branches on the reversed comparisons swap the operands
+        "bgt" => vec![Instruction::Blt {
+            rs1: o.x(1)?,
+            rs2: o.x(0)?,
+            imm: o.target(2, 13)?,
+        }],
+        "ble" => vec![Instruction::Bge {
+            rs1: o.x(1)?,
+            rs2: o.x(0)?,
+            imm: o.target(2, 13)?,
+        }],
+        "bgtu" => vec![Instruction::Bltu {
+            rs1: o.x(1)?,
+            rs2: o.x(0)?,
+            imm: o.target(2, 13)?,
+        }],
+        "bleu" => vec![Instruction::Bgeu {
+            rs1: o.x(1)?,
+            rs2: o.x(0)?,
+            imm: o.target(2, 13)?,
+        }],
+        "j" => vec![Instruction::Jal {
+            rd: zero,
+            imm: o.target(0, 21)?,
+        }],
+        "jal" if o.len() == 1 => vec![Instruction::Jal {
+            rd: ra,
+            imm: o.target(0, 21)?,
+        }],
+        "jr" => vec![Instruction::Jalr {
+            rd: zero,
+            rs1: o.x(0)?,
+            imm: 0,
+        }],
+        "jalr" if o.len() == 1 => vec![Instruction::Jalr {
+            rd: ra,
+            rs1: o.x(0)?,
+            imm: 0,
+        }],
+        "ret" => vec![Instruction::Jalr {
+            rd: zero,
+            rs1: ra,
+            imm: 0,
+        }],
+        "csrr" => vec![Instruction::Csrrs {
+            rd: o.x(0)?,
+            csr: o.csr(1)?,
+            rs1: zero,
+        }],
+        "csrw" => vec![Instruction::Csrrw {
+            rd: zero,
+            csr: o.csr(0)?,
+            rs1: o.x(1)?,
+        }],
+        "csrs" => vec![Instruction::Csrrs {
+            rd: zero,
+            csr: o.csr(0)?,
+            rs1: o.x(1)?,
+        }],
+        "csrc" => vec![Instruction::Csrrc {
+            rd: zero,
+            csr: o.csr(0)?,
+            rs1: o.x(1)?,
+        }],
+        "csrwi" => vec![Instruction::Csrrwi {
+            rd: zero,
+            csr: o.csr(0)?,
+            uimm: o.unsigned(1, 5)?,
+        }],
+        "csrsi" => vec![Instruction::Csrrsi {
+            rd: zero,
+            csr: o.csr(0)?,
+            uimm: o.unsigned(1, 5)?,
+        }],
+        "csrci" => vec![Instruction::Csrrci {
+            rd: zero,
+            csr: o.csr(0)?,
+            uimm: o.unsigned(1, 5)?,
+        }],
+        "fmv.s" => vec![Instruction::FsgnjS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(1)?,
+        }],
+        "fneg.s" => vec![Instruction::FsgnjnS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(1)?,
+        }],
+        "fabs.s" => vec![Instruction::FsgnjxS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(1)?,
+        }],
+        "fmv.d" => vec![Instruction::FsgnjD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(1)?,
+        }],
+        "fneg.d" => vec![Instruction::FsgnjnD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(1)?,
+        }],
+        "fabs.d" => vec![Instruction::FsgnjxD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(1)?,
+        }],
+        "fence" => {
+            let (pred, succ) = match o.len() {
+                0 => (FENCE_IORW, FENCE_IORW),
+                _ => (o.fence_set(0)?, o.fence_set(1)?),
+            };
+            vec![Instruction::Fence {
+                rd: zero,
+                rs1: zero,
+                succ,
+                pred,
+                fm: 0,
+            }]
+        }
+        "fence.tso" => vec![Instruction::Fence {
+            rd: zero,
+            rs1: zero,
+            succ: FENCE_RW,
+            pred: FENCE_RW,
+            fm: FENCE_TSO,
+        }],
+        "fence.i" => vec![Instruction::FenceI {
+            rd: zero,
+            rs1: zero,
+            imm: 0,
+        }],
+        "sfence.vma" => vec![Instruction::SfenceVma {
+            rs1: o.optional_x(0)?,
+            rs2: o.optional_x(1)?,
+        }],
+        "unimp" => vec![Instruction::Undefined],
+        _ => vec![instruction(mnemonic, o)?],
+    };
+    if o.len() > o.used.get() {
+        return Err(format!("too many operands for {}", mnemonic));
+    }
+    Ok(instructions)
+}
+
+// An instruction that isn't a pseudo-instruction. Atomics take their
+// ordering as a suffix of the mnemonic.
+fn instruction(mnemonic: &str, o: &Operands) -> Result<Instruction, String> {
+    let atomic =
+        mnemonic.starts_with("lr.") || mnemonic.starts_with("sc.") || mnemonic.starts_with("amo");
+    let (mnemonic, aq, rl) = match mnemonic.rsplit_once('.') {
+        Some((base, "aq")) if atomic => (base, true, false),
+        Some((base, "rl")) if atomic => (base, false, true),
+        Some((base, "aqrl")) if atomic => (base, true, true),
+        _ => (mnemonic, false, false),
+    };
+    Ok(match mnemonic {
+        "beq" => Instruction::Beq {
+            rs1: o.x(0)?,
+            rs2: o.x(1)?,
+            imm: o.target(2, 13)?,
+        },
+        "bne" => Instruction::Bne {
+            rs1: o.x(0)?,
+            rs2: o.x(1)?,
+            imm: o.target(2, 13)?,
+        },
+        "blt" => Instruction::Blt {
+            rs1: o.x(0)?,
+            rs2: o.x(1)?,
+            imm: o.target(2, 13)?,
+        },
+        "bge" => Instruction::Bge {
+            rs1: o.x(0)?,
+            rs2: o.x(1)?,
+            imm: o.target(2, 13)?,
+        },
+        "bltu" => Instruction::Bltu {
+            rs1: o.x(0)?,
+            rs2: o.x(1)?,
+            imm: o.target(2, 13)?,
+        },
+        "bgeu" => Instruction::Bgeu {
+            rs1: o.x(0)?,
+            rs2: o.x(1)?,
+            imm: o.target(2, 13)?,
+        },
+        "lb" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Lb {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "lh" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Lh {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "lw" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Lw {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "lbu" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Lbu {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "lhu" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Lhu {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "lwu" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Lwu {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "ld" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Ld {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "addi" => Instruction::Addi {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: o.signed(2, 12)?,
+        },
+        "slti" => Instruction::Slti {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: o.signed(2, 12)?,
+        },
+        "sltiu" => Instruction::Sltiu {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: o.signed(2, 12)?,
+        },
+        "xori" => Instruction::Xori {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: o.signed(2, 12)?,
+        },
+        "ori" => Instruction::Ori {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: o.signed(2, 12)?,
+        },
+        "andi" => Instruction::Andi {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: o.signed(2, 12)?,
+        },
+        "slli" => Instruction::Slli {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            shamt: o.unsigned(2, 6)?,
+        },
+        "srli" => Instruction::Srli {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            shamt: o.unsigned(2, 6)?,
+        },
+        "srai" => Instruction::Srai {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            shamt: o.unsigned(2, 6)?,
+        },
+        "addiw" => Instruction::Addiw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            imm: o.signed(2, 12)?,
+        },
+        "slliw" => Instruction::Slliw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            shamt: o.unsigned(2, 5)?,
+        },
+        "srliw" => Instruction::Srliw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            shamt: o.unsigned(2, 5)?,
+        },
+        "sraiw" => Instruction::Sraiw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            shamt: o.unsigned(2, 5)?,
+        },
+        "jalr" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Jalr {
+                rd: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "ebreak" => Instruction::Ebreak,
+        "ecall" => Instruction::Ecall,
+        "mret" => Instruction::Mret,
+        "sret" => Instruction::Sret,
+        "wfi" => Instruction::Wfi,
+        "csrrw" => Instruction::Csrrw {
+            rd: o.x(0)?,
+            csr: o.csr(1)?,
+            rs1: o.x(2)?,
+        },
+        "csrrs" => Instruction::Csrrs {
+            rd: o.x(0)?,
+            csr: o.csr(1)?,
+            rs1: o.x(2)?,
+        },
+        "csrrc" => Instruction::Csrrc {
+            rd: o.x(0)?,
+            csr: o.csr(1)?,
+            rs1: o.x(2)?,
+        },
+        "csrrwi" => Instruction::Csrrwi {
+            rd: o.x(0)?,
+            csr: o.csr(1)?,
+            uimm: o.unsigned(2, 5)?,
+        },
+        "csrrsi" => Instruction::Csrrsi {
+            rd: o.x(0)?,
+            csr: o.csr(1)?,
+            uimm: o.unsigned(2, 5)?,
+        },
+        "csrrci" => Instruction::Csrrci {
+            rd: o.x(0)?,
+            csr: o.csr(1)?,
+            uimm: o.unsigned(2, 5)?,
+        },
+        "jal" => Instruction::Jal {
+            rd: o.x(0)?,
+            imm: o.target(1, 21)?,
+        },
+        "add" => Instruction::Add {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "sub" => Instruction::Sub {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "sll" => Instruction::Sll {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "slt" => Instruction::Slt {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "sltu" => Instruction::Sltu {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "xor" => Instruction::Xor {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "srl" => Instruction::Srl {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "sra" => Instruction::Sra {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "or" => Instruction::Or {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "and" => Instruction::And {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "addw" => Instruction::Addw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "subw" => Instruction::Subw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "sllw" => Instruction::Sllw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "srlw" => Instruction::Srlw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "sraw" => Instruction::Sraw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "mul" => Instruction::Mul {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "mulh" => Instruction::Mulh {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "mulhsu" => Instruction::Mulhsu {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "mulhu" => Instruction::Mulhu {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "div" => Instruction::Div {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "divu" => Instruction::Divu {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "rem" => Instruction::Rem {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "remu" => Instruction::Remu {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "mulw" => Instruction::Mulw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "divw" => Instruction::Divw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "divuw" => Instruction::Divuw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "remw" => Instruction::Remw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "remuw" => Instruction::Remuw {
+            rd: o.x(0)?,
+            rs1: o.x(1)?,
+            rs2: o.x(2)?,
+        },
+        "lr.w" => Instruction::LrW {
+            rd: o.x(0)?,
+            rs1: o.address(1)?,
+            aq,
+            rl,
+        },
+        "sc.w" => Instruction::ScW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoswap.w" => Instruction::AmoswapW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoadd.w" => Instruction::AmoaddW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoxor.w" => Instruction::AmoxorW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoand.w" => Instruction::AmoandW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoor.w" => Instruction::AmoorW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amomin.w" => Instruction::AmominW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amomax.w" => Instruction::AmomaxW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amominu.w" => Instruction::AmominuW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amomaxu.w" => Instruction::AmomaxuW {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "lr.d" => Instruction::LrD {
+            rd: o.x(0)?,
+            rs1: o.address(1)?,
+            aq,
+            rl,
+        },
+        "sc.d" => Instruction::ScD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoswap.d" => Instruction::AmoswapD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoadd.d" => Instruction::AmoaddD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoxor.d" => Instruction::AmoxorD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoand.d" => Instruction::AmoandD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amoor.d" => Instruction::AmoorD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amomin.d" => Instruction::AmominD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amomax.d" => Instruction::AmomaxD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amominu.d" => Instruction::AmominuD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "amomaxu.d" => Instruction::AmomaxuD {
+            rd: o.x(0)?,
+            rs2: o.x(1)?,
+            rs1: o.address(2)?,
+            aq,
+            rl,
+        },
+        "flw" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Flw {
+                rd: o.f(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "fsw" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Fsw {
+                rs2: o.f(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "fmadd.s" => Instruction::FmaddS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fmsub.s" => Instruction::FmsubS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fnmsub.s" => Instruction::FnmsubS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fnmadd.s" => Instruction::FnmaddS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fadd.s" => Instruction::FaddS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fsub.s" => Instruction::FsubS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fmul.s" => Instruction::FmulS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fdiv.s" => Instruction::FdivS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fsqrt.s" => Instruction::FsqrtS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fsgnj.s" => Instruction::FsgnjS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fsgnjn.s" => Instruction::FsgnjnS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fsgnjx.s" => Instruction::FsgnjxS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fmin.s" => Instruction::FminS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fmax.s" => Instruction::FmaxS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "feq.s" => Instruction::FeqS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "flt.s" => Instruction::FltS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fle.s" => Instruction::FleS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fclass.s" => Instruction::FclassS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+        },
+        "fcvt.w.s" => Instruction::FcvtWS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.wu.s" => Instruction::FcvtWuS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.l.s" => Instruction::FcvtLS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.lu.s" => Instruction::FcvtLuS {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.s.w" => Instruction::FcvtSW {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.s.wu" => Instruction::FcvtSWu {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.s.l" => Instruction::FcvtSL {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.s.lu" => Instruction::FcvtSLu {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fmv.x.w" => Instruction::FmvXW {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+        },
+        "fmv.w.x" => Instruction::FmvWX {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+        },
+        "fld" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Fld {
+                rd: o.f(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "fsd" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Fsd {
+                rs2: o.f(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "fmadd.d" => Instruction::FmaddD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fmsub.d" => Instruction::FmsubD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fnmsub.d" => Instruction::FnmsubD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fnmadd.d" => Instruction::FnmaddD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rs3: o.f(3)?,
+            rm: o.rounding(4)?,
+        },
+        "fadd.d" => Instruction::FaddD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fsub.d" => Instruction::FsubD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fmul.d" => Instruction::FmulD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fdiv.d" => Instruction::FdivD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+            rm: o.rounding(3)?,
+        },
+        "fsqrt.d" => Instruction::FsqrtD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fsgnj.d" => Instruction::FsgnjD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fsgnjn.d" => Instruction::FsgnjnD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fsgnjx.d" => Instruction::FsgnjxD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fmin.d" => Instruction::FminD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fmax.d" => Instruction::FmaxD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fcvt.s.d" => Instruction::FcvtSD {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.d.s" => Instruction::FcvtDS {
+            rd: o.f(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "feq.d" => Instruction::FeqD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "flt.d" => Instruction::FltD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fle.d" => Instruction::FleD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rs2: o.f(2)?,
+        },
+        "fclass.d" => Instruction::FclassD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+        },
+        "fcvt.w.d" => Instruction::FcvtWD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.wu.d" => Instruction::FcvtWuD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.l.d" => Instruction::FcvtLD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.lu.d" => Instruction::FcvtLuD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.d.w" => Instruction::FcvtDW {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.d.wu" => Instruction::FcvtDWu {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.d.l" => Instruction::FcvtDL {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fcvt.d.lu" => Instruction::FcvtDLu {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+            rm: o.rounding(2)?,
+        },
+        "fmv.x.d" => Instruction::FmvXD {
+            rd: o.x(0)?,
+            rs1: o.f(1)?,
+        },
+        "fmv.d.x" => Instruction::FmvDX {
+            rd: o.f(0)?,
+            rs1: o.x(1)?,
+        },
+        "sb" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Sb {
+                rs2: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "sh" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Sh {
+                rs2: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "sw" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Sw {
+                rs2: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "sd" => {
+            let (imm, rs1) = o.memory(1)?;
+            Instruction::Sd {
+                rs2: o.x(0)?,
+                rs1,
+                imm,
+            }
+        }
+        "auipc" => Instruction::Auipc {
+            rd: o.x(0)?,
+            imm: o.upper(1)?,
+        },
+        "lui" => Instruction::Lui {
+            rd: o.x(0)?,
+            imm: o.upper(1)?,
+        },
+        _ => return Err(format!("unknown instruction {}", mnemonic)),
+    })
+}
+
+// Assemble `source` into an image to load at `base`
+pub fn assemble(source: &str, base: u64) -> Result<Image, AssemblerError> {
+    let mut sections = vec![Section {
+        name: ".text",
+        size: 0,
+        alignment: 4,
+    }];
+    let mut current = 0;
+    let mut statements = Vec::new();
+    // Labels are found by section and offset until the sections are laid
+    // out, while constants from .equ are known straight away
+    let mut labels: Vec<(&str, usize, u64)> = Vec::new();
+    let mut constants: HashMap<&str, i64> = HashMap::new();
+    // Numeric labels can be defined many times, so each definition also
+    // records how many statements came before it
+    let mut locals: Vec<(&str, usize, usize, u64)> = Vec::new();
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message| AssemblerError { line, message };
+        let mut text = strip_comment(text).trim();
+        while let Some((label, rest)) = split_label(text) {
+            text = rest.trim();
+            if label.starts_with(|c: char| c.is_ascii_digit()) {
+                locals.push((label, statements.len(), current, sections[current].size));
+                continue;
+            }
+            if constants.contains_key(label) || labels.iter().any(|(name, _, _)| *name == label) {
+                return Err(error(format!("{} is already defined", label)));
+            }
+            labels.push((label, current, sections[current].size));
+        }
+        if text.is_empty() {
+            continue;
+        }
+        let (word, rest) = match text.split_once(char::is_whitespace) {
+            Some((word, rest)) => (word, rest.trim()),
+            None => (text, ""),
+        };
+        let operands = split_operands(rest);
+        let constant =
+            |text: &str| evaluate(text, 0, &|name| constants.get(name).copied()).map_err(error);
+        let width = match word {
+            ".byte" => 1,
+            ".half" | ".short" | ".2byte" => 2,
+            ".word" | ".long" | ".4byte" => 4,
+            ".dword" | ".quad" | ".8byte" => 8,
+            _ => 0,
+        };
+        let contents = match word {
+            ".text" | ".data" | ".rodata" | ".bss" | ".section" => {
+                let name = match word {
+                    ".section" => operands.first().copied().unwrap_or_default(),
+                    _ => word,
+                };
+                current = match sections.iter().position(|section| section.name == name) {
+                    Some(section) => section,
+                    None => {
+                        sections.push(Section {
+                            name,
+                            size: 0,
+                            alignment: 1,
+                        });
+                        sections.len() - 1
+                    }
+                };
+                continue;
+            }
+            _ if width != 0 => Contents::Data {
+                width,
+                expressions: operands,
+            },
+            ".ascii" | ".asciz" | ".string" => {
+                let mut bytes = Vec::new();
+                for operand in operands {
+                    bytes.extend(string(operand).map_err(error)?);
+                    if word != ".ascii" {
+                        bytes.push(0);
+                    }
+                }
+                Contents::Bytes(bytes)
+            }
+            ".zero" | ".space" | ".skip" => {
+                let size = constant(operands.first().copied().unwrap_or_default())?.max(0) as u64;
+                if size > MAX_SIZE - sections[current].size {
+                    return Err(error(too_large()));
+                }
+                Contents::Bytes(vec![0; size as usize])
+            }
+            // .align is a power of two, as on RISC-V
+            ".align" | ".p2align" | ".balign" => {
+                let value = constant(operands.first().copied().unwrap_or_default())?;
+                let alignment = match word {
+                    ".balign" => value as u64,
+                    _ => 1u64.checked_shl(value as u32).unwrap_or(0),
+                };
+                if !alignment.is_power_of_two() || alignment > MAX_ALIGNMENT {
+                    return Err(error(format!("invalid alignment {}", value)));
+                }
+                let section = &mut sections[current];
+                section.alignment = section.alignment.max(alignment);
+                let padding = section.size.wrapping_neg() & (alignment - 1);
+                Contents::Bytes(vec![0; padding as usize])
+            }
+            ".equ" | ".set" => {
+                let (name, value) = match operands.as_slice() {
+                    [name, value] => (*name, constant(value)?),
+                    _ => return Err(error(format!("{} takes a name and a value", word))),
+                };
+                if labels.iter().any(|(label, _, _)| *label == name) {
+                    return Err(error(format!("{} is already defined", name)));
+                }
+                constants.insert(name, value);
+                continue;
+            }
+            // Directives for linking and debugging that don't affect the image
+            ".globl" | ".global" | ".local" | ".type" | ".size" | ".option" | ".file"
+            | ".ident" | ".attribute" => continue,
+            _ if word.starts_with('.') => return Err(error(format!("unknown directive {}", word))),
+            _ => {
+                // Sizes are needed before the addresses of labels are
+                // known, so the value li loads has to be a constant
+                let size = match word {
+                    "li" => {
+                        let value = constant(operands.get(1).copied().unwrap_or_default())?;
+                        4 * load_immediate(Register::X0, value).len() as u64
+                    }
+                    "la" | "lla" | "call" | "tail" => 8,
+                    _ => 4,
+                };
+                Contents::Instruction {
+                    mnemonic: word,
+                    operands,
+                    size,
+                }
+            }
+        };
+        let section = &mut sections[current];
+        let size = match &contents {
+            Contents::Bytes(bytes) => bytes.len() as u64,
+            Contents::Data { width, expressions } => (width * expressions.len()) as u64,
+            Contents::Instruction { size, .. } => *size,
+        };
+        if size > MAX_SIZE - section.size {
+            return Err(error(too_large()));
+        }
+        statements.push(Statement {
+            line,
+            section: current,
+            offset: section.size,
+            contents,
+        });
+        section.size += size;
+    }
+
+    let mut addresses = Vec::new();
+    let mut end = base;
+    for section in &sections {
+        let address = (end + section.alignment - 1) & !(section.alignment - 1);
+        addresses.push(address);
+        end = address + section.size;
+    }
+    let mut values: HashMap<&str, i64> = constants;
+    for &(name, section, offset) in &labels {
+        values.insert(name, (addresses[section] + offset) as i64);
+    }
+
+    if end - base > MAX_SIZE {
+        let line = source.lines().count();
+        return Err(AssemblerError {
+            line,
+            message: too_large(),
+        });
+    }
+    let mut bytes = vec![0; (end - base) as usize];
+    for (index, statement) in statements.iter().enumerate() {
+        // 1b is the closest 1: at or before this statement and 1f the
+        // closest one after it
+        let symbols = |name: &str| match local_reference(name) {
+            Some((number, backward)) => {
+                let mut matching = locals.iter().filter(|local| local.0 == number);
+                let local = match backward {
+                    true => matching.rfind(|local| local.1 <= index),
+                    false => matching.find(|local| local.1 > index),
+                };
+                local.map(|&(_, _, section, offset)| (addresses[section] + offset) as i64)
+            }
+            None => values.get(name).copied(),
+        };
+        let error = |message| AssemblerError {
+            line: statement.line,
+            message,
+        };
+        let address = addresses[statement.section] + statement.offset;
+        let assembled = match &statement.contents {
+            Contents::Bytes(bytes) => bytes.clone(),
+            Contents::Data { width, expressions } => {
+                let mut data = Vec::new();
+                for expression in expressions {
+                    let value = evaluate(expression, address, &symbols).map_err(error)?;
+                    data.extend_from_slice(&value.to_le_bytes()[..*width]);
+                }
+                data
+            }
+            Contents::Instruction {
+                mnemonic,
+                operands,
+                size,
+            } => {
+                let operands = Operands {
+                    operands,
+                    address,
+                    symbols: &symbols,
+                    used: Cell::new(0),
+                };
+                let instructions = expand(mnemonic, &operands).map_err(error)?;
+                let code: Vec<u8> = instructions
+                    .iter()
+                    .flat_map(|instruction| encode(instruction).to_le_bytes())
+                    .collect();
+                // Only li has a size that depends on its operands, and
+                // that was worked out from the same constant
+                debug_assert_eq!(code.len() as u64, *size);
+                code
+            }
+        };
+        let start = (address - base) as usize;
+        bytes[start..start + assembled.len()].copy_from_slice(&assembled);
+    }
+
+    // Labels cover the bytes up to the next label or the end of their
+    // section
+    let mut located: Vec<(&str, usize, u64)> = labels
+        .iter()
+        .map(|&(name, section, offset)| (name, section, addresses[section] + offset))
+        .collect();
+    located.sort_by_key(|&(_, _, address)| address);
+    let table = located
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, section, address))| {
+            let section_end = addresses[section] + sections[section].size;
+            let next = located
+                .get(i + 1)
+                .map_or(section_end, |&(_, _, next)| next.min(section_end));
+            Symbol {
+                name: name.to_string(),
+                address,
+                size: next.max(address) - address,
+            }
+        })
+        .collect();
+    Ok(Image {
+        bytes,
+        symbols: SymbolTable::new(table),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+    use crate::riscv::cpu::{AbiRegister, Cpu};
+    use crate::riscv::instruction::decode;
+    use crate::riscv::trap::Exception;
+
+    // Assemble and run a program at DRAM_BASE until it reaches an ebreak
+    fn run(source: &str) -> Cpu {
+        let image = assemble(source, DRAM_BASE).unwrap();
+        let mut bus = Bus::new(image.bytes.len() as u64 + 0x1000);
+        bus.load(DRAM_BASE, &image.bytes).unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.pc = DRAM_BASE;
+        for _ in 0..10_000 {
+            match cpu.step() {
+                Ok(()) => {}
+                Err(Exception::Breakpoint(_)) => return cpu,
+                Err(exception) => panic!("{:?} at {:x}", exception, cpu.pc),
+            }
+        }
+        panic!("program didn't finish");
+    }
+
+    fn words(source: &str) -> Vec<u32> {
+        assemble(source, DRAM_BASE)
+            .unwrap()
+            .bytes
+            .chunks(4)
+            .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+            .collect()
+    }
+
+    fn error(source: &str) -> String {
+        match assemble(source, DRAM_BASE) {
+            Ok(_) => panic!("{:?} assembled", source),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn program() {
+        let cpu = run("
+            .equ COUNT, 10
+            _start:
+                li a0, 0
+                li t0, COUNT
+            1:  add a0, a0, t0      # sum COUNT down to 1
+                addi t0, t0, -1
+                bnez t0, 1b
+                la t1, table
+                ld a1, 8(t1)
+                call double
+                j 1f
+            double:
+                slli a2, a1, 1
+                ret
+            1:  ebreak
+            .data
+            .align 3
+            table: .dword 0, end - _start, -1
+            end:
+        ");
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 55);
+        // .data starts right after the 14 instructions of .text
+        assert_eq!(cpu.read_register(AbiRegister::A1.into()), 56 + 24);
+        assert_eq!(cpu.read_register(AbiRegister::A2.into()), 2 * (56 + 24));
+    }
+
+    #[test]
+    fn load_immediate_values() {
+        let values = [
+            0,
+            1,
+            -1,
+            2047,
+            -2048,
+            2048,
+            0x7fff_f7ff,
+            0x7fff_ffff,
+            -0x8000_0000,
+            0x8000_0000,
+            0xffff_ffff,
+            0x1234_5678_9abc_def0,
+            i64::MAX,
+            i64::MIN,
+            0x0000_0fff_0000_0000,
+            -0x1_0000_0001,
+        ];
+        for &value in &values {
+            let cpu = run(&format!("li a0, {}\nebreak", value));
+            assert_eq!(cpu.read_register(AbiRegister::A0.into()) as i64, value);
+        }
+    }
+
+    #[test]
+    fn instructions() {
+        let cases = [
+            ("addi a0, a1, -3", 0xffd5_8513),
+            ("lw a0, -8(sp)", 0xff81_2503),
+            ("sd ra, 24(sp)", 0x0011_3c23),
+            ("ld s0, (a0)", 0x0005_3403),
+            ("lui a0, 0xfffff", 0xffff_f537),
+            ("mv a0, s1", 0x0004_8513),
+            ("not t0, t1", 0xfff3_4293),
+            ("neg a0, a1", 0x40b0_0533),
+            ("sext.w a0, a1", 0x0005_851b),
+            ("seqz a0, a1", 0x0015_b513),
+            ("sraiw a0, a1, 31", 0x41f5_d51b),
+            ("mulhsu s2, s3, s4", 0x0349_a933),
+            ("ret", 0x0000_8067),
+            ("jalr a5", 0x0007_80e7),
+            ("jalr t0, 16(a1)", 0x0105_82e7),
+            ("csrr a0, mstatus", 0x3000_2573),
+            ("csrwi mie, 8", 0x3044_5073),
+            ("lr.w.aq a0, (a1)", 0x1405_a52f),
+            ("amoadd.d.aqrl a0, a2, (a1)", 0x06c5_b52f),
+            ("fadd.d fa0, fa1, fa2", 0x02c5_f553),
+            ("fadd.s fa0, fa1, fa2, rtz", 0x00c5_9553),
+            ("fmadd.s f1, f2, f3, f4", 0x2031_70c3),
+            ("fmv.d fa0, fa1", 0x22b5_8553),
+            ("fcvt.w.s a0, fa0, rtz", 0xc005_1553),
+            ("fld fa0, 8(sp)", 0x0081_3507),
+            ("fence", 0x0ff0_000f),
+            ("fence r, w", 0x0210_000f),
+            ("fence.tso", 0x8330_000f),
+            ("fence.i", 0x0000_100f),
+            ("sfence.vma", 0x1200_0073),
+            ("ecall", 0x0000_0073),
+            ("mret", 0x3020_0073),
+            ("wfi", 0x1050_0073),
+        ];
+        for &(text, word) in &cases {
+            assert_eq!(words(text), vec![word], "{}", text);
+            // What the disassembler prints reads back the same
+            let instruction = decode(word);
+            assert_eq!(words(&instruction.to_string()), vec![word], "{}", text);
+        }
+    }
+
+    #[test]
+    fn branches_and_relocations() {
+        assert_eq!(
+            words("loop: beqz a0, loop\nj loop\nbgt a0, a1, loop"),
+            vec![0x0005_0063, 0xffdf_f06f, 0xfea5_cce3]
+        );
+        // %hi rounds up when %lo is negative
+        assert_eq!(
+            words("lui a0, %hi(0x12345800)\naddi a0, a0, %lo(0x12345800)"),
+            vec![0x1234_6537, 0x8005_0513]
+        );
+        // la is pc relative, so it doesn't depend on the base
+        assert_eq!(
+            words("la a0, data\nnop\ndata: .word 0"),
+            vec![0x0000_0517, 0x00c5_0513, 0x0000_0013, 0]
+        );
+    }
+
+    #[test]
+    fn data() {
+        let image = assemble(
+            "
+            .section .rodata
+            message: .asciz \"hi\\n\"
+            .balign 4
+            half: .half 0x1234, 'A'
+            .byte 1, 2, 3
+            .zero 2
+            .text
+            nop
+            ",
+            0x1000,
+        )
+        .unwrap();
+        assert_eq!(
+            image.bytes,
+            [0x13, 0, 0, 0, b'h', b'i', b'\n', 0, 0x34, 0x12, b'A', 0, 1, 2, 3, 0, 0]
+        );
+        let (symbol, offset) = image.symbols.symbolize(0x1009).unwrap();
+        assert_eq!(
+            (symbol.name.as_str(), symbol.address, offset),
+            ("half", 0x1008, 1)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(error("nop\nfoo a0"), "line 2: unknown instruction foo");
+        assert_eq!(error("j nowhere"), "line 1: undefined symbol nowhere");
+        assert_eq!(error("add a0, a1"), "line 1: missing operand 3");
+        assert_eq!(error("nop a0"), "line 1: too many operands for nop");
+        assert_eq!(
+            error("addi a0, a0, 2048"),
+            "line 1: 2048 doesn't fit in 12 signed bits"
+        );
+        assert_eq!(error("x: nop\nx: nop"), "line 2: x is already defined");
+        assert_eq!(error("j 1f"), "line 1: undefined local label 1f");
+        assert_eq!(error(".align 3 + x"), "line 1: undefined symbol x");
+        assert_eq!(error(".align 40"), "line 1: invalid alignment 40");
+        assert_eq!(error(".balign 8192"), "line 1: invalid alignment 8192");
+        let too_large = "the image is larger than 67108864 bytes";
+        assert_eq!(error(".zero 0x7fffffff"), format!("line 1: {}", too_large));
+        assert_eq!(
+            error(".zero 0x3000000\n.zero 0x1000000\n.zero 1"),
+            format!("line 3: {}", too_large)
+        );
+        // Each section fits, but not all of them together
+        assert_eq!(
+            error(".zero 0x3000000\n.data\n.zero 0x3000000\nnop"),
+            format!("line 4: {}", too_large)
+        );
+    }
+}
@@ -0,0 +1,254 @@
+// Basic blocks of translated instructions, by the physical address they
+// start at. A block runs straight through to an instruction that can change
+// control flow or the state that instructions run under, or to the end of
+// its page. Each block links to the block that ran after its branch or jump
+// was taken and the one that ran after it fell through, so that a hot loop
+// goes from block to block without looking them up. Blocks are dropped a
+// page at a time when code in their page is written.
+use crate::riscv::decode_cache::PAGE_SIZE;
+use crate::riscv::instruction::Instruction;
+use crate::riscv::micro_op::Op;
+use std::collections::HashMap;
+
+// Most instructions run through blocks between returns to the caller, so
+// that interrupts are seen at least this often. It is also the longest
+// block.
+pub const MAX_INSTRUCTIONS: usize = 64;
+
+struct Block {
+    start: u64,
+    ops: Vec<Op>,
+    // Size of the code in bytes
+    size: u64,
+    // Blocks that ran next, by index
+    taken: Option<usize>,
+    fall_through: Option<usize>,
+}
+impl Block {
+    // The link followed to get to `physical`
+    fn link(&mut self, physical: u64) -> &mut Option<usize> {
+        if physical == self.start + self.size {
+            &mut self.fall_through
+        } else {
+            &mut self.taken
+        }
+    }
+}
+
+pub struct BlockCache {
+    // Dropped blocks leave their slot free for another
+    blocks: Vec<Option<Block>>,
+    free: Vec<usize>,
+    starts: HashMap<u64, usize>,
+    // Blocks by the physical address of the page they lie in
+    pages: HashMap<u64, Vec<usize>>,
+    // The block found last, which links to the one found next
+    last: Option<usize>,
+    // misa when the blocks were translated, which decides the instructions
+    // that are illegal
+    misa: u64,
+}
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl BlockCache {
+    pub fn new() -> Self {
+        Self {
+            blocks: Vec::new(),
+            free: Vec::new(),
+            starts: HashMap::new(),
+            pages: HashMap::new(),
+            last: None,
+            misa: 0,
+        }
+    }
+    // Drop every block once the extensions they were translated for change
+    pub fn revalidate(&mut self, misa: u64) {
+        if misa != self.misa {
+            self.flush();
+            self.misa = misa;
+        }
+    }
+    pub fn flush(&mut self) {
+        self.blocks.clear();
+        self.free.clear();
+        self.starts.clear();
+        self.pages.clear();
+        self.last = None;
+    }
+    // Drop the blocks in the page at a physical address
+    pub fn invalidate_page(&mut self, page: u64) {
+        for index in self.pages.remove(&page).unwrap_or_default() {
+            if let Some(block) = self.blocks[index].take() {
+                self.starts.remove(&block.start);
+                self.free.push(index);
+            }
+        }
+        self.last = None;
+    }
+    // The block starting at `physical`, taken from the links of the block
+    // found last if it led there before
+    pub fn find(&mut self, physical: u64) -> Option<usize> {
+        let linked = self
+            .last
+            .and_then(|last| *self.blocks[last].as_mut()?.link(physical))
+            .filter(|&index| self.starts_at(index, physical));
+        let index = match linked {
+            Some(index) => index,
+            None => {
+                let index = *self.starts.get(&physical)?;
+                self.link(physical, index);
+                index
+            }
+        };
+        self.last = Some(index);
+        Some(index)
+    }
+    fn starts_at(&self, index: usize, physical: u64) -> bool {
+        matches!(&self.blocks[index], Some(block) if block.start == physical)
+    }
+    pub fn insert(&mut self, physical: u64, ops: Vec<Op>) -> usize {
+        let block = Block {
+            start: physical,
+            size: ops.iter().map(|op| op.length).sum(),
+            ops,
+            taken: None,
+            fall_through: None,
+        };
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.blocks[index] = Some(block);
+                index
+            }
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            }
+        };
+        self.starts.insert(physical, index);
+        self.pages
+            .entry(physical & !(PAGE_SIZE - 1))
+            .or_default()
+            .push(index);
+        self.link(physical, index);
+        self.last = Some(index);
+        index
+    }
+    // Link the block found last to its successor
+    fn link(&mut self, physical: u64, index: usize) {
+        if let Some(block) = self.last.and_then(|last| self.blocks[last].as_mut()) {
+            *block.link(physical) = Some(index);
+        }
+    }
+    // None once the block has been dropped
+    pub fn op(&self, index: usize, position: usize) -> Option<Op> {
+        self.blocks.get(index)?.as_ref()?.ops.get(position).copied()
+    }
+    pub fn size(&self, index: usize) -> u64 {
+        self.blocks[index].as_ref().map_or(0, |block| block.size)
+    }
+}
+
+// Whether a block ends after an instruction. Besides jumps and branches,
+// these are instructions that trap, return from traps, or write CSRs, any
+// of which can change privilege, translation, PMP or the extensions
+// enabled, or let an interrupt in.
+pub fn ends_block(instruction: &Instruction) -> bool {
+    jumps(instruction)
+        || matches!(
+            instruction,
+            Instruction::Undefined
+                | Instruction::FenceI { .. }
+                | Instruction::Ebreak
+                | Instruction::Ecall
+                | Instruction::Mret
+                | Instruction::Sret
+                | Instruction::Wfi
+                | Instruction::SfenceVma { .. }
+                | Instruction::Csrrw { .. }
+                | Instruction::Csrrs { .. }
+                | Instruction::Csrrc { .. }
+                | Instruction::Csrrwi { .. }
+                | Instruction::Csrrsi { .. }
+                | Instruction::Csrrci { .. }
+        )
+}
+
+// Jumps and branches, after which the next block can run straight away
+pub fn jumps(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Beq { .. }
+            | Instruction::Bne { .. }
+            | Instruction::Blt { .. }
+            | Instruction::Bge { .. }
+            | Instruction::Bltu { .. }
+            | Instruction::Bgeu { .. }
+            | Instruction::Jal { .. }
+            | Instruction::Jalr { .. }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::csr::Csr;
+    use crate::riscv::instruction::decode;
+    use crate::riscv::micro_op::translate;
+
+    fn ops(encoded: &[u32]) -> Vec<Op> {
+        let csr = Csr::new();
+        encoded
+            .iter()
+            .map(|&encoded| translate(encoded, decode(encoded), &csr))
+            .collect()
+    }
+
+    #[test]
+    fn find_and_link() {
+        let mut cache = BlockCache::new();
+        // addi a0, a0, 1; c.addi a0, 1; bne a0, a1, -6
+        let first = cache.insert(0x100, ops(&[0x0015_0513, 0x0505, 0xfeb5_1de3]));
+        assert_eq!(cache.size(first), 10);
+        assert!(ends_block(&cache.op(first, 2).unwrap().instruction));
+        assert!(cache.op(first, 3).is_none());
+        // Falling through to the ecall after the loop
+        let second = cache.insert(0x10a, ops(&[0x0000_0073]));
+        assert_eq!(
+            cache.blocks[first].as_ref().unwrap().fall_through,
+            Some(second)
+        );
+
+        // Following the loop back links the second block to the first, and
+        // the first's branch taken back to itself
+        assert_eq!(cache.find(0x100), Some(first));
+        assert_eq!(cache.blocks[second].as_ref().unwrap().taken, Some(first));
+        assert_eq!(cache.find(0x100), Some(first));
+        let block = cache.blocks[first].as_ref().unwrap();
+        assert_eq!(
+            (block.taken, block.fall_through),
+            (Some(first), Some(second))
+        );
+        assert_eq!(cache.find(0x10a), Some(second));
+        assert_eq!(cache.find(0x200), None);
+
+        // Only the blocks of the page written are dropped, and their slots
+        // go to the next blocks
+        let other_page = cache.insert(0x1000, ops(&[0x0000_0073]));
+        cache.invalidate_page(0);
+        assert_eq!(cache.find(0x100), None);
+        assert_eq!(cache.find(0x10a), None);
+        assert!(cache.op(first, 0).is_none());
+        assert_eq!(cache.find(0x1000), Some(other_page));
+        let third = cache.insert(0x200, ops(&[0x0000_0073]));
+        assert!(third == first || third == second);
+        assert_eq!(cache.find(0x200), Some(third));
+
+        cache.revalidate(0);
+        assert_eq!(cache.find(0x1000), Some(other_page));
+        cache.revalidate(1);
+        assert_eq!(cache.find(0x1000), None);
+    }
+}
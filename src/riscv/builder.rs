@@ -0,0 +1,326 @@
+// Configuration of the machine an Emulator runs on, and loading of the
+// program it starts with
+use crate::riscv::{
+    assembler, bus, clint, cpu, csr, elf, memory, mmu, pmp, syscall, tlb, uart, Emulator,
+    EmulatorError,
+};
+
+pub struct Builder {
+    // Size of DRAM, which defaults to bus::DRAM_SIZE in system mode and
+    // syscall::MEMORY_SIZE in user mode
+    memory_size: Option<u64>,
+    isa: String,
+    // Left disconnected unless one is given
+    uart: Option<uart::Uart>,
+    // Ticks per second of the CLINT timer
+    timebase: u64,
+    // Translations the TLB holds
+    tlb_entries: usize,
+    trace: bool,
+    // Keep decoded instructions to run again, and run them a basic block
+    // at a time. Both are on unless turned off to compare against, and
+    // blocks are only made from cached instructions.
+    decode_cache: bool,
+    block_cache: bool,
+    // End a system mode program once pc leaves its code, as well as when
+    // it writes the finisher
+    exit_outside_text: bool,
+}
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Builder {
+    pub fn new() -> Self {
+        Self {
+            memory_size: None,
+            isa: "rv64imafdc".to_string(),
+            uart: None,
+            timebase: clint::DEFAULT_FREQUENCY,
+            tlb_entries: tlb::DEFAULT_ENTRIES,
+            trace: false,
+            decode_cache: true,
+            block_cache: true,
+            exit_outside_text: false,
+        }
+    }
+    pub fn memory_size(mut self, size: u64) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+    // The extensions of the hart, as an ISA string like rv64imac or rv64gc
+    pub fn isa(mut self, isa: &str) -> Self {
+        self.isa = isa.to_string();
+        self
+    }
+    pub fn uart(mut self, uart: uart::Uart) -> Self {
+        self.uart = Some(uart);
+        self
+    }
+    pub fn timebase(mut self, frequency: u64) -> Self {
+        self.timebase = frequency;
+        self
+    }
+    pub fn tlb_entries(mut self, entries: usize) -> Self {
+        self.tlb_entries = entries;
+        self
+    }
+    // Print each instruction to stderr before running it
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+    pub fn decode_cache(mut self, enabled: bool) -> Self {
+        self.decode_cache = enabled;
+        self
+    }
+    pub fn block_cache(mut self, enabled: bool) -> Self {
+        self.block_cache = enabled;
+        self
+    }
+    pub fn exit_outside_text(mut self, enabled: bool) -> Self {
+        self.exit_outside_text = enabled;
+        self
+    }
+    // A hart with DRAM at `dram_base`, of `default_size` bytes unless a
+    // size was given
+    fn cpu(&mut self, dram_base: u64, default_size: u64) -> Result<cpu::Cpu, EmulatorError> {
+        let extensions = extensions(&self.isa)
+            .ok_or_else(|| EmulatorError::Config(format!("unsupported ISA string {}", self.isa)))?;
+        let size = self.memory_size.unwrap_or(default_size);
+        if dram_base.checked_add(size).is_none() {
+            let message = format!("{:#x} bytes of memory don't fit the address space", size);
+            return Err(EmulatorError::Config(message));
+        }
+        if size == 0 || !size.is_multiple_of(mmu::PAGE_SIZE) {
+            let message = format!("{:#x} bytes of memory aren't a whole number of pages", size);
+            return Err(EmulatorError::Config(message));
+        }
+        if self.tlb_entries == 0 {
+            return Err(EmulatorError::Config(
+                "a TLB needs at least one entry".to_string(),
+            ));
+        }
+        let dram = memory::Memory::try_new(size).ok_or_else(|| {
+            EmulatorError::Config(format!("couldn't allocate {:#x} bytes of memory", size))
+        })?;
+        let mut bus = bus::Bus::with_memory(dram, self.timebase);
+        bus.dram_base = dram_base;
+        bus.set_decode_cache(self.decode_cache);
+        if let Some(uart) = self.uart.take() {
+            bus.uart = uart;
+        }
+        let mut cpu = cpu::Cpu::new(bus);
+        cpu.csr = csr::Csr::with_extensions(extensions);
+        cpu.tlb = tlb::Tlb::new(self.tlb_entries);
+        Ok(cpu)
+    }
+    // Load an ELF executable, or a flat binary image at the start of DRAM,
+    // to run in system mode
+    pub fn system(mut self, image: &[u8]) -> Result<Emulator, EmulatorError> {
+        let mut cpu = self.cpu(bus::DRAM_BASE, bus::DRAM_SIZE)?;
+        let (text, symbols) = if elf::is_elf(image) {
+            let elf = elf::load(image, &mut cpu, elf::Placement::Physical)?;
+            let text = elf
+                .segments
+                .into_iter()
+                .filter(|segment| segment.is_executable())
+                .collect();
+            (text, elf.symbols)
+        } else {
+            cpu.bus.load(bus::DRAM_BASE, image)?;
+            cpu.pc = bus::DRAM_BASE;
+            let text = vec![elf::Segment {
+                address: bus::DRAM_BASE,
+                size: image.len() as u64,
+                flags: elf::PF_R | elf::PF_X,
+            }];
+            (text, elf::SymbolTable::default())
+        };
+        // The stack grows down from the end of DRAM
+        let sp = bus::DRAM_BASE + cpu.bus.dram.size();
+        cpu.write_register(cpu::AbiRegister::Sp.into(), sp);
+        Ok(Emulator {
+            cpu,
+            text,
+            process: None,
+            symbols,
+            trace: self.trace,
+            blocks: self.block_cache,
+            exit_outside_text: self.exit_outside_text,
+        })
+    }
+    // Assemble a program and load it at the start of DRAM
+    pub fn assembly(self, source: &str) -> Result<Emulator, EmulatorError> {
+        let image = assembler::assemble(source, bus::DRAM_BASE)?;
+        let mut emulator = self.system(&image.bytes)?;
+        emulator.symbols = image.symbols;
+        Ok(emulator)
+    }
+    // Load a Linux executable to run in user mode, with its system calls
+    // handled on the host. `args` starts with the name the program is run
+    // as, and `env` holds NAME=value strings.
+    pub fn user(
+        mut self,
+        image: &[u8],
+        args: &[String],
+        env: &[String],
+    ) -> Result<Emulator, EmulatorError> {
+        let mut cpu = self.cpu(0, syscall::MEMORY_SIZE)?;
+        let memory_size = cpu.bus.dram.size();
+        let elf = elf::load(image, &mut cpu, elf::Placement::Virtual)?;
+        let program_end = elf
+            .segments
+            .iter()
+            .map(|segment| segment.address + segment.size)
+            .max()
+            .unwrap_or(0);
+        let process = syscall::Process::new(program_end, memory_size);
+        // There's no firmware to set up PMP for the process
+        pmp::allow_all(&mut cpu.csr);
+        // nor to let the process read the counters, as Linux does
+        cpu.csr.write(csr::MCOUNTEREN, u64::MAX);
+        cpu.csr.write(csr::SCOUNTEREN, u64::MAX);
+        cpu.privilege = cpu::Privilege::User;
+        let sp = syscall::setup_stack(&mut cpu, memory_size, args, env, &elf)?;
+        cpu.write_register(cpu::AbiRegister::Sp.into(), sp);
+        Ok(Emulator {
+            cpu,
+            text: elf.segments,
+            process: Some(process),
+            symbols: elf.symbols,
+            trace: self.trace,
+            blocks: self.block_cache,
+            exit_outside_text: self.exit_outside_text,
+        })
+    }
+}
+
+// The misa bits of an ISA string, which names the base ISA and then single
+// letter extensions, with any Z extensions after underscores. Zicsr and
+// Zifencei are always implemented, as are supervisor and user modes.
+fn extensions(isa: &str) -> Option<u64> {
+    let isa = isa.to_ascii_lowercase();
+    let mut parts = isa.split('_');
+    let letters = parts.next()?.strip_prefix("rv64")?;
+    let mut extensions = csr::extension(b'I') | csr::extension(b'S') | csr::extension(b'U');
+    let letters = match letters.as_bytes().first()? {
+        b'i' => &letters[1..],
+        // G is shorthand for IMAFD
+        b'g' => {
+            extensions |= csr::extension(b'M')
+                | csr::extension(b'A')
+                | csr::extension(b'F')
+                | csr::extension(b'D');
+            &letters[1..]
+        }
+        _ => return None,
+    };
+    for letter in letters.bytes() {
+        match letter {
+            b'm' | b'a' | b'f' | b'd' | b'c' => {
+                extensions |= csr::extension(letter.to_ascii_uppercase())
+            }
+            _ => return None,
+        }
+    }
+    if !parts.all(|part| part == "zicsr" || part == "zifencei") {
+        return None;
+    }
+    // D builds on F
+    if extensions & csr::extension(b'D') != 0 && extensions & csr::extension(b'F') == 0 {
+        return None;
+    }
+    Some(extensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::cpu::AbiRegister;
+    use crate::riscv::trap::Exception;
+
+    fn letters(extensions: u64) -> String {
+        (b'A'..=b'Z')
+            .filter(|&letter| extensions & csr::extension(letter) != 0)
+            .map(char::from)
+            .collect()
+    }
+
+    #[test]
+    fn isa_strings() {
+        let cases = [
+            ("rv64i", Some("ISU")),
+            ("rv64imac", Some("ACIMSU")),
+            ("RV64GC", Some("ACDFIMSU")),
+            ("rv64imafd_zicsr_zifencei", Some("ADFIMSU")),
+            ("rv64ic", Some("CISU")),
+            ("rv32i", None),
+            ("rv64", None),
+            ("rv64e", None),
+            ("rv64id", None),
+            ("rv64iv", None),
+            ("rv64i_zba", None),
+        ];
+        for &(isa, expected) in &cases {
+            assert_eq!(extensions(isa).map(letters).as_deref(), expected, "{}", isa);
+        }
+    }
+
+    #[test]
+    fn configured_hart() {
+        // mul a0, a0, a0 without the M extension
+        let program = 0x02a5_0533u32.to_le_bytes();
+        let mut emulator = Builder::new()
+            .isa("rv64ic")
+            .memory_size(0x1000)
+            .system(&program)
+            .unwrap();
+        assert_eq!(
+            letters(emulator.cpu.csr.read(csr::MISA) & 0x3ff_ffff),
+            "CISU"
+        );
+        assert_eq!(
+            emulator.register(AbiRegister::Sp.into()),
+            bus::DRAM_BASE + 0x1000
+        );
+        match emulator.step() {
+            Err(EmulatorError::Halted {
+                exception: Exception::IllegalInstruction(0x02a5_0533),
+                pc: bus::DRAM_BASE,
+            }) => {}
+            result => panic!("{:?}", result.map_err(|error| error.to_string())),
+        }
+
+        match Builder::new().isa("rv64q").system(&program) {
+            Err(EmulatorError::Config(message)) => {
+                assert_eq!(message, "unsupported ISA string rv64q")
+            }
+            _ => panic!("rv64q was accepted"),
+        }
+        match Builder::new().tlb_entries(0).system(&program) {
+            Err(EmulatorError::Config(message)) => {
+                assert_eq!(message, "a TLB needs at least one entry")
+            }
+            _ => panic!("an empty TLB was accepted"),
+        }
+        let config = |size| match Builder::new().memory_size(size).system(&program) {
+            Err(EmulatorError::Config(message)) => message,
+            _ => panic!("{:#x} bytes of memory were accepted", size),
+        };
+        assert_eq!(
+            config(0x1001),
+            "0x1001 bytes of memory aren't a whole number of pages"
+        );
+        assert_eq!(
+            config(0),
+            "0x0 bytes of memory aren't a whole number of pages"
+        );
+        // Too much to allocate, but not to address
+        assert_eq!(
+            config(1 << 62),
+            "couldn't allocate 0x4000000000000000 bytes of memory"
+        );
+    }
+}
@@ -0,0 +1,368 @@
+use crate::riscv::clint::{self, Clint};
+use crate::riscv::decode_cache::{self, DecodeCache};
+use crate::riscv::finisher::Finisher;
+use crate::riscv::instruction::{self, Instruction};
+use crate::riscv::memory::Memory;
+use crate::riscv::plic::Plic;
+use crate::riscv::uart::Uart;
+use std::fmt;
+
+pub const FINISHER_BASE: u64 = 0x10_0000;
+pub const FINISHER_SIZE: u64 = 0x1000;
+pub const CLINT_BASE: u64 = 0x0200_0000;
+pub const CLINT_SIZE: u64 = 0x1_0000;
+pub const PLIC_BASE: u64 = 0x0c00_0000;
+pub const PLIC_SIZE: u64 = 0x400_0000;
+pub const UART_BASE: u64 = 0x1000_0000;
+pub const UART_SIZE: u64 = 0x100;
+pub const DRAM_BASE: u64 = 0x8000_0000;
+pub const DRAM_SIZE: u64 = 128 * 1024 * 1024;
+
+#[derive(Debug, PartialEq)]
+pub enum BusError {
+    // Nothing is mapped at the address for the whole width of the access
+    Unmapped(u64),
+}
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BusError::Unmapped(address) => write!(f, "nothing is mapped at {:#x}", address),
+        }
+    }
+}
+impl std::error::Error for BusError {}
+
+// The physical address space seen by the hart
+pub struct Bus {
+    // DRAM starts at DRAM_BASE, except for user programs that expect
+    // memory from address zero
+    pub dram_base: u64,
+    pub dram: Memory,
+    // Where bare metal programs write their exit status
+    pub finisher: Finisher,
+    pub clint: Clint,
+    // Devices signal interrupts by setting the level of their source
+    pub plic: Plic,
+    pub uart: Uart,
+    // Instructions decoded from DRAM, which writes through the bus keep up
+    // to date
+    decoded: DecodeCache,
+    // One bit for each page of DRAM that basic blocks were built from, and
+    // the physical addresses of those pages written since they were
+    code_pages: Vec<u64>,
+    written_code: Vec<u64>,
+}
+
+// PLIC sources of the devices
+pub const UART_IRQ: usize = 10;
+
+impl Bus {
+    pub fn new(dram_size: u64) -> Self {
+        Self::with_timebase(dram_size, clint::DEFAULT_FREQUENCY)
+    }
+    // A bus whose timer counts `frequency` ticks per second
+    pub fn with_timebase(dram_size: u64, frequency: u64) -> Self {
+        Self::with_memory(Memory::new(dram_size), frequency)
+    }
+    // A bus with `dram` allocated already
+    pub fn with_memory(dram: Memory, frequency: u64) -> Self {
+        let dram_size = dram.size();
+        Self {
+            dram_base: DRAM_BASE,
+            dram,
+            finisher: Finisher::new(),
+            clint: Clint::new(frequency),
+            plic: Plic::new(1),
+            uart: Uart::disconnected(),
+            decoded: DecodeCache::new(dram_size),
+            code_pages: vec![0; dram_size.div_ceil(64 * decode_cache::PAGE_SIZE) as usize],
+            written_code: Vec::new(),
+        }
+    }
+    // Turn caching of decoded instructions on or off, dropping those
+    // already cached
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        let size = if enabled { self.dram.size() } else { 0 };
+        self.decoded = DecodeCache::new(size);
+        self.forget_code();
+    }
+    // The instruction at a physical address, along with its encoding, if it
+    // was decoded before and hasn't been overwritten since
+    pub fn decoded(&self, address: u64) -> Option<(u32, Instruction)> {
+        self.decoded.get(self.dram_offset(address, 2)?)
+    }
+    // Returns whether the instruction was cached, which it isn't outside
+    // DRAM, across a page boundary or with the cache turned off
+    pub fn insert_decoded(&mut self, address: u64, encoded: u32, decoded: Instruction) -> bool {
+        let length = instruction::length(encoded);
+        match self.dram_offset(address, length) {
+            Some(offset) => self.decoded.insert(offset, encoded, decoded),
+            None => false,
+        }
+    }
+    pub fn flush_decoded(&mut self) {
+        self.decoded.flush();
+        self.forget_code();
+    }
+    // Note that basic blocks were built from the page of DRAM at a physical
+    // address, so that writes over their instructions are reported by
+    // take_written_code. Blocks are only built from instructions in the
+    // decode cache, so writes that clear none of those leave them be.
+    pub fn mark_code(&mut self, address: u64) {
+        if let Some(offset) = self.dram_offset(address, 1) {
+            let page = offset / decode_cache::PAGE_SIZE;
+            self.code_pages[(page / 64) as usize] |= 1 << (page % 64);
+        }
+    }
+    // The pages holding basic blocks that were written since they were
+    // marked, which are unmarked until marked again
+    pub fn take_written_code(&mut self) -> Vec<u64> {
+        std::mem::take(&mut self.written_code)
+    }
+    pub fn code_written(&self) -> bool {
+        !self.written_code.is_empty()
+    }
+    fn invalidate_decoded(&mut self, offset: u64, size: u64) {
+        if !self.decoded.invalidate(offset, size) {
+            return;
+        }
+        let first = offset / decode_cache::PAGE_SIZE;
+        let last = (offset + size - 1) / decode_cache::PAGE_SIZE;
+        for page in first..=last {
+            let (word, bit) = ((page / 64) as usize, 1 << (page % 64));
+            if self.code_pages[word] & bit != 0 {
+                self.code_pages[word] &= !bit;
+                self.written_code
+                    .push(self.dram_base + page * decode_cache::PAGE_SIZE);
+            }
+        }
+    }
+    // Report every page blocks were built from as written, once the decoded
+    // instructions that tell when they are written are gone
+    fn forget_code(&mut self) {
+        for (word, bits) in self.code_pages.iter_mut().enumerate() {
+            for bit in 0..64 {
+                if *bits & 1 << bit != 0 {
+                    let page = (word * 64 + bit) as u64;
+                    self.written_code
+                        .push(self.dram_base + page * decode_cache::PAGE_SIZE);
+                }
+            }
+            *bits = 0;
+        }
+    }
+    // Offset into DRAM of an access, if DRAM covers all of it
+    fn dram_offset(&self, address: u64, size: u64) -> Option<u64> {
+        region_offset(address, size, self.dram_base, self.dram.size())
+    }
+    // Let devices take in host input and pass their interrupt lines on to
+    // the PLIC
+    pub fn update_interrupts(&mut self) {
+        self.uart.poll();
+        self.plic.set_level(UART_IRQ, self.uart.interrupting());
+    }
+    // Copy an image into memory starting at `address`
+    pub fn load(&mut self, address: u64, bytes: &[u8]) -> Result<(), BusError> {
+        let offset = self
+            .dram_offset(address, bytes.len() as u64)
+            .ok_or(BusError::Unmapped(address))?;
+        self.dram.write_bytes(offset, bytes);
+        self.invalidate_decoded(offset, bytes.len() as u64);
+        Ok(())
+    }
+    // Reads go through `&mut self` because reading a device register can
+    // change its state, as claiming a PLIC interrupt does
+    // DRAM comes first, and hides the devices when it is moved over them
+    fn read(&mut self, address: u64, size: usize) -> Result<u64, BusError> {
+        if let Some(offset) = self.dram_offset(address, size as u64) {
+            return Ok(self.dram.read(offset, size));
+        }
+        if let Some(offset) = region_offset(address, size as u64, FINISHER_BASE, FINISHER_SIZE) {
+            return Ok(self.finisher.read(offset, size));
+        }
+        if let Some(offset) = region_offset(address, size as u64, CLINT_BASE, CLINT_SIZE) {
+            return Ok(self.clint.read(offset, size));
+        }
+        if let Some(offset) = region_offset(address, size as u64, PLIC_BASE, PLIC_SIZE) {
+            return Ok(self.plic.read(offset, size));
+        }
+        if let Some(offset) = region_offset(address, size as u64, UART_BASE, UART_SIZE) {
+            return Ok(self.uart.read(offset, size));
+        }
+        Err(BusError::Unmapped(address))
+    }
+    fn write(&mut self, address: u64, size: usize, value: u64) -> Result<(), BusError> {
+        if let Some(offset) = self.dram_offset(address, size as u64) {
+            self.dram.write(offset, size, value);
+            self.invalidate_decoded(offset, size as u64);
+            return Ok(());
+        }
+        if let Some(offset) = region_offset(address, size as u64, FINISHER_BASE, FINISHER_SIZE) {
+            self.finisher.write(offset, size, value);
+            return Ok(());
+        }
+        if let Some(offset) = region_offset(address, size as u64, CLINT_BASE, CLINT_SIZE) {
+            self.clint.write(offset, size, value);
+            return Ok(());
+        }
+        if let Some(offset) = region_offset(address, size as u64, PLIC_BASE, PLIC_SIZE) {
+            self.plic.write(offset, size, value);
+            return Ok(());
+        }
+        if let Some(offset) = region_offset(address, size as u64, UART_BASE, UART_SIZE) {
+            self.uart.write(offset, size, value);
+            return Ok(());
+        }
+        Err(BusError::Unmapped(address))
+    }
+    // A byte of DRAM, for debuggers. Devices aren't read, since reading
+    // their registers can change their state.
+    pub fn peek(&self, address: u64) -> Option<u8> {
+        let offset = self.dram_offset(address, 1)?;
+        Some(self.dram.read(offset, 1) as u8)
+    }
+    pub fn read_byte(&mut self, address: u64) -> Result<u8, BusError> {
+        self.read(address, 1).map(|value| value as u8)
+    }
+    pub fn read_half(&mut self, address: u64) -> Result<u16, BusError> {
+        self.read(address, 2).map(|value| value as u16)
+    }
+    pub fn read_word(&mut self, address: u64) -> Result<u32, BusError> {
+        self.read(address, 4).map(|value| value as u32)
+    }
+    pub fn read_double(&mut self, address: u64) -> Result<u64, BusError> {
+        self.read(address, 8)
+    }
+    pub fn write_byte(&mut self, address: u64, value: u8) -> Result<(), BusError> {
+        self.write(address, 1, value as u64)
+    }
+    pub fn write_half(&mut self, address: u64, value: u16) -> Result<(), BusError> {
+        self.write(address, 2, value as u64)
+    }
+    pub fn write_word(&mut self, address: u64, value: u32) -> Result<(), BusError> {
+        self.write(address, 4, value as u64)
+    }
+    pub fn write_double(&mut self, address: u64, value: u64) -> Result<(), BusError> {
+        self.write(address, 8, value)
+    }
+}
+
+// Offset into a region of an access, if the region covers all of it
+fn region_offset(address: u64, size: u64, base: u64, region_size: u64) -> Option<u64> {
+    let offset = address.checked_sub(base)?;
+    if offset.checked_add(size)? <= region_size {
+        Some(offset)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::instruction::decode;
+    use crate::riscv::{finisher, plic, uart};
+    #[test]
+    fn read_write_dram() {
+        let mut bus = Bus::new(0x100);
+        bus.write_double(DRAM_BASE + 8, 0x1122_3344_5566_7788)
+            .unwrap();
+        assert_eq!(bus.read_byte(DRAM_BASE + 8), Ok(0x88));
+        assert_eq!(bus.read_half(DRAM_BASE + 14), Ok(0x1122));
+        assert_eq!(bus.read_word(DRAM_BASE + 10), Ok(0x3344_5566));
+        bus.write_half(DRAM_BASE + 8, 0xabcd).unwrap();
+        bus.write_byte(DRAM_BASE + 15, 0).unwrap();
+        bus.write_word(DRAM_BASE + 0xfc, 0xffff_ffff).unwrap();
+        assert_eq!(bus.read_double(DRAM_BASE + 8), Ok(0x0022_3344_5566_abcd));
+    }
+    #[test]
+    fn written_code() {
+        let mut bus = Bus::new(0x2000);
+        let addi = decode(0x0015_0513);
+        assert!(bus.insert_decoded(DRAM_BASE + 0x100, 0x0015_0513, addi));
+        bus.mark_code(DRAM_BASE + 0x100);
+        bus.mark_code(DRAM_BASE + 0x1000);
+        // Data next to the code leaves it be
+        bus.write_word(DRAM_BASE + 0x200, 1).unwrap();
+        assert!(!bus.code_written());
+        bus.write_byte(DRAM_BASE + 0x102, 1).unwrap();
+        assert_eq!(bus.take_written_code(), vec![DRAM_BASE]);
+        assert!(!bus.code_written());
+        // The page is only reported again once marked again
+        assert!(bus.insert_decoded(DRAM_BASE + 0x100, 0x0015_0513, addi));
+        bus.write_byte(DRAM_BASE + 0x100, 1).unwrap();
+        assert!(!bus.code_written());
+        bus.flush_decoded();
+        assert_eq!(bus.take_written_code(), vec![DRAM_BASE + 0x1000]);
+    }
+    #[test]
+    fn finisher() {
+        let mut bus = Bus::new(0x100);
+        assert_eq!(bus.read_word(FINISHER_BASE), Ok(0));
+        bus.write_word(FINISHER_BASE, (1 << 16 | finisher::FAIL) as u32)
+            .unwrap();
+        assert_eq!(bus.finisher.status(), Some(1));
+    }
+    #[test]
+    fn clint() {
+        let mut bus = Bus::new(0x100);
+        bus.write_word(CLINT_BASE + clint::MSIP, 1).unwrap();
+        assert!(bus.clint.software_interrupt());
+        bus.write_double(CLINT_BASE + clint::MTIMECMP, 0x1234)
+            .unwrap();
+        assert_eq!(bus.read_half(CLINT_BASE + clint::MTIMECMP), Ok(0x1234));
+        assert_eq!(bus.read_word(CLINT_BASE + 0x8), Ok(0));
+        // A timer that never ticks
+        let mut bus = Bus::with_timebase(0x100, 0);
+        bus.write_double(CLINT_BASE + clint::MTIME, 42).unwrap();
+        assert_eq!(bus.read_double(CLINT_BASE + clint::MTIME), Ok(42));
+        assert_eq!(
+            bus.read_word(CLINT_BASE + CLINT_SIZE - 2),
+            Err(BusError::Unmapped(CLINT_BASE + CLINT_SIZE - 2))
+        );
+    }
+    #[test]
+    fn plic() {
+        let mut bus = Bus::new(0x100);
+        bus.write_word(PLIC_BASE + plic::PRIORITY + 4, 1).unwrap();
+        bus.write_word(PLIC_BASE + plic::ENABLE, 1 << 1).unwrap();
+        bus.plic.set_level(1, true);
+        assert!(bus.plic.interrupting(0));
+        assert_eq!(bus.read_word(PLIC_BASE + plic::CLAIM), Ok(1));
+        assert!(!bus.plic.interrupting(0));
+    }
+    #[test]
+    fn uart() {
+        let mut bus = Bus::new(0x100);
+        assert_eq!(
+            bus.read_byte(UART_BASE + uart::LSR),
+            Ok(uart::LSR_THRE | uart::LSR_TEMT)
+        );
+        // The UART's interrupt reaches the PLIC
+        bus.write_word(PLIC_BASE + plic::PRIORITY + 4 * UART_IRQ as u64, 1)
+            .unwrap();
+        bus.write_word(PLIC_BASE + plic::ENABLE, 1 << UART_IRQ)
+            .unwrap();
+        bus.write_byte(UART_BASE + uart::IER, uart::IER_THRI)
+            .unwrap();
+        bus.update_interrupts();
+        assert!(bus.plic.interrupting(0));
+    }
+    #[test]
+    fn unmapped() {
+        let mut bus = Bus::new(0x100);
+        assert_eq!(bus.read_byte(0), Err(BusError::Unmapped(0)));
+        assert_eq!(
+            bus.read_word(DRAM_BASE + 0xfe),
+            Err(BusError::Unmapped(DRAM_BASE + 0xfe))
+        );
+        assert_eq!(
+            bus.write_double(DRAM_BASE + 0x100, 0),
+            Err(BusError::Unmapped(DRAM_BASE + 0x100))
+        );
+        assert_eq!(
+            bus.load(DRAM_BASE + 0xff, &[0, 0]),
+            Err(BusError::Unmapped(DRAM_BASE + 0xff))
+        );
+    }
+}
@@ -0,0 +1,135 @@
+// Core local interruptor: the machine timer and the machine software
+// interrupt of a single hart, laid out as in the SiFive CLINT
+use std::time::Instant;
+
+pub const MSIP: u64 = 0x0;
+pub const MTIMECMP: u64 = 0x4000;
+pub const MTIME: u64 = 0xbff8;
+
+// Rate at which mtime counts, in ticks per second
+pub const DEFAULT_FREQUENCY: u64 = 10_000_000;
+
+pub struct Clint {
+    msip: u32,
+    mtimecmp: u64,
+    // mtime counts up from `mtime_base` at `frequency` from `started`
+    mtime_base: u64,
+    started: Instant,
+    frequency: u64,
+}
+impl Clint {
+    pub fn new(frequency: u64) -> Self {
+        Self {
+            msip: 0,
+            // The timer interrupt stays clear until software sets mtimecmp
+            mtimecmp: u64::MAX,
+            mtime_base: 0,
+            started: Instant::now(),
+            frequency,
+        }
+    }
+    pub fn mtime(&self) -> u64 {
+        let elapsed = self.started.elapsed().as_nanos() * u128::from(self.frequency);
+        self.mtime_base
+            .wrapping_add((elapsed / 1_000_000_000) as u64)
+    }
+    fn set_mtime(&mut self, value: u64) {
+        self.mtime_base = value;
+        self.started = Instant::now();
+    }
+    // Whether the machine software and timer interrupts are pending
+    pub fn software_interrupt(&self) -> bool {
+        self.msip & 1 != 0
+    }
+    pub fn timer_interrupt(&self) -> bool {
+        self.mtime() >= self.mtimecmp
+    }
+    // Register holding the byte at `offset`, and the offset it starts at
+    fn register(&self, offset: u64) -> Option<(u64, u64)> {
+        match offset {
+            MSIP..=0x3 => Some((u64::from(self.msip), MSIP)),
+            MTIMECMP..=0x4007 => Some((self.mtimecmp, MTIMECMP)),
+            MTIME..=0xbfff => Some((self.mtime(), MTIME)),
+            _ => None,
+        }
+    }
+    // Read `size` bytes at `offset`. Reserved offsets read as zero.
+    pub fn read(&self, offset: u64, size: usize) -> u64 {
+        let (value, start) = match self.register(offset) {
+            Some(register) => register,
+            None => return 0,
+        };
+        let value = value >> (8 * (offset - start));
+        match size {
+            8 => value,
+            size => value & ((1 << (8 * size)) - 1),
+        }
+    }
+    // Write the low `size` bytes of `value` at `offset`, leaving the rest of
+    // the register as it was. Writes to reserved offsets are ignored.
+    pub fn write(&mut self, offset: u64, size: usize, value: u64) {
+        let (old, start) = match self.register(offset) {
+            Some(register) => register,
+            None => return,
+        };
+        let shift = 8 * (offset - start);
+        let mask = match size {
+            8 => u64::MAX,
+            size => (1 << (8 * size)) - 1,
+        } << shift;
+        let value = (old & !mask) | ((value << shift) & mask);
+        match start {
+            MSIP => self.msip = value as u32 & 1,
+            MTIMECMP => self.mtimecmp = value,
+            _ => self.set_mtime(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn msip() {
+        let mut clint = Clint::new(DEFAULT_FREQUENCY);
+        assert!(!clint.software_interrupt());
+        clint.write(MSIP, 4, 0xffff_ffff);
+        assert_eq!(clint.read(MSIP, 4), 1);
+        assert!(clint.software_interrupt());
+        clint.write(MSIP, 4, 0);
+        assert!(!clint.software_interrupt());
+    }
+    #[test]
+    fn mtimecmp() {
+        let mut clint = Clint::new(DEFAULT_FREQUENCY);
+        assert!(!clint.timer_interrupt());
+        // Written a half at a time, as RV32 software does
+        clint.write(MTIMECMP, 4, 0);
+        clint.write(MTIMECMP + 4, 4, 0);
+        assert_eq!(clint.read(MTIMECMP, 8), 0);
+        assert!(clint.timer_interrupt());
+        clint.write(MTIMECMP, 8, 0x1122_3344_5566_7788);
+        assert_eq!(clint.read(MTIMECMP + 4, 4), 0x1122_3344);
+        assert!(!clint.timer_interrupt());
+    }
+    #[test]
+    fn mtime() {
+        let mut clint = Clint::new(DEFAULT_FREQUENCY);
+        clint.write(MTIME, 8, 1 << 40);
+        let mtime = clint.read(MTIME, 8);
+        assert!((1 << 40..(1 << 40) + DEFAULT_FREQUENCY).contains(&mtime));
+        // mtime keeps counting
+        let earlier = clint.mtime();
+        std::thread::sleep(std::time::Duration::from_millis(1));
+        assert!(clint.mtime() > earlier);
+        // A stopped clock
+        let clint = Clint::new(0);
+        assert_eq!(clint.mtime(), 0);
+    }
+    #[test]
+    fn reserved() {
+        let mut clint = Clint::new(DEFAULT_FREQUENCY);
+        clint.write(0x8, 4, 1);
+        assert_eq!(clint.read(0x8, 4), 0);
+    }
+}
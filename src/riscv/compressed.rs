@@ -0,0 +1,704 @@
+use crate::riscv::cpu::{register_field, FRegister, Register};
+use crate::riscv::instruction::Instruction;
+
+// Decode a 16-bit RVC instruction by expanding it into the 32-bit
+// instruction it is shorthand for. Reserved encodings decode as Undefined.
+pub fn decode(instruction: u16) -> Instruction {
+    let instruction = u32::from(instruction);
+    let quadrant = instruction & 0b11;
+    let funct3 = (instruction >> 13) & 0b111;
+
+    // Full register fields, in bits 11:7 and 6:2
+    let rd: Register = register_field((instruction >> 7) & 0b11111);
+    let rs2: Register = register_field((instruction >> 2) & 0b11111);
+    // Compressed register fields only address x8 to x15
+    let rd_prime: Register = register_field(((instruction >> 2) & 0b111) + 8);
+    let rs1_prime: Register = register_field(((instruction >> 7) & 0b111) + 8);
+    // The same fields naming floating point registers
+    let frd: FRegister = register_field((instruction >> 7) & 0b11111);
+    let frs2: FRegister = register_field((instruction >> 2) & 0b11111);
+    let frd_prime: FRegister = register_field(((instruction >> 2) & 0b111) + 8);
+    let sp = Register::X2;
+
+    // 6-bit immediate split between bit 12 and bits 6:2, used by the CI
+    // format arithmetic instructions
+    let imm6 = ((instruction >> 7) & 0b100000) | ((instruction >> 2) & 0b11111);
+    let imm6 = ((imm6 as i32) << 26) >> 26;
+    let shamt = ((instruction >> 7) & 0b100000) | ((instruction >> 2) & 0b11111);
+
+    match quadrant {
+        0b00 => {
+            // Word and doubleword offsets of the CL and CS formats
+            let offset_w = ((instruction >> 7) & 0b111000)
+                | ((instruction << 1) & 0b1000000)
+                | ((instruction >> 4) & 0b100);
+            let offset_d = ((instruction >> 7) & 0b111000) | ((instruction << 1) & 0b11000000);
+            match funct3 {
+                // C.ADDI4SPN
+                0b000 => {
+                    let imm = ((instruction >> 7) & 0b110000)
+                        | ((instruction >> 1) & 0b1111000000)
+                        | ((instruction >> 4) & 0b100)
+                        | ((instruction >> 2) & 0b1000);
+                    match imm {
+                        // Also covers the all zero instruction, which is
+                        // defined to be illegal
+                        0 => Instruction::Undefined,
+                        imm => Instruction::Addi {
+                            rd: rd_prime,
+                            rs1: sp,
+                            imm: imm as i32,
+                        },
+                    }
+                }
+                // C.FLD
+                0b001 => Instruction::Fld {
+                    rd: frd_prime,
+                    rs1: rs1_prime,
+                    imm: offset_d as i32,
+                },
+                // C.LW
+                0b010 => Instruction::Lw {
+                    rd: rd_prime,
+                    rs1: rs1_prime,
+                    imm: offset_w as i32,
+                },
+                // C.LD
+                0b011 => Instruction::Ld {
+                    rd: rd_prime,
+                    rs1: rs1_prime,
+                    imm: offset_d as i32,
+                },
+                // C.FSD
+                0b101 => Instruction::Fsd {
+                    rs2: frd_prime,
+                    rs1: rs1_prime,
+                    imm: offset_d as i32,
+                },
+                // C.SW
+                0b110 => Instruction::Sw {
+                    rs2: rd_prime,
+                    rs1: rs1_prime,
+                    imm: offset_w as i32,
+                },
+                // C.SD
+                0b111 => Instruction::Sd {
+                    rs2: rd_prime,
+                    rs1: rs1_prime,
+                    imm: offset_d as i32,
+                },
+                _ => Instruction::Undefined,
+            }
+        }
+        0b01 => match funct3 {
+            // C.ADDI, and C.NOP when rd is x0
+            0b000 => Instruction::Addi {
+                rd,
+                rs1: rd,
+                imm: imm6,
+            },
+            // C.ADDIW
+            0b001 if rd != Register::X0 => Instruction::Addiw {
+                rd,
+                rs1: rd,
+                imm: imm6,
+            },
+            // C.LI
+            0b010 => Instruction::Addi {
+                rd,
+                rs1: Register::X0,
+                imm: imm6,
+            },
+            // C.ADDI16SP
+            0b011 if rd == sp => {
+                let imm = ((instruction >> 3) & 0b1000000000)
+                    | ((instruction >> 2) & 0b10000)
+                    | ((instruction << 1) & 0b1000000)
+                    | ((instruction << 4) & 0b110000000)
+                    | ((instruction << 3) & 0b100000);
+                match ((imm as i32) << 22) >> 22 {
+                    0 => Instruction::Undefined,
+                    imm => Instruction::Addi { rd, rs1: rd, imm },
+                }
+            }
+            // C.LUI
+            0b011 => match imm6 {
+                0 => Instruction::Undefined,
+                // The U-type immediate is the unsigned 20-bit field
+                imm => Instruction::Lui {
+                    rd,
+                    imm: imm & 0xfffff,
+                },
+            },
+            0b100 => {
+                let rd = rs1_prime;
+                let rs2 = rd_prime;
+                match (instruction >> 10) & 0b11 {
+                    // C.SRLI
+                    0b00 => Instruction::Srli { rd, rs1: rd, shamt },
+                    // C.SRAI
+                    0b01 => Instruction::Srai { rd, rs1: rd, shamt },
+                    // C.ANDI
+                    0b10 => Instruction::Andi {
+                        rd,
+                        rs1: rd,
+                        imm: imm6,
+                    },
+                    _ => match (((instruction >> 12) & 0b1), (instruction >> 5) & 0b11) {
+                        (0, 0b00) => Instruction::Sub { rd, rs1: rd, rs2 },
+                        (0, 0b01) => Instruction::Xor { rd, rs1: rd, rs2 },
+                        (0, 0b10) => Instruction::Or { rd, rs1: rd, rs2 },
+                        (0, 0b11) => Instruction::And { rd, rs1: rd, rs2 },
+                        (1, 0b00) => Instruction::Subw { rd, rs1: rd, rs2 },
+                        (1, 0b01) => Instruction::Addw { rd, rs1: rd, rs2 },
+                        _ => Instruction::Undefined,
+                    },
+                }
+            }
+            // C.J
+            0b101 => {
+                let imm = ((instruction >> 1) & 0b100000000000)
+                    | ((instruction >> 7) & 0b10000)
+                    | ((instruction >> 1) & 0b1100000000)
+                    | ((instruction << 2) & 0b10000000000)
+                    | ((instruction >> 1) & 0b1000000)
+                    | ((instruction << 1) & 0b10000000)
+                    | ((instruction >> 2) & 0b1110)
+                    | ((instruction << 3) & 0b100000);
+                Instruction::Jal {
+                    rd: Register::X0,
+                    imm: ((imm as i32) << 20) >> 20,
+                }
+            }
+            // C.BEQZ and C.BNEZ
+            0b110 | 0b111 => {
+                let imm = ((instruction >> 4) & 0b100000000)
+                    | ((instruction >> 7) & 0b11000)
+                    | ((instruction << 1) & 0b11000000)
+                    | ((instruction >> 2) & 0b110)
+                    | ((instruction << 3) & 0b100000);
+                let imm = ((imm as i32) << 23) >> 23;
+                let rs1 = rs1_prime;
+                let rs2 = Register::X0;
+                match funct3 {
+                    0b110 => Instruction::Beq { rs1, rs2, imm },
+                    _ => Instruction::Bne { rs1, rs2, imm },
+                }
+            }
+            _ => Instruction::Undefined,
+        },
+        0b10 => {
+            // Doubleword offsets from sp of the CI and CSS formats
+            let offset_dsp = ((instruction >> 7) & 0b100000)
+                | ((instruction >> 2) & 0b11000)
+                | ((instruction << 4) & 0b111000000);
+            let offset_sdsp = ((instruction >> 7) & 0b111000) | ((instruction >> 1) & 0b111000000);
+            match funct3 {
+                // C.SLLI
+                0b000 => Instruction::Slli { rd, rs1: rd, shamt },
+                // C.LWSP
+                0b010 if rd != Register::X0 => {
+                    let imm = ((instruction >> 7) & 0b100000)
+                        | ((instruction >> 2) & 0b11100)
+                        | ((instruction << 4) & 0b11000000);
+                    Instruction::Lw {
+                        rd,
+                        rs1: sp,
+                        imm: imm as i32,
+                    }
+                }
+                // C.FLDSP
+                0b001 => Instruction::Fld {
+                    rd: frd,
+                    rs1: sp,
+                    imm: offset_dsp as i32,
+                },
+                // C.LDSP
+                0b011 if rd != Register::X0 => Instruction::Ld {
+                    rd,
+                    rs1: sp,
+                    imm: offset_dsp as i32,
+                },
+                0b100 => match ((instruction >> 12) & 0b1, rd, rs2) {
+                    // C.JR
+                    (0, Register::X0, Register::X0) => Instruction::Undefined,
+                    (0, rs1, Register::X0) => Instruction::Jalr {
+                        rd: Register::X0,
+                        rs1,
+                        imm: 0,
+                    },
+                    // C.MV
+                    (0, rd, rs2) => Instruction::Add {
+                        rd,
+                        rs1: Register::X0,
+                        rs2,
+                    },
+                    // C.EBREAK
+                    (_, Register::X0, Register::X0) => Instruction::Ebreak,
+                    // C.JALR
+                    (_, rs1, Register::X0) => Instruction::Jalr {
+                        rd: Register::X1,
+                        rs1,
+                        imm: 0,
+                    },
+                    // C.ADD
+                    (_, rd, rs2) => Instruction::Add { rd, rs1: rd, rs2 },
+                },
+                // C.SWSP
+                0b110 => {
+                    let imm = ((instruction >> 7) & 0b111100) | ((instruction >> 1) & 0b11000000);
+                    Instruction::Sw {
+                        rs2,
+                        rs1: sp,
+                        imm: imm as i32,
+                    }
+                }
+                // C.FSDSP
+                0b101 => Instruction::Fsd {
+                    rs2: frs2,
+                    rs1: sp,
+                    imm: offset_sdsp as i32,
+                },
+                // C.SDSP
+                0b111 => Instruction::Sd {
+                    rs2,
+                    rs1: sp,
+                    imm: offset_sdsp as i32,
+                },
+                _ => Instruction::Undefined,
+            }
+        }
+        // The low bits are 0b11 for instructions that are 32 bits or longer
+        _ => Instruction::Undefined,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::cpu::AbiRegister;
+    #[test]
+    fn decode_illegal() {
+        assert_eq!(decode(0x0000), Instruction::Undefined);
+    }
+    #[test]
+    fn decode_c_addi4spn() {
+        assert_eq!(
+            decode(0x1fe8),
+            Instruction::Addi {
+                rd: AbiRegister::A0.into(),
+                rs1: AbiRegister::Sp.into(),
+                imm: 1020
+            }
+        );
+    }
+    #[test]
+    fn decode_c_fld() {
+        assert_eq!(
+            decode(0x2588),
+            Instruction::Fld {
+                rd: FRegister::F10,
+                rs1: AbiRegister::A1.into(),
+                imm: 8
+            }
+        );
+    }
+    #[test]
+    fn decode_c_lw() {
+        assert_eq!(
+            decode(0x5f7c),
+            Instruction::Lw {
+                rd: AbiRegister::A5.into(),
+                rs1: AbiRegister::A4.into(),
+                imm: 124
+            }
+        );
+    }
+    #[test]
+    fn decode_c_ld() {
+        assert_eq!(
+            decode(0x7ce0),
+            Instruction::Ld {
+                rd: AbiRegister::S0Fp.into(),
+                rs1: AbiRegister::S1.into(),
+                imm: 248
+            }
+        );
+    }
+    #[test]
+    fn decode_c_fsd() {
+        assert_eq!(
+            decode(0xbce0),
+            Instruction::Fsd {
+                rs2: FRegister::F8,
+                rs1: AbiRegister::S1.into(),
+                imm: 248
+            }
+        );
+    }
+    #[test]
+    fn decode_c_sw() {
+        assert_eq!(
+            decode(0xc2b0),
+            Instruction::Sw {
+                rs2: AbiRegister::A2.into(),
+                rs1: AbiRegister::A3.into(),
+                imm: 64
+            }
+        );
+    }
+    #[test]
+    fn decode_c_sd() {
+        assert_eq!(
+            decode(0xe588),
+            Instruction::Sd {
+                rs2: AbiRegister::A0.into(),
+                rs1: AbiRegister::A1.into(),
+                imm: 8
+            }
+        );
+    }
+    #[test]
+    fn decode_c_nop() {
+        assert_eq!(
+            decode(0x0001),
+            Instruction::Addi {
+                rd: AbiRegister::Zero.into(),
+                rs1: AbiRegister::Zero.into(),
+                imm: 0
+            }
+        );
+    }
+    #[test]
+    fn decode_c_addi() {
+        assert_eq!(
+            decode(0x1501),
+            Instruction::Addi {
+                rd: AbiRegister::A0.into(),
+                rs1: AbiRegister::A0.into(),
+                imm: -32
+            }
+        );
+    }
+    #[test]
+    fn decode_c_addiw() {
+        assert_eq!(
+            decode(0x22fd),
+            Instruction::Addiw {
+                rd: AbiRegister::T0.into(),
+                rs1: AbiRegister::T0.into(),
+                imm: 31
+            }
+        );
+    }
+    #[test]
+    fn decode_c_li() {
+        assert_eq!(
+            decode(0x57fd),
+            Instruction::Addi {
+                rd: AbiRegister::A5.into(),
+                rs1: AbiRegister::Zero.into(),
+                imm: -1
+            }
+        );
+    }
+    #[test]
+    fn decode_c_addi16sp() {
+        assert_eq!(
+            decode(0x7101),
+            Instruction::Addi {
+                rd: AbiRegister::Sp.into(),
+                rs1: AbiRegister::Sp.into(),
+                imm: -512
+            }
+        );
+        assert_eq!(
+            decode(0x617d),
+            Instruction::Addi {
+                rd: AbiRegister::Sp.into(),
+                rs1: AbiRegister::Sp.into(),
+                imm: 496
+            }
+        );
+    }
+    #[test]
+    fn decode_c_lui() {
+        assert_eq!(
+            decode(0x7405),
+            Instruction::Lui {
+                rd: AbiRegister::S0Fp.into(),
+                imm: 0xfffe1
+            }
+        );
+        assert_eq!(
+            decode(0x657d),
+            Instruction::Lui {
+                rd: AbiRegister::A0.into(),
+                imm: 31
+            }
+        );
+    }
+    #[test]
+    fn decode_c_srli() {
+        assert_eq!(
+            decode(0x937d),
+            Instruction::Srli {
+                rd: AbiRegister::A4.into(),
+                rs1: AbiRegister::A4.into(),
+                shamt: 63
+            }
+        );
+    }
+    #[test]
+    fn decode_c_srai() {
+        assert_eq!(
+            decode(0x8485),
+            Instruction::Srai {
+                rd: AbiRegister::S1.into(),
+                rs1: AbiRegister::S1.into(),
+                shamt: 1
+            }
+        );
+    }
+    #[test]
+    fn decode_c_andi() {
+        assert_eq!(
+            decode(0x9ac1),
+            Instruction::Andi {
+                rd: AbiRegister::A3.into(),
+                rs1: AbiRegister::A3.into(),
+                imm: -16
+            }
+        );
+    }
+    #[test]
+    fn decode_c_sub() {
+        assert_eq!(
+            decode(0x8c05),
+            Instruction::Sub {
+                rd: AbiRegister::S0Fp.into(),
+                rs1: AbiRegister::S0Fp.into(),
+                rs2: AbiRegister::S1.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_xor() {
+        assert_eq!(
+            decode(0x8d2d),
+            Instruction::Xor {
+                rd: AbiRegister::A0.into(),
+                rs1: AbiRegister::A0.into(),
+                rs2: AbiRegister::A1.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_or() {
+        assert_eq!(
+            decode(0x8e55),
+            Instruction::Or {
+                rd: AbiRegister::A2.into(),
+                rs1: AbiRegister::A2.into(),
+                rs2: AbiRegister::A3.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_and() {
+        assert_eq!(
+            decode(0x8f7d),
+            Instruction::And {
+                rd: AbiRegister::A4.into(),
+                rs1: AbiRegister::A4.into(),
+                rs2: AbiRegister::A5.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_subw() {
+        assert_eq!(
+            decode(0x9c1d),
+            Instruction::Subw {
+                rd: AbiRegister::S0Fp.into(),
+                rs1: AbiRegister::S0Fp.into(),
+                rs2: AbiRegister::A5.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_addw() {
+        assert_eq!(
+            decode(0x9d25),
+            Instruction::Addw {
+                rd: AbiRegister::A0.into(),
+                rs1: AbiRegister::A0.into(),
+                rs2: AbiRegister::S1.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_j() {
+        assert_eq!(
+            decode(0xb001),
+            Instruction::Jal {
+                rd: AbiRegister::Zero.into(),
+                imm: -2048
+            }
+        );
+        assert_eq!(
+            decode(0xaffd),
+            Instruction::Jal {
+                rd: AbiRegister::Zero.into(),
+                imm: 2046
+            }
+        );
+    }
+    #[test]
+    fn decode_c_beqz() {
+        assert_eq!(
+            decode(0xd101),
+            Instruction::Beq {
+                rs1: AbiRegister::A0.into(),
+                rs2: AbiRegister::Zero.into(),
+                imm: -256
+            }
+        );
+    }
+    #[test]
+    fn decode_c_bnez() {
+        assert_eq!(
+            decode(0xecfd),
+            Instruction::Bne {
+                rs1: AbiRegister::S1.into(),
+                rs2: AbiRegister::Zero.into(),
+                imm: 254
+            }
+        );
+    }
+    #[test]
+    fn decode_c_slli() {
+        assert_eq!(
+            decode(0x12fe),
+            Instruction::Slli {
+                rd: AbiRegister::T0.into(),
+                rs1: AbiRegister::T0.into(),
+                shamt: 63
+            }
+        );
+    }
+    #[test]
+    fn decode_c_fldsp() {
+        assert_eq!(
+            decode(0x307e),
+            Instruction::Fld {
+                rd: FRegister::F0,
+                rs1: AbiRegister::Sp.into(),
+                imm: 504
+            }
+        );
+    }
+    #[test]
+    fn decode_c_lwsp() {
+        assert_eq!(
+            decode(0x50fe),
+            Instruction::Lw {
+                rd: AbiRegister::Ra.into(),
+                rs1: AbiRegister::Sp.into(),
+                imm: 252
+            }
+        );
+    }
+    #[test]
+    fn decode_c_ldsp() {
+        assert_eq!(
+            decode(0x7dfe),
+            Instruction::Ld {
+                rd: AbiRegister::S11.into(),
+                rs1: AbiRegister::Sp.into(),
+                imm: 504
+            }
+        );
+    }
+    #[test]
+    fn decode_c_jr() {
+        assert_eq!(
+            decode(0x8082),
+            Instruction::Jalr {
+                rd: AbiRegister::Zero.into(),
+                rs1: AbiRegister::Ra.into(),
+                imm: 0
+            }
+        );
+    }
+    #[test]
+    fn decode_c_mv() {
+        assert_eq!(
+            decode(0x857e),
+            Instruction::Add {
+                rd: AbiRegister::A0.into(),
+                rs1: AbiRegister::Zero.into(),
+                rs2: AbiRegister::T6.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_ebreak() {
+        assert_eq!(decode(0x9002), Instruction::Ebreak);
+    }
+    #[test]
+    fn decode_c_jalr() {
+        assert_eq!(
+            decode(0x9782),
+            Instruction::Jalr {
+                rd: AbiRegister::Ra.into(),
+                rs1: AbiRegister::A5.into(),
+                imm: 0
+            }
+        );
+    }
+    #[test]
+    fn decode_c_add() {
+        assert_eq!(
+            decode(0x9122),
+            Instruction::Add {
+                rd: AbiRegister::Sp.into(),
+                rs1: AbiRegister::Sp.into(),
+                rs2: AbiRegister::S0Fp.into()
+            }
+        );
+    }
+    #[test]
+    fn decode_c_swsp() {
+        assert_eq!(
+            decode(0xdf9a),
+            Instruction::Sw {
+                rs2: AbiRegister::T1.into(),
+                rs1: AbiRegister::Sp.into(),
+                imm: 252
+            }
+        );
+    }
+    #[test]
+    fn decode_c_fsdsp() {
+        assert_eq!(
+            decode(0xa46e),
+            Instruction::Fsd {
+                rs2: FRegister::F27,
+                rs1: AbiRegister::Sp.into(),
+                imm: 8
+            }
+        );
+    }
+    #[test]
+    fn decode_c_sdsp() {
+        assert_eq!(
+            decode(0xffca),
+            Instruction::Sd {
+                rs2: AbiRegister::S2.into(),
+                rs1: AbiRegister::Sp.into(),
+                imm: 504
+            }
+        );
+    }
+}
@@ -1,41 +1,405 @@
+use crate::riscv::block_cache::{self, BlockCache};
+use crate::riscv::bus;
+use crate::riscv::csr;
 use crate::riscv::execute;
+use crate::riscv::float;
 use crate::riscv::instruction;
+use crate::riscv::micro_op;
+use crate::riscv::mmu;
+use crate::riscv::pmp;
+use crate::riscv::tlb;
+use crate::riscv::trap::Exception;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
 
+// Encoded as in the MXL field of misa
 pub enum Xlen {
-    Bit64,
+    Bit64 = 2,
+}
+
+// Privilege levels, encoded as in the MPP field of mstatus
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Privilege {
+    User = 0,
+    Supervisor = 1,
+    Machine = 3,
+}
+impl From<u64> for Privilege {
+    // The reserved level 0b10 can't be written to MPP
+    fn from(bits: u64) -> Privilege {
+        match bits {
+            0 => Privilege::User,
+            1 => Privilege::Supervisor,
+            _ => Privilege::Machine,
+        }
+    }
 }
 
 pub struct Cpu {
     // XLEN is 64 bits in rv64i
     pub registers: [u64; 32],
+    // Floating point registers are as wide as the widest format, D
+    pub fregisters: [u64; 32],
     pub pc: u64,
-    pub encoded_instructions: Vec<u8>,
+    // Privilege level the hart is running at
+    pub privilege: Privilege,
+    // Address of the instruction that follows the one being executed.
+    // Control transfer instructions overwrite it with their target.
+    pub next_pc: u64,
+    // Address reserved by the last LR, if the reservation is still held
+    pub reservation: Option<u64>,
+    pub csr: csr::Csr,
+    pub tlb: tlb::Tlb,
+    pub bus: bus::Bus,
+    pub blocks: BlockCache,
 }
 impl Cpu {
-    pub fn new(encoded_instructions: Vec<u8>) -> Self {
+    pub fn new(bus: bus::Bus) -> Self {
         Self {
             registers: [0; 32],
+            fregisters: [0; 32],
             pc: 0,
-            encoded_instructions,
+            privilege: Privilege::Machine,
+            next_pc: 0,
+            reservation: None,
+            csr: csr::Csr::new(),
+            tlb: tlb::Tlb::new(tlb::DEFAULT_ENTRIES),
+            bus,
+            blocks: BlockCache::new(),
         }
     }
-    pub fn fetch(&self) -> u32 {
-        let index = self.pc as usize;
-        let encoded_instruction = (self.encoded_instructions[index] as u32)
-            | ((self.encoded_instructions[index + 1] as u32) << 8)
-            | ((self.encoded_instructions[index + 2] as u32) << 16)
-            | ((self.encoded_instructions[index + 3] as u32) << 24);
-        encoded_instruction
+    fn fetch_parcel(&mut self, address: u64) -> Result<u32, Exception> {
+        let physical = self.fetch_address(address)?;
+        self.bus
+            .read_half(physical)
+            .map(u32::from)
+            .map_err(|_| Exception::InstructionAccessFault(address))
+    }
+    // The physical address of a parcel fetched from `address`
+    fn fetch_address(&mut self, address: u64) -> Result<u64, Exception> {
+        let physical = mmu::translate(address, mmu::Access::Instruction, self)?;
+        let access = mmu::Access::Instruction;
+        if !pmp::check(physical, 2, access, self.privilege, &self.csr) {
+            return Err(access.access_fault(address));
+        }
+        Ok(physical)
+    }
+    // Fetch and decode the instruction at pc, taking it from the bus's cache
+    // of decoded instructions when it ran before. Cached instructions don't
+    // cross a page, so the second parcel of one only needs checking by PMP.
+    fn fetch_decoded(&mut self) -> Result<(u32, instruction::Instruction), Exception> {
+        let physical = self.fetch_address(self.pc)?;
+        if let Some((encoded, decoded)) = self.bus.decoded(physical) {
+            let access = mmu::Access::Instruction;
+            if instruction::length(encoded) == 4
+                && !pmp::check(physical + 2, 2, access, self.privilege, &self.csr)
+            {
+                return Err(access.access_fault(self.pc.wrapping_add(2)));
+            }
+            return Ok((encoded, decoded));
+        }
+        let low = self
+            .bus
+            .read_half(physical)
+            .map(u32::from)
+            .map_err(|_| Exception::InstructionAccessFault(self.pc))?;
+        let encoded = if instruction::length(low) == 2 {
+            low
+        } else {
+            self.fetch_parcel(self.pc.wrapping_add(2))? << 16 | low
+        };
+        let decoded = instruction::decode(encoded);
+        self.bus.insert_decoded(physical, encoded, decoded);
+        Ok((encoded, decoded))
+    }
+    // Fetch the instruction at pc a parcel at a time, so that a compressed
+    // instruction at the end of memory or of a page can be fetched
+    pub fn fetch(&mut self) -> Result<u32, Exception> {
+        let low = self.fetch_parcel(self.pc)?;
+        if instruction::length(low) == 2 {
+            return Ok(low);
+        }
+        let high = self.fetch_parcel(self.pc.wrapping_add(2))?;
+        Ok(high << 16 | low)
     }
-    pub fn execute(&mut self, instruction: instruction::Instruction) {
-        println!("{:?}", instruction);
+    pub fn read_register(&self, reg: Register) -> u64 {
+        match reg {
+            Register::PC => self.pc,
+            // x0 is hard-wired to zero
+            Register::X0 => 0,
+            reg => self.registers[usize::from(reg)],
+        }
+    }
+    pub fn write_register(&mut self, reg: Register, value: u64) {
+        match reg {
+            Register::PC => self.next_pc = value,
+            // Writes to x0 are discarded
+            Register::X0 => (),
+            reg => self.registers[usize::from(reg)] = value,
+        }
+    }
+    // Read a floating point register as a value of `format`. Single
+    // precision values are NaN-boxed in the upper 32 bits, and an
+    // improperly boxed value reads as the canonical NaN.
+    pub fn read_float(&self, reg: FRegister, format: float::Format) -> u64 {
+        let value = self.fregisters[usize::from(reg)];
+        match format {
+            float::SINGLE if value >> 32 != 0xffff_ffff => float::SINGLE.canonical_nan(),
+            float::SINGLE => value & 0xffff_ffff,
+            _ => value,
+        }
+    }
+    pub fn write_float(&mut self, reg: FRegister, format: float::Format, value: u64) {
+        self.csr.dirty_float();
+        self.fregisters[usize::from(reg)] = match format {
+            float::SINGLE => 0xffff_ffff_0000_0000 | value,
+            _ => value,
+        };
+    }
+    // Check that both pages of an access that straddles a page boundary can
+    // be accessed, before any of it is carried out a byte at a time
+    fn translate_straddling(
+        &mut self,
+        address: u64,
+        size: usize,
+        access: mmu::Access,
+    ) -> Result<bool, Exception> {
+        if !mmu::crosses_page(address, size) {
+            return Ok(false);
+        }
+        let next_page = (address | (mmu::PAGE_SIZE - 1)).wrapping_add(1);
+        mmu::translate(address, access, self)?;
+        mmu::translate(next_page, access, self)?;
+        Ok(true)
+    }
+    // Raise an access fault at the virtual `address` when PMP denies a data
+    // access to its physical address
+    fn check_pmp(
+        &self,
+        address: u64,
+        physical: u64,
+        size: usize,
+        access: mmu::Access,
+    ) -> Result<(), Exception> {
+        let privilege = mmu::effective_privilege(access, self);
+        if !pmp::check(physical, size as u64, access, privilege, &self.csr) {
+            return Err(access.access_fault(address));
+        }
+        Ok(())
+    }
+    // Read a `size` byte value from virtual memory, zero extended to 64 bits
+    pub fn load(&mut self, address: u64, size: usize) -> Result<u64, Exception> {
+        if self.translate_straddling(address, size, mmu::Access::Load)? {
+            let mut value = 0;
+            for i in 0..size {
+                value |= self.load(address.wrapping_add(i as u64), 1)? << (8 * i);
+            }
+            return Ok(value);
+        }
+        let physical = mmu::translate(address, mmu::Access::Load, self)?;
+        self.check_pmp(address, physical, size, mmu::Access::Load)?;
+        match size {
+            1 => self.bus.read_byte(physical).map(u64::from),
+            2 => self.bus.read_half(physical).map(u64::from),
+            4 => self.bus.read_word(physical).map(u64::from),
+            _ => self.bus.read_double(physical),
+        }
+        .map_err(|_| Exception::LoadAccessFault(address))
+    }
+    // Write the low `size` bytes of a value to virtual memory
+    pub fn store(&mut self, address: u64, size: usize, value: u64) -> Result<(), Exception> {
+        if self.translate_straddling(address, size, mmu::Access::Store)? {
+            for i in 0..size {
+                self.store(address.wrapping_add(i as u64), 1, value >> (8 * i))?;
+            }
+            return Ok(());
+        }
+        let physical = mmu::translate(address, mmu::Access::Store, self)?;
+        self.check_pmp(address, physical, size, mmu::Access::Store)?;
+        match size {
+            1 => self.bus.write_byte(physical, value as u8),
+            2 => self.bus.write_half(physical, value as u16),
+            4 => self.bus.write_word(physical, value as u32),
+            _ => self.bus.write_double(physical, value),
+        }
+        .map_err(|_| Exception::StoreAccessFault(address))
+    }
+    // Fetch, decode and execute the instruction at pc. When an exception is
+    // raised pc is left pointing at the offending instruction.
+    pub fn step(&mut self) -> Result<(), Exception> {
+        let (encoded_instruction, instruction) = self.fetch_decoded()?;
+        self.run_decoded(encoded_instruction, instruction)
+    }
+    // Execute an instruction fetched from pc
+    fn run_decoded(
+        &mut self,
+        encoded_instruction: u32,
+        instruction: instruction::Instruction,
+    ) -> Result<(), Exception> {
+        let length = instruction::length(encoded_instruction);
+        let instruction = if length == 2 && !self.csr.has_extension(b'C') {
+            instruction::Instruction::Undefined
+        } else {
+            instruction
+        };
+        // Instructions of extensions that misa leaves out are illegal
+        let instruction = if self.csr.has_extension(instruction.extension()) {
+            instruction
+        } else {
+            instruction::Instruction::Undefined
+        };
+        self.next_pc = self.pc.wrapping_add(length);
+        self.execute(instruction)
+            .map_err(|exception| match exception {
+                Exception::IllegalInstruction(_) => {
+                    Exception::IllegalInstruction(encoded_instruction as u64)
+                }
+                exception => exception,
+            })?;
+        self.pc = self.next_pc;
+        self.csr.retire();
+        Ok(())
+    }
+    // Read a CSR, including time, which counts in the CLINT
+    pub fn read_csr(&self, address: usize) -> u64 {
+        match address {
+            csr::TIME => self.bus.clint.mtime(),
+            _ => self.csr.read(address),
+        }
+    }
+    pub fn execute(&mut self, instruction: instruction::Instruction) -> Result<(), Exception> {
+        // Floating point instructions are illegal while mstatus.FS is Off
+        if self.csr.float_off() && matches!(instruction.extension(), b'F' | b'D') {
+            return Err(Exception::IllegalInstruction(0));
+        }
         execute::execute_instruction(instruction, self)
     }
+    // Run up to `limit` instructions a basic block at a time, starting with
+    // the block at pc and carrying on into the blocks its branches and jumps
+    // lead to, for at most block_cache::MAX_INSTRUCTIONS. Running stops early
+    // once pc leaves `within`. Translation and PMP are checked once for each
+    // block, which lies in one page, and code that can't be cached is
+    // stepped through an instruction at a time. Returns the number of
+    // instructions run, including one that raised an exception, which leaves
+    // pc pointing at it as step does.
+    pub fn run_block(&mut self, limit: u64, within: Range<u64>) -> (u64, Result<(), Exception>) {
+        for page in self.bus.take_written_code() {
+            self.blocks.invalidate_page(page);
+        }
+        self.blocks.revalidate(self.csr.read(csr::MISA));
+        let limit = limit.min(block_cache::MAX_INSTRUCTIONS as u64);
+        let mut index = match self.enter_block() {
+            Ok(Some(index)) => index,
+            Ok(None) => return (1, self.step()),
+            Err(exception) => return (1, Err(exception)),
+        };
+        let mut count = 0;
+        loop {
+            let mut position = 0;
+            while let Some(op) = self.blocks.op(index, position) {
+                if count > 0 && (count == limit || !within.contains(&self.pc)) {
+                    return (count, Ok(()));
+                }
+                count += 1;
+                position += 1;
+                self.next_pc = self.pc.wrapping_add(op.length);
+                if let Err(exception) = op.run(self) {
+                    return (count, Err(exception));
+                }
+                self.pc = self.next_pc;
+                self.csr.retire();
+                // A store may have overwritten code of a block, or ended the
+                // program through the finisher
+                if self.bus.code_written() || self.bus.finisher.status().is_some() {
+                    return (count, Ok(()));
+                }
+                let instruction = op.instruction;
+                if block_cache::ends_block(&instruction) && !block_cache::jumps(&instruction) {
+                    return (count, Ok(()));
+                }
+            }
+            index = match self.enter_block() {
+                Ok(Some(index)) if count < limit => index,
+                _ => return (count, Ok(())),
+            };
+        }
+    }
+    // The block at pc, once translation and PMP allow running it, or None if
+    // its code can't be cached
+    fn enter_block(&mut self) -> Result<Option<usize>, Exception> {
+        let physical = self.fetch_address(self.pc)?;
+        let index = match self.blocks.find(physical) {
+            Some(index) => index,
+            None => match self.decode_block(physical) {
+                Some(index) => index,
+                None => return Ok(None),
+            },
+        };
+        let access = mmu::Access::Instruction;
+        let size = self.blocks.size(index);
+        if !pmp::check(physical, size, access, self.privilege, &self.csr) {
+            return Ok(None);
+        }
+        Ok(Some(index))
+    }
+    // Decode and translate the basic block starting at a physical address,
+    // for as long as its instructions can be cached
+    fn decode_block(&mut self, physical: u64) -> Option<usize> {
+        let mut ops = Vec::new();
+        let mut address = physical;
+        while let Some((encoded, instruction)) = self.decode_at(address) {
+            ops.push(micro_op::translate(encoded, instruction, &self.csr));
+            address += instruction::length(encoded);
+            if block_cache::ends_block(&instruction)
+                || ops.len() == block_cache::MAX_INSTRUCTIONS
+                || address.is_multiple_of(mmu::PAGE_SIZE)
+            {
+                break;
+            }
+        }
+        if ops.is_empty() {
+            return None;
+        }
+        self.bus.mark_code(physical);
+        Some(self.blocks.insert(physical, ops))
+    }
+    // The instruction at a physical address, if it is in the cache of
+    // decoded instructions or can be put there
+    fn decode_at(&mut self, address: u64) -> Option<(u32, instruction::Instruction)> {
+        if let Some(decoded) = self.bus.decoded(address) {
+            return Some(decoded);
+        }
+        let low = u32::from(self.bus.read_half(address).ok()?);
+        let encoded = if instruction::length(low) == 2 {
+            low
+        } else {
+            let high = u32::from(self.bus.read_half(address.wrapping_add(2)).ok()?);
+            high << 16 | low
+        };
+        let decoded = instruction::decode(encoded);
+        if self.bus.insert_decoded(address, encoded, decoded) {
+            Some((encoded, decoded))
+        } else {
+            None
+        }
+    }
 }
 
-#[derive(Debug, PartialEq)]
+// ABI names of the integer and floating point registers, by number
+pub const ABI_NAMES: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+pub const FLOAT_ABI_NAMES: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+    "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+    "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Register {
-    PC,
     X0,
     X1,
     X2,
@@ -68,8 +432,44 @@ pub enum Register {
     X29,
     X30,
     X31,
+    PC,
+}
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FRegister {
+    F0,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    F13,
+    F14,
+    F15,
+    F16,
+    F17,
+    F18,
+    F19,
+    F20,
+    F21,
+    F22,
+    F23,
+    F24,
+    F25,
+    F26,
+    F27,
+    F28,
+    F29,
+    F30,
+    F31,
 }
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum AbiRegister {
     Zero,
     Ra,
@@ -105,14 +505,31 @@ pub enum AbiRegister {
     T6,
 }
 
+// The register named by a field of an encoding, which the decoders have
+// already masked to five bits
+pub fn register_field<R: TryFrom<usize>>(field: u32) -> R {
+    match R::try_from(field as usize) {
+        Ok(register) => register,
+        Err(_) => unreachable!("register field {} is wider than five bits", field),
+    }
+}
+// A register number past the 32 registers of a file
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidRegister(pub usize);
+impl fmt::Display for InvalidRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no register numbered {}", self.0)
+    }
+}
 impl From<Register> for usize {
     fn from(reg: Register) -> usize {
         reg as usize
     }
 }
-impl From<usize> for Register {
-    fn from(val: usize) -> Register {
-        match val {
+impl TryFrom<usize> for Register {
+    type Error = InvalidRegister;
+    fn try_from(val: usize) -> Result<Register, InvalidRegister> {
+        Ok(match val {
             0 => Register::X0,
             1 => Register::X1,
             2 => Register::X2,
@@ -145,8 +562,53 @@ impl From<usize> for Register {
             29 => Register::X29,
             30 => Register::X30,
             31 => Register::X31,
-            _ => unreachable!(),
-        }
+            _ => return Err(InvalidRegister(val)),
+        })
+    }
+}
+impl From<FRegister> for usize {
+    fn from(reg: FRegister) -> usize {
+        reg as usize
+    }
+}
+impl TryFrom<usize> for FRegister {
+    type Error = InvalidRegister;
+    fn try_from(val: usize) -> Result<FRegister, InvalidRegister> {
+        Ok(match val {
+            0 => FRegister::F0,
+            1 => FRegister::F1,
+            2 => FRegister::F2,
+            3 => FRegister::F3,
+            4 => FRegister::F4,
+            5 => FRegister::F5,
+            6 => FRegister::F6,
+            7 => FRegister::F7,
+            8 => FRegister::F8,
+            9 => FRegister::F9,
+            10 => FRegister::F10,
+            11 => FRegister::F11,
+            12 => FRegister::F12,
+            13 => FRegister::F13,
+            14 => FRegister::F14,
+            15 => FRegister::F15,
+            16 => FRegister::F16,
+            17 => FRegister::F17,
+            18 => FRegister::F18,
+            19 => FRegister::F19,
+            20 => FRegister::F20,
+            21 => FRegister::F21,
+            22 => FRegister::F22,
+            23 => FRegister::F23,
+            24 => FRegister::F24,
+            25 => FRegister::F25,
+            26 => FRegister::F26,
+            27 => FRegister::F27,
+            28 => FRegister::F28,
+            29 => FRegister::F29,
+            30 => FRegister::F30,
+            31 => FRegister::F31,
+            _ => return Err(InvalidRegister(val)),
+        })
     }
 }
 impl From<AbiRegister> for Register {
@@ -187,3 +649,274 @@ impl From<AbiRegister> for Register {
         }
     }
 }
+// Registers display as their ABI names
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Register::PC => f.write_str("pc"),
+            &reg => f.write_str(ABI_NAMES[usize::from(reg)]),
+        }
+    }
+}
+impl fmt::Display for FRegister {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(FLOAT_ABI_NAMES[usize::from(*self)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+    use crate::riscv::clint;
+    #[test]
+    fn step_compressed() {
+        // c.li a0, 1; addi a0, a0, 1; c.addi a0, 1
+        let mut bus = Bus::new(8);
+        bus.load(DRAM_BASE, &[0x05, 0x45, 0x13, 0x05, 0x15, 0x00, 0x05, 0x05])
+            .unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.pc = DRAM_BASE;
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, DRAM_BASE + 2);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, DRAM_BASE + 6);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, DRAM_BASE + 8);
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 3);
+    }
+    #[test]
+    fn run_block() {
+        // addi a0, a0, 1 three times, then ecall
+        let mut bus = Bus::new(0x1000);
+        let program = [0x0015_0513u32, 0x0015_0513, 0x0015_0513, 0x0000_0073];
+        let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+        bus.load(DRAM_BASE, &bytes).unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.pc = DRAM_BASE;
+        assert_eq!(cpu.run_block(2, 0..u64::MAX), (2, Ok(())));
+        assert_eq!(cpu.pc, DRAM_BASE + 8);
+        // Another block starts at pc, and is cut short once pc leaves the
+        // range
+        assert_eq!(cpu.run_block(10, DRAM_BASE..DRAM_BASE + 12), (1, Ok(())));
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 3);
+        assert_eq!(
+            cpu.run_block(10, 0..u64::MAX),
+            (1, Err(Exception::EnvironmentCallFromMMode))
+        );
+        assert_eq!(cpu.pc, DRAM_BASE + 12);
+
+        // The first block is still cached, and runs to the ecall
+        cpu.pc = DRAM_BASE;
+        assert_eq!(
+            cpu.run_block(10, 0..u64::MAX),
+            (4, Err(Exception::EnvironmentCallFromMMode))
+        );
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 6);
+    }
+    #[test]
+    fn float_state() {
+        // fadd.d f0, f1, f2 twice
+        let mut bus = Bus::new(0x1000);
+        let program = [0x0220_f053u32, 0x0220_f053];
+        let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+        bus.load(DRAM_BASE, &bytes).unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.pc = DRAM_BASE;
+        cpu.csr.write(csr::MSTATUS, csr::FS_OFF);
+        assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(0x0220_f053)));
+        assert_eq!(cpu.pc, DRAM_BASE);
+
+        // Once on, writing a register leaves the state Dirty
+        cpu.csr.write(csr::MSTATUS, csr::FS_CLEAN);
+        assert_eq!(cpu.step(), Ok(()));
+        let status = cpu.csr.read(csr::MSTATUS);
+        assert_eq!(
+            status & (csr::MSTATUS_FS | csr::MSTATUS_SD),
+            csr::FS_DIRTY | csr::MSTATUS_SD
+        );
+        // Blocks check the state as each instruction runs
+        cpu.csr.write(csr::MSTATUS, csr::FS_OFF);
+        assert_eq!(
+            cpu.run_block(1, 0..u64::MAX),
+            (1, Err(Exception::IllegalInstruction(0x0220_f053)))
+        );
+    }
+    #[test]
+    fn run_chained_blocks() {
+        // addi a0, a0, 1; bne a0, a1, -4; ecall
+        let mut bus = Bus::new(0x1000);
+        let program = [0x0015_0513u32, 0xfeb5_1ee3, 0x0000_0073];
+        let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+        bus.load(DRAM_BASE, &bytes).unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.pc = DRAM_BASE;
+        cpu.write_register(AbiRegister::A1.into(), 100);
+        // The loop goes round through its own link until the most a call runs
+        let max = block_cache::MAX_INSTRUCTIONS as u64;
+        assert_eq!(cpu.run_block(1000, 0..u64::MAX), (max, Ok(())));
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), max / 2);
+
+        // Writing the code drops the loop, so that the new addi runs
+        cpu.bus.write_word(DRAM_BASE, 0x0025_0513).unwrap();
+        assert_eq!(cpu.run_block(2, 0..u64::MAX), (2, Ok(())));
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), max / 2 + 2);
+    }
+    #[test]
+    fn fetch_at_end_of_memory() {
+        let mut bus = Bus::new(4);
+        // c.nop followed by the first half of a 32-bit instruction
+        bus.load(DRAM_BASE, &[0x01, 0x00, 0x13, 0x05]).unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.pc = DRAM_BASE;
+        assert_eq!(cpu.fetch(), Ok(0x0001));
+        cpu.pc = DRAM_BASE + 2;
+        assert_eq!(
+            cpu.fetch(),
+            Err(Exception::InstructionAccessFault(DRAM_BASE + 4))
+        );
+    }
+    // A supervisor mode hart with Sv39 translation, mapping virtual page 0x0
+    // read-write at DRAM_BASE + 0x3000 and page 0x1 read-execute at
+    // DRAM_BASE + 0x4000
+    fn paged_cpu() -> Cpu {
+        let mut cpu = Cpu::new(Bus::new(0x5000));
+        let entry = |address: u64, flags: u64| (address >> 12) << 10 | flags | 1;
+        cpu.bus
+            .write_double(DRAM_BASE, entry(DRAM_BASE + 0x1000, 0))
+            .unwrap();
+        cpu.bus
+            .write_double(DRAM_BASE + 0x1000, entry(DRAM_BASE + 0x2000, 0))
+            .unwrap();
+        cpu.bus
+            .write_double(DRAM_BASE + 0x2000, entry(DRAM_BASE + 0x3000, 0b0110))
+            .unwrap();
+        cpu.bus
+            .write_double(DRAM_BASE + 0x2008, entry(DRAM_BASE + 0x4000, 0b1010))
+            .unwrap();
+        cpu.csr
+            .write(csr::SATP, (mmu::SATP_SV39 << 60) | (DRAM_BASE >> 12));
+        pmp::allow_all(&mut cpu.csr);
+        cpu.privilege = Privilege::Supervisor;
+        cpu
+    }
+    #[test]
+    fn translated_load_and_store() {
+        let mut cpu = paged_cpu();
+        cpu.store(0x10, 8, 0x1122_3344_5566_7788).unwrap();
+        assert_eq!(
+            cpu.bus.read_double(DRAM_BASE + 0x3010),
+            Ok(0x1122_3344_5566_7788)
+        );
+        assert_eq!(cpu.load(0x10, 8), Ok(0x1122_3344_5566_7788));
+        // An access spanning both pages is split between their frames
+        cpu.bus.write_word(DRAM_BASE + 0x3ffc, 0x4433_2211).unwrap();
+        cpu.bus.write_word(DRAM_BASE + 0x4000, 0x8877_6655).unwrap();
+        assert_eq!(cpu.load(0xffc, 8), Ok(0x8877_6655_4433_2211));
+        // Nothing is written when the second page is read-only
+        assert_eq!(
+            cpu.store(0xffc, 8, 0),
+            Err(Exception::StorePageFault(0x1000))
+        );
+        assert_eq!(cpu.bus.read_word(DRAM_BASE + 0x3ffc), Ok(0x4433_2211));
+    }
+    #[test]
+    fn translated_fetch() {
+        let mut cpu = paged_cpu();
+        cpu.bus.write_word(DRAM_BASE + 0x4000, 0x0000_0001).unwrap();
+        cpu.pc = 0x1000;
+        assert_eq!(cpu.fetch(), Ok(0x0001));
+        cpu.pc = 0;
+        assert_eq!(cpu.fetch(), Err(Exception::InstructionPageFault(0)));
+    }
+    #[test]
+    fn counters() {
+        // csrr a0, instret; csrr a0, time
+        let mut bus = Bus::new(0x1000);
+        let program = [0xc020_2573u32, 0xc010_2573];
+        let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+        bus.load(DRAM_BASE, &bytes).unwrap();
+        let mut cpu = Cpu::new(bus);
+        pmp::allow_all(&mut cpu.csr);
+        cpu.pc = DRAM_BASE;
+        cpu.csr.write(csr::MINSTRET, 5);
+        assert_eq!(cpu.step(), Ok(()));
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 5);
+        assert_eq!(cpu.read_csr(csr::INSTRET), 6);
+        // time is mtime
+        cpu.bus.clint.write(clint::MTIME, 8, 1 << 40);
+        assert_eq!(cpu.step(), Ok(()));
+        assert!(cpu.read_register(AbiRegister::A0.into()) >= 1 << 40);
+
+        // User mode needs the counter enabled at both levels
+        cpu.privilege = Privilege::User;
+        cpu.pc = DRAM_BASE + 4;
+        assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(0xc010_2573)));
+        cpu.csr.write(csr::MCOUNTEREN, 1 << 1);
+        cpu.csr.write(csr::SCOUNTEREN, 1 << 1);
+        assert_eq!(cpu.step(), Ok(()));
+        // Blocks count the instructions they run too
+        cpu.privilege = Privilege::Machine;
+        cpu.pc = DRAM_BASE;
+        let instret = cpu.read_csr(csr::INSTRET);
+        assert_eq!(cpu.run_block(2, 0..u64::MAX), (1, Ok(())));
+        assert_eq!(cpu.read_csr(csr::INSTRET), instret + 1);
+    }
+    #[test]
+    fn pmp_unconfigured() {
+        // Until firmware turns an entry on, user mode can't touch DRAM
+        let mut cpu = Cpu::new(Bus::new(0x1000));
+        cpu.privilege = Privilege::User;
+        assert_eq!(
+            cpu.load(DRAM_BASE, 8),
+            Err(Exception::LoadAccessFault(DRAM_BASE))
+        );
+        cpu.privilege = Privilege::Machine;
+        assert!(cpu.load(DRAM_BASE, 8).is_ok());
+    }
+    #[test]
+    fn pmp_access_faults() {
+        let mut cpu = Cpu::new(Bus::new(0x2000));
+        // The first page is read-execute and nothing else is accessible
+        cpu.csr.write(csr::PMPADDR0, (DRAM_BASE >> 2) | 0x1ff);
+        cpu.csr
+            .write(csr::PMPCFG0, pmp::NAPOT << 3 | pmp::PMP_X | pmp::PMP_R);
+        cpu.privilege = Privilege::User;
+        cpu.pc = DRAM_BASE;
+        assert!(cpu.fetch().is_ok());
+        assert!(cpu.load(DRAM_BASE, 8).is_ok());
+        assert_eq!(
+            cpu.store(DRAM_BASE, 8, 0),
+            Err(Exception::StoreAccessFault(DRAM_BASE))
+        );
+        assert_eq!(
+            cpu.load(DRAM_BASE + 0x1000, 8),
+            Err(Exception::LoadAccessFault(DRAM_BASE + 0x1000))
+        );
+        cpu.pc = DRAM_BASE + 0x1000;
+        assert_eq!(
+            cpu.fetch(),
+            Err(Exception::InstructionAccessFault(DRAM_BASE + 0x1000))
+        );
+        // Machine mode is only checked through MPRV
+        cpu.privilege = Privilege::Machine;
+        assert!(cpu.store(DRAM_BASE, 8, 0).is_ok());
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_MPRV);
+        assert_eq!(
+            cpu.store(DRAM_BASE, 8, 0),
+            Err(Exception::StoreAccessFault(DRAM_BASE))
+        );
+    }
+    #[test]
+    fn pmp_page_table_access() {
+        let mut cpu = paged_cpu();
+        // Only the mapped frames are accessible, not the page tables
+        cpu.csr
+            .write(csr::PMPADDR0, ((DRAM_BASE + 0x3000) >> 2) | 0x1ff);
+        cpu.csr
+            .write(csr::PMPADDR0 + 1, ((DRAM_BASE + 0x4000) >> 2) | 0x1ff);
+        let config = pmp::NAPOT << 3 | pmp::PMP_W | pmp::PMP_R;
+        cpu.csr.write(csr::PMPCFG0, config << 8 | config);
+        assert_eq!(cpu.load(0x10, 8), Err(Exception::LoadAccessFault(0x10)));
+    }
+}
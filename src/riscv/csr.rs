@@ -0,0 +1,715 @@
+use crate::riscv::cpu::{Privilege, Xlen};
+use crate::riscv::mmu;
+use crate::riscv::pmp;
+use crate::riscv::trap::Exception;
+
+// Floating point control and status
+pub const FFLAGS: usize = 0x001;
+pub const FRM: usize = 0x002;
+pub const FCSR: usize = 0x003;
+// Supervisor trap setup
+pub const SSTATUS: usize = 0x100;
+pub const SIE: usize = 0x104;
+pub const STVEC: usize = 0x105;
+pub const SCOUNTEREN: usize = 0x106;
+// Supervisor trap handling
+pub const SSCRATCH: usize = 0x140;
+pub const SEPC: usize = 0x141;
+pub const SCAUSE: usize = 0x142;
+pub const STVAL: usize = 0x143;
+pub const SIP: usize = 0x144;
+// Supervisor protection and translation
+pub const SATP: usize = 0x180;
+// Machine information registers
+pub const MVENDORID: usize = 0xf11;
+pub const MARCHID: usize = 0xf12;
+pub const MIMPID: usize = 0xf13;
+pub const MHARTID: usize = 0xf14;
+// Machine trap setup
+pub const MSTATUS: usize = 0x300;
+pub const MISA: usize = 0x301;
+pub const MEDELEG: usize = 0x302;
+pub const MIDELEG: usize = 0x303;
+pub const MIE: usize = 0x304;
+pub const MTVEC: usize = 0x305;
+pub const MCOUNTEREN: usize = 0x306;
+// Machine trap handling
+pub const MSCRATCH: usize = 0x340;
+pub const MEPC: usize = 0x341;
+pub const MCAUSE: usize = 0x342;
+pub const MTVAL: usize = 0x343;
+pub const MIP: usize = 0x344;
+// Machine counters
+pub const MCYCLE: usize = 0xb00;
+pub const MINSTRET: usize = 0xb02;
+// Unprivileged views of the counters, and of mtime in the CLINT
+pub const CYCLE: usize = 0xc00;
+pub const TIME: usize = 0xc01;
+pub const INSTRET: usize = 0xc02;
+// Machine memory protection. Only the even pmpcfg registers exist on RV64.
+pub const PMPCFG0: usize = 0x3a0;
+pub const PMPCFG15: usize = 0x3af;
+pub const PMPADDR0: usize = 0x3b0;
+pub const PMPADDR63: usize = 0x3ef;
+
+// Assembler names of the CSRs other than the PMP ones
+pub const NAMES: [(usize, &str); 34] = [
+    (FFLAGS, "fflags"),
+    (FRM, "frm"),
+    (FCSR, "fcsr"),
+    (SSTATUS, "sstatus"),
+    (SIE, "sie"),
+    (STVEC, "stvec"),
+    (SCOUNTEREN, "scounteren"),
+    (SSCRATCH, "sscratch"),
+    (SEPC, "sepc"),
+    (SCAUSE, "scause"),
+    (STVAL, "stval"),
+    (SIP, "sip"),
+    (SATP, "satp"),
+    (MVENDORID, "mvendorid"),
+    (MARCHID, "marchid"),
+    (MIMPID, "mimpid"),
+    (MHARTID, "mhartid"),
+    (MSTATUS, "mstatus"),
+    (MISA, "misa"),
+    (MEDELEG, "medeleg"),
+    (MIDELEG, "mideleg"),
+    (MIE, "mie"),
+    (MTVEC, "mtvec"),
+    (MCOUNTEREN, "mcounteren"),
+    (MSCRATCH, "mscratch"),
+    (MEPC, "mepc"),
+    (MCAUSE, "mcause"),
+    (MTVAL, "mtval"),
+    (MIP, "mip"),
+    (MCYCLE, "mcycle"),
+    (MINSTRET, "minstret"),
+    (CYCLE, "cycle"),
+    (TIME, "time"),
+    (INSTRET, "instret"),
+];
+
+// Name of the CSR at `address`, if it is implemented
+pub fn name(address: usize) -> Option<String> {
+    match address {
+        PMPCFG0..=PMPCFG15 if address & 1 == 0 => Some(format!("pmpcfg{}", address - PMPCFG0)),
+        PMPADDR0..=PMPADDR63 => Some(format!("pmpaddr{}", address - PMPADDR0)),
+        _ => NAMES
+            .iter()
+            .find(|(named, _)| *named == address)
+            .map(|(_, name)| name.to_string()),
+    }
+}
+
+// mstatus fields
+pub const MSTATUS_SIE: u64 = 1 << 1;
+pub const MSTATUS_MIE: u64 = 1 << 3;
+pub const MSTATUS_SPIE: u64 = 1 << 5;
+pub const MSTATUS_MPIE: u64 = 1 << 7;
+pub const MSTATUS_SPP: u64 = 1 << 8;
+pub const MSTATUS_MPP: u64 = 0b11 << 11;
+pub const MSTATUS_FS: u64 = 0b11 << 13;
+pub const MSTATUS_MPRV: u64 = 1 << 17;
+pub const MSTATUS_SUM: u64 = 1 << 18;
+pub const MSTATUS_MXR: u64 = 1 << 19;
+pub const MSTATUS_TVM: u64 = 1 << 20;
+pub const MSTATUS_TW: u64 = 1 << 21;
+pub const MSTATUS_TSR: u64 = 1 << 22;
+pub const MSTATUS_UXL: u64 = 0b11 << 32;
+// Set while FS is Dirty, so that a single test tells whether there is
+// state to save on a context switch
+pub const MSTATUS_SD: u64 = 1 << 63;
+// The fields of mstatus visible through sstatus
+const SSTATUS_MASK: u64 = MSTATUS_SIE
+    | MSTATUS_SPIE
+    | MSTATUS_SPP
+    | MSTATUS_FS
+    | MSTATUS_SUM
+    | MSTATUS_MXR
+    | MSTATUS_UXL
+    | MSTATUS_SD;
+
+// States of the floating point registers and fcsr in mstatus.FS. While FS
+// is Off, floating point instructions and CSRs are illegal.
+pub const FS_OFF: u64 = 0;
+pub const FS_INITIAL: u64 = 1 << 13;
+pub const FS_CLEAN: u64 = 2 << 13;
+pub const FS_DIRTY: u64 = 3 << 13;
+
+// Interrupt bits shared by mie and mip
+pub const SSIP: u64 = 1 << 1;
+pub const MSIP: u64 = 1 << 3;
+pub const STIP: u64 = 1 << 5;
+pub const MTIP: u64 = 1 << 7;
+pub const SEIP: u64 = 1 << 9;
+pub const MEIP: u64 = 1 << 11;
+// Supervisor interrupts, the only ones that can be delegated
+const SUPERVISOR_INTERRUPTS: u64 = SSIP | STIP | SEIP;
+
+// Bits of mcounteren and scounteren for cycle, time and instret, the only
+// counters implemented
+const COUNTERS: u64 = 0b111;
+
+// Exceptions that can be delegated to supervisor mode. An environment call
+// from machine mode always traps to machine mode.
+const DELEGABLE_EXCEPTIONS: u64 = 0xb3ff;
+
+// misa extension bits, one per letter
+pub const fn extension(letter: u8) -> u64 {
+    1 << (letter - b'A')
+}
+
+// Extensions of a hart built without an ISA string, which is rv64imafdc
+// with supervisor and user modes
+pub const DEFAULT_EXTENSIONS: u64 = extension(b'I')
+    | extension(b'M')
+    | extension(b'A')
+    | extension(b'F')
+    | extension(b'D')
+    | extension(b'C')
+    | extension(b'S')
+    | extension(b'U');
+
+pub struct Csr {
+    registers: [u64; 4096],
+    // The extensions the hart implements, of which misa can only turn C off
+    extensions: u64,
+    // Interrupt pending bits driven by devices, which read as set in mip
+    // while the device holds them but are never written by software
+    lines: u64,
+}
+impl Default for Csr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl Csr {
+    pub fn new() -> Self {
+        Self::with_extensions(DEFAULT_EXTENSIONS)
+    }
+    // CSRs of a hart implementing `extensions`, a set of misa bits
+    pub fn with_extensions(extensions: u64) -> Self {
+        let mut registers = [0; 4096];
+        registers[MISA] = (Xlen::Bit64 as u64) << 62 | extensions;
+        // UXL and SXL
+        registers[MSTATUS] = (Xlen::Bit64 as u64) << 32 | (Xlen::Bit64 as u64) << 34;
+        // The floating point unit starts out on, so that programs that
+        // never touch mstatus can use it
+        if extensions & extension(b'F') != 0 {
+            registers[MSTATUS] |= FS_INITIAL;
+        }
+        Self {
+            registers,
+            extensions,
+            lines: 0,
+        }
+    }
+    // Whether the CSR at `address` is implemented
+    fn exists(address: usize) -> bool {
+        let pmp = matches!(address, PMPCFG0..=PMPCFG15 if address & 1 == 0)
+            || matches!(address, PMPADDR0..=PMPADDR63);
+        pmp || matches!(
+            address,
+            FFLAGS
+                | FRM
+                | FCSR
+                | SSTATUS
+                | SIE
+                | STVEC
+                | SCOUNTEREN
+                | SSCRATCH
+                | SEPC
+                | SCAUSE
+                | STVAL
+                | SIP
+                | SATP
+                | MVENDORID
+                | MARCHID
+                | MIMPID
+                | MHARTID
+                | MSTATUS
+                | MISA
+                | MEDELEG
+                | MIDELEG
+                | MIE
+                | MTVEC
+                | MCOUNTEREN
+                | MSCRATCH
+                | MEPC
+                | MCAUSE
+                | MTVAL
+                | MIP
+                | MCYCLE
+                | MINSTRET
+                | CYCLE
+                | TIME
+                | INSTRET
+        )
+    }
+    // Check that a CSR instruction running at `privilege` may access the
+    // CSR at `address`. Bits 11:10 of the address are 0b11 for read-only
+    // CSRs and bits 9:8 hold the lowest privilege level that can access it.
+    pub fn check_access(
+        &self,
+        address: usize,
+        write: bool,
+        privilege: Privilege,
+    ) -> Result<(), Exception> {
+        let read_only = write && address >> 10 == 0b11;
+        let privileged = (privilege as usize) < (address >> 8) & 0b11;
+        // Supervisor mode can be kept from changing the address space
+        let trapped = address == SATP
+            && privilege == Privilege::Supervisor
+            && self.registers[MSTATUS] & MSTATUS_TVM != 0;
+        let float = matches!(address, FFLAGS | FRM | FCSR);
+        let missing =
+            !Csr::exists(address) || (float && (!self.has_extension(b'F') || self.float_off()));
+        // Below machine mode the counters have to be enabled by mcounteren,
+        // and in user mode also by scounteren
+        let counter = 1 << (address & 0x1f);
+        let disabled = matches!(address, CYCLE | TIME | INSTRET)
+            && match privilege {
+                Privilege::Machine => false,
+                Privilege::Supervisor => self.registers[MCOUNTEREN] & counter == 0,
+                Privilege::User => {
+                    self.registers[MCOUNTEREN] & counter == 0
+                        || (self.has_extension(b'S') && self.registers[SCOUNTEREN] & counter == 0)
+                }
+            };
+        if missing || read_only || privileged || trapped || disabled {
+            return Err(Exception::IllegalInstruction(0));
+        }
+        Ok(())
+    }
+    pub fn float_off(&self) -> bool {
+        self.registers[MSTATUS] & MSTATUS_FS == FS_OFF
+    }
+    // Note that the floating point registers or fcsr changed, for an OS to
+    // save them on a context switch
+    pub fn dirty_float(&mut self) {
+        self.registers[MSTATUS] |= FS_DIRTY | MSTATUS_SD;
+    }
+    pub fn has_extension(&self, letter: u8) -> bool {
+        self.registers[MISA] & extension(letter) != 0
+    }
+    // Mask of the address bits that must be zero for an instruction to be
+    // aligned. IALIGN is 16 bits with the C extension and 32 bits without.
+    pub fn instruction_alignment(&self) -> u64 {
+        if self.has_extension(b'C') {
+            0b1
+        } else {
+            0b11
+        }
+    }
+    pub fn read(&self, address: usize) -> u64 {
+        match address {
+            // fflags and frm are fields of fcsr
+            FFLAGS => self.registers[FCSR] & 0b11111,
+            FRM => (self.registers[FCSR] >> 5) & 0b111,
+            // sstatus, sie and sip are views of the machine registers
+            SSTATUS => self.registers[MSTATUS] & SSTATUS_MASK,
+            SIE => self.registers[MIE] & self.registers[MIDELEG],
+            SIP => self.read(MIP) & self.registers[MIDELEG],
+            MIP => self.registers[MIP] | self.lines,
+            // One instruction retires each cycle. time has no register
+            // here, and is read from the CLINT by Cpu::read_csr.
+            CYCLE => self.registers[MCYCLE],
+            INSTRET => self.registers[MINSTRET],
+            _ => self.registers[address],
+        }
+    }
+    // Count an instruction that retired
+    pub fn retire(&mut self) {
+        self.registers[MCYCLE] = self.registers[MCYCLE].wrapping_add(1);
+        self.registers[MINSTRET] = self.registers[MINSTRET].wrapping_add(1);
+    }
+    // The value a read-modify-write of a CSR starts from. For mip and sip
+    // this leaves out the device lines so that setting or clearing other
+    // bits doesn't latch them into the software written bits.
+    pub fn read_written(&self, address: usize) -> u64 {
+        match address {
+            MIP => self.registers[MIP],
+            SIP => self.registers[MIP] & self.registers[MIDELEG],
+            _ => self.read(address),
+        }
+    }
+    // Configuration of a PMP entry, packed a byte per entry into the even
+    // pmpcfg registers
+    pub fn pmp_config(&self, entry: usize) -> u64 {
+        (self.registers[PMPCFG0 + entry / 8 * 2] >> (8 * (entry % 8))) & 0xff
+    }
+    // Set the floating point exception flags raised by an instruction
+    pub fn accrue_fflags(&mut self, flags: u32) {
+        if flags != 0 {
+            self.dirty_float();
+        }
+        self.registers[FCSR] |= u64::from(flags);
+    }
+    // Raise or lower a device's interrupt line into mip
+    pub fn set_pending(&mut self, interrupt: u64, pending: bool) {
+        if pending {
+            self.lines |= interrupt;
+        } else {
+            self.lines &= !interrupt;
+        }
+    }
+    // Write a CSR, keeping fields that are read-only or hold an illegal
+    // value (WARL) unchanged
+    pub fn write(&mut self, address: usize, value: u64) {
+        match address {
+            FFLAGS => return self.write(FCSR, (self.read(FCSR) & !0b11111) | (value & 0b11111)),
+            FRM => return self.write(FCSR, (self.read(FCSR) & 0b11111) | (value & 0b111) << 5),
+            SSTATUS => {
+                let value = (self.read(MSTATUS) & !SSTATUS_MASK) | (value & SSTATUS_MASK);
+                return self.write(MSTATUS, value);
+            }
+            SIE => {
+                let delegated = self.registers[MIDELEG];
+                return self.write(MIE, (self.read(MIE) & !delegated) | (value & delegated));
+            }
+            // Only the software interrupt can be raised through sip
+            SIP => {
+                let writable = self.registers[MIDELEG] & SSIP;
+                let old = self.registers[MIP];
+                return self.write(MIP, (old & !writable) | (value & writable));
+            }
+            FCSR => self.dirty_float(),
+            _ => (),
+        }
+        let old = self.registers[address];
+        self.registers[address] = match address {
+            MSTATUS => {
+                let writable = MSTATUS_SIE
+                    | MSTATUS_MIE
+                    | MSTATUS_SPIE
+                    | MSTATUS_MPIE
+                    | MSTATUS_SPP
+                    | MSTATUS_MPP
+                    | MSTATUS_MPRV
+                    | MSTATUS_SUM
+                    | MSTATUS_MXR
+                    | MSTATUS_TVM
+                    | MSTATUS_TW
+                    | MSTATUS_TSR;
+                // FS is read-only zero without F
+                let writable = match self.extensions & extension(b'F') {
+                    0 => writable,
+                    _ => writable | MSTATUS_FS,
+                };
+                let value = (old & !writable) | (value & writable);
+                // 0b10 is a reserved privilege level
+                let value = if (value & MSTATUS_MPP) >> 11 == 0b10 {
+                    (value & !MSTATUS_MPP) | (old & MSTATUS_MPP)
+                } else {
+                    value
+                };
+                match value & MSTATUS_FS {
+                    FS_DIRTY => value | MSTATUS_SD,
+                    _ => value & !MSTATUS_SD,
+                }
+            }
+            FCSR => value & 0xff,
+            MEDELEG => value & DELEGABLE_EXCEPTIONS,
+            MIDELEG => value & SUPERVISOR_INTERRUPTS,
+            MIE => {
+                let writable = MSIP | MTIP | MEIP | SUPERVISOR_INTERRUPTS;
+                (old & !writable) | (value & writable)
+            }
+            // The machine interrupt pending bits are only set by devices,
+            // while the supervisor ones can also be raised by machine mode
+            MIP => (old & !SUPERVISOR_INTERRUPTS) | (value & SUPERVISOR_INTERRUPTS),
+            MTVEC | STVEC => match value & 0b11 {
+                // Direct and vectored modes
+                0b00 | 0b01 => value,
+                _ => old,
+            },
+            MEPC | SEPC => value & !self.instruction_alignment(),
+            // Writing an unsupported translation mode has no effect
+            SATP => match value >> 60 {
+                mmu::SATP_BARE | mmu::SATP_SV39 | mmu::SATP_SV48 | mmu::SATP_SV57 => value,
+                _ => old,
+            },
+            // Only the C extension can be turned off, and back on if the
+            // hart implements it
+            MISA => {
+                let writable = extension(b'C') & self.extensions;
+                (old & !writable) | (value & writable)
+            }
+            PMPCFG0..=PMPCFG15 => pmp::write_config(old, value),
+            PMPADDR0..=PMPADDR63 if pmp::address_locked(address - PMPADDR0, self) => old,
+            PMPADDR0..=PMPADDR63 => value & pmp::ADDRESS_MASK,
+            MCOUNTEREN | SCOUNTEREN => value & COUNTERS,
+            MVENDORID | MARCHID | MIMPID | MHARTID | CYCLE | TIME | INSTRET => old,
+            _ => value,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn misa() {
+        let mut csr = Csr::new();
+        let base = 2 << 62
+            | extension(b'I')
+            | extension(b'M')
+            | extension(b'A')
+            | extension(b'F')
+            | extension(b'D')
+            | extension(b'S')
+            | extension(b'U');
+        assert_eq!(csr.read(MISA), base | extension(b'C'));
+        assert!(csr.has_extension(b'C'));
+        csr.write(MISA, 0);
+        assert_eq!(csr.read(MISA), base);
+        assert!(!csr.has_extension(b'C'));
+
+        // A hart without C can't turn it on, and one without F has no
+        // floating point CSRs
+        let mut csr = Csr::with_extensions(extension(b'I') | extension(b'M'));
+        csr.write(MISA, u64::MAX);
+        assert_eq!(csr.read(MISA), 2 << 62 | extension(b'I') | extension(b'M'));
+        assert_eq!(
+            csr.check_access(FCSR, false, Privilege::Machine),
+            Err(Exception::IllegalInstruction(0))
+        );
+    }
+    #[test]
+    fn mstatus_warl() {
+        let mut csr = Csr::new();
+        csr.write(MSTATUS, u64::MAX);
+        assert_eq!(
+            csr.read(MSTATUS),
+            MSTATUS_SD | 0x7e_79aa | 2 << 32 | 2 << 34
+        );
+        csr.write(MSTATUS, 0);
+        assert_eq!(csr.read(MSTATUS), 2 << 32 | 2 << 34);
+        // MPP keeps its value when written with the reserved level
+        csr.write(MSTATUS, 0b01 << 11);
+        csr.write(MSTATUS, 0b10 << 11);
+        assert_eq!(csr.read(MSTATUS) & MSTATUS_MPP, 0b01 << 11);
+    }
+    #[test]
+    fn float_state() {
+        let mut csr = Csr::new();
+        assert_eq!(csr.read(SSTATUS) & MSTATUS_FS, FS_INITIAL);
+        // Writing fcsr, or raising flags, leaves the state Dirty
+        csr.write(FRM, 1);
+        assert_eq!(
+            csr.read(SSTATUS) & (MSTATUS_FS | MSTATUS_SD),
+            FS_DIRTY | MSTATUS_SD
+        );
+        csr.write(SSTATUS, FS_CLEAN);
+        assert_eq!(csr.read(MSTATUS) & (MSTATUS_FS | MSTATUS_SD), FS_CLEAN);
+        csr.accrue_fflags(0);
+        assert_eq!(csr.read(MSTATUS) & MSTATUS_FS, FS_CLEAN);
+        csr.accrue_fflags(1);
+        assert_eq!(csr.read(MSTATUS) & MSTATUS_FS, FS_DIRTY);
+        // SD is read-only
+        csr.write(MSTATUS, FS_INITIAL | MSTATUS_SD);
+        assert_eq!(csr.read(MSTATUS) & (MSTATUS_FS | MSTATUS_SD), FS_INITIAL);
+
+        // The CSRs can't be reached while the unit is off
+        csr.write(SSTATUS, FS_OFF);
+        assert!(csr.float_off());
+        for &address in &[FFLAGS, FRM, FCSR] {
+            assert_eq!(
+                csr.check_access(address, false, Privilege::Machine),
+                Err(Exception::IllegalInstruction(0))
+            );
+        }
+        // and without F it can't be turned on
+        let mut csr = Csr::with_extensions(extension(b'I'));
+        assert!(csr.float_off());
+        csr.write(MSTATUS, FS_DIRTY);
+        assert!(csr.float_off());
+    }
+    #[test]
+    fn counters() {
+        let mut csr = Csr::new();
+        csr.retire();
+        csr.retire();
+        assert_eq!((csr.read(CYCLE), csr.read(INSTRET)), (2, 2));
+        csr.write(MINSTRET, 10);
+        csr.write(INSTRET, 0);
+        assert_eq!((csr.read(CYCLE), csr.read(INSTRET)), (2, 10));
+        csr.write(MCOUNTEREN, u64::MAX);
+        assert_eq!(csr.read(MCOUNTEREN), 0b111);
+
+        // Machine mode can always read them, supervisor mode once
+        // mcounteren allows it and user mode once scounteren does too
+        let access = |csr: &Csr, address, privilege| csr.check_access(address, false, privilege);
+        let illegal = Err(Exception::IllegalInstruction(0));
+        let mut csr = Csr::new();
+        for &address in &[CYCLE, TIME, INSTRET] {
+            assert_eq!(access(&csr, address, Privilege::Machine), Ok(()));
+            assert_eq!(access(&csr, address, Privilege::Supervisor), illegal);
+            assert_eq!(access(&csr, address, Privilege::User), illegal);
+            assert_eq!(csr.check_access(address, true, Privilege::Machine), illegal);
+        }
+        csr.write(MCOUNTEREN, 1 << 1);
+        assert_eq!(access(&csr, TIME, Privilege::Supervisor), Ok(()));
+        assert_eq!(access(&csr, CYCLE, Privilege::Supervisor), illegal);
+        assert_eq!(access(&csr, TIME, Privilege::User), illegal);
+        csr.write(SCOUNTEREN, 1 << 1);
+        assert_eq!(access(&csr, TIME, Privilege::User), Ok(()));
+        assert_eq!(access(&csr, INSTRET, Privilege::User), illegal);
+        // Without supervisor mode only mcounteren counts
+        let mut csr = Csr::with_extensions(extension(b'I') | extension(b'U'));
+        csr.write(MCOUNTEREN, 1 << 2);
+        assert_eq!(access(&csr, INSTRET, Privilege::User), Ok(()));
+    }
+    #[test]
+    fn sstatus_view() {
+        let mut csr = Csr::new();
+        csr.write(MSTATUS, MSTATUS_MIE | MSTATUS_SIE | MSTATUS_MPP);
+        assert_eq!(csr.read(SSTATUS), MSTATUS_SIE | 2 << 32);
+        // Machine fields can't be changed through sstatus
+        csr.write(SSTATUS, MSTATUS_SPP | MSTATUS_SUM | MSTATUS_MPRV);
+        assert_eq!(
+            csr.read(MSTATUS),
+            MSTATUS_MIE | MSTATUS_MPP | MSTATUS_SPP | MSTATUS_SUM | 2 << 32 | 2 << 34
+        );
+    }
+    #[test]
+    fn mtvec_warl() {
+        let mut csr = Csr::new();
+        csr.write(MTVEC, 0x8000_0001);
+        assert_eq!(csr.read(MTVEC), 0x8000_0001);
+        csr.write(MTVEC, 0x9000_0002);
+        assert_eq!(csr.read(MTVEC), 0x8000_0001);
+    }
+    #[test]
+    fn mie_mip_warl() {
+        let mut csr = Csr::new();
+        csr.write(MIE, u64::MAX);
+        assert_eq!(csr.read(MIE), MSIP | MTIP | MEIP | SSIP | STIP | SEIP);
+        csr.write(MIP, u64::MAX);
+        assert_eq!(csr.read(MIP), SSIP | STIP | SEIP);
+    }
+    #[test]
+    fn device_interrupts() {
+        let mut csr = Csr::new();
+        csr.set_pending(MTIP, true);
+        csr.set_pending(MSIP, true);
+        assert_eq!(csr.read(MIP), MSIP | MTIP);
+        // Software can't clear the machine level bits
+        csr.write(MIP, 0);
+        assert_eq!(csr.read(MIP), MSIP | MTIP);
+        csr.set_pending(MTIP, false);
+        assert_eq!(csr.read(MIP), MSIP);
+        // The external line and the software written bit are separate
+        csr.set_pending(SEIP, true);
+        assert_eq!(csr.read(MIP), MSIP | SEIP);
+        assert_eq!(csr.read_written(MIP), 0);
+        csr.write(MIP, SEIP);
+        csr.set_pending(SEIP, false);
+        assert_eq!(csr.read(MIP), MSIP | SEIP);
+        csr.write(MIP, 0);
+        assert_eq!(csr.read(MIP), MSIP);
+    }
+    #[test]
+    fn delegation() {
+        let mut csr = Csr::new();
+        csr.write(MEDELEG, u64::MAX);
+        assert_eq!(csr.read(MEDELEG), 0xb3ff);
+        csr.write(MIDELEG, u64::MAX);
+        assert_eq!(csr.read(MIDELEG), SSIP | STIP | SEIP);
+    }
+    #[test]
+    fn sie_sip_view() {
+        let mut csr = Csr::new();
+        csr.write(MIE, MTIP | STIP);
+        csr.write(MIP, STIP | SSIP);
+        // Only delegated interrupts are visible
+        assert_eq!(csr.read(SIE), 0);
+        assert_eq!(csr.read(SIP), 0);
+        csr.write(MIDELEG, SSIP | STIP);
+        assert_eq!(csr.read(SIE), STIP);
+        assert_eq!(csr.read(SIP), SSIP | STIP);
+        csr.write(SIE, SSIP | MSIP);
+        assert_eq!(csr.read(MIE), MTIP | SSIP);
+        // Supervisor software can only clear its software interrupt
+        csr.write(SIP, 0);
+        assert_eq!(csr.read(MIP), STIP);
+    }
+    #[test]
+    fn names() {
+        assert_eq!(name(MSTATUS).as_deref(), Some("mstatus"));
+        assert_eq!(name(PMPCFG0 + 2).as_deref(), Some("pmpcfg2"));
+        assert_eq!(name(PMPCFG0 + 1), None);
+        assert_eq!(name(PMPADDR63).as_deref(), Some("pmpaddr63"));
+        assert_eq!(name(0x7ff), None);
+    }
+    #[test]
+    fn satp_warl() {
+        let mut csr = Csr::new();
+        csr.write(SATP, 0x1234);
+        assert_eq!(csr.read(SATP), 0x1234);
+        csr.write(SATP, 1 << 60 | 0x5678);
+        assert_eq!(csr.read(SATP), 0x1234);
+        csr.write(SATP, mmu::SATP_SV48 << 60 | 0x5678);
+        assert_eq!(csr.read(SATP), 9 << 60 | 0x5678);
+    }
+    #[test]
+    fn mepc_alignment() {
+        let mut csr = Csr::new();
+        csr.write(MEPC, 0x8000_0003);
+        assert_eq!(csr.read(MEPC), 0x8000_0002);
+        csr.write(MISA, 0);
+        csr.write(MEPC, 0x8000_0003);
+        assert_eq!(csr.read(MEPC), 0x8000_0000);
+    }
+    #[test]
+    fn fcsr_fields() {
+        let mut csr = Csr::new();
+        csr.write(FCSR, u64::MAX);
+        assert_eq!(csr.read(FCSR), 0xff);
+        csr.write(FRM, 0b001);
+        assert_eq!(csr.read(FCSR), 0b001_11111);
+        csr.write(FFLAGS, 0);
+        assert_eq!(csr.read(FCSR), 0b001_00000);
+        csr.accrue_fflags(0b10);
+        csr.accrue_fflags(0b1);
+        assert_eq!(csr.read(FFLAGS), 0b11);
+        assert_eq!(csr.read(FRM), 0b001);
+    }
+    #[test]
+    fn check_access() {
+        let mut csr = Csr::new();
+        let illegal = Err(Exception::IllegalInstruction(0));
+        assert_eq!(csr.check_access(MHARTID, false, Privilege::Machine), Ok(()));
+        assert_eq!(csr.check_access(MHARTID, true, Privilege::Machine), illegal);
+        assert_eq!(csr.check_access(MSCRATCH, true, Privilege::Machine), Ok(()));
+        assert_eq!(csr.check_access(0x7c0, false, Privilege::Machine), illegal);
+        // Bits 9:8 of the address give the lowest privilege level
+        assert_eq!(
+            csr.check_access(MSCRATCH, false, Privilege::Supervisor),
+            illegal
+        );
+        assert_eq!(
+            csr.check_access(SSCRATCH, true, Privilege::Supervisor),
+            Ok(())
+        );
+        assert_eq!(csr.check_access(SSCRATCH, false, Privilege::User), illegal);
+        assert_eq!(csr.check_access(FCSR, true, Privilege::User), Ok(()));
+        // Odd pmpcfg registers only exist on RV32
+        assert_eq!(csr.check_access(PMPCFG0, true, Privilege::Machine), Ok(()));
+        assert_eq!(
+            csr.check_access(PMPCFG0 + 1, true, Privilege::Machine),
+            illegal
+        );
+        assert_eq!(
+            csr.check_access(PMPADDR63, true, Privilege::Machine),
+            Ok(())
+        );
+        csr.write(MSTATUS, MSTATUS_TVM);
+        assert_eq!(
+            csr.check_access(SATP, false, Privilege::Supervisor),
+            illegal
+        );
+        assert_eq!(csr.check_access(SATP, false, Privilege::Machine), Ok(()));
+    }
+}
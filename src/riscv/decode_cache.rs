@@ -0,0 +1,128 @@
+// Instructions already decoded, by offset into DRAM, so that code that runs
+// again skips the fetch and the decoder. Pages get slots for each 16 bit
+// parcel the first time code runs from them, and writes to DRAM clear the
+// slots of any instruction they overlap.
+use crate::riscv::instruction::{self, Instruction};
+
+pub const PAGE_SIZE: u64 = 4096;
+// Instructions start on 16 bit parcels, 2048 to a page
+const PARCEL_BITS: u32 = 11;
+const PARCELS: u64 = 1 << PARCEL_BITS;
+
+// The encoded instruction, kept for the value of an illegal instruction
+// exception, and what it decodes to
+type Slot = Option<(u32, Instruction)>;
+
+pub struct DecodeCache {
+    pages: Vec<Option<Box<[Slot]>>>,
+}
+impl DecodeCache {
+    // A cache for `dram_size` bytes of DRAM. An empty one caches nothing.
+    pub fn new(dram_size: u64) -> Self {
+        let pages = dram_size.div_ceil(PAGE_SIZE);
+        Self {
+            pages: (0..pages).map(|_| None).collect(),
+        }
+    }
+    pub fn get(&self, offset: u64) -> Slot {
+        let parcel = offset >> 1;
+        match self.pages.get((parcel >> PARCEL_BITS) as usize) {
+            Some(Some(slots)) => slots[(parcel & (PARCELS - 1)) as usize],
+            _ => None,
+        }
+    }
+    // Instructions that run over the end of their page aren't cached, so
+    // that each entry lies within one page. Returns whether it was cached.
+    pub fn insert(&mut self, offset: u64, encoded: u32, decoded: Instruction) -> bool {
+        let length = instruction::length(encoded);
+        if offset & (PAGE_SIZE - 1) > PAGE_SIZE - length {
+            return false;
+        }
+        let parcel = offset >> 1;
+        match self.pages.get_mut((parcel >> PARCEL_BITS) as usize) {
+            Some(page) => {
+                let slots = page.get_or_insert_with(|| vec![None; PARCELS as usize].into());
+                slots[(parcel & (PARCELS - 1)) as usize] = Some((encoded, decoded));
+                true
+            }
+            None => false,
+        }
+    }
+    // Forget the instructions overlapping `size` bytes written at `offset`,
+    // returning whether there were any
+    pub fn invalidate(&mut self, offset: u64, size: u64) -> bool {
+        let mut cleared = false;
+        if size == 0 {
+            return cleared;
+        }
+        // A 32 bit instruction starting in the parcel before also overlaps.
+        // Whatever is cached there goes, which at worst means decoding a
+        // compressed instruction again.
+        let mut parcel = offset.saturating_sub(2) >> 1;
+        let end = (offset + size + 1) >> 1;
+        while parcel < end {
+            let page = parcel >> PARCEL_BITS;
+            let page_end = ((page + 1) << PARCEL_BITS).min(end);
+            if let Some(Some(slots)) = self.pages.get_mut(page as usize) {
+                let first = (parcel & (PARCELS - 1)) as usize;
+                let last = ((page_end - 1) & (PARCELS - 1)) as usize;
+                for slot in &mut slots[first..=last] {
+                    cleared |= slot.take().is_some();
+                }
+            }
+            parcel = page_end;
+        }
+        cleared
+    }
+    pub fn flush(&mut self) {
+        for page in &mut self.pages {
+            *page = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::instruction::decode;
+
+    // addi a0, a0, 1
+    const ADDI: u32 = 0x0015_0513;
+    // c.addi a0, 1
+    const C_ADDI: u32 = 0x0505;
+
+    #[test]
+    fn insert_and_invalidate() {
+        let mut cache = DecodeCache::new(2 * PAGE_SIZE);
+        assert!(cache.insert(0x100, ADDI, decode(ADDI)));
+        assert!(cache.insert(0x104, C_ADDI, Instruction::Undefined));
+        assert_eq!(cache.get(0x100), Some((ADDI, decode(ADDI))));
+        assert_eq!(cache.get(0x102), None);
+        // A write to the second half of the instruction or the byte before
+        // it misses the compressed instruction after it
+        assert!(cache.invalidate(0x103, 1));
+        assert_eq!(cache.get(0x100), None);
+        assert!(cache.get(0x104).is_some());
+        assert!(!cache.invalidate(0x108, 8));
+        assert!(cache.get(0x104).is_some());
+        cache.invalidate(0xf0, 0x15);
+        assert_eq!(cache.get(0x104), None);
+
+        // Nothing runs over the end of a page, or past the end of DRAM
+        assert!(!cache.insert(PAGE_SIZE - 2, ADDI, decode(ADDI)));
+        assert!(cache.insert(PAGE_SIZE - 2, C_ADDI, Instruction::Undefined));
+        assert!(!cache.insert(2 * PAGE_SIZE, ADDI, decode(ADDI)));
+        assert_eq!(
+            cache.get(PAGE_SIZE - 2),
+            Some((C_ADDI, Instruction::Undefined))
+        );
+        assert_eq!(cache.get(2 * PAGE_SIZE), None);
+        // Writes at the start of a page reach back into the page before
+        cache.invalidate(PAGE_SIZE, 1);
+        assert_eq!(cache.get(PAGE_SIZE - 2), None);
+
+        cache.insert(PAGE_SIZE, ADDI, decode(ADDI));
+        cache.flush();
+        assert_eq!(cache.get(PAGE_SIZE), None);
+    }
+}
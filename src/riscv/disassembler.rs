@@ -0,0 +1,877 @@
+// Disassembly of decoded instructions into the assembly objdump prints,
+// with ABI register names and the usual pseudo-instructions. Jump and
+// branch targets are absolute addresses when the address of the
+// instruction is known, and relative to it in GNU as syntax otherwise.
+use crate::riscv::cpu::Register;
+use crate::riscv::csr;
+use crate::riscv::elf::SymbolTable;
+use crate::riscv::instruction::Instruction;
+use std::fmt;
+
+// Names of the rounding modes in the rm field. The dynamic rounding mode
+// is the default and isn't shown.
+pub const ROUNDING_MODES: [&str; 8] = ["rne", "rtz", "rdn", "rup", "rmm", "", "", "dyn"];
+pub const DYNAMIC: u32 = 7;
+
+// FENCE predecessor and successor bits, and the fence mode of FENCE.TSO
+pub const FENCE_IORW: u32 = 0b1111;
+pub const FENCE_RW: u32 = 0b0011;
+pub const FENCE_TSO: u32 = 0b1000;
+
+fn rounding(rm: u32) -> String {
+    match rm {
+        DYNAMIC => String::new(),
+        rm => format!(", {}", ROUNDING_MODES[(rm & 0b111) as usize]),
+    }
+}
+fn ordering(aq: bool, rl: bool) -> &'static str {
+    match (aq, rl) {
+        (false, false) => "",
+        (true, false) => ".aq",
+        (false, true) => ".rl",
+        (true, true) => ".aqrl",
+    }
+}
+fn csr_name(csr: u32) -> String {
+    csr::name(csr as usize).unwrap_or_else(|| format!("{:#x}", csr))
+}
+fn fence_set(bits: u32) -> String {
+    let set: String = "iorw"
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| bits & (0b1000 >> i) != 0)
+        .map(|(_, c)| c)
+        .collect();
+    if set.is_empty() {
+        "0".to_string()
+    } else {
+        set
+    }
+}
+
+impl Instruction {
+    // The mnemonic of the instruction itself, rather than of any
+    // pseudo-instruction it stands for
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instruction::Undefined => "unimp",
+            Instruction::Beq { .. } => "beq",
+            Instruction::Bne { .. } => "bne",
+            Instruction::Blt { .. } => "blt",
+            Instruction::Bge { .. } => "bge",
+            Instruction::Bltu { .. } => "bltu",
+            Instruction::Bgeu { .. } => "bgeu",
+            Instruction::Lb { .. } => "lb",
+            Instruction::Lh { .. } => "lh",
+            Instruction::Lw { .. } => "lw",
+            Instruction::Lbu { .. } => "lbu",
+            Instruction::Lhu { .. } => "lhu",
+            Instruction::Lwu { .. } => "lwu",
+            Instruction::Ld { .. } => "ld",
+            Instruction::Fence { .. } => "fence",
+            Instruction::FenceI { .. } => "fence.i",
+            Instruction::Addi { .. } => "addi",
+            Instruction::Slti { .. } => "slti",
+            Instruction::Sltiu { .. } => "sltiu",
+            Instruction::Xori { .. } => "xori",
+            Instruction::Ori { .. } => "ori",
+            Instruction::Andi { .. } => "andi",
+            Instruction::Slli { .. } => "slli",
+            Instruction::Srli { .. } => "srli",
+            Instruction::Srai { .. } => "srai",
+            Instruction::Addiw { .. } => "addiw",
+            Instruction::Slliw { .. } => "slliw",
+            Instruction::Srliw { .. } => "srliw",
+            Instruction::Sraiw { .. } => "sraiw",
+            Instruction::Jalr { .. } => "jalr",
+            Instruction::Ebreak => "ebreak",
+            Instruction::Ecall => "ecall",
+            Instruction::Mret => "mret",
+            Instruction::Sret => "sret",
+            Instruction::Wfi => "wfi",
+            Instruction::SfenceVma { .. } => "sfence.vma",
+            Instruction::Csrrw { .. } => "csrrw",
+            Instruction::Csrrs { .. } => "csrrs",
+            Instruction::Csrrc { .. } => "csrrc",
+            Instruction::Csrrwi { .. } => "csrrwi",
+            Instruction::Csrrsi { .. } => "csrrsi",
+            Instruction::Csrrci { .. } => "csrrci",
+            Instruction::Jal { .. } => "jal",
+            Instruction::Add { .. } => "add",
+            Instruction::Sub { .. } => "sub",
+            Instruction::Sll { .. } => "sll",
+            Instruction::Slt { .. } => "slt",
+            Instruction::Sltu { .. } => "sltu",
+            Instruction::Xor { .. } => "xor",
+            Instruction::Srl { .. } => "srl",
+            Instruction::Sra { .. } => "sra",
+            Instruction::Or { .. } => "or",
+            Instruction::And { .. } => "and",
+            Instruction::Addw { .. } => "addw",
+            Instruction::Subw { .. } => "subw",
+            Instruction::Sllw { .. } => "sllw",
+            Instruction::Srlw { .. } => "srlw",
+            Instruction::Sraw { .. } => "sraw",
+            Instruction::Mul { .. } => "mul",
+            Instruction::Mulh { .. } => "mulh",
+            Instruction::Mulhsu { .. } => "mulhsu",
+            Instruction::Mulhu { .. } => "mulhu",
+            Instruction::Div { .. } => "div",
+            Instruction::Divu { .. } => "divu",
+            Instruction::Rem { .. } => "rem",
+            Instruction::Remu { .. } => "remu",
+            Instruction::Mulw { .. } => "mulw",
+            Instruction::Divw { .. } => "divw",
+            Instruction::Divuw { .. } => "divuw",
+            Instruction::Remw { .. } => "remw",
+            Instruction::Remuw { .. } => "remuw",
+            Instruction::LrW { .. } => "lr.w",
+            Instruction::ScW { .. } => "sc.w",
+            Instruction::AmoswapW { .. } => "amoswap.w",
+            Instruction::AmoaddW { .. } => "amoadd.w",
+            Instruction::AmoxorW { .. } => "amoxor.w",
+            Instruction::AmoandW { .. } => "amoand.w",
+            Instruction::AmoorW { .. } => "amoor.w",
+            Instruction::AmominW { .. } => "amomin.w",
+            Instruction::AmomaxW { .. } => "amomax.w",
+            Instruction::AmominuW { .. } => "amominu.w",
+            Instruction::AmomaxuW { .. } => "amomaxu.w",
+            Instruction::LrD { .. } => "lr.d",
+            Instruction::ScD { .. } => "sc.d",
+            Instruction::AmoswapD { .. } => "amoswap.d",
+            Instruction::AmoaddD { .. } => "amoadd.d",
+            Instruction::AmoxorD { .. } => "amoxor.d",
+            Instruction::AmoandD { .. } => "amoand.d",
+            Instruction::AmoorD { .. } => "amoor.d",
+            Instruction::AmominD { .. } => "amomin.d",
+            Instruction::AmomaxD { .. } => "amomax.d",
+            Instruction::AmominuD { .. } => "amominu.d",
+            Instruction::AmomaxuD { .. } => "amomaxu.d",
+            Instruction::Flw { .. } => "flw",
+            Instruction::Fsw { .. } => "fsw",
+            Instruction::FmaddS { .. } => "fmadd.s",
+            Instruction::FmsubS { .. } => "fmsub.s",
+            Instruction::FnmsubS { .. } => "fnmsub.s",
+            Instruction::FnmaddS { .. } => "fnmadd.s",
+            Instruction::FaddS { .. } => "fadd.s",
+            Instruction::FsubS { .. } => "fsub.s",
+            Instruction::FmulS { .. } => "fmul.s",
+            Instruction::FdivS { .. } => "fdiv.s",
+            Instruction::FsqrtS { .. } => "fsqrt.s",
+            Instruction::FsgnjS { .. } => "fsgnj.s",
+            Instruction::FsgnjnS { .. } => "fsgnjn.s",
+            Instruction::FsgnjxS { .. } => "fsgnjx.s",
+            Instruction::FminS { .. } => "fmin.s",
+            Instruction::FmaxS { .. } => "fmax.s",
+            Instruction::FeqS { .. } => "feq.s",
+            Instruction::FltS { .. } => "flt.s",
+            Instruction::FleS { .. } => "fle.s",
+            Instruction::FclassS { .. } => "fclass.s",
+            Instruction::FcvtWS { .. } => "fcvt.w.s",
+            Instruction::FcvtWuS { .. } => "fcvt.wu.s",
+            Instruction::FcvtLS { .. } => "fcvt.l.s",
+            Instruction::FcvtLuS { .. } => "fcvt.lu.s",
+            Instruction::FcvtSW { .. } => "fcvt.s.w",
+            Instruction::FcvtSWu { .. } => "fcvt.s.wu",
+            Instruction::FcvtSL { .. } => "fcvt.s.l",
+            Instruction::FcvtSLu { .. } => "fcvt.s.lu",
+            Instruction::FmvXW { .. } => "fmv.x.w",
+            Instruction::FmvWX { .. } => "fmv.w.x",
+            Instruction::Fld { .. } => "fld",
+            Instruction::Fsd { .. } => "fsd",
+            Instruction::FmaddD { .. } => "fmadd.d",
+            Instruction::FmsubD { .. } => "fmsub.d",
+            Instruction::FnmsubD { .. } => "fnmsub.d",
+            Instruction::FnmaddD { .. } => "fnmadd.d",
+            Instruction::FaddD { .. } => "fadd.d",
+            Instruction::FsubD { .. } => "fsub.d",
+            Instruction::FmulD { .. } => "fmul.d",
+            Instruction::FdivD { .. } => "fdiv.d",
+            Instruction::FsqrtD { .. } => "fsqrt.d",
+            Instruction::FsgnjD { .. } => "fsgnj.d",
+            Instruction::FsgnjnD { .. } => "fsgnjn.d",
+            Instruction::FsgnjxD { .. } => "fsgnjx.d",
+            Instruction::FminD { .. } => "fmin.d",
+            Instruction::FmaxD { .. } => "fmax.d",
+            Instruction::FcvtSD { .. } => "fcvt.s.d",
+            Instruction::FcvtDS { .. } => "fcvt.d.s",
+            Instruction::FeqD { .. } => "feq.d",
+            Instruction::FltD { .. } => "flt.d",
+            Instruction::FleD { .. } => "fle.d",
+            Instruction::FclassD { .. } => "fclass.d",
+            Instruction::FcvtWD { .. } => "fcvt.w.d",
+            Instruction::FcvtWuD { .. } => "fcvt.wu.d",
+            Instruction::FcvtLD { .. } => "fcvt.l.d",
+            Instruction::FcvtLuD { .. } => "fcvt.lu.d",
+            Instruction::FcvtDW { .. } => "fcvt.d.w",
+            Instruction::FcvtDWu { .. } => "fcvt.d.wu",
+            Instruction::FcvtDL { .. } => "fcvt.d.l",
+            Instruction::FcvtDLu { .. } => "fcvt.d.lu",
+            Instruction::FmvXD { .. } => "fmv.x.d",
+            Instruction::FmvDX { .. } => "fmv.d.x",
+            Instruction::Sb { .. } => "sb",
+            Instruction::Sh { .. } => "sh",
+            Instruction::Sw { .. } => "sw",
+            Instruction::Sd { .. } => "sd",
+            Instruction::Auipc { .. } => "auipc",
+            Instruction::Lui { .. } => "lui",
+        }
+    }
+    // Disassemble the instruction as found at `address`, naming jump and
+    // branch targets after the symbols covering them
+    pub fn disassemble<'a>(
+        &'a self,
+        address: u64,
+        symbols: Option<&'a SymbolTable>,
+    ) -> Disassembly<'a> {
+        Disassembly {
+            instruction: self,
+            address: Some(address),
+            symbols,
+        }
+    }
+}
+
+// The target of a jump or branch at `offset` from the instruction
+struct Target<'a> {
+    offset: i32,
+    address: Option<u64>,
+    symbols: Option<&'a SymbolTable>,
+}
+impl fmt::Display for Target<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let address = match self.address {
+            Some(address) => address.wrapping_add(self.offset as i64 as u64),
+            None => return write!(f, ".{:+}", self.offset),
+        };
+        write!(f, "{:#x}", address)?;
+        match self.symbols.and_then(|symbols| symbols.symbolize(address)) {
+            Some((symbol, 0)) => write!(f, " <{}>", symbol.name),
+            Some((symbol, offset)) => write!(f, " <{}+{:#x}>", symbol.name, offset),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct Disassembly<'a> {
+    instruction: &'a Instruction,
+    address: Option<u64>,
+    symbols: Option<&'a SymbolTable>,
+}
+impl fmt::Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let target = |offset| Target {
+            offset,
+            address: self.address,
+            symbols: self.symbols,
+        };
+        let zero = Register::X0;
+        let mnemonic = self.instruction.mnemonic();
+        match *self.instruction {
+            // Pseudo-instructions
+            Instruction::Addi { rd, rs1, imm: 0 } if rd == zero && rs1 == zero => {
+                write!(f, "nop")
+            }
+            Instruction::Addi { rd, rs1, imm } if rs1 == zero => write!(f, "li {}, {}", rd, imm),
+            Instruction::Addi { rd, rs1, imm: 0 } => write!(f, "mv {}, {}", rd, rs1),
+            // C.MV expands to ADD
+            Instruction::Add { rd, rs1, rs2 } if rs1 == zero => write!(f, "mv {}, {}", rd, rs2),
+            Instruction::Addiw { rd, rs1, imm: 0 } => write!(f, "sext.w {}, {}", rd, rs1),
+            Instruction::Xori { rd, rs1, imm: -1 } => write!(f, "not {}, {}", rd, rs1),
+            Instruction::Sltiu { rd, rs1, imm: 1 } => write!(f, "seqz {}, {}", rd, rs1),
+            Instruction::Sub { rd, rs1, rs2 } if rs1 == zero => write!(f, "neg {}, {}", rd, rs2),
+            Instruction::Subw { rd, rs1, rs2 } if rs1 == zero => {
+                write!(f, "negw {}, {}", rd, rs2)
+            }
+            Instruction::Sltu { rd, rs1, rs2 } if rs1 == zero => {
+                write!(f, "snez {}, {}", rd, rs2)
+            }
+            Instruction::Slt { rd, rs1, rs2 } if rs2 == zero => write!(f, "sltz {}, {}", rd, rs1),
+            Instruction::Slt { rd, rs1, rs2 } if rs1 == zero => write!(f, "sgtz {}, {}", rd, rs2),
+            Instruction::Beq { rs1, rs2, imm } if rs2 == zero => {
+                write!(f, "beqz {}, {}", rs1, target(imm))
+            }
+            Instruction::Bne { rs1, rs2, imm } if rs2 == zero => {
+                write!(f, "bnez {}, {}", rs1, target(imm))
+            }
+            Instruction::Blt { rs1, rs2, imm } if rs2 == zero => {
+                write!(f, "bltz {}, {}", rs1, target(imm))
+            }
+            Instruction::Blt { rs1, rs2, imm } if rs1 == zero => {
+                write!(f, "bgtz {}, {}", rs2, target(imm))
+            }
+            Instruction::Bge { rs1, rs2, imm } if rs2 == zero => {
+                write!(f, "bgez {}, {}", rs1, target(imm))
+            }
+            Instruction::Bge { rs1, rs2, imm } if rs1 == zero => {
+                write!(f, "blez {}, {}", rs2, target(imm))
+            }
+            Instruction::Jal { rd, imm } if rd == zero => write!(f, "j {}", target(imm)),
+            Instruction::Jal {
+                rd: Register::X1,
+                imm,
+            } => {
+                write!(f, "jal {}", target(imm))
+            }
+            Instruction::Jalr {
+                rd: Register::X0,
+                rs1: Register::X1,
+                imm: 0,
+            } => write!(f, "ret"),
+            Instruction::Jalr { rd, rs1, imm: 0 } if rd == zero => write!(f, "jr {}", rs1),
+            Instruction::Jalr {
+                rd: Register::X1,
+                rs1,
+                imm: 0,
+            } => {
+                write!(f, "jalr {}", rs1)
+            }
+            Instruction::Csrrs { rd, rs1, csr } if rs1 == zero => {
+                write!(f, "csrr {}, {}", rd, csr_name(csr))
+            }
+            Instruction::Csrrw { rd, rs1, csr } if rd == zero => {
+                write!(f, "csrw {}, {}", csr_name(csr), rs1)
+            }
+            Instruction::Csrrs { rd, rs1, csr } if rd == zero => {
+                write!(f, "csrs {}, {}", csr_name(csr), rs1)
+            }
+            Instruction::Csrrc { rd, rs1, csr } if rd == zero => {
+                write!(f, "csrc {}, {}", csr_name(csr), rs1)
+            }
+            Instruction::Csrrwi { rd, uimm, csr } if rd == zero => {
+                write!(f, "csrwi {}, {}", csr_name(csr), uimm)
+            }
+            Instruction::Csrrsi { rd, uimm, csr } if rd == zero => {
+                write!(f, "csrsi {}, {}", csr_name(csr), uimm)
+            }
+            Instruction::Csrrci { rd, uimm, csr } if rd == zero => {
+                write!(f, "csrci {}, {}", csr_name(csr), uimm)
+            }
+            Instruction::FsgnjS { rd, rs1, rs2 } if rs1 == rs2 => {
+                write!(f, "fmv.s {}, {}", rd, rs1)
+            }
+            Instruction::FsgnjnS { rd, rs1, rs2 } if rs1 == rs2 => {
+                write!(f, "fneg.s {}, {}", rd, rs1)
+            }
+            Instruction::FsgnjxS { rd, rs1, rs2 } if rs1 == rs2 => {
+                write!(f, "fabs.s {}, {}", rd, rs1)
+            }
+            Instruction::FsgnjD { rd, rs1, rs2 } if rs1 == rs2 => {
+                write!(f, "fmv.d {}, {}", rd, rs1)
+            }
+            Instruction::FsgnjnD { rd, rs1, rs2 } if rs1 == rs2 => {
+                write!(f, "fneg.d {}, {}", rd, rs1)
+            }
+            Instruction::FsgnjxD { rd, rs1, rs2 } if rs1 == rs2 => {
+                write!(f, "fabs.d {}, {}", rd, rs1)
+            }
+            Instruction::Fence { pred, succ, fm, .. } => match (fm, pred, succ) {
+                (FENCE_TSO, FENCE_RW, FENCE_RW) => write!(f, "fence.tso"),
+                (_, FENCE_IORW, FENCE_IORW) => write!(f, "fence"),
+                _ => write!(f, "fence {}, {}", fence_set(pred), fence_set(succ)),
+            },
+            Instruction::FenceI { .. } => write!(f, "{}", mnemonic),
+            Instruction::SfenceVma { rs1, rs2 } => match (rs1 == zero, rs2 == zero) {
+                (true, true) => write!(f, "{}", mnemonic),
+                (false, true) => write!(f, "{} {}", mnemonic, rs1),
+                _ => write!(f, "{} {}, {}", mnemonic, rs1, rs2),
+            },
+            Instruction::Beq { rs1, rs2, imm }
+            | Instruction::Bne { rs1, rs2, imm }
+            | Instruction::Blt { rs1, rs2, imm }
+            | Instruction::Bge { rs1, rs2, imm }
+            | Instruction::Bltu { rs1, rs2, imm }
+            | Instruction::Bgeu { rs1, rs2, imm } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rs1, rs2, target(imm))
+            }
+            Instruction::Lb { rd, rs1, imm }
+            | Instruction::Lh { rd, rs1, imm }
+            | Instruction::Lw { rd, rs1, imm }
+            | Instruction::Lbu { rd, rs1, imm }
+            | Instruction::Lhu { rd, rs1, imm }
+            | Instruction::Lwu { rd, rs1, imm }
+            | Instruction::Ld { rd, rs1, imm }
+            | Instruction::Jalr { rd, rs1, imm } => {
+                write!(f, "{} {}, {}({})", mnemonic, rd, imm, rs1)
+            }
+            Instruction::Addi { rd, rs1, imm }
+            | Instruction::Slti { rd, rs1, imm }
+            | Instruction::Sltiu { rd, rs1, imm }
+            | Instruction::Xori { rd, rs1, imm }
+            | Instruction::Ori { rd, rs1, imm }
+            | Instruction::Andi { rd, rs1, imm }
+            | Instruction::Addiw { rd, rs1, imm } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rd, rs1, imm)
+            }
+            Instruction::Slli { rd, rs1, shamt }
+            | Instruction::Srli { rd, rs1, shamt }
+            | Instruction::Srai { rd, rs1, shamt }
+            | Instruction::Slliw { rd, rs1, shamt }
+            | Instruction::Srliw { rd, rs1, shamt }
+            | Instruction::Sraiw { rd, rs1, shamt } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rd, rs1, shamt)
+            }
+            Instruction::Ebreak
+            | Instruction::Ecall
+            | Instruction::Mret
+            | Instruction::Sret
+            | Instruction::Wfi => write!(f, "{}", mnemonic),
+            Instruction::Csrrw { rd, rs1, csr }
+            | Instruction::Csrrs { rd, rs1, csr }
+            | Instruction::Csrrc { rd, rs1, csr } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rd, csr_name(csr), rs1)
+            }
+            Instruction::Csrrwi { rd, uimm, csr }
+            | Instruction::Csrrsi { rd, uimm, csr }
+            | Instruction::Csrrci { rd, uimm, csr } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rd, csr_name(csr), uimm)
+            }
+            Instruction::Jal { rd, imm } => write!(f, "{} {}, {}", mnemonic, rd, target(imm)),
+            Instruction::Add { rd, rs1, rs2 }
+            | Instruction::Sub { rd, rs1, rs2 }
+            | Instruction::Sll { rd, rs1, rs2 }
+            | Instruction::Slt { rd, rs1, rs2 }
+            | Instruction::Sltu { rd, rs1, rs2 }
+            | Instruction::Xor { rd, rs1, rs2 }
+            | Instruction::Srl { rd, rs1, rs2 }
+            | Instruction::Sra { rd, rs1, rs2 }
+            | Instruction::Or { rd, rs1, rs2 }
+            | Instruction::And { rd, rs1, rs2 }
+            | Instruction::Addw { rd, rs1, rs2 }
+            | Instruction::Subw { rd, rs1, rs2 }
+            | Instruction::Sllw { rd, rs1, rs2 }
+            | Instruction::Srlw { rd, rs1, rs2 }
+            | Instruction::Sraw { rd, rs1, rs2 }
+            | Instruction::Mul { rd, rs1, rs2 }
+            | Instruction::Mulh { rd, rs1, rs2 }
+            | Instruction::Mulhsu { rd, rs1, rs2 }
+            | Instruction::Mulhu { rd, rs1, rs2 }
+            | Instruction::Div { rd, rs1, rs2 }
+            | Instruction::Divu { rd, rs1, rs2 }
+            | Instruction::Rem { rd, rs1, rs2 }
+            | Instruction::Remu { rd, rs1, rs2 }
+            | Instruction::Mulw { rd, rs1, rs2 }
+            | Instruction::Divw { rd, rs1, rs2 }
+            | Instruction::Divuw { rd, rs1, rs2 }
+            | Instruction::Remw { rd, rs1, rs2 }
+            | Instruction::Remuw { rd, rs1, rs2 } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rd, rs1, rs2)
+            }
+            Instruction::LrW { rd, rs1, aq, rl } | Instruction::LrD { rd, rs1, aq, rl } => {
+                write!(f, "{}{} {}, ({})", mnemonic, ordering(aq, rl), rd, rs1)
+            }
+            Instruction::ScW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoswapW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoaddW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoxorW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoandW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoorW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmominW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmomaxW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmominuW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmomaxuW {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::ScD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoswapD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoaddD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoxorD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoandD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmoorD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmominD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmomaxD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmominuD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            }
+            | Instruction::AmomaxuD {
+                rd,
+                rs1,
+                rs2,
+                aq,
+                rl,
+            } => write!(
+                f,
+                "{}{} {}, {}, ({})",
+                mnemonic,
+                ordering(aq, rl),
+                rd,
+                rs2,
+                rs1
+            ),
+            Instruction::Flw { rd, rs1, imm } | Instruction::Fld { rd, rs1, imm } => {
+                write!(f, "{} {}, {}({})", mnemonic, rd, imm, rs1)
+            }
+            Instruction::Fsw { rs2, rs1, imm } | Instruction::Fsd { rs2, rs1, imm } => {
+                write!(f, "{} {}, {}({})", mnemonic, rs2, imm, rs1)
+            }
+            Instruction::FmaddS {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            }
+            | Instruction::FmsubS {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            }
+            | Instruction::FnmsubS {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            }
+            | Instruction::FnmaddS {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            }
+            | Instruction::FmaddD {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            }
+            | Instruction::FmsubD {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            }
+            | Instruction::FnmsubD {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            }
+            | Instruction::FnmaddD {
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rm,
+            } => write!(
+                f,
+                "{} {}, {}, {}, {}{}",
+                mnemonic,
+                rd,
+                rs1,
+                rs2,
+                rs3,
+                rounding(rm)
+            ),
+            Instruction::FaddS { rd, rs1, rs2, rm }
+            | Instruction::FsubS { rd, rs1, rs2, rm }
+            | Instruction::FmulS { rd, rs1, rs2, rm }
+            | Instruction::FdivS { rd, rs1, rs2, rm }
+            | Instruction::FaddD { rd, rs1, rs2, rm }
+            | Instruction::FsubD { rd, rs1, rs2, rm }
+            | Instruction::FmulD { rd, rs1, rs2, rm }
+            | Instruction::FdivD { rd, rs1, rs2, rm } => {
+                write!(f, "{} {}, {}, {}{}", mnemonic, rd, rs1, rs2, rounding(rm))
+            }
+            Instruction::FsqrtS { rd, rs1, rm }
+            | Instruction::FsqrtD { rd, rs1, rm }
+            | Instruction::FcvtSD { rd, rs1, rm }
+            | Instruction::FcvtDS { rd, rs1, rm } => {
+                write!(f, "{} {}, {}{}", mnemonic, rd, rs1, rounding(rm))
+            }
+            Instruction::FsgnjS { rd, rs1, rs2 }
+            | Instruction::FsgnjnS { rd, rs1, rs2 }
+            | Instruction::FsgnjxS { rd, rs1, rs2 }
+            | Instruction::FminS { rd, rs1, rs2 }
+            | Instruction::FmaxS { rd, rs1, rs2 }
+            | Instruction::FsgnjD { rd, rs1, rs2 }
+            | Instruction::FsgnjnD { rd, rs1, rs2 }
+            | Instruction::FsgnjxD { rd, rs1, rs2 }
+            | Instruction::FminD { rd, rs1, rs2 }
+            | Instruction::FmaxD { rd, rs1, rs2 } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rd, rs1, rs2)
+            }
+            Instruction::FeqS { rd, rs1, rs2 }
+            | Instruction::FltS { rd, rs1, rs2 }
+            | Instruction::FleS { rd, rs1, rs2 }
+            | Instruction::FeqD { rd, rs1, rs2 }
+            | Instruction::FltD { rd, rs1, rs2 }
+            | Instruction::FleD { rd, rs1, rs2 } => {
+                write!(f, "{} {}, {}, {}", mnemonic, rd, rs1, rs2)
+            }
+            Instruction::FclassS { rd, rs1 }
+            | Instruction::FmvXW { rd, rs1 }
+            | Instruction::FclassD { rd, rs1 }
+            | Instruction::FmvXD { rd, rs1 } => write!(f, "{} {}, {}", mnemonic, rd, rs1),
+            Instruction::FcvtWS { rd, rs1, rm }
+            | Instruction::FcvtWuS { rd, rs1, rm }
+            | Instruction::FcvtLS { rd, rs1, rm }
+            | Instruction::FcvtLuS { rd, rs1, rm }
+            | Instruction::FcvtWD { rd, rs1, rm }
+            | Instruction::FcvtWuD { rd, rs1, rm }
+            | Instruction::FcvtLD { rd, rs1, rm }
+            | Instruction::FcvtLuD { rd, rs1, rm } => {
+                write!(f, "{} {}, {}{}", mnemonic, rd, rs1, rounding(rm))
+            }
+            Instruction::FcvtSW { rd, rs1, rm }
+            | Instruction::FcvtSWu { rd, rs1, rm }
+            | Instruction::FcvtSL { rd, rs1, rm }
+            | Instruction::FcvtSLu { rd, rs1, rm }
+            | Instruction::FcvtDW { rd, rs1, rm }
+            | Instruction::FcvtDWu { rd, rs1, rm }
+            | Instruction::FcvtDL { rd, rs1, rm }
+            | Instruction::FcvtDLu { rd, rs1, rm } => {
+                write!(f, "{} {}, {}{}", mnemonic, rd, rs1, rounding(rm))
+            }
+            Instruction::FmvWX { rd, rs1 } | Instruction::FmvDX { rd, rs1 } => {
+                write!(f, "{} {}, {}", mnemonic, rd, rs1)
+            }
+            Instruction::Sb { rs2, rs1, imm }
+            | Instruction::Sh { rs2, rs1, imm }
+            | Instruction::Sw { rs2, rs1, imm }
+            | Instruction::Sd { rs2, rs1, imm } => {
+                write!(f, "{} {}, {}({})", mnemonic, rs2, imm, rs1)
+            }
+            Instruction::Auipc { rd, imm } | Instruction::Lui { rd, imm } => {
+                write!(f, "{} {}, {:#x}", mnemonic, rd, imm & 0xfffff)
+            }
+            Instruction::Undefined => write!(f, "{}", mnemonic),
+        }
+    }
+}
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Disassembly {
+            instruction: self,
+            address: None,
+            symbols: None,
+        }
+        .fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::elf::Symbol;
+    use crate::riscv::instruction;
+
+    #[test]
+    fn display() {
+        let cases = [
+            (0x0050_0513, "li a0, 5"),
+            (0xffd5_8513, "addi a0, a1, -3"),
+            (0x0000_0013, "nop"),
+            (0x0004_8513, "mv a0, s1"),
+            (0x0005_851b, "sext.w a0, a1"),
+            (0xfff3_4293, "not t0, t1"),
+            (0x0015_b513, "seqz a0, a1"),
+            (0x40b0_0533, "neg a0, a1"),
+            (0x00b0_3533, "snez a0, a1"),
+            (0x0035_1513, "slli a0, a0, 3"),
+            (0x41f5_d51b, "sraiw a0, a1, 31"),
+            (0x40c5_8533, "sub a0, a1, a2"),
+            (0x0349_a933, "mulhsu s2, s3, s4"),
+            (0xff81_2503, "lw a0, -8(sp)"),
+            (0x0011_3c23, "sd ra, 24(sp)"),
+            (0x0005_3403, "ld s0, 0(a0)"),
+            (0x1234_5537, "lui a0, 0x12345"),
+            (0xffff_f537, "lui a0, 0xfffff"),
+            (0x0000_1317, "auipc t1, 0x1"),
+            (0x0000_8067, "ret"),
+            (0x0007_8067, "jr a5"),
+            (0x0007_80e7, "jalr a5"),
+            (0x0105_82e7, "jalr t0, 16(a1)"),
+            (0x3000_2573, "csrr a0, mstatus"),
+            (0x3052_9073, "csrw mtvec, t0"),
+            (0x3045_a073, "csrs mie, a1"),
+            (0x3405_1573, "csrrw a0, mscratch, a0"),
+            (0x1001_6073, "csrsi sstatus, 2"),
+            (0x7c00_d073, "csrwi 0x7c0, 1"),
+            (0x0000_0073, "ecall"),
+            (0x0010_0073, "ebreak"),
+            (0x3020_0073, "mret"),
+            (0x1050_0073, "wfi"),
+            (0x1200_0073, "sfence.vma"),
+            (0x1205_0073, "sfence.vma a0"),
+            (0x12b5_0073, "sfence.vma a0, a1"),
+            (0x0ff0_000f, "fence"),
+            (0x0310_000f, "fence rw, w"),
+            (0x8330_000f, "fence.tso"),
+            (0x0000_100f, "fence.i"),
+            (0x1005_a52f, "lr.w a0, (a1)"),
+            (0x1ec5_b52f, "sc.d.aqrl a0, a2, (a1)"),
+            (0x04c5_a52f, "amoadd.w.aq a0, a2, (a1)"),
+            (0x00c5_f553, "fadd.s fa0, fa1, fa2"),
+            (0x02c5_9553, "fadd.d fa0, fa1, fa2, rtz"),
+            (0x6ac5_f543, "fmadd.d fa0, fa1, fa2, fa3"),
+            (0x5a00_f053, "fsqrt.d ft0, ft1"),
+            (0x22b5_8553, "fmv.d fa0, fa1"),
+            (0x20b5_9553, "fneg.s fa0, fa1"),
+            (0x22b5_a553, "fabs.d fa0, fa1"),
+            (0xc205_1553, "fcvt.w.d a0, fa0, rtz"),
+            (0xd235_7553, "fcvt.d.lu fa0, a0"),
+            (0xe205_0553, "fmv.x.d a0, fa0"),
+            (0xe000_1553, "fclass.s a0, ft0"),
+            (0xa2b5_2553, "feq.d a0, fa0, fa1"),
+            (0x0045_2007, "flw ft0, 4(a0)"),
+            (0xfe81_3827, "fsd fs0, -16(sp)"),
+            // C.MV
+            (0x842a, "mv s0, a0"),
+            (0x0000_0000, "unimp"),
+        ];
+        for &(encoded, text) in cases.iter() {
+            assert_eq!(instruction::decode(encoded).to_string(), text);
+        }
+    }
+    #[test]
+    fn targets() {
+        // beqz a0, .+8 and j .-4 and jal .+0x100, as found at 0x1000
+        let beqz = instruction::decode(0x0005_0463);
+        let j = instruction::decode(0xffdf_f06f);
+        let jal = instruction::decode(0x1000_00ef);
+        assert_eq!(beqz.to_string(), "beqz a0, .+8");
+        assert_eq!(j.to_string(), "j .-4");
+        assert_eq!(jal.disassemble(0x1000, None).to_string(), "jal 0x1100");
+        let symbols = SymbolTable::new(vec![
+            Symbol {
+                name: "loop".to_string(),
+                address: 0xff8,
+                size: 0x10,
+            },
+            Symbol {
+                name: "done".to_string(),
+                address: 0x1008,
+                size: 4,
+            },
+        ]);
+        assert_eq!(
+            beqz.disassemble(0x1000, Some(&symbols)).to_string(),
+            "beqz a0, 0x1008 <done>"
+        );
+        assert_eq!(
+            j.disassemble(0x1000, Some(&symbols)).to_string(),
+            "j 0xffc <loop+0x4>"
+        );
+        assert_eq!(
+            jal.disassemble(0x1000, Some(&symbols)).to_string(),
+            "jal 0x1100"
+        );
+    }
+}
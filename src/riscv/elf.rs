@@ -0,0 +1,463 @@
+use crate::riscv::bus::BusError;
+use crate::riscv::cpu::Cpu;
+use std::convert::TryInto;
+use std::fmt;
+
+pub const EM_RISCV: u16 = 243;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+
+const PT_LOAD: u32 = 1;
+pub const PF_X: u32 = 0b001;
+pub const PF_R: u32 = 0b100;
+
+const SHT_SYMTAB: u32 = 2;
+const ELF64_SYM_SIZE: usize = 24;
+
+#[derive(Debug, PartialEq)]
+pub enum ElfError {
+    // The file ends before a structure it describes
+    Truncated,
+    NotElf,
+    NotElf64,
+    NotLittleEndian,
+    WrongMachine(u16),
+    // A PT_LOAD segment does not fit in guest memory
+    Unmapped(BusError),
+}
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElfError::Truncated => write!(f, "the file is truncated"),
+            ElfError::NotElf => write!(f, "not an ELF file"),
+            ElfError::NotElf64 => write!(f, "not a 64-bit ELF file"),
+            ElfError::NotLittleEndian => write!(f, "not little-endian"),
+            ElfError::WrongMachine(machine) => {
+                write!(f, "built for machine {}, not RISC-V", machine)
+            }
+            ElfError::Unmapped(error) => write!(f, "a segment doesn't fit in memory: {}", error),
+        }
+    }
+}
+impl std::error::Error for ElfError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ElfError::Unmapped(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<BusError> for ElfError {
+    fn from(error: BusError) -> ElfError {
+        ElfError::Unmapped(error)
+    }
+}
+
+// A PT_LOAD segment as it was mapped into guest memory
+#[derive(Debug, PartialEq)]
+pub struct Segment {
+    pub address: u64,
+    pub size: u64,
+    pub flags: u32,
+}
+impl Segment {
+    pub fn is_executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+    pub fn contains(&self, address: u64) -> bool {
+        address >= self.address && address - self.address < self.size
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub address: u64,
+    pub size: u64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SymbolTable {
+    // Sorted by address
+    symbols: Vec<Symbol>,
+}
+impl SymbolTable {
+    pub fn new(mut symbols: Vec<Symbol>) -> Self {
+        symbols.sort_by_key(|symbol| symbol.address);
+        Self { symbols }
+    }
+    pub fn lookup(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+    // Find the symbol covering an address and the offset of the address into it
+    pub fn symbolize(&self, address: u64) -> Option<(&Symbol, u64)> {
+        let index = self
+            .symbols
+            .iter()
+            .rposition(|symbol| symbol.address <= address)?;
+        let symbol = &self.symbols[index];
+        let offset = address - symbol.address;
+        if offset == 0 || offset < symbol.size {
+            Some((symbol, offset))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Elf {
+    // Where the hart starts, at the placement segments were loaded at
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+    // Where the program headers ended up in guest memory, if a segment
+    // loaded them, and their number and size
+    pub program_headers: Option<u64>,
+    pub program_header_count: u64,
+    pub program_header_size: u64,
+    pub symbols: SymbolTable,
+}
+
+// Which address of each segment it is loaded at. Programs see virtual
+// addresses, which are also where they run from without an MMU, while a
+// system image such as a kernel linked at a high virtual address is loaded
+// at its physical addresses, and enters at the physical entry point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Placement {
+    Virtual,
+    Physical,
+}
+
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(&ELF_MAGIC)
+}
+
+// Map every PT_LOAD segment of an ELF64 RISC-V executable into guest memory
+// and point the hart at its entry point
+pub fn load(bytes: &[u8], cpu: &mut Cpu, placement: Placement) -> Result<Elf, ElfError> {
+    if !is_elf(bytes) {
+        return Err(ElfError::NotElf);
+    }
+    if read_u8(bytes, 4)? != ELFCLASS64 {
+        return Err(ElfError::NotElf64);
+    }
+    if read_u8(bytes, 5)? != ELFDATA2LSB {
+        return Err(ElfError::NotLittleEndian);
+    }
+    let machine = read_u16(bytes, 18)?;
+    if machine != EM_RISCV {
+        return Err(ElfError::WrongMachine(machine));
+    }
+    let virtual_entry = read_u64(bytes, 24)?;
+    let mut entry = virtual_entry;
+    let phoff = read_u64(bytes, 32)? as usize;
+    let shoff = read_u64(bytes, 40)? as usize;
+    let phentsize = read_u16(bytes, 54)? as usize;
+    let phnum = read_u16(bytes, 56)? as usize;
+    let shentsize = read_u16(bytes, 58)? as usize;
+    let shnum = read_u16(bytes, 60)? as usize;
+
+    let mut segments = Vec::new();
+    let mut program_headers = None;
+    let headers_end = phoff.saturating_add(phnum * phentsize);
+    for header in (0..phnum).map(|i| position(bytes, phoff, i * phentsize)) {
+        if read_u32(bytes, header)? != PT_LOAD {
+            continue;
+        }
+        let flags = read_u32(bytes, header + 4)?;
+        let offset = read_u64(bytes, header + 8)? as usize;
+        let virtual_address = read_u64(bytes, header + 16)?;
+        let physical_address = read_u64(bytes, header + 24)?;
+        let file_size = read_u64(bytes, header + 32)? as usize;
+        let memory_size = read_u64(bytes, header + 40)?;
+
+        if offset <= phoff && headers_end <= offset.saturating_add(file_size) {
+            program_headers = Some(virtual_address.wrapping_add((phoff - offset) as u64));
+        }
+        let address = match placement {
+            Placement::Virtual => virtual_address,
+            Placement::Physical => {
+                let in_segment = virtual_entry.wrapping_sub(virtual_address);
+                if in_segment < memory_size && physical_address != virtual_address {
+                    entry = physical_address.wrapping_add(in_segment);
+                }
+                physical_address
+            }
+        };
+        let contents = slice(bytes, offset, file_size)?;
+        cpu.bus.load(address, contents)?;
+        // The part of the segment not backed by the file (.bss) is zero
+        // filled, and can't be any bigger than memory
+        let bss = address.wrapping_add(file_size as u64);
+        let zeros = memory_size.saturating_sub(file_size as u64);
+        if zeros > cpu.bus.dram.size() {
+            return Err(ElfError::Unmapped(BusError::Unmapped(bss)));
+        }
+        cpu.bus.load(bss, &vec![0; zeros as usize])?;
+
+        segments.push(Segment {
+            address,
+            size: memory_size,
+            flags,
+        });
+    }
+
+    let mut symbols = Vec::new();
+    for header in (0..shnum).map(|i| position(bytes, shoff, i * shentsize)) {
+        if read_u32(bytes, header + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let offset = read_u64(bytes, header + 24)? as usize;
+        let size = read_u64(bytes, header + 32)? as usize;
+        // The associated string table is given by sh_link
+        let link = read_u32(bytes, header + 40)? as usize;
+        let strtab_header = position(bytes, shoff, link.saturating_mul(shentsize));
+        let strtab_offset = read_u64(bytes, strtab_header + 24)? as usize;
+        let strtab_size = read_u64(bytes, strtab_header + 32)? as usize;
+        let strtab = slice(bytes, strtab_offset, strtab_size)?;
+
+        // Entry 0 is the reserved undefined symbol
+        for entry in (ELF64_SYM_SIZE..size).step_by(ELF64_SYM_SIZE) {
+            let entry = position(bytes, offset, entry);
+            let name = read_u32(bytes, entry)? as usize;
+            let name = strtab.get(name..).ok_or(ElfError::Truncated)?;
+            let name = name.split(|&byte| byte == 0).next().unwrap_or_default();
+            if name.is_empty() {
+                continue;
+            }
+            symbols.push(Symbol {
+                name: String::from_utf8_lossy(name).into_owned(),
+                address: read_u64(bytes, entry + 8)?,
+                size: read_u64(bytes, entry + 16)?,
+            });
+        }
+    }
+
+    cpu.pc = entry;
+    Ok(Elf {
+        entry,
+        segments,
+        program_headers,
+        program_header_count: phnum as u64,
+        program_header_size: phentsize as u64,
+        symbols: SymbolTable::new(symbols),
+    })
+}
+
+// Offsets and sizes come straight from the file, so a position computed
+// from them is clamped to the end of the file, where reading any field
+// fails as truncated rather than overflowing
+fn position(bytes: &[u8], base: usize, offset: usize) -> usize {
+    base.saturating_add(offset).min(bytes.len())
+}
+fn slice(bytes: &[u8], offset: usize, size: usize) -> Result<&[u8], ElfError> {
+    let end = offset.checked_add(size).ok_or(ElfError::Truncated)?;
+    bytes.get(offset..end).ok_or(ElfError::Truncated)
+}
+fn read_u8(bytes: &[u8], offset: usize) -> Result<u8, ElfError> {
+    Ok(slice(bytes, offset, 1)?[0])
+}
+fn read_u16(bytes: &[u8], offset: usize) -> Result<u16, ElfError> {
+    Ok(u16::from_le_bytes(
+        slice(bytes, offset, 2)?.try_into().unwrap(),
+    ))
+}
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, ElfError> {
+    Ok(u32::from_le_bytes(
+        slice(bytes, offset, 4)?.try_into().unwrap(),
+    ))
+}
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, ElfError> {
+    Ok(u64::from_le_bytes(
+        slice(bytes, offset, 8)?.try_into().unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+
+    // Build an executable with a text segment, a .bss-like segment and a
+    // symbol table holding `_start`
+    fn build(machine: u16) -> Vec<u8> {
+        let text: [u8; 8] = [0x13, 0x05, 0x50, 0x00, 0x73, 0x00, 0x00, 0x00];
+        let strtab = b"\0_start\0buffer\0";
+        let phoff = 64;
+        let text_offset = phoff + 2 * 56;
+        let symtab_offset = text_offset + text.len();
+        let strtab_offset = symtab_offset + 3 * 24;
+        let shoff = strtab_offset + strtab.len();
+
+        let mut elf = Vec::new();
+        elf.extend_from_slice(&ELF_MAGIC);
+        elf.extend_from_slice(&[ELFCLASS64, ELFDATA2LSB, 1, 0]);
+        elf.extend_from_slice(&[0; 8]);
+        elf.extend_from_slice(&2u16.to_le_bytes());
+        elf.extend_from_slice(&machine.to_le_bytes());
+        elf.extend_from_slice(&1u32.to_le_bytes());
+        elf.extend_from_slice(&(DRAM_BASE + 4).to_le_bytes());
+        elf.extend_from_slice(&(phoff as u64).to_le_bytes());
+        elf.extend_from_slice(&(shoff as u64).to_le_bytes());
+        elf.extend_from_slice(&0u32.to_le_bytes());
+        elf.extend_from_slice(&64u16.to_le_bytes());
+        elf.extend_from_slice(&56u16.to_le_bytes());
+        elf.extend_from_slice(&2u16.to_le_bytes());
+        elf.extend_from_slice(&64u16.to_le_bytes());
+        elf.extend_from_slice(&3u16.to_le_bytes());
+        elf.extend_from_slice(&0u16.to_le_bytes());
+
+        let segments = [
+            (PF_R | PF_X, text_offset, DRAM_BASE, text.len(), text.len()),
+            (PF_R, 0, DRAM_BASE + 0x100, 0, 0x10),
+        ];
+        for &(flags, offset, address, file_size, memory_size) in segments.iter() {
+            elf.extend_from_slice(&PT_LOAD.to_le_bytes());
+            elf.extend_from_slice(&flags.to_le_bytes());
+            elf.extend_from_slice(&(offset as u64).to_le_bytes());
+            elf.extend_from_slice(&address.to_le_bytes());
+            elf.extend_from_slice(&address.to_le_bytes());
+            elf.extend_from_slice(&(file_size as u64).to_le_bytes());
+            elf.extend_from_slice(&(memory_size as u64).to_le_bytes());
+            elf.extend_from_slice(&0x1000u64.to_le_bytes());
+        }
+        elf.extend_from_slice(&text);
+
+        let symbols = [
+            (0, 0, 0),
+            (1, DRAM_BASE + 4, 4),
+            (8, DRAM_BASE + 0x100, 0x10),
+        ];
+        for &(name, address, size) in symbols.iter() {
+            elf.extend_from_slice(&(name as u32).to_le_bytes());
+            elf.extend_from_slice(&[0; 4]);
+            elf.extend_from_slice(&address.to_le_bytes());
+            elf.extend_from_slice(&(size as u64).to_le_bytes());
+        }
+        elf.extend_from_slice(strtab);
+
+        let sections = [
+            (0, 0, 0, 0),
+            (SHT_SYMTAB, symtab_offset, 3 * 24, 2),
+            (3, strtab_offset, strtab.len(), 0),
+        ];
+        for &(kind, offset, size, link) in sections.iter() {
+            elf.extend_from_slice(&0u32.to_le_bytes());
+            elf.extend_from_slice(&kind.to_le_bytes());
+            elf.extend_from_slice(&[0; 16]);
+            elf.extend_from_slice(&(offset as u64).to_le_bytes());
+            elf.extend_from_slice(&(size as u64).to_le_bytes());
+            elf.extend_from_slice(&(link as u32).to_le_bytes());
+            elf.extend_from_slice(&[0; 20]);
+        }
+        elf
+    }
+    #[test]
+    fn load_executable() {
+        let mut cpu = Cpu::new(Bus::new(0x1000));
+        cpu.bus.write_double(DRAM_BASE + 0x100, u64::MAX).unwrap();
+        let elf = load(&build(EM_RISCV), &mut cpu, Placement::Virtual).unwrap();
+
+        assert_eq!(cpu.pc, DRAM_BASE + 4);
+        assert_eq!(elf.entry, DRAM_BASE + 4);
+        // No segment covers the program headers
+        assert_eq!(elf.program_headers, None);
+        assert_eq!(elf.program_header_count, 2);
+        assert_eq!(cpu.bus.read_word(DRAM_BASE), Ok(0x00500513));
+        assert_eq!(cpu.bus.read_double(DRAM_BASE + 0x100), Ok(0));
+        assert_eq!(
+            elf.segments,
+            vec![
+                Segment {
+                    address: DRAM_BASE,
+                    size: 8,
+                    flags: PF_R | PF_X
+                },
+                Segment {
+                    address: DRAM_BASE + 0x100,
+                    size: 0x10,
+                    flags: PF_R
+                },
+            ]
+        );
+        assert_eq!(
+            elf.symbols.lookup("_start").map(|symbol| symbol.address),
+            Some(DRAM_BASE + 4)
+        );
+        let (symbol, offset) = elf.symbols.symbolize(DRAM_BASE + 0x108).unwrap();
+        assert_eq!((symbol.name.as_str(), offset), ("buffer", 8));
+        assert!(elf.symbols.symbolize(DRAM_BASE + 0x110).is_none());
+    }
+    #[test]
+    fn load_at_virtual_or_physical_addresses() {
+        // Link both segments at 0x1000 onwards, keeping their physical
+        // addresses in DRAM
+        let mut elf = build(EM_RISCV);
+        elf[24..32].copy_from_slice(&0x1004u64.to_le_bytes());
+        for (segment, address) in [0x1000u64, 0x1100].iter().enumerate() {
+            let header = 64 + 56 * segment + 16;
+            elf[header..header + 8].copy_from_slice(&address.to_le_bytes());
+        }
+
+        let mut bus = Bus::new(0x2000);
+        bus.dram_base = 0;
+        let mut cpu = Cpu::new(bus);
+        let loaded = load(&elf, &mut cpu, Placement::Virtual).unwrap();
+        assert_eq!(cpu.pc, 0x1004);
+        assert_eq!(loaded.segments[0].address, 0x1000);
+        assert_eq!(cpu.bus.read_word(0x1000), Ok(0x00500513));
+
+        let mut cpu = Cpu::new(Bus::new(0x1000));
+        let loaded = load(&elf, &mut cpu, Placement::Physical).unwrap();
+        assert_eq!(cpu.pc, DRAM_BASE + 4);
+        assert_eq!(loaded.entry, DRAM_BASE + 4);
+        assert_eq!(loaded.segments[1].address, DRAM_BASE + 0x100);
+        assert_eq!(cpu.bus.read_word(DRAM_BASE), Ok(0x00500513));
+    }
+    #[test]
+    fn load_rejects_other_machines() {
+        let mut cpu = Cpu::new(Bus::new(0x1000));
+        // EM_X86_64
+        assert_eq!(
+            load(&build(62), &mut cpu, Placement::Virtual).err(),
+            Some(ElfError::WrongMachine(62))
+        );
+        assert_eq!(
+            load(b"\x7fELF", &mut cpu, Placement::Virtual).err(),
+            Some(ElfError::Truncated)
+        );
+        assert_eq!(
+            load(&[0; 64], &mut cpu, Placement::Virtual).err(),
+            Some(ElfError::NotElf)
+        );
+    }
+    #[test]
+    fn load_rejects_unmapped_segments() {
+        let mut cpu = Cpu::new(Bus::new(0x10));
+        assert_eq!(
+            load(&build(EM_RISCV), &mut cpu, Placement::Virtual).err(),
+            Some(ElfError::Unmapped(BusError::Unmapped(DRAM_BASE + 0x100)))
+        );
+    }
+    #[test]
+    fn load_rejects_malformed_headers() {
+        let mut cpu = Cpu::new(Bus::new(0x1000));
+        // p_memsz of the second segment, far bigger than memory
+        let mut elf = build(EM_RISCV);
+        elf[64 + 56 + 40..64 + 56 + 48].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(
+            load(&elf, &mut cpu, Placement::Virtual).err(),
+            Some(ElfError::Unmapped(BusError::Unmapped(DRAM_BASE + 0x100)))
+        );
+        // e_phoff and e_shoff at the end of the address space
+        for field in [32, 40].iter() {
+            let mut elf = build(EM_RISCV);
+            elf[*field..*field + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+            assert_eq!(
+                load(&elf, &mut cpu, Placement::Virtual).err(),
+                Some(ElfError::Truncated)
+            );
+        }
+    }
+}
@@ -0,0 +1,631 @@
+// Encoding of instructions back into machine code, the inverse of
+// instruction::decode. Instructions are always encoded in 32 bits, even
+// those the decoder produces from compressed ones.
+use crate::riscv::cpu::{FRegister, Register};
+use crate::riscv::instruction::Instruction;
+
+// Major opcodes
+const LOAD: u32 = 0b0000011;
+const LOAD_FP: u32 = 0b0000111;
+const MISC_MEM: u32 = 0b0001111;
+const OP_IMM: u32 = 0b0010011;
+const AUIPC: u32 = 0b0010111;
+const OP_IMM_32: u32 = 0b0011011;
+const STORE: u32 = 0b0100011;
+const STORE_FP: u32 = 0b0100111;
+const AMO: u32 = 0b0101111;
+const OP: u32 = 0b0110011;
+const LUI: u32 = 0b0110111;
+const OP_32: u32 = 0b0111011;
+const MADD: u32 = 0b1000011;
+const MSUB: u32 = 0b1000111;
+const NMSUB: u32 = 0b1001011;
+const NMADD: u32 = 0b1001111;
+const OP_FP: u32 = 0b1010011;
+const BRANCH: u32 = 0b1100011;
+const JALR: u32 = 0b1100111;
+const JAL: u32 = 0b1101111;
+const SYSTEM: u32 = 0b1110011;
+
+// Bit 30, set in the immediate of right shifts that are arithmetic
+const ARITHMETIC_SHIFT: u32 = 1 << 10;
+
+fn x(register: Register) -> u32 {
+    usize::from(register) as u32
+}
+fn f(register: FRegister) -> u32 {
+    usize::from(register) as u32
+}
+
+fn r_type(opcode: u32, funct3: u32, funct7: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    funct7 << 25 | rs2 << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+fn r4_type(opcode: u32, fmt: u32, rd: u32, rs1: u32, rs2: u32, rs3: u32, rm: u32) -> u32 {
+    rs3 << 27 | fmt << 25 | rs2 << 20 | rs1 << 15 | rm << 12 | rd << 7 | opcode
+}
+// Atomics split funct7 into funct5 and the ordering bits
+fn amo_type(funct3: u32, funct5: u32, aq: bool, rl: bool, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    let funct7 = funct5 << 2 | (aq as u32) << 1 | rl as u32;
+    r_type(AMO, funct3, funct7, rd, rs1, rs2)
+}
+fn i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+    (imm as u32 & 0xfff) << 20 | rs1 << 15 | funct3 << 12 | rd << 7 | opcode
+}
+fn s_type(opcode: u32, funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0b1111111) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | (imm & 0b11111) << 7
+        | opcode
+}
+fn b_type(funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0b111111) << 25
+        | rs2 << 20
+        | rs1 << 15
+        | funct3 << 12
+        | (imm >> 1 & 0b1111) << 8
+        | (imm >> 11 & 1) << 7
+        | BRANCH
+}
+// The immediate is the 20-bit upper immediate field, as decoded
+fn u_type(opcode: u32, rd: u32, imm: i32) -> u32 {
+    (imm as u32 & 0xfffff) << 12 | rd << 7 | opcode
+}
+fn j_type(rd: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0b11_1111_1111) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0b1111_1111) << 12
+        | rd << 7
+        | JAL
+}
+
+// Encode an instruction. Immediates and offsets are truncated to the
+// width of their fields.
+pub fn encode(instruction: &Instruction) -> u32 {
+    match *instruction {
+        // Decodes as the all-zero compressed instruction, which is defined
+        // to be illegal
+        Instruction::Undefined => 0,
+        Instruction::Beq { rs1, rs2, imm } => b_type(0b000, x(rs1), x(rs2), imm),
+        Instruction::Bne { rs1, rs2, imm } => b_type(0b001, x(rs1), x(rs2), imm),
+        Instruction::Blt { rs1, rs2, imm } => b_type(0b100, x(rs1), x(rs2), imm),
+        Instruction::Bge { rs1, rs2, imm } => b_type(0b101, x(rs1), x(rs2), imm),
+        Instruction::Bltu { rs1, rs2, imm } => b_type(0b110, x(rs1), x(rs2), imm),
+        Instruction::Bgeu { rs1, rs2, imm } => b_type(0b111, x(rs1), x(rs2), imm),
+        Instruction::Lb { rd, rs1, imm } => i_type(LOAD, 0b000, x(rd), x(rs1), imm),
+        Instruction::Lh { rd, rs1, imm } => i_type(LOAD, 0b001, x(rd), x(rs1), imm),
+        Instruction::Lw { rd, rs1, imm } => i_type(LOAD, 0b010, x(rd), x(rs1), imm),
+        Instruction::Lbu { rd, rs1, imm } => i_type(LOAD, 0b100, x(rd), x(rs1), imm),
+        Instruction::Lhu { rd, rs1, imm } => i_type(LOAD, 0b101, x(rd), x(rs1), imm),
+        Instruction::Lwu { rd, rs1, imm } => i_type(LOAD, 0b110, x(rd), x(rs1), imm),
+        Instruction::Ld { rd, rs1, imm } => i_type(LOAD, 0b011, x(rd), x(rs1), imm),
+        Instruction::Fence {
+            rd,
+            rs1,
+            succ,
+            pred,
+            fm,
+        } => i_type(
+            MISC_MEM,
+            0b000,
+            x(rd),
+            x(rs1),
+            (fm << 8 | pred << 4 | succ) as i32,
+        ),
+        Instruction::FenceI { rd, rs1, imm } => i_type(MISC_MEM, 0b001, x(rd), x(rs1), imm),
+        Instruction::Addi { rd, rs1, imm } => i_type(OP_IMM, 0b000, x(rd), x(rs1), imm),
+        Instruction::Slti { rd, rs1, imm } => i_type(OP_IMM, 0b010, x(rd), x(rs1), imm),
+        Instruction::Sltiu { rd, rs1, imm } => i_type(OP_IMM, 0b011, x(rd), x(rs1), imm),
+        Instruction::Xori { rd, rs1, imm } => i_type(OP_IMM, 0b100, x(rd), x(rs1), imm),
+        Instruction::Ori { rd, rs1, imm } => i_type(OP_IMM, 0b110, x(rd), x(rs1), imm),
+        Instruction::Andi { rd, rs1, imm } => i_type(OP_IMM, 0b111, x(rd), x(rs1), imm),
+        Instruction::Slli { rd, rs1, shamt } => i_type(OP_IMM, 0b001, x(rd), x(rs1), shamt as i32),
+        Instruction::Srli { rd, rs1, shamt } => i_type(OP_IMM, 0b101, x(rd), x(rs1), shamt as i32),
+        Instruction::Srai { rd, rs1, shamt } => i_type(
+            OP_IMM,
+            0b101,
+            x(rd),
+            x(rs1),
+            (ARITHMETIC_SHIFT | shamt) as i32,
+        ),
+        Instruction::Addiw { rd, rs1, imm } => i_type(OP_IMM_32, 0b000, x(rd), x(rs1), imm),
+        Instruction::Slliw { rd, rs1, shamt } => {
+            i_type(OP_IMM_32, 0b001, x(rd), x(rs1), shamt as i32)
+        }
+        Instruction::Srliw { rd, rs1, shamt } => {
+            i_type(OP_IMM_32, 0b101, x(rd), x(rs1), shamt as i32)
+        }
+        Instruction::Sraiw { rd, rs1, shamt } => i_type(
+            OP_IMM_32,
+            0b101,
+            x(rd),
+            x(rs1),
+            (ARITHMETIC_SHIFT | shamt) as i32,
+        ),
+        Instruction::Jalr { rd, rs1, imm } => i_type(JALR, 0b000, x(rd), x(rs1), imm),
+        Instruction::Ebreak => i_type(SYSTEM, 0b000, 0, 0, 0x001),
+        Instruction::Ecall => i_type(SYSTEM, 0b000, 0, 0, 0x000),
+        Instruction::Mret => i_type(SYSTEM, 0b000, 0, 0, 0x302),
+        Instruction::Sret => i_type(SYSTEM, 0b000, 0, 0, 0x102),
+        Instruction::Wfi => i_type(SYSTEM, 0b000, 0, 0, 0x105),
+        Instruction::SfenceVma { rs1, rs2 } => r_type(SYSTEM, 0b000, 0b0001001, 0, x(rs1), x(rs2)),
+        Instruction::Csrrw { rd, rs1, csr } => i_type(SYSTEM, 0b001, x(rd), x(rs1), csr as i32),
+        Instruction::Csrrs { rd, rs1, csr } => i_type(SYSTEM, 0b010, x(rd), x(rs1), csr as i32),
+        Instruction::Csrrc { rd, rs1, csr } => i_type(SYSTEM, 0b011, x(rd), x(rs1), csr as i32),
+        Instruction::Csrrwi { rd, uimm, csr } => i_type(SYSTEM, 0b101, x(rd), uimm, csr as i32),
+        Instruction::Csrrsi { rd, uimm, csr } => i_type(SYSTEM, 0b110, x(rd), uimm, csr as i32),
+        Instruction::Csrrci { rd, uimm, csr } => i_type(SYSTEM, 0b111, x(rd), uimm, csr as i32),
+        Instruction::Jal { rd, imm } => j_type(x(rd), imm),
+        Instruction::Add { rd, rs1, rs2 } => r_type(OP, 0b000, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::Sub { rd, rs1, rs2 } => r_type(OP, 0b000, 0b0100000, x(rd), x(rs1), x(rs2)),
+        Instruction::Sll { rd, rs1, rs2 } => r_type(OP, 0b001, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::Slt { rd, rs1, rs2 } => r_type(OP, 0b010, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::Sltu { rd, rs1, rs2 } => r_type(OP, 0b011, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::Xor { rd, rs1, rs2 } => r_type(OP, 0b100, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::Srl { rd, rs1, rs2 } => r_type(OP, 0b101, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::Sra { rd, rs1, rs2 } => r_type(OP, 0b101, 0b0100000, x(rd), x(rs1), x(rs2)),
+        Instruction::Or { rd, rs1, rs2 } => r_type(OP, 0b110, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::And { rd, rs1, rs2 } => r_type(OP, 0b111, 0b0000000, x(rd), x(rs1), x(rs2)),
+        Instruction::Addw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b000, 0b0000000, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Subw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b000, 0b0100000, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Sllw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b001, 0b0000000, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Srlw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b101, 0b0000000, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Sraw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b101, 0b0100000, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Mul { rd, rs1, rs2 } => r_type(OP, 0b000, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Mulh { rd, rs1, rs2 } => r_type(OP, 0b001, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Mulhsu { rd, rs1, rs2 } => r_type(OP, 0b010, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Mulhu { rd, rs1, rs2 } => r_type(OP, 0b011, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Div { rd, rs1, rs2 } => r_type(OP, 0b100, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Divu { rd, rs1, rs2 } => r_type(OP, 0b101, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Rem { rd, rs1, rs2 } => r_type(OP, 0b110, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Remu { rd, rs1, rs2 } => r_type(OP, 0b111, 0b0000001, x(rd), x(rs1), x(rs2)),
+        Instruction::Mulw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b000, 0b0000001, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Divw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b100, 0b0000001, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Divuw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b101, 0b0000001, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Remw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b110, 0b0000001, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::Remuw { rd, rs1, rs2 } => {
+            r_type(OP_32, 0b111, 0b0000001, x(rd), x(rs1), x(rs2))
+        }
+        Instruction::LrW { rd, rs1, aq, rl } => amo_type(0b010, 0b00010, aq, rl, x(rd), x(rs1), 0),
+        Instruction::ScW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b00011, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoswapW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b00001, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoaddW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b00000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoxorW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b00100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoandW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b01100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoorW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b01000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmominW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b10000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmomaxW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b10100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmominuW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b11000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmomaxuW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b010, 0b11100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::LrD { rd, rs1, aq, rl } => amo_type(0b011, 0b00010, aq, rl, x(rd), x(rs1), 0),
+        Instruction::ScD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b00011, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoswapD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b00001, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoaddD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b00000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoxorD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b00100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoandD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b01100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmoorD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b01000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmominD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b10000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmomaxD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b10100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmominuD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b11000, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::AmomaxuD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => amo_type(0b011, 0b11100, aq, rl, x(rd), x(rs1), x(rs2)),
+        Instruction::Flw { rd, rs1, imm } => i_type(LOAD_FP, 0b010, f(rd), x(rs1), imm),
+        Instruction::Fsw { rs2, rs1, imm } => s_type(STORE_FP, 0b010, x(rs1), f(rs2), imm),
+        Instruction::FmaddS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(MADD, 0b00, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FmsubS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(MSUB, 0b00, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FnmsubS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(NMSUB, 0b00, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FnmaddS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(NMADD, 0b00, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FaddS { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0000000, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsubS { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0000100, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FmulS { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0001000, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FdivS { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0001100, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsqrtS { rd, rs1, rm } => r_type(OP_FP, rm, 0b0101100, f(rd), f(rs1), 0),
+        Instruction::FsgnjS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b000, 0b0010000, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsgnjnS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b001, 0b0010000, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsgnjxS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b010, 0b0010000, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FminS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b000, 0b0010100, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FmaxS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b001, 0b0010100, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FeqS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b010, 0b1010000, x(rd), f(rs1), f(rs2))
+        }
+        Instruction::FltS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b001, 0b1010000, x(rd), f(rs1), f(rs2))
+        }
+        Instruction::FleS { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b000, 0b1010000, x(rd), f(rs1), f(rs2))
+        }
+        Instruction::FclassS { rd, rs1 } => r_type(OP_FP, 0b001, 0b1110000, x(rd), f(rs1), 0),
+        Instruction::FcvtWS { rd, rs1, rm } => r_type(OP_FP, rm, 0b1100000, x(rd), f(rs1), 0b00000),
+        Instruction::FcvtWuS { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1100000, x(rd), f(rs1), 0b00001)
+        }
+        Instruction::FcvtLS { rd, rs1, rm } => r_type(OP_FP, rm, 0b1100000, x(rd), f(rs1), 0b00010),
+        Instruction::FcvtLuS { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1100000, x(rd), f(rs1), 0b00011)
+        }
+        Instruction::FcvtSW { rd, rs1, rm } => r_type(OP_FP, rm, 0b1101000, f(rd), x(rs1), 0b00000),
+        Instruction::FcvtSWu { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1101000, f(rd), x(rs1), 0b00001)
+        }
+        Instruction::FcvtSL { rd, rs1, rm } => r_type(OP_FP, rm, 0b1101000, f(rd), x(rs1), 0b00010),
+        Instruction::FcvtSLu { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1101000, f(rd), x(rs1), 0b00011)
+        }
+        Instruction::FmvXW { rd, rs1 } => r_type(OP_FP, 0b000, 0b1110000, x(rd), f(rs1), 0),
+        Instruction::FmvWX { rd, rs1 } => r_type(OP_FP, 0b000, 0b1111000, f(rd), x(rs1), 0),
+        Instruction::Fld { rd, rs1, imm } => i_type(LOAD_FP, 0b011, f(rd), x(rs1), imm),
+        Instruction::Fsd { rs2, rs1, imm } => s_type(STORE_FP, 0b011, x(rs1), f(rs2), imm),
+        Instruction::FmaddD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(MADD, 0b01, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FmsubD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(MSUB, 0b01, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FnmsubD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(NMSUB, 0b01, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FnmaddD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4_type(NMADD, 0b01, f(rd), f(rs1), f(rs2), f(rs3), rm),
+        Instruction::FaddD { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0000001, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsubD { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0000101, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FmulD { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0001001, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FdivD { rd, rs1, rs2, rm } => {
+            r_type(OP_FP, rm, 0b0001101, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsqrtD { rd, rs1, rm } => r_type(OP_FP, rm, 0b0101101, f(rd), f(rs1), 0),
+        Instruction::FsgnjD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b000, 0b0010001, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsgnjnD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b001, 0b0010001, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FsgnjxD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b010, 0b0010001, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FminD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b000, 0b0010101, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FmaxD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b001, 0b0010101, f(rd), f(rs1), f(rs2))
+        }
+        Instruction::FcvtSD { rd, rs1, rm } => r_type(OP_FP, rm, 0b0100000, f(rd), f(rs1), 0b00001),
+        Instruction::FcvtDS { rd, rs1, rm } => r_type(OP_FP, rm, 0b0100001, f(rd), f(rs1), 0b00000),
+        Instruction::FeqD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b010, 0b1010001, x(rd), f(rs1), f(rs2))
+        }
+        Instruction::FltD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b001, 0b1010001, x(rd), f(rs1), f(rs2))
+        }
+        Instruction::FleD { rd, rs1, rs2 } => {
+            r_type(OP_FP, 0b000, 0b1010001, x(rd), f(rs1), f(rs2))
+        }
+        Instruction::FclassD { rd, rs1 } => r_type(OP_FP, 0b001, 0b1110001, x(rd), f(rs1), 0),
+        Instruction::FcvtWD { rd, rs1, rm } => r_type(OP_FP, rm, 0b1100001, x(rd), f(rs1), 0b00000),
+        Instruction::FcvtWuD { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1100001, x(rd), f(rs1), 0b00001)
+        }
+        Instruction::FcvtLD { rd, rs1, rm } => r_type(OP_FP, rm, 0b1100001, x(rd), f(rs1), 0b00010),
+        Instruction::FcvtLuD { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1100001, x(rd), f(rs1), 0b00011)
+        }
+        Instruction::FcvtDW { rd, rs1, rm } => r_type(OP_FP, rm, 0b1101001, f(rd), x(rs1), 0b00000),
+        Instruction::FcvtDWu { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1101001, f(rd), x(rs1), 0b00001)
+        }
+        Instruction::FcvtDL { rd, rs1, rm } => r_type(OP_FP, rm, 0b1101001, f(rd), x(rs1), 0b00010),
+        Instruction::FcvtDLu { rd, rs1, rm } => {
+            r_type(OP_FP, rm, 0b1101001, f(rd), x(rs1), 0b00011)
+        }
+        Instruction::FmvXD { rd, rs1 } => r_type(OP_FP, 0b000, 0b1110001, x(rd), f(rs1), 0),
+        Instruction::FmvDX { rd, rs1 } => r_type(OP_FP, 0b000, 0b1111001, f(rd), x(rs1), 0),
+        Instruction::Sb { rs2, rs1, imm } => s_type(STORE, 0b000, x(rs1), x(rs2), imm),
+        Instruction::Sh { rs2, rs1, imm } => s_type(STORE, 0b001, x(rs1), x(rs2), imm),
+        Instruction::Sw { rs2, rs1, imm } => s_type(STORE, 0b010, x(rs1), x(rs2), imm),
+        Instruction::Sd { rs2, rs1, imm } => s_type(STORE, 0b011, x(rs1), x(rs2), imm),
+        Instruction::Auipc { rd, imm } => u_type(AUIPC, x(rd), imm),
+        Instruction::Lui { rd, imm } => u_type(LUI, x(rd), imm),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::instruction::decode;
+
+    #[test]
+    fn encode_known() {
+        let words = [
+            0x0050_0513,
+            0xffd5_8513,
+            0x0000_0013,
+            0x0004_8513,
+            0x0005_851b,
+            0xfff3_4293,
+            0x0015_b513,
+            0x40b0_0533,
+            0x00b0_3533,
+            0x0035_1513,
+            0x41f5_d51b,
+            0x40c5_8533,
+            0x0349_a933,
+            0xff81_2503,
+            0x0011_3c23,
+            0x0005_3403,
+            0x1234_5537,
+            0xffff_f537,
+            0x0000_1317,
+            0x0000_8067,
+            0x0007_8067,
+            0x0007_80e7,
+            0x0105_82e7,
+            0x3000_2573,
+            0x3052_9073,
+            0x3045_a073,
+            0x3405_1573,
+            0x1001_6073,
+            0x7c00_d073,
+            0x0000_0073,
+            0x0010_0073,
+            0x3020_0073,
+            0x1050_0073,
+            0x1200_0073,
+            0x1205_0073,
+            0x12b5_0073,
+            0x0ff0_000f,
+            0x0310_000f,
+            0x8330_000f,
+            0x1005_a52f,
+            0x1ec5_b52f,
+            0x04c5_a52f,
+            0x00c5_f553,
+            0x02c5_9553,
+            0x6ac5_f543,
+            0x5a00_f053,
+            0x22b5_8553,
+            0x20b5_9553,
+            0x22b5_a553,
+            0xc205_1553,
+            0xd235_7553,
+            0xe205_0553,
+            0xe000_1553,
+            0xa2b5_2553,
+            0x0045_2007,
+            0xfe81_3827,
+            // Branches and jumps with negative offsets
+            0xfe05_1ee3,
+            0xffdf_f06f,
+            0x8000_006f,
+        ];
+        for &word in words.iter() {
+            assert_eq!(encode(&decode(word)), word, "{:#010x}", word);
+        }
+    }
+    #[test]
+    fn round_trip() {
+        // Every word that decodes to an instruction encodes to one that
+        // decodes the same, compressed ones included
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        for _ in 0..1_000_000 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            let instruction = decode(state as u32);
+            if instruction != Instruction::Undefined {
+                assert_eq!(
+                    decode(encode(&instruction)),
+                    instruction,
+                    "{:#010x}",
+                    state as u32
+                );
+            }
+        }
+    }
+}
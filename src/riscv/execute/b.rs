@@ -1,9 +1,132 @@
 use crate::riscv::cpu::Cpu;
 use crate::riscv::cpu::Register;
+use crate::riscv::execute;
+use crate::riscv::trap::Exception;
 
-pub fn execute_beq(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_bne(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_blt(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_bge(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_bltu(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_bgeu(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) {}
+// Take the branch by redirecting the next pc relative to the branch itself
+fn branch(condition: bool, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    if condition {
+        execute::jump(cpu.pc.wrapping_add(imm as i64 as u64), cpu)?;
+    }
+    Ok(())
+}
+
+pub fn execute_beq(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let condition = cpu.read_register(rs1) == cpu.read_register(rs2);
+    branch(condition, imm, cpu)
+}
+pub fn execute_bne(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let condition = cpu.read_register(rs1) != cpu.read_register(rs2);
+    branch(condition, imm, cpu)
+}
+pub fn execute_blt(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let condition = (cpu.read_register(rs1) as i64) < (cpu.read_register(rs2) as i64);
+    branch(condition, imm, cpu)
+}
+pub fn execute_bge(rs1: Register, rs2: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let condition = (cpu.read_register(rs1) as i64) >= (cpu.read_register(rs2) as i64);
+    branch(condition, imm, cpu)
+}
+pub fn execute_bltu(
+    rs1: Register,
+    rs2: Register,
+    imm: i32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let condition = cpu.read_register(rs1) < cpu.read_register(rs2);
+    branch(condition, imm, cpu)
+}
+pub fn execute_bgeu(
+    rs1: Register,
+    rs2: Register,
+    imm: i32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let condition = cpu.read_register(rs1) >= cpu.read_register(rs2);
+    branch(condition, imm, cpu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::Bus;
+    use crate::riscv::cpu::AbiRegister;
+
+    fn setup(a0: u64, a1: u64) -> Cpu {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.pc = 0x100;
+        cpu.next_pc = 0x104;
+        cpu.write_register(AbiRegister::A0.into(), a0);
+        cpu.write_register(AbiRegister::A1.into(), a1);
+        cpu
+    }
+    #[test]
+    fn execute_beq() {
+        let mut cpu = setup(5, 5);
+        super::execute_beq(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            -16,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.next_pc, 0xf0);
+        let mut cpu = setup(5, 6);
+        super::execute_beq(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            -16,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.next_pc, 0x104);
+    }
+    #[test]
+    fn execute_bne() {
+        let mut cpu = setup(5, 6);
+        super::execute_bne(AbiRegister::A0.into(), AbiRegister::A1.into(), 32, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x120);
+        let mut cpu = setup(5, 5);
+        super::execute_bne(AbiRegister::A0.into(), AbiRegister::A1.into(), 32, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x104);
+    }
+    #[test]
+    fn execute_blt() {
+        let mut cpu = setup(-1i64 as u64, 1);
+        super::execute_blt(AbiRegister::A0.into(), AbiRegister::A1.into(), 8, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x108);
+        let mut cpu = setup(1, -1i64 as u64);
+        super::execute_blt(AbiRegister::A0.into(), AbiRegister::A1.into(), 8, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x104);
+    }
+    #[test]
+    fn execute_bge() {
+        let mut cpu = setup(1, -1i64 as u64);
+        super::execute_bge(AbiRegister::A0.into(), AbiRegister::A1.into(), 8, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x108);
+        let mut cpu = setup(3, 3);
+        super::execute_bge(AbiRegister::A0.into(), AbiRegister::A1.into(), 8, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x108);
+        let mut cpu = setup(-1i64 as u64, 1);
+        super::execute_bge(AbiRegister::A0.into(), AbiRegister::A1.into(), 8, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x104);
+    }
+    #[test]
+    fn execute_bltu() {
+        let mut cpu = setup(1, -1i64 as u64);
+        super::execute_bltu(AbiRegister::A0.into(), AbiRegister::A1.into(), -4, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0xfc);
+        let mut cpu = setup(-1i64 as u64, 1);
+        super::execute_bltu(AbiRegister::A0.into(), AbiRegister::A1.into(), -4, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x104);
+    }
+    #[test]
+    fn execute_bgeu() {
+        let mut cpu = setup(-1i64 as u64, 1);
+        super::execute_bgeu(AbiRegister::A0.into(), AbiRegister::A1.into(), -4, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0xfc);
+        let mut cpu = setup(1, -1i64 as u64);
+        super::execute_bgeu(AbiRegister::A0.into(), AbiRegister::A1.into(), -4, &mut cpu).unwrap();
+        assert_eq!(cpu.next_pc, 0x104);
+    }
+}
@@ -1,31 +1,814 @@
 use crate::riscv::cpu::Cpu;
+use crate::riscv::cpu::FRegister;
+use crate::riscv::cpu::Privilege;
 use crate::riscv::cpu::Register;
+use crate::riscv::csr;
+use crate::riscv::execute;
+use crate::riscv::float;
+use crate::riscv::trap::Exception;
 
-pub fn execute_lb(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_lh(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_lw(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_lbu(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_lhu(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_lwu(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_ld(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-
-pub fn execute_fence(rd: Register, rs1: Register, succ: u32, pred: u32, fm: u32, cpu: &mut Cpu) {}
-
-pub fn execute_addi(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_slti(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_sltiu(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_xori(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_ori(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_andi(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_slli(rd: Register, rs1: Register, shamt: u32, cpu: &mut Cpu) {}
-pub fn execute_srli(rd: Register, rs1: Register, shamt: u32, cpu: &mut Cpu) {}
-pub fn execute_srai(rd: Register, rs1: Register, shamt: u32, cpu: &mut Cpu) {}
-pub fn execute_addiw(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_slliw(rd: Register, rs1: Register, shamt: u32, cpu: &mut Cpu) {}
-pub fn execute_srliw(rd: Register, rs1: Register, shamt: u32, cpu: &mut Cpu) {}
-pub fn execute_sraiw(rd: Register, rs1: Register, shamt: u32, cpu: &mut Cpu) {}
-
-pub fn execute_jalr(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-
-pub fn execute_ebreak(cpu: &mut Cpu) {}
-pub fn execute_ecall(cpu: &mut Cpu) {}
+fn effective_address(rs1: Register, imm: i32, cpu: &Cpu) -> u64 {
+    cpu.read_register(rs1).wrapping_add(imm as i64 as u64)
+}
+
+pub fn execute_lb(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 1)? as i8;
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_lh(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 2)? as i16;
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_lw(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 4)? as i32;
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_lbu(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 1)?;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_lhu(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 2)?;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_lwu(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 4)?;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_ld(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 8)?;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_flw(rd: FRegister, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 4)?;
+    cpu.write_float(rd, float::SINGLE, value);
+    Ok(())
+}
+pub fn execute_fld(rd: FRegister, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.load(effective_address(rs1, imm, cpu), 8)?;
+    cpu.write_float(rd, float::DOUBLE, value);
+    Ok(())
+}
+
+// A single hart observes its own memory accesses in program order,
+// so there is nothing to order
+pub fn execute_fence(
+    _rd: Register,
+    _rs1: Register,
+    _succ: u32,
+    _pred: u32,
+    _fm: u32,
+    _cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    Ok(())
+}
+
+// Stores through the bus already drop the decoded instructions and basic
+// blocks they overwrite, so this flush only matters if a path around the bus
+// is ever added. It is kept as cheap insurance, since FENCE.I is rare.
+pub fn execute_fence_i(
+    _rd: Register,
+    _rs1: Register,
+    _imm: i32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    cpu.bus.flush_decoded();
+    cpu.blocks.flush();
+    Ok(())
+}
+
+pub fn execute_addi(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1).wrapping_add(imm as i64 as u64);
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_slti(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i64) < imm as i64;
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_sltiu(
+    rd: Register,
+    rs1: Register,
+    imm: i32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    // The immediate is sign extended and then compared as unsigned
+    let value = cpu.read_register(rs1) < imm as i64 as u64;
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_xori(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) ^ imm as i64 as u64;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_ori(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) | imm as i64 as u64;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_andi(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) & imm as i64 as u64;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_slli(
+    rd: Register,
+    rs1: Register,
+    shamt: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) << shamt;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_srli(
+    rd: Register,
+    rs1: Register,
+    shamt: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) >> shamt;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_srai(
+    rd: Register,
+    rs1: Register,
+    shamt: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i64) >> shamt;
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_addiw(
+    rd: Register,
+    rs1: Register,
+    imm: i32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i32).wrapping_add(imm);
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_slliw(
+    rd: Register,
+    rs1: Register,
+    shamt: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as u32) << shamt;
+    cpu.write_register(rd, value as i32 as i64 as u64);
+    Ok(())
+}
+pub fn execute_srliw(
+    rd: Register,
+    rs1: Register,
+    shamt: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as u32) >> shamt;
+    cpu.write_register(rd, value as i32 as i64 as u64);
+    Ok(())
+}
+pub fn execute_sraiw(
+    rd: Register,
+    rs1: Register,
+    shamt: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i32) >> shamt;
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+
+pub fn execute_jalr(rd: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    // The target is computed before rd is written since rd may equal rs1
+    let target = effective_address(rs1, imm, cpu) & !1;
+    let link = cpu.next_pc;
+    execute::jump(target, cpu)?;
+    cpu.write_register(rd, link);
+    Ok(())
+}
+
+// Read the old value of a CSR into rd and write a new value computed from it.
+// The read is skipped when rd is x0 and the write when `write` is false.
+fn csr_access(
+    rd: Register,
+    csr: u32,
+    write: bool,
+    new_value: impl FnOnce(u64) -> u64,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let csr = csr as usize;
+    cpu.csr.check_access(csr, write, cpu.privilege)?;
+    let old = cpu.read_csr(csr);
+    if write {
+        let value = new_value(cpu.csr.read_written(csr));
+        // Turning off the C extension is suppressed when the following
+        // instruction would no longer be aligned
+        let misaligned =
+            csr == csr::MISA && value & csr::extension(b'C') == 0 && cpu.next_pc & 0b11 != 0;
+        if !misaligned {
+            cpu.csr.write(csr, value);
+        }
+        // Switching address spaces drops every cached translation
+        if csr == csr::SATP {
+            cpu.tlb.flush_all();
+        }
+    }
+    cpu.write_register(rd, old);
+    Ok(())
+}
+
+pub fn execute_csrrw(
+    rd: Register,
+    rs1: Register,
+    csr: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1);
+    csr_access(rd, csr, true, |_| value, cpu)
+}
+pub fn execute_csrrs(
+    rd: Register,
+    rs1: Register,
+    csr: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mask = cpu.read_register(rs1);
+    csr_access(rd, csr, rs1 != Register::X0, |old| old | mask, cpu)
+}
+pub fn execute_csrrc(
+    rd: Register,
+    rs1: Register,
+    csr: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mask = cpu.read_register(rs1);
+    csr_access(rd, csr, rs1 != Register::X0, |old| old & !mask, cpu)
+}
+pub fn execute_csrrwi(rd: Register, uimm: u32, csr: u32, cpu: &mut Cpu) -> Result<(), Exception> {
+    csr_access(rd, csr, true, |_| uimm as u64, cpu)
+}
+pub fn execute_csrrsi(rd: Register, uimm: u32, csr: u32, cpu: &mut Cpu) -> Result<(), Exception> {
+    csr_access(rd, csr, uimm != 0, |old| old | uimm as u64, cpu)
+}
+pub fn execute_csrrci(rd: Register, uimm: u32, csr: u32, cpu: &mut Cpu) -> Result<(), Exception> {
+    csr_access(rd, csr, uimm != 0, |old| old & !(uimm as u64), cpu)
+}
+
+pub fn execute_ebreak(cpu: &mut Cpu) -> Result<(), Exception> {
+    Err(Exception::Breakpoint(cpu.pc))
+}
+pub fn execute_ecall(cpu: &mut Cpu) -> Result<(), Exception> {
+    Err(match cpu.privilege {
+        Privilege::User => Exception::EnvironmentCallFromUMode,
+        Privilege::Supervisor => Exception::EnvironmentCallFromSMode,
+        Privilege::Machine => Exception::EnvironmentCallFromMMode,
+    })
+}
+// Return from a machine mode trap to the privilege level in MPP, restoring
+// MIE from MPIE
+pub fn execute_mret(cpu: &mut Cpu) -> Result<(), Exception> {
+    if cpu.privilege != Privilege::Machine {
+        return Err(Exception::IllegalInstruction(0));
+    }
+    let mstatus = cpu.csr.read(csr::MSTATUS);
+    let previous = Privilege::from((mstatus & csr::MSTATUS_MPP) >> 11);
+    let mie = if mstatus & csr::MSTATUS_MPIE != 0 {
+        csr::MSTATUS_MIE
+    } else {
+        0
+    };
+    // MPP is left at the least privileged level
+    let mut mstatus = (mstatus & !(csr::MSTATUS_MIE | csr::MSTATUS_MPP)) | mie | csr::MSTATUS_MPIE;
+    if previous != Privilege::Machine {
+        mstatus &= !csr::MSTATUS_MPRV;
+    }
+    cpu.csr.write(csr::MSTATUS, mstatus);
+    cpu.privilege = previous;
+    cpu.next_pc = cpu.csr.read(csr::MEPC);
+    Ok(())
+}
+// Return from a supervisor mode trap to the privilege level in SPP,
+// restoring SIE from SPIE. TSR traps it in supervisor mode.
+pub fn execute_sret(cpu: &mut Cpu) -> Result<(), Exception> {
+    let mstatus = cpu.csr.read(csr::MSTATUS);
+    let trapped = cpu.privilege == Privilege::Supervisor && mstatus & csr::MSTATUS_TSR != 0;
+    if cpu.privilege == Privilege::User || trapped {
+        return Err(Exception::IllegalInstruction(0));
+    }
+    let previous = Privilege::from((mstatus & csr::MSTATUS_SPP) >> 8);
+    let sie = if mstatus & csr::MSTATUS_SPIE != 0 {
+        csr::MSTATUS_SIE
+    } else {
+        0
+    };
+    // Returning to a lower privilege level always clears MPRV
+    let mstatus = (mstatus & !(csr::MSTATUS_SIE | csr::MSTATUS_SPP | csr::MSTATUS_MPRV))
+        | sie
+        | csr::MSTATUS_SPIE;
+    cpu.csr.write(csr::MSTATUS, mstatus);
+    cpu.privilege = previous;
+    cpu.next_pc = cpu.csr.read(csr::SEPC);
+    Ok(())
+}
+// WFI is only a hint, and the spec allows it to complete at once, so the
+// hart carries on and takes any interrupt between instructions as usual.
+// User mode may not wait, and neither may supervisor mode when TW is set.
+pub fn execute_wfi(cpu: &mut Cpu) -> Result<(), Exception> {
+    let timeout_wait = cpu.csr.read(csr::MSTATUS) & csr::MSTATUS_TW != 0;
+    match cpu.privilege {
+        Privilege::User => Err(Exception::IllegalInstruction(0)),
+        Privilege::Supervisor if timeout_wait => Err(Exception::IllegalInstruction(0)),
+        _ => Ok(()),
+    }
+}
+// Flush cached translations for the page in rs1 and the address space in
+// rs2, where x0 selects every page or every address space. TVM traps it in
+// supervisor mode.
+pub fn execute_sfence_vma(rs1: Register, rs2: Register, cpu: &mut Cpu) -> Result<(), Exception> {
+    let trapped = cpu.privilege == Privilege::Supervisor
+        && cpu.csr.read(csr::MSTATUS) & csr::MSTATUS_TVM != 0;
+    if cpu.privilege == Privilege::User || trapped {
+        return Err(Exception::IllegalInstruction(0));
+    }
+    let vpn = match rs1 {
+        Register::X0 => None,
+        rs1 => Some(cpu.read_register(rs1) >> 12),
+    };
+    let asid = match rs2 {
+        Register::X0 => None,
+        rs2 => Some(cpu.read_register(rs2) & 0xffff),
+    };
+    cpu.tlb.flush(vpn, asid);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+    use crate::riscv::cpu::AbiRegister;
+    use crate::riscv::tlb;
+
+    // Load from a1 + 4 with a1 pointing at a buffer of 0x80 | i bytes
+    fn run_load(execute: fn(Register, Register, i32, &mut Cpu) -> Result<(), Exception>) -> u64 {
+        let mut bus = Bus::new(16);
+        let buffer: Vec<u8> = (0..16).map(|i| 0x80 | i).collect();
+        bus.load(DRAM_BASE, &buffer).unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE);
+        execute(AbiRegister::A0.into(), AbiRegister::A1.into(), 4, &mut cpu).unwrap();
+        cpu.read_register(AbiRegister::A0.into())
+    }
+    // Run an I-type executor with a1 as the source and return a0
+    fn run<T>(
+        execute: fn(Register, Register, T, &mut Cpu) -> Result<(), Exception>,
+        a1: u64,
+        imm: T,
+    ) -> u64 {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_register(AbiRegister::A1.into(), a1);
+        execute(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            imm,
+            &mut cpu,
+        )
+        .unwrap();
+        cpu.read_register(AbiRegister::A0.into())
+    }
+    #[test]
+    fn execute_lb() {
+        assert_eq!(run_load(super::execute_lb), 0xffff_ffff_ffff_ff84);
+    }
+    #[test]
+    fn execute_lh() {
+        assert_eq!(run_load(super::execute_lh), 0xffff_ffff_ffff_8584);
+    }
+    #[test]
+    fn execute_lw() {
+        assert_eq!(run_load(super::execute_lw), 0xffff_ffff_8786_8584);
+    }
+    #[test]
+    fn execute_lbu() {
+        assert_eq!(run_load(super::execute_lbu), 0x84);
+    }
+    #[test]
+    fn execute_lhu() {
+        assert_eq!(run_load(super::execute_lhu), 0x8584);
+    }
+    #[test]
+    fn execute_lwu() {
+        assert_eq!(run_load(super::execute_lwu), 0x8786_8584);
+    }
+    #[test]
+    fn execute_ld() {
+        assert_eq!(run_load(super::execute_ld), 0x8b8a_8988_8786_8584);
+    }
+    #[test]
+    fn execute_flw() {
+        let mut bus = Bus::new(16);
+        bus.load(DRAM_BASE + 4, &1.5f32.to_bits().to_le_bytes())
+            .unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE);
+        super::execute_flw(FRegister::F0, AbiRegister::A1.into(), 4, &mut cpu).unwrap();
+        // Single precision values are NaN-boxed
+        assert_eq!(cpu.fregisters[0], 0xffff_ffff_3fc0_0000);
+    }
+    #[test]
+    fn execute_fld() {
+        let mut bus = Bus::new(16);
+        bus.load(DRAM_BASE + 4, &1.5f64.to_bits().to_le_bytes())
+            .unwrap();
+        let mut cpu = Cpu::new(bus);
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE);
+        super::execute_fld(FRegister::F0, AbiRegister::A1.into(), 4, &mut cpu).unwrap();
+        assert_eq!(cpu.fregisters[0], 1.5f64.to_bits());
+    }
+    #[test]
+    fn execute_addi() {
+        assert_eq!(run(super::execute_addi, 5, -6), u64::MAX);
+    }
+    #[test]
+    fn execute_slti() {
+        assert_eq!(run(super::execute_slti, -2i64 as u64, -1), 1);
+        assert_eq!(run(super::execute_slti, 0, -1), 0);
+    }
+    #[test]
+    fn execute_sltiu() {
+        assert_eq!(run(super::execute_sltiu, 5, -1), 1);
+        assert_eq!(run(super::execute_sltiu, 5, 5), 0);
+    }
+    #[test]
+    fn execute_xori() {
+        assert_eq!(run(super::execute_xori, 0x0f, -1), !0x0f);
+    }
+    #[test]
+    fn execute_ori() {
+        assert_eq!(run(super::execute_ori, 0x10, -2048), 0xffff_ffff_ffff_f810);
+    }
+    #[test]
+    fn execute_andi() {
+        assert_eq!(run(super::execute_andi, u64::MAX, 0x7f0), 0x7f0);
+    }
+    #[test]
+    fn execute_slli() {
+        assert_eq!(run(super::execute_slli, 1, 63), 1 << 63);
+    }
+    #[test]
+    fn execute_srli() {
+        assert_eq!(run(super::execute_srli, 1 << 63, 62), 2);
+    }
+    #[test]
+    fn execute_srai() {
+        assert_eq!(run(super::execute_srai, 1 << 63, 62), -2i64 as u64);
+    }
+    #[test]
+    fn execute_addiw() {
+        assert_eq!(
+            run(super::execute_addiw, 0x7fff_ffff, 1),
+            0xffff_ffff_8000_0000
+        );
+        assert_eq!(run(super::execute_addiw, 0x1_0000_0000, -1), u64::MAX);
+    }
+    #[test]
+    fn execute_slliw() {
+        assert_eq!(run(super::execute_slliw, 3, 31), 0xffff_ffff_8000_0000);
+    }
+    #[test]
+    fn execute_srliw() {
+        assert_eq!(
+            run(super::execute_srliw, 0xffff_ffff_8000_0000, 4),
+            0x0800_0000
+        );
+    }
+    #[test]
+    fn execute_sraiw() {
+        assert_eq!(
+            run(super::execute_sraiw, 0x8000_0000, 4),
+            0xffff_ffff_f800_0000
+        );
+    }
+    #[test]
+    fn execute_jalr() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.pc = 0x100;
+        cpu.next_pc = 0x104;
+        cpu.write_register(AbiRegister::Ra.into(), 0x201);
+        super::execute_jalr(AbiRegister::Ra.into(), AbiRegister::Ra.into(), 4, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::Ra.into()), 0x104);
+        assert_eq!(cpu.next_pc, 0x204);
+    }
+    #[test]
+    fn execute_csrrw() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.csr.write(csr::MSCRATCH, 0x1234);
+        cpu.write_register(AbiRegister::A1.into(), 0x5678);
+        super::execute_csrrw(
+            AbiRegister::A1.into(),
+            AbiRegister::A1.into(),
+            csr::MSCRATCH as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A1.into()), 0x1234);
+        assert_eq!(cpu.csr.read(csr::MSCRATCH), 0x5678);
+    }
+    #[test]
+    fn execute_csrrs() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.csr.write(csr::MSCRATCH, 0b0011);
+        cpu.write_register(AbiRegister::A1.into(), 0b0110);
+        super::execute_csrrs(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            csr::MSCRATCH as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 0b0011);
+        assert_eq!(cpu.csr.read(csr::MSCRATCH), 0b0111);
+    }
+    #[test]
+    fn execute_csrrs_read_only() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        // Reading a read-only CSR is allowed as long as rs1 is x0
+        super::execute_csrrs(
+            AbiRegister::A0.into(),
+            AbiRegister::Zero.into(),
+            csr::MHARTID as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 0);
+        assert_eq!(
+            super::execute_csrrs(
+                AbiRegister::A0.into(),
+                AbiRegister::A1.into(),
+                csr::MHARTID as u32,
+                &mut cpu,
+            ),
+            Err(Exception::IllegalInstruction(0))
+        );
+    }
+    #[test]
+    fn execute_csrrc() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.csr.write(csr::MSCRATCH, 0b0111);
+        cpu.write_register(AbiRegister::A1.into(), 0b0110);
+        super::execute_csrrc(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            csr::MSCRATCH as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 0b0111);
+        assert_eq!(cpu.csr.read(csr::MSCRATCH), 0b0001);
+    }
+    #[test]
+    fn execute_csrrwi() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        super::execute_csrrwi(AbiRegister::A0.into(), 0x1f, csr::MSCRATCH as u32, &mut cpu)
+            .unwrap();
+        assert_eq!(cpu.csr.read(csr::MSCRATCH), 0x1f);
+        assert_eq!(
+            super::execute_csrrwi(AbiRegister::A0.into(), 0, 0x7ff, &mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+    }
+    #[test]
+    fn execute_csrrsi() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        super::execute_csrrsi(
+            AbiRegister::A0.into(),
+            csr::MSTATUS_MIE as u32,
+            csr::MSTATUS as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_ne!(cpu.csr.read(csr::MSTATUS) & csr::MSTATUS_MIE, 0);
+    }
+    #[test]
+    fn execute_csrrci() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.csr.write(csr::MSCRATCH, 0b1111);
+        super::execute_csrrci(
+            AbiRegister::A0.into(),
+            0b0101,
+            csr::MSCRATCH as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 0b1111);
+        assert_eq!(cpu.csr.read(csr::MSCRATCH), 0b1010);
+    }
+    #[test]
+    fn execute_csrrci_misa() {
+        // Clearing misa.C is suppressed while the next instruction is only
+        // 2-byte aligned
+        let mut cpu = Cpu::new(Bus::new(0));
+        let c = csr::extension(b'C') as u32;
+        cpu.next_pc = DRAM_BASE + 2;
+        super::execute_csrrci(AbiRegister::Zero.into(), c, csr::MISA as u32, &mut cpu).unwrap();
+        assert!(cpu.csr.has_extension(b'C'));
+        cpu.next_pc = DRAM_BASE + 4;
+        super::execute_csrrci(AbiRegister::Zero.into(), c, csr::MISA as u32, &mut cpu).unwrap();
+        assert!(!cpu.csr.has_extension(b'C'));
+    }
+    #[test]
+    fn execute_csr_privilege() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.privilege = Privilege::Supervisor;
+        assert_eq!(
+            super::execute_csrrw(
+                AbiRegister::A0.into(),
+                AbiRegister::A1.into(),
+                csr::MSCRATCH as u32,
+                &mut cpu,
+            ),
+            Err(Exception::IllegalInstruction(0))
+        );
+        cpu.write_register(AbiRegister::A1.into(), 0x42);
+        super::execute_csrrw(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            csr::SSCRATCH as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.csr.read(csr::SSCRATCH), 0x42);
+    }
+    #[test]
+    fn execute_ecall() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        assert_eq!(
+            super::execute_ecall(&mut cpu),
+            Err(Exception::EnvironmentCallFromMMode)
+        );
+        cpu.privilege = Privilege::Supervisor;
+        assert_eq!(
+            super::execute_ecall(&mut cpu),
+            Err(Exception::EnvironmentCallFromSMode)
+        );
+        cpu.privilege = Privilege::User;
+        assert_eq!(
+            super::execute_ecall(&mut cpu),
+            Err(Exception::EnvironmentCallFromUMode)
+        );
+    }
+    #[test]
+    fn execute_mret() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.csr.write(csr::MEPC, DRAM_BASE + 0x10);
+        cpu.csr.write(
+            csr::MSTATUS,
+            csr::MSTATUS_MPIE | 0b01 << 11 | csr::MSTATUS_MPRV,
+        );
+        super::execute_mret(&mut cpu).unwrap();
+        assert_eq!(cpu.privilege, Privilege::Supervisor);
+        assert_eq!(cpu.next_pc, DRAM_BASE + 0x10);
+        let fields = csr::MSTATUS_MIE | csr::MSTATUS_MPIE | csr::MSTATUS_MPP | csr::MSTATUS_MPRV;
+        assert_eq!(
+            cpu.csr.read(csr::MSTATUS) & fields,
+            csr::MSTATUS_MIE | csr::MSTATUS_MPIE
+        );
+        // Only machine mode can return from a machine mode trap
+        assert_eq!(
+            super::execute_mret(&mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+    }
+    #[test]
+    fn execute_sret() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.privilege = Privilege::Supervisor;
+        cpu.csr.write(csr::SEPC, DRAM_BASE + 0x20);
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_SIE);
+        super::execute_sret(&mut cpu).unwrap();
+        assert_eq!(cpu.privilege, Privilege::User);
+        assert_eq!(cpu.next_pc, DRAM_BASE + 0x20);
+        let fields = csr::MSTATUS_SIE | csr::MSTATUS_SPIE | csr::MSTATUS_SPP;
+        assert_eq!(cpu.csr.read(csr::MSTATUS) & fields, csr::MSTATUS_SPIE);
+        assert_eq!(
+            super::execute_sret(&mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+        // TSR traps sret in supervisor mode but not in machine mode
+        cpu.csr
+            .write(csr::MSTATUS, csr::MSTATUS_TSR | csr::MSTATUS_SPP);
+        cpu.privilege = Privilege::Supervisor;
+        assert_eq!(
+            super::execute_sret(&mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+        cpu.privilege = Privilege::Machine;
+        super::execute_sret(&mut cpu).unwrap();
+        assert_eq!(cpu.privilege, Privilege::Supervisor);
+    }
+    #[test]
+    fn execute_wfi() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_TW);
+        assert_eq!(super::execute_wfi(&mut cpu), Ok(()));
+        cpu.privilege = Privilege::Supervisor;
+        assert_eq!(
+            super::execute_wfi(&mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+        cpu.csr.write(csr::MSTATUS, 0);
+        assert_eq!(super::execute_wfi(&mut cpu), Ok(()));
+        cpu.privilege = Privilege::User;
+        assert_eq!(
+            super::execute_wfi(&mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+    }
+    #[test]
+    fn execute_sfence_vma() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        let (rs1, rs2) = (AbiRegister::A0.into(), AbiRegister::A1.into());
+        cpu.privilege = Privilege::Supervisor;
+        assert_eq!(super::execute_sfence_vma(rs1, rs2, &mut cpu), Ok(()));
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_TVM);
+        assert_eq!(
+            super::execute_sfence_vma(rs1, rs2, &mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+        cpu.privilege = Privilege::User;
+        cpu.csr.write(csr::MSTATUS, 0);
+        assert_eq!(
+            super::execute_sfence_vma(rs1, rs2, &mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+    }
+    #[test]
+    fn execute_sfence_vma_flush() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        let entry = |vpn: u64, asid: u64| tlb::Entry {
+            vpn,
+            asid,
+            global: false,
+            flags: 0,
+            frame: 0,
+        };
+        cpu.tlb.insert(entry(1, 1));
+        cpu.tlb.insert(entry(2, 2));
+        cpu.write_register(AbiRegister::A0.into(), 0x1abc);
+        cpu.write_register(AbiRegister::A1.into(), 2);
+        // The page in a0 is flushed in every address space
+        super::execute_sfence_vma(AbiRegister::A0.into(), AbiRegister::Zero.into(), &mut cpu)
+            .unwrap();
+        assert_eq!(cpu.tlb.lookup(1, 1), None);
+        assert!(cpu.tlb.lookup(2, 2).is_some());
+        super::execute_sfence_vma(AbiRegister::Zero.into(), AbiRegister::A1.into(), &mut cpu)
+            .unwrap();
+        assert_eq!(cpu.tlb.lookup(2, 2), None);
+    }
+    #[test]
+    fn execute_csrrw_satp() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.tlb.insert(tlb::Entry {
+            vpn: 1,
+            asid: 0,
+            global: true,
+            flags: 0,
+            frame: 0,
+        });
+        super::execute_csrrw(
+            AbiRegister::Zero.into(),
+            AbiRegister::Zero.into(),
+            csr::SATP as u32,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.tlb.lookup(1, 0), None);
+    }
+    #[test]
+    fn execute_fence() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        super::execute_fence(
+            AbiRegister::Zero.into(),
+            AbiRegister::Zero.into(),
+            0b1111,
+            0b1111,
+            0,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.registers, [0; 32]);
+    }
+}
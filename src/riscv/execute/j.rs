@@ -1,4 +1,28 @@
 use crate::riscv::cpu::Cpu;
 use crate::riscv::cpu::Register;
+use crate::riscv::execute;
+use crate::riscv::trap::Exception;
 
-pub fn execute_jal(rd: Register, imm: i32, cpu: &mut Cpu) {}
+pub fn execute_jal(rd: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    let link = cpu.next_pc;
+    execute::jump(cpu.pc.wrapping_add(imm as i64 as u64), cpu)?;
+    cpu.write_register(rd, link);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::Bus;
+    use crate::riscv::cpu::AbiRegister;
+
+    #[test]
+    fn execute_jal() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.pc = 0x100;
+        cpu.next_pc = 0x104;
+        super::execute_jal(AbiRegister::Ra.into(), -0x40, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::Ra.into()), 0x104);
+        assert_eq!(cpu.next_pc, 0xc0);
+    }
+}
@@ -2,13 +2,37 @@ pub mod b;
 pub mod i;
 pub mod j;
 pub mod r;
+pub mod r4;
 pub mod s;
 pub mod u;
 use crate::riscv::cpu::Cpu;
+use crate::riscv::csr;
+use crate::riscv::float::RoundingMode;
 use crate::riscv::instruction::Instruction;
+use crate::riscv::trap::Exception;
 
-pub fn execute_instruction(instruction: Instruction, cpu: &mut Cpu) {
-    return match instruction {
+// Transfer control to `target`, which must be aligned to an instruction
+pub fn jump(target: u64, cpu: &mut Cpu) -> Result<(), Exception> {
+    if target & cpu.csr.instruction_alignment() != 0 {
+        return Err(Exception::InstructionAddressMisaligned(target));
+    }
+    cpu.next_pc = target;
+    Ok(())
+}
+
+// Rounding mode selected by the rm field of a floating point instruction,
+// where 0b111 selects the dynamic rounding mode in frm. Reserved modes are
+// illegal.
+pub fn rounding_mode(rm: u32, cpu: &Cpu) -> Result<RoundingMode, Exception> {
+    let rm = match rm {
+        0b111 => cpu.csr.read(csr::FRM) as u32,
+        rm => rm,
+    };
+    RoundingMode::from_bits(rm).ok_or(Exception::IllegalInstruction(0))
+}
+
+pub fn execute_instruction(instruction: Instruction, cpu: &mut Cpu) -> Result<(), Exception> {
+    match instruction {
         // B-Type
         Instruction::Beq { rs1, rs2, imm } => b::execute_beq(rs1, rs2, imm, cpu),
         Instruction::Bne { rs1, rs2, imm } => b::execute_bne(rs1, rs2, imm, cpu),
@@ -31,6 +55,7 @@ pub fn execute_instruction(instruction: Instruction, cpu: &mut Cpu) {
             pred,
             fm,
         } => i::execute_fence(rd, rs1, succ, pred, fm, cpu),
+        Instruction::FenceI { rd, rs1, imm } => i::execute_fence_i(rd, rs1, imm, cpu),
         Instruction::Addi { rd, rs1, imm } => i::execute_addi(rd, rs1, imm, cpu),
         Instruction::Slti { rd, rs1, imm } => i::execute_slti(rd, rs1, imm, cpu),
         Instruction::Sltiu { rd, rs1, imm } => i::execute_sltiu(rd, rs1, imm, cpu),
@@ -47,6 +72,16 @@ pub fn execute_instruction(instruction: Instruction, cpu: &mut Cpu) {
         Instruction::Jalr { rd, rs1, imm } => i::execute_jalr(rd, rs1, imm, cpu),
         Instruction::Ebreak => i::execute_ebreak(cpu),
         Instruction::Ecall => i::execute_ecall(cpu),
+        Instruction::Mret => i::execute_mret(cpu),
+        Instruction::Sret => i::execute_sret(cpu),
+        Instruction::Wfi => i::execute_wfi(cpu),
+        Instruction::SfenceVma { rs1, rs2 } => i::execute_sfence_vma(rs1, rs2, cpu),
+        Instruction::Csrrw { rd, rs1, csr } => i::execute_csrrw(rd, rs1, csr, cpu),
+        Instruction::Csrrs { rd, rs1, csr } => i::execute_csrrs(rd, rs1, csr, cpu),
+        Instruction::Csrrc { rd, rs1, csr } => i::execute_csrrc(rd, rs1, csr, cpu),
+        Instruction::Csrrwi { rd, uimm, csr } => i::execute_csrrwi(rd, uimm, csr, cpu),
+        Instruction::Csrrsi { rd, uimm, csr } => i::execute_csrrsi(rd, uimm, csr, cpu),
+        Instruction::Csrrci { rd, uimm, csr } => i::execute_csrrci(rd, uimm, csr, cpu),
         // J-Type
         Instruction::Jal { rd, imm } => j::execute_jal(rd, imm, cpu),
         // R-Type
@@ -65,6 +100,275 @@ pub fn execute_instruction(instruction: Instruction, cpu: &mut Cpu) {
         Instruction::Sllw { rd, rs1, rs2 } => r::execute_sllw(rd, rs1, rs2, cpu),
         Instruction::Srlw { rd, rs1, rs2 } => r::execute_srlw(rd, rs1, rs2, cpu),
         Instruction::Sraw { rd, rs1, rs2 } => r::execute_sraw(rd, rs1, rs2, cpu),
+        // M Extension
+        Instruction::Mul { rd, rs1, rs2 } => r::execute_mul(rd, rs1, rs2, cpu),
+        Instruction::Mulh { rd, rs1, rs2 } => r::execute_mulh(rd, rs1, rs2, cpu),
+        Instruction::Mulhsu { rd, rs1, rs2 } => r::execute_mulhsu(rd, rs1, rs2, cpu),
+        Instruction::Mulhu { rd, rs1, rs2 } => r::execute_mulhu(rd, rs1, rs2, cpu),
+        Instruction::Div { rd, rs1, rs2 } => r::execute_div(rd, rs1, rs2, cpu),
+        Instruction::Divu { rd, rs1, rs2 } => r::execute_divu(rd, rs1, rs2, cpu),
+        Instruction::Rem { rd, rs1, rs2 } => r::execute_rem(rd, rs1, rs2, cpu),
+        Instruction::Remu { rd, rs1, rs2 } => r::execute_remu(rd, rs1, rs2, cpu),
+        Instruction::Mulw { rd, rs1, rs2 } => r::execute_mulw(rd, rs1, rs2, cpu),
+        Instruction::Divw { rd, rs1, rs2 } => r::execute_divw(rd, rs1, rs2, cpu),
+        Instruction::Divuw { rd, rs1, rs2 } => r::execute_divuw(rd, rs1, rs2, cpu),
+        Instruction::Remw { rd, rs1, rs2 } => r::execute_remw(rd, rs1, rs2, cpu),
+        Instruction::Remuw { rd, rs1, rs2 } => r::execute_remuw(rd, rs1, rs2, cpu),
+        // A Extension
+        Instruction::LrW { rd, rs1, aq, rl } => r::execute_lr_w(rd, rs1, aq, rl, cpu),
+        Instruction::ScW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_sc_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoswapW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoswap_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoaddW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoadd_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoxorW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoxor_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoandW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoand_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoorW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoor_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmominW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amomin_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmomaxW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amomax_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmominuW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amominu_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmomaxuW {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amomaxu_w(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::LrD { rd, rs1, aq, rl } => r::execute_lr_d(rd, rs1, aq, rl, cpu),
+        Instruction::ScD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_sc_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoswapD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoswap_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoaddD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoadd_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoxorD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoxor_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoandD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoand_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmoorD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amoor_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmominD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amomin_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmomaxD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amomax_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmominuD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amominu_d(rd, rs1, rs2, aq, rl, cpu),
+        Instruction::AmomaxuD {
+            rd,
+            rs1,
+            rs2,
+            aq,
+            rl,
+        } => r::execute_amomaxu_d(rd, rs1, rs2, aq, rl, cpu),
+        // F Extension
+        Instruction::Flw { rd, rs1, imm } => i::execute_flw(rd, rs1, imm, cpu),
+        Instruction::Fsw { rs2, rs1, imm } => s::execute_fsw(rs2, rs1, imm, cpu),
+        Instruction::FmaddS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fmadd_s(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FmsubS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fmsub_s(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FnmsubS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fnmsub_s(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FnmaddS {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fnmadd_s(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FaddS { rd, rs1, rs2, rm } => r::execute_fadd_s(rd, rs1, rs2, rm, cpu),
+        Instruction::FsubS { rd, rs1, rs2, rm } => r::execute_fsub_s(rd, rs1, rs2, rm, cpu),
+        Instruction::FmulS { rd, rs1, rs2, rm } => r::execute_fmul_s(rd, rs1, rs2, rm, cpu),
+        Instruction::FdivS { rd, rs1, rs2, rm } => r::execute_fdiv_s(rd, rs1, rs2, rm, cpu),
+        Instruction::FsqrtS { rd, rs1, rm } => r::execute_fsqrt_s(rd, rs1, rm, cpu),
+        Instruction::FsgnjS { rd, rs1, rs2 } => r::execute_fsgnj_s(rd, rs1, rs2, cpu),
+        Instruction::FsgnjnS { rd, rs1, rs2 } => r::execute_fsgnjn_s(rd, rs1, rs2, cpu),
+        Instruction::FsgnjxS { rd, rs1, rs2 } => r::execute_fsgnjx_s(rd, rs1, rs2, cpu),
+        Instruction::FminS { rd, rs1, rs2 } => r::execute_fmin_s(rd, rs1, rs2, cpu),
+        Instruction::FmaxS { rd, rs1, rs2 } => r::execute_fmax_s(rd, rs1, rs2, cpu),
+        Instruction::FeqS { rd, rs1, rs2 } => r::execute_feq_s(rd, rs1, rs2, cpu),
+        Instruction::FltS { rd, rs1, rs2 } => r::execute_flt_s(rd, rs1, rs2, cpu),
+        Instruction::FleS { rd, rs1, rs2 } => r::execute_fle_s(rd, rs1, rs2, cpu),
+        Instruction::FclassS { rd, rs1 } => r::execute_fclass_s(rd, rs1, cpu),
+        Instruction::FcvtWS { rd, rs1, rm } => r::execute_fcvt_w_s(rd, rs1, rm, cpu),
+        Instruction::FcvtWuS { rd, rs1, rm } => r::execute_fcvt_wu_s(rd, rs1, rm, cpu),
+        Instruction::FcvtLS { rd, rs1, rm } => r::execute_fcvt_l_s(rd, rs1, rm, cpu),
+        Instruction::FcvtLuS { rd, rs1, rm } => r::execute_fcvt_lu_s(rd, rs1, rm, cpu),
+        Instruction::FcvtSW { rd, rs1, rm } => r::execute_fcvt_s_w(rd, rs1, rm, cpu),
+        Instruction::FcvtSWu { rd, rs1, rm } => r::execute_fcvt_s_wu(rd, rs1, rm, cpu),
+        Instruction::FcvtSL { rd, rs1, rm } => r::execute_fcvt_s_l(rd, rs1, rm, cpu),
+        Instruction::FcvtSLu { rd, rs1, rm } => r::execute_fcvt_s_lu(rd, rs1, rm, cpu),
+        Instruction::FmvXW { rd, rs1 } => r::execute_fmv_x_w(rd, rs1, cpu),
+        Instruction::FmvWX { rd, rs1 } => r::execute_fmv_w_x(rd, rs1, cpu),
+        // D Extension
+        Instruction::Fld { rd, rs1, imm } => i::execute_fld(rd, rs1, imm, cpu),
+        Instruction::Fsd { rs2, rs1, imm } => s::execute_fsd(rs2, rs1, imm, cpu),
+        Instruction::FmaddD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fmadd_d(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FmsubD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fmsub_d(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FnmsubD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fnmsub_d(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FnmaddD {
+            rd,
+            rs1,
+            rs2,
+            rs3,
+            rm,
+        } => r4::execute_fnmadd_d(rd, rs1, rs2, rs3, rm, cpu),
+        Instruction::FaddD { rd, rs1, rs2, rm } => r::execute_fadd_d(rd, rs1, rs2, rm, cpu),
+        Instruction::FsubD { rd, rs1, rs2, rm } => r::execute_fsub_d(rd, rs1, rs2, rm, cpu),
+        Instruction::FmulD { rd, rs1, rs2, rm } => r::execute_fmul_d(rd, rs1, rs2, rm, cpu),
+        Instruction::FdivD { rd, rs1, rs2, rm } => r::execute_fdiv_d(rd, rs1, rs2, rm, cpu),
+        Instruction::FsqrtD { rd, rs1, rm } => r::execute_fsqrt_d(rd, rs1, rm, cpu),
+        Instruction::FsgnjD { rd, rs1, rs2 } => r::execute_fsgnj_d(rd, rs1, rs2, cpu),
+        Instruction::FsgnjnD { rd, rs1, rs2 } => r::execute_fsgnjn_d(rd, rs1, rs2, cpu),
+        Instruction::FsgnjxD { rd, rs1, rs2 } => r::execute_fsgnjx_d(rd, rs1, rs2, cpu),
+        Instruction::FminD { rd, rs1, rs2 } => r::execute_fmin_d(rd, rs1, rs2, cpu),
+        Instruction::FmaxD { rd, rs1, rs2 } => r::execute_fmax_d(rd, rs1, rs2, cpu),
+        Instruction::FcvtSD { rd, rs1, rm } => r::execute_fcvt_s_d(rd, rs1, rm, cpu),
+        Instruction::FcvtDS { rd, rs1, rm } => r::execute_fcvt_d_s(rd, rs1, rm, cpu),
+        Instruction::FeqD { rd, rs1, rs2 } => r::execute_feq_d(rd, rs1, rs2, cpu),
+        Instruction::FltD { rd, rs1, rs2 } => r::execute_flt_d(rd, rs1, rs2, cpu),
+        Instruction::FleD { rd, rs1, rs2 } => r::execute_fle_d(rd, rs1, rs2, cpu),
+        Instruction::FclassD { rd, rs1 } => r::execute_fclass_d(rd, rs1, cpu),
+        Instruction::FcvtWD { rd, rs1, rm } => r::execute_fcvt_w_d(rd, rs1, rm, cpu),
+        Instruction::FcvtWuD { rd, rs1, rm } => r::execute_fcvt_wu_d(rd, rs1, rm, cpu),
+        Instruction::FcvtLD { rd, rs1, rm } => r::execute_fcvt_l_d(rd, rs1, rm, cpu),
+        Instruction::FcvtLuD { rd, rs1, rm } => r::execute_fcvt_lu_d(rd, rs1, rm, cpu),
+        Instruction::FcvtDW { rd, rs1, rm } => r::execute_fcvt_d_w(rd, rs1, rm, cpu),
+        Instruction::FcvtDWu { rd, rs1, rm } => r::execute_fcvt_d_wu(rd, rs1, rm, cpu),
+        Instruction::FcvtDL { rd, rs1, rm } => r::execute_fcvt_d_l(rd, rs1, rm, cpu),
+        Instruction::FcvtDLu { rd, rs1, rm } => r::execute_fcvt_d_lu(rd, rs1, rm, cpu),
+        Instruction::FmvXD { rd, rs1 } => r::execute_fmv_x_d(rd, rs1, cpu),
+        Instruction::FmvDX { rd, rs1 } => r::execute_fmv_d_x(rd, rs1, cpu),
         // S-Type
         Instruction::Sb { rs2, rs1, imm } => s::execute_sb(rs2, rs1, imm, cpu),
         Instruction::Sh { rs2, rs1, imm } => s::execute_sh(rs2, rs1, imm, cpu),
@@ -73,6 +377,7 @@ pub fn execute_instruction(instruction: Instruction, cpu: &mut Cpu) {
         // U-Type
         Instruction::Auipc { rd, imm } => u::execute_auipc(rd, imm, cpu),
         Instruction::Lui { rd, imm } => u::execute_lui(rd, imm, cpu),
-        Instruction::Undefined => (),
-    };
+        // The instruction bits are filled in by the caller
+        Instruction::Undefined => Err(Exception::IllegalInstruction(0)),
+    }
 }
@@ -1,18 +1,2390 @@
 use crate::riscv::cpu::Cpu;
+use crate::riscv::cpu::FRegister;
 use crate::riscv::cpu::Register;
+use crate::riscv::execute;
+use crate::riscv::float;
+use crate::riscv::float::RoundingMode;
+use crate::riscv::trap::Exception;
 
-pub fn execute_add(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_sub(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_sll(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_slt(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_sltu(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_xor(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_srl(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_sra(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_or(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_and(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_addw(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_subw(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_sllw(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_srlw(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
-pub fn execute_sraw(rd: Register, rs1: Register, rs2: Register, cpu: &mut Cpu) {}
+pub fn execute_add(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1).wrapping_add(cpu.read_register(rs2));
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_sub(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1).wrapping_sub(cpu.read_register(rs2));
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_sll(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let shamt = cpu.read_register(rs2) & 0b111111;
+    let value = cpu.read_register(rs1) << shamt;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_slt(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i64) < (cpu.read_register(rs2) as i64);
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_sltu(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) < cpu.read_register(rs2);
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_xor(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) ^ cpu.read_register(rs2);
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_srl(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let shamt = cpu.read_register(rs2) & 0b111111;
+    let value = cpu.read_register(rs1) >> shamt;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_sra(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let shamt = cpu.read_register(rs2) & 0b111111;
+    let value = (cpu.read_register(rs1) as i64) >> shamt;
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_or(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) | cpu.read_register(rs2);
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_and(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) & cpu.read_register(rs2);
+    cpu.write_register(rd, value);
+    Ok(())
+}
+// The W variants operate on the low 32 bits and sign extend the 32-bit result
+pub fn execute_addw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i32).wrapping_add(cpu.read_register(rs2) as i32);
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_subw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i32).wrapping_sub(cpu.read_register(rs2) as i32);
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_sllw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let shamt = cpu.read_register(rs2) & 0b11111;
+    let value = (cpu.read_register(rs1) as u32) << shamt;
+    cpu.write_register(rd, value as i32 as i64 as u64);
+    Ok(())
+}
+pub fn execute_srlw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let shamt = cpu.read_register(rs2) & 0b11111;
+    let value = (cpu.read_register(rs1) as u32) >> shamt;
+    cpu.write_register(rd, value as i32 as i64 as u64);
+    Ok(())
+}
+pub fn execute_sraw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let shamt = cpu.read_register(rs2) & 0b11111;
+    let value = (cpu.read_register(rs1) as i32) >> shamt;
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+
+// M Extension
+pub fn execute_mul(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1).wrapping_mul(cpu.read_register(rs2));
+    cpu.write_register(rd, value);
+    Ok(())
+}
+// The high variants return the upper 64 bits of the 128-bit product
+pub fn execute_mulh(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let product = cpu.read_register(rs1) as i64 as i128 * cpu.read_register(rs2) as i64 as i128;
+    cpu.write_register(rd, (product >> 64) as u64);
+    Ok(())
+}
+pub fn execute_mulhsu(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let product = cpu.read_register(rs1) as i64 as i128 * cpu.read_register(rs2) as i128;
+    cpu.write_register(rd, (product >> 64) as u64);
+    Ok(())
+}
+pub fn execute_mulhu(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let product = cpu.read_register(rs1) as u128 * cpu.read_register(rs2) as u128;
+    cpu.write_register(rd, (product >> 64) as u64);
+    Ok(())
+}
+// Division never traps: dividing by zero returns all ones for the quotient
+// and the dividend for the remainder, and the signed overflow of the most
+// negative value divided by -1 returns the dividend with a remainder of zero
+pub fn execute_div(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1) as i64;
+    let divisor = cpu.read_register(rs2) as i64;
+    let value = if divisor == 0 {
+        -1
+    } else {
+        dividend.wrapping_div(divisor)
+    };
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_divu(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1);
+    let divisor = cpu.read_register(rs2);
+    let value = dividend.checked_div(divisor).unwrap_or(u64::MAX);
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_rem(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1) as i64;
+    let divisor = cpu.read_register(rs2) as i64;
+    let value = if divisor == 0 {
+        dividend
+    } else {
+        dividend.wrapping_rem(divisor)
+    };
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_remu(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1);
+    let divisor = cpu.read_register(rs2);
+    let value = dividend.checked_rem(divisor).unwrap_or(dividend);
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_mulw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let value = (cpu.read_register(rs1) as i32).wrapping_mul(cpu.read_register(rs2) as i32);
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_divw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1) as i32;
+    let divisor = cpu.read_register(rs2) as i32;
+    let value = if divisor == 0 {
+        -1
+    } else {
+        dividend.wrapping_div(divisor)
+    };
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_divuw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1) as u32;
+    let divisor = cpu.read_register(rs2) as u32;
+    let value = dividend.checked_div(divisor).unwrap_or(u32::MAX);
+    cpu.write_register(rd, value as i32 as i64 as u64);
+    Ok(())
+}
+pub fn execute_remw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1) as i32;
+    let divisor = cpu.read_register(rs2) as i32;
+    let value = if divisor == 0 {
+        dividend
+    } else {
+        dividend.wrapping_rem(divisor)
+    };
+    cpu.write_register(rd, value as i64 as u64);
+    Ok(())
+}
+pub fn execute_remuw(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let dividend = cpu.read_register(rs1) as u32;
+    let divisor = cpu.read_register(rs2) as u32;
+    let value = dividend.checked_rem(divisor).unwrap_or(dividend);
+    cpu.write_register(rd, value as i32 as i64 as u64);
+    Ok(())
+}
+
+// A Extension
+//
+// With a single hart every memory access is already performed in program
+// order, so the aq and rl ordering bits need no further handling.
+
+// Sign extend a `size` byte value loaded from memory
+fn sign_extend(value: u64, size: usize) -> u64 {
+    match size {
+        4 => value as i32 as i64 as u64,
+        _ => value,
+    }
+}
+fn load_reserved(rd: Register, rs1: Register, size: usize, cpu: &mut Cpu) -> Result<(), Exception> {
+    let address = cpu.read_register(rs1);
+    if address & (size as u64 - 1) != 0 {
+        return Err(Exception::LoadAddressMisaligned(address));
+    }
+    let value = cpu.load(address, size)?;
+    cpu.reservation = Some(address);
+    cpu.write_register(rd, sign_extend(value, size));
+    Ok(())
+}
+// Store only if the hart still holds a reservation on the address, writing
+// zero to rd on success and one on failure
+fn store_conditional(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    size: usize,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let address = cpu.read_register(rs1);
+    if address & (size as u64 - 1) != 0 {
+        return Err(Exception::StoreAddressMisaligned(address));
+    }
+    // Every SC gives up the reservation, whether it succeeds or not
+    let reserved = cpu.reservation.take() == Some(address);
+    if reserved {
+        cpu.store(address, size, cpu.read_register(rs2))?;
+    }
+    cpu.write_register(rd, !reserved as u64);
+    Ok(())
+}
+// Replace the value in memory at rs1 with `op(value, rs2)` and return the
+// original value in rd. Word operands are sign extended first, which keeps
+// both the signed and unsigned orderings of the low 32 bits.
+fn amo(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    size: usize,
+    op: fn(u64, u64) -> u64,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let address = cpu.read_register(rs1);
+    if address & (size as u64 - 1) != 0 {
+        return Err(Exception::StoreAddressMisaligned(address));
+    }
+    // AMOs report faults as store faults even on the read
+    let value = cpu
+        .load(address, size)
+        .map_err(|exception| match exception {
+            Exception::LoadPageFault(address) => Exception::StorePageFault(address),
+            _ => Exception::StoreAccessFault(address),
+        })?;
+    let value = sign_extend(value, size);
+    let operand = sign_extend(cpu.read_register(rs2), size);
+    cpu.store(address, size, op(value, operand))?;
+    cpu.write_register(rd, value);
+    Ok(())
+}
+pub fn execute_lr_w(
+    rd: Register,
+    rs1: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    load_reserved(rd, rs1, 4, cpu)
+}
+pub fn execute_sc_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    store_conditional(rd, rs1, rs2, 4, cpu)
+}
+pub fn execute_amoswap_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 4, |_, operand| operand, cpu)
+}
+pub fn execute_amoadd_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(
+        rd,
+        rs1,
+        rs2,
+        4,
+        |value, operand| value.wrapping_add(operand),
+        cpu,
+    )
+}
+pub fn execute_amoxor_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 4, |value, operand| value ^ operand, cpu)
+}
+pub fn execute_amoand_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 4, |value, operand| value & operand, cpu)
+}
+pub fn execute_amoor_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 4, |value, operand| value | operand, cpu)
+}
+pub fn execute_amomin_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(
+        rd,
+        rs1,
+        rs2,
+        4,
+        |value, operand| (value as i64).min(operand as i64) as u64,
+        cpu,
+    )
+}
+pub fn execute_amomax_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(
+        rd,
+        rs1,
+        rs2,
+        4,
+        |value, operand| (value as i64).max(operand as i64) as u64,
+        cpu,
+    )
+}
+pub fn execute_amominu_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 4, |value, operand| value.min(operand), cpu)
+}
+pub fn execute_amomaxu_w(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 4, |value, operand| value.max(operand), cpu)
+}
+pub fn execute_lr_d(
+    rd: Register,
+    rs1: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    load_reserved(rd, rs1, 8, cpu)
+}
+pub fn execute_sc_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    store_conditional(rd, rs1, rs2, 8, cpu)
+}
+pub fn execute_amoswap_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 8, |_, operand| operand, cpu)
+}
+pub fn execute_amoadd_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(
+        rd,
+        rs1,
+        rs2,
+        8,
+        |value, operand| value.wrapping_add(operand),
+        cpu,
+    )
+}
+pub fn execute_amoxor_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 8, |value, operand| value ^ operand, cpu)
+}
+pub fn execute_amoand_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 8, |value, operand| value & operand, cpu)
+}
+pub fn execute_amoor_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 8, |value, operand| value | operand, cpu)
+}
+pub fn execute_amomin_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(
+        rd,
+        rs1,
+        rs2,
+        8,
+        |value, operand| (value as i64).min(operand as i64) as u64,
+        cpu,
+    )
+}
+pub fn execute_amomax_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(
+        rd,
+        rs1,
+        rs2,
+        8,
+        |value, operand| (value as i64).max(operand as i64) as u64,
+        cpu,
+    )
+}
+pub fn execute_amominu_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 8, |value, operand| value.min(operand), cpu)
+}
+pub fn execute_amomaxu_d(
+    rd: Register,
+    rs1: Register,
+    rs2: Register,
+    _aq: bool,
+    _rl: bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    amo(rd, rs1, rs2, 8, |value, operand| value.max(operand), cpu)
+}
+
+// F and D Extensions
+//
+// Single and double precision instructions share these helpers and only
+// differ in the format they pass.
+
+type FloatArithmetic = fn(u64, u64, float::Format, RoundingMode) -> (u64, u32);
+
+fn float_arithmetic(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    format: float::Format,
+    op: FloatArithmetic,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mode = execute::rounding_mode(rm, cpu)?;
+    let a = cpu.read_float(rs1, format);
+    let b = cpu.read_float(rs2, format);
+    let (value, flags) = op(a, b, format, mode);
+    cpu.write_float(rd, format, value);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+fn float_sqrt(
+    rd: FRegister,
+    rs1: FRegister,
+    rm: u32,
+    format: float::Format,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mode = execute::rounding_mode(rm, cpu)?;
+    let (value, flags) = float::sqrt(cpu.read_float(rs1, format), format, mode);
+    cpu.write_float(rd, format, value);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+// Write rs1 to rd with its sign replaced by `sign(rs1 sign, rs2 sign)`
+fn sign_injection(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    format: float::Format,
+    sign: fn(bool, bool) -> bool,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let sign_bit = format.sign_bit();
+    let a = cpu.read_float(rs1, format);
+    let b = cpu.read_float(rs2, format);
+    let negative = sign(a & sign_bit != 0, b & sign_bit != 0);
+    let value = (a & !sign_bit) | if negative { sign_bit } else { 0 };
+    cpu.write_float(rd, format, value);
+    Ok(())
+}
+fn float_min_max(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    format: float::Format,
+    op: fn(u64, u64, float::Format) -> (u64, u32),
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let a = cpu.read_float(rs1, format);
+    let b = cpu.read_float(rs2, format);
+    let (value, flags) = op(a, b, format);
+    cpu.write_float(rd, format, value);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+fn float_compare(
+    rd: Register,
+    rs1: FRegister,
+    rs2: FRegister,
+    format: float::Format,
+    op: fn(u64, u64, float::Format) -> (bool, u32),
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let a = cpu.read_float(rs1, format);
+    let b = cpu.read_float(rs2, format);
+    let (value, flags) = op(a, b, format);
+    cpu.write_register(rd, value as u64);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+fn float_to_integer(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    format: float::Format,
+    signed: bool,
+    width: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mode = execute::rounding_mode(rm, cpu)?;
+    let a = cpu.read_float(rs1, format);
+    let (value, flags) = float::to_integer(a, format, signed, width, mode);
+    cpu.write_register(rd, value);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+fn integer_to_float(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    format: float::Format,
+    signed: bool,
+    width: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mode = execute::rounding_mode(rm, cpu)?;
+    let a = cpu.read_register(rs1);
+    let (value, flags) = float::from_integer(a, signed, width, format, mode);
+    cpu.write_float(rd, format, value);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+fn float_convert(
+    rd: FRegister,
+    rs1: FRegister,
+    rm: u32,
+    from: float::Format,
+    to: float::Format,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mode = execute::rounding_mode(rm, cpu)?;
+    let (value, flags) = float::convert(cpu.read_float(rs1, from), from, to, mode);
+    cpu.write_float(rd, to, value);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+pub fn execute_fadd_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::SINGLE, float::add, cpu)
+}
+pub fn execute_fsub_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::SINGLE, float::sub, cpu)
+}
+pub fn execute_fmul_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::SINGLE, float::mul, cpu)
+}
+pub fn execute_fdiv_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::SINGLE, float::div, cpu)
+}
+pub fn execute_fsqrt_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_sqrt(rd, rs1, rm, float::SINGLE, cpu)
+}
+pub fn execute_fsgnj_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    sign_injection(rd, rs1, rs2, float::SINGLE, |_, b| b, cpu)
+}
+pub fn execute_fsgnjn_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    sign_injection(rd, rs1, rs2, float::SINGLE, |_, b| !b, cpu)
+}
+pub fn execute_fsgnjx_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    sign_injection(rd, rs1, rs2, float::SINGLE, |a, b| a != b, cpu)
+}
+pub fn execute_fmin_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_min_max(rd, rs1, rs2, float::SINGLE, float::min, cpu)
+}
+pub fn execute_fmax_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_min_max(rd, rs1, rs2, float::SINGLE, float::max, cpu)
+}
+pub fn execute_feq_s(
+    rd: Register,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_compare(rd, rs1, rs2, float::SINGLE, float::eq, cpu)
+}
+pub fn execute_flt_s(
+    rd: Register,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_compare(rd, rs1, rs2, float::SINGLE, float::lt, cpu)
+}
+pub fn execute_fle_s(
+    rd: Register,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_compare(rd, rs1, rs2, float::SINGLE, float::le, cpu)
+}
+pub fn execute_fclass_s(rd: Register, rs1: FRegister, cpu: &mut Cpu) -> Result<(), Exception> {
+    cpu.write_register(
+        rd,
+        float::classify(cpu.read_float(rs1, float::SINGLE), float::SINGLE),
+    );
+    Ok(())
+}
+pub fn execute_fcvt_w_s(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::SINGLE, true, 32, cpu)
+}
+pub fn execute_fcvt_wu_s(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::SINGLE, false, 32, cpu)
+}
+pub fn execute_fcvt_l_s(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::SINGLE, true, 64, cpu)
+}
+pub fn execute_fcvt_lu_s(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::SINGLE, false, 64, cpu)
+}
+pub fn execute_fcvt_s_w(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::SINGLE, true, 32, cpu)
+}
+pub fn execute_fcvt_s_wu(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::SINGLE, false, 32, cpu)
+}
+pub fn execute_fcvt_s_l(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::SINGLE, true, 64, cpu)
+}
+pub fn execute_fcvt_s_lu(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::SINGLE, false, 64, cpu)
+}
+// The moves transfer bits unchanged, without checking the NaN-boxing
+pub fn execute_fmv_x_w(rd: Register, rs1: FRegister, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.fregisters[usize::from(rs1)] as i32;
+    cpu.write_register(rd, value as u64);
+    Ok(())
+}
+pub fn execute_fmv_w_x(rd: FRegister, rs1: Register, cpu: &mut Cpu) -> Result<(), Exception> {
+    let value = cpu.read_register(rs1) & 0xffff_ffff;
+    cpu.write_float(rd, float::SINGLE, value);
+    Ok(())
+}
+pub fn execute_fadd_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::DOUBLE, float::add, cpu)
+}
+pub fn execute_fsub_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::DOUBLE, float::sub, cpu)
+}
+pub fn execute_fmul_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::DOUBLE, float::mul, cpu)
+}
+pub fn execute_fdiv_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_arithmetic(rd, rs1, rs2, rm, float::DOUBLE, float::div, cpu)
+}
+pub fn execute_fsqrt_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_sqrt(rd, rs1, rm, float::DOUBLE, cpu)
+}
+pub fn execute_fsgnj_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    sign_injection(rd, rs1, rs2, float::DOUBLE, |_, b| b, cpu)
+}
+pub fn execute_fsgnjn_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    sign_injection(rd, rs1, rs2, float::DOUBLE, |_, b| !b, cpu)
+}
+pub fn execute_fsgnjx_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    sign_injection(rd, rs1, rs2, float::DOUBLE, |a, b| a != b, cpu)
+}
+pub fn execute_fmin_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_min_max(rd, rs1, rs2, float::DOUBLE, float::min, cpu)
+}
+pub fn execute_fmax_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_min_max(rd, rs1, rs2, float::DOUBLE, float::max, cpu)
+}
+pub fn execute_fcvt_s_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_convert(rd, rs1, rm, float::DOUBLE, float::SINGLE, cpu)
+}
+pub fn execute_fcvt_d_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_convert(rd, rs1, rm, float::SINGLE, float::DOUBLE, cpu)
+}
+pub fn execute_feq_d(
+    rd: Register,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_compare(rd, rs1, rs2, float::DOUBLE, float::eq, cpu)
+}
+pub fn execute_flt_d(
+    rd: Register,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_compare(rd, rs1, rs2, float::DOUBLE, float::lt, cpu)
+}
+pub fn execute_fle_d(
+    rd: Register,
+    rs1: FRegister,
+    rs2: FRegister,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_compare(rd, rs1, rs2, float::DOUBLE, float::le, cpu)
+}
+pub fn execute_fclass_d(rd: Register, rs1: FRegister, cpu: &mut Cpu) -> Result<(), Exception> {
+    cpu.write_register(
+        rd,
+        float::classify(cpu.read_float(rs1, float::DOUBLE), float::DOUBLE),
+    );
+    Ok(())
+}
+pub fn execute_fcvt_w_d(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::DOUBLE, true, 32, cpu)
+}
+pub fn execute_fcvt_wu_d(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::DOUBLE, false, 32, cpu)
+}
+pub fn execute_fcvt_l_d(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::DOUBLE, true, 64, cpu)
+}
+pub fn execute_fcvt_lu_d(
+    rd: Register,
+    rs1: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    float_to_integer(rd, rs1, rm, float::DOUBLE, false, 64, cpu)
+}
+pub fn execute_fcvt_d_w(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::DOUBLE, true, 32, cpu)
+}
+pub fn execute_fcvt_d_wu(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::DOUBLE, false, 32, cpu)
+}
+pub fn execute_fcvt_d_l(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::DOUBLE, true, 64, cpu)
+}
+pub fn execute_fcvt_d_lu(
+    rd: FRegister,
+    rs1: Register,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    integer_to_float(rd, rs1, rm, float::DOUBLE, false, 64, cpu)
+}
+pub fn execute_fmv_x_d(rd: Register, rs1: FRegister, cpu: &mut Cpu) -> Result<(), Exception> {
+    cpu.write_register(rd, cpu.fregisters[usize::from(rs1)]);
+    Ok(())
+}
+pub fn execute_fmv_d_x(rd: FRegister, rs1: Register, cpu: &mut Cpu) -> Result<(), Exception> {
+    cpu.write_float(rd, float::DOUBLE, cpu.read_register(rs1));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+    use crate::riscv::cpu::AbiRegister;
+    use crate::riscv::csr;
+
+    // Run an R-type executor with a1 and a2 as sources and return a0
+    fn run(
+        execute: fn(Register, Register, Register, &mut Cpu) -> Result<(), Exception>,
+        a1: u64,
+        a2: u64,
+    ) -> u64 {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_register(AbiRegister::A1.into(), a1);
+        cpu.write_register(AbiRegister::A2.into(), a2);
+        execute(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            AbiRegister::A2.into(),
+            &mut cpu,
+        )
+        .unwrap();
+        cpu.read_register(AbiRegister::A0.into())
+    }
+    #[test]
+    fn execute_add() {
+        assert_eq!(run(super::execute_add, 2, 3), 5);
+        assert_eq!(run(super::execute_add, u64::MAX, 1), 0);
+    }
+    #[test]
+    fn execute_add_discards_x0() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_register(AbiRegister::A1.into(), 7);
+        super::execute_add(
+            AbiRegister::Zero.into(),
+            AbiRegister::A1.into(),
+            AbiRegister::A1.into(),
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::Zero.into()), 0);
+    }
+    #[test]
+    fn execute_sub() {
+        assert_eq!(run(super::execute_sub, 2, 3), -1i64 as u64);
+    }
+    #[test]
+    fn execute_sll() {
+        assert_eq!(run(super::execute_sll, 1, 63), 1 << 63);
+        assert_eq!(run(super::execute_sll, 1, 64 + 4), 16);
+    }
+    #[test]
+    fn execute_slt() {
+        assert_eq!(run(super::execute_slt, -1i64 as u64, 0), 1);
+        assert_eq!(run(super::execute_slt, 0, -1i64 as u64), 0);
+    }
+    #[test]
+    fn execute_sltu() {
+        assert_eq!(run(super::execute_sltu, -1i64 as u64, 0), 0);
+        assert_eq!(run(super::execute_sltu, 0, -1i64 as u64), 1);
+    }
+    #[test]
+    fn execute_xor() {
+        assert_eq!(run(super::execute_xor, 0b1100, 0b1010), 0b0110);
+    }
+    #[test]
+    fn execute_srl() {
+        assert_eq!(run(super::execute_srl, 1 << 63, 63), 1);
+    }
+    #[test]
+    fn execute_sra() {
+        assert_eq!(run(super::execute_sra, 1 << 63, 63), u64::MAX);
+    }
+    #[test]
+    fn execute_or() {
+        assert_eq!(run(super::execute_or, 0b1100, 0b1010), 0b1110);
+    }
+    #[test]
+    fn execute_and() {
+        assert_eq!(run(super::execute_and, 0b1100, 0b1010), 0b1000);
+    }
+    #[test]
+    fn execute_addw() {
+        assert_eq!(
+            run(super::execute_addw, 0x7fff_ffff, 1),
+            0xffff_ffff_8000_0000
+        );
+        assert_eq!(run(super::execute_addw, 0xffff_ffff_0000_0001, 1), 2);
+    }
+    #[test]
+    fn execute_subw() {
+        assert_eq!(run(super::execute_subw, 0, 1), u64::MAX);
+    }
+    #[test]
+    fn execute_sllw() {
+        assert_eq!(run(super::execute_sllw, 1, 31), 0xffff_ffff_8000_0000);
+        assert_eq!(run(super::execute_sllw, 1, 32 + 1), 2);
+    }
+    #[test]
+    fn execute_srlw() {
+        assert_eq!(run(super::execute_srlw, 0xffff_ffff_8000_0000, 31), 1);
+        assert_eq!(
+            run(super::execute_srlw, 0x8000_0000, 0),
+            0xffff_ffff_8000_0000
+        );
+    }
+    #[test]
+    fn execute_sraw() {
+        assert_eq!(run(super::execute_sraw, 0x8000_0000, 31), u64::MAX);
+    }
+    #[test]
+    fn execute_mul() {
+        assert_eq!(run(super::execute_mul, 3, -4i64 as u64), -12i64 as u64);
+        assert_eq!(run(super::execute_mul, 1 << 63, 2), 0);
+    }
+    #[test]
+    fn execute_mulh() {
+        assert_eq!(run(super::execute_mulh, -1i64 as u64, -1i64 as u64), 0);
+        assert_eq!(run(super::execute_mulh, 1 << 62, -4i64 as u64), u64::MAX);
+        assert_eq!(
+            run(super::execute_mulh, i64::MIN as u64, i64::MIN as u64),
+            1 << 62
+        );
+    }
+    #[test]
+    fn execute_mulhsu() {
+        assert_eq!(run(super::execute_mulhsu, -1i64 as u64, u64::MAX), u64::MAX);
+        assert_eq!(run(super::execute_mulhsu, 2, u64::MAX), 1);
+    }
+    #[test]
+    fn execute_mulhu() {
+        assert_eq!(run(super::execute_mulhu, u64::MAX, u64::MAX), u64::MAX - 1);
+        assert_eq!(run(super::execute_mulhu, 1 << 32, 1 << 32), 1);
+    }
+    #[test]
+    fn execute_div() {
+        assert_eq!(run(super::execute_div, -7i64 as u64, 2), -3i64 as u64);
+        assert_eq!(run(super::execute_div, 7, 0), u64::MAX);
+        assert_eq!(
+            run(super::execute_div, i64::MIN as u64, -1i64 as u64),
+            i64::MIN as u64
+        );
+    }
+    #[test]
+    fn execute_divu() {
+        assert_eq!(
+            run(super::execute_divu, -7i64 as u64, 2),
+            (-7i64 as u64) / 2
+        );
+        assert_eq!(run(super::execute_divu, 7, 0), u64::MAX);
+    }
+    #[test]
+    fn execute_rem() {
+        assert_eq!(run(super::execute_rem, -7i64 as u64, 2), -1i64 as u64);
+        assert_eq!(run(super::execute_rem, -7i64 as u64, 0), -7i64 as u64);
+        assert_eq!(run(super::execute_rem, i64::MIN as u64, -1i64 as u64), 0);
+    }
+    #[test]
+    fn execute_remu() {
+        assert_eq!(run(super::execute_remu, 7, 2), 1);
+        assert_eq!(run(super::execute_remu, 7, 0), 7);
+    }
+    #[test]
+    fn execute_mulw() {
+        assert_eq!(
+            run(super::execute_mulw, 0x1_0000_0003, 0x7fff_ffff),
+            0x7fff_fffd
+        );
+        assert_eq!(
+            run(super::execute_mulw, 0x10000, 0x8000),
+            0xffff_ffff_8000_0000
+        );
+    }
+    #[test]
+    fn execute_divw() {
+        assert_eq!(run(super::execute_divw, 0x1_ffff_fff9, 2), -3i64 as u64);
+        assert_eq!(run(super::execute_divw, 7, 0x1_0000_0000), u64::MAX);
+        assert_eq!(
+            run(super::execute_divw, 0x8000_0000, u64::MAX),
+            0xffff_ffff_8000_0000
+        );
+    }
+    #[test]
+    fn execute_divuw() {
+        assert_eq!(run(super::execute_divuw, 0xffff_fff8, 2), 0x7fff_fffc);
+        assert_eq!(run(super::execute_divuw, 7, 0), u64::MAX);
+    }
+    #[test]
+    fn execute_remw() {
+        assert_eq!(run(super::execute_remw, 0xffff_fff9, 2), u64::MAX);
+        assert_eq!(run(super::execute_remw, 0xffff_fff9, 0), -7i64 as u64);
+        assert_eq!(run(super::execute_remw, 0x8000_0000, u64::MAX), 0);
+    }
+    #[test]
+    fn execute_remuw() {
+        assert_eq!(run(super::execute_remuw, 0xffff_fff9, 0x10), 9);
+        assert_eq!(
+            run(super::execute_remuw, 0x1_8000_0000, 0),
+            0xffff_ffff_8000_0000
+        );
+    }
+
+    type AmoExecutor =
+        fn(Register, Register, Register, bool, bool, &mut Cpu) -> Result<(), Exception>;
+
+    // Run an AMO on the value in memory at a1 with a2 as the operand,
+    // returning a0 and the value left in memory
+    fn run_amo(execute: AmoExecutor, memory: u64, a2: u64) -> (u64, u64) {
+        let mut cpu = Cpu::new(Bus::new(16));
+        cpu.bus.write_double(DRAM_BASE, memory).unwrap();
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE);
+        cpu.write_register(AbiRegister::A2.into(), a2);
+        execute(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            AbiRegister::A2.into(),
+            false,
+            false,
+            &mut cpu,
+        )
+        .unwrap();
+        (
+            cpu.read_register(AbiRegister::A0.into()),
+            cpu.bus.read_double(DRAM_BASE).unwrap(),
+        )
+    }
+    #[test]
+    fn execute_lr_sc_w() {
+        let mut cpu = Cpu::new(Bus::new(16));
+        cpu.bus
+            .write_double(DRAM_BASE, 0x1234_5678_8000_0000)
+            .unwrap();
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE);
+        cpu.write_register(AbiRegister::A2.into(), 7);
+        super::execute_lr_w(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            false,
+            false,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(
+            cpu.read_register(AbiRegister::A0.into()),
+            0xffff_ffff_8000_0000
+        );
+        super::execute_sc_w(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            AbiRegister::A2.into(),
+            false,
+            true,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 0);
+        assert_eq!(cpu.bus.read_double(DRAM_BASE), Ok(0x1234_5678_0000_0007));
+        // The reservation was consumed by the first SC
+        super::execute_sc_w(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            AbiRegister::Zero.into(),
+            false,
+            false,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 1);
+        assert_eq!(cpu.bus.read_double(DRAM_BASE), Ok(0x1234_5678_0000_0007));
+    }
+    #[test]
+    fn execute_lr_sc_d() {
+        let mut cpu = Cpu::new(Bus::new(16));
+        cpu.bus.write_double(DRAM_BASE + 8, u64::MAX).unwrap();
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE + 8);
+        super::execute_lr_d(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            true,
+            false,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), u64::MAX);
+        // A reservation on a different address does not match
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE);
+        super::execute_sc_d(
+            AbiRegister::A0.into(),
+            AbiRegister::A1.into(),
+            AbiRegister::Zero.into(),
+            false,
+            false,
+            &mut cpu,
+        )
+        .unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 1);
+        assert_eq!(cpu.reservation, None);
+    }
+    #[test]
+    fn execute_misaligned_atomics() {
+        let mut cpu = Cpu::new(Bus::new(16));
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE + 4);
+        assert_eq!(
+            super::execute_lr_d(
+                AbiRegister::A0.into(),
+                AbiRegister::A1.into(),
+                false,
+                false,
+                &mut cpu
+            ),
+            Err(Exception::LoadAddressMisaligned(DRAM_BASE + 4))
+        );
+        assert_eq!(
+            super::execute_amoadd_d(
+                AbiRegister::A0.into(),
+                AbiRegister::A1.into(),
+                AbiRegister::A2.into(),
+                false,
+                false,
+                &mut cpu
+            ),
+            Err(Exception::StoreAddressMisaligned(DRAM_BASE + 4))
+        );
+        cpu.write_register(AbiRegister::A1.into(), DRAM_BASE + 2);
+        assert_eq!(
+            super::execute_sc_w(
+                AbiRegister::A0.into(),
+                AbiRegister::A1.into(),
+                AbiRegister::A2.into(),
+                false,
+                false,
+                &mut cpu
+            ),
+            Err(Exception::StoreAddressMisaligned(DRAM_BASE + 2))
+        );
+    }
+    #[test]
+    fn execute_amo_access_fault() {
+        let mut cpu = Cpu::new(Bus::new(16));
+        assert_eq!(
+            super::execute_amoswap_w(
+                AbiRegister::A0.into(),
+                AbiRegister::Zero.into(),
+                AbiRegister::A2.into(),
+                false,
+                false,
+                &mut cpu
+            ),
+            Err(Exception::StoreAccessFault(0))
+        );
+    }
+    #[test]
+    fn execute_amoswap_w() {
+        assert_eq!(
+            run_amo(super::execute_amoswap_w, 0xaaaa_aaaa_8000_0001, 0x1234),
+            (0xffff_ffff_8000_0001, 0xaaaa_aaaa_0000_1234)
+        );
+    }
+    #[test]
+    fn execute_amoadd_w() {
+        assert_eq!(
+            run_amo(super::execute_amoadd_w, 0xaaaa_aaaa_ffff_ffff, 2),
+            (u64::MAX, 0xaaaa_aaaa_0000_0001)
+        );
+    }
+    #[test]
+    fn execute_amoxor_w() {
+        assert_eq!(
+            run_amo(super::execute_amoxor_w, 0b1100, 0b1010),
+            (0b1100, 0b0110)
+        );
+    }
+    #[test]
+    fn execute_amoand_w() {
+        assert_eq!(
+            run_amo(super::execute_amoand_w, 0b1100, 0b1010),
+            (0b1100, 0b1000)
+        );
+    }
+    #[test]
+    fn execute_amoor_w() {
+        assert_eq!(
+            run_amo(super::execute_amoor_w, 0b1100, 0b1010),
+            (0b1100, 0b1110)
+        );
+    }
+    #[test]
+    fn execute_amomin_w() {
+        assert_eq!(
+            run_amo(super::execute_amomin_w, 1, 0xffff_ffff),
+            (1, 0xffff_ffff)
+        );
+    }
+    #[test]
+    fn execute_amomax_w() {
+        assert_eq!(run_amo(super::execute_amomax_w, 1, 0xffff_ffff), (1, 1));
+    }
+    #[test]
+    fn execute_amominu_w() {
+        assert_eq!(run_amo(super::execute_amominu_w, 1, 0xffff_ffff), (1, 1));
+    }
+    #[test]
+    fn execute_amomaxu_w() {
+        assert_eq!(
+            run_amo(super::execute_amomaxu_w, 0x8000_0000, 0x7fff_ffff),
+            (0xffff_ffff_8000_0000, 0x8000_0000)
+        );
+    }
+    #[test]
+    fn execute_amoswap_d() {
+        assert_eq!(run_amo(super::execute_amoswap_d, 5, 7), (5, 7));
+    }
+    #[test]
+    fn execute_amoadd_d() {
+        assert_eq!(run_amo(super::execute_amoadd_d, u64::MAX, 2), (u64::MAX, 1));
+    }
+    #[test]
+    fn execute_amoxor_d() {
+        assert_eq!(
+            run_amo(super::execute_amoxor_d, 1 << 63, u64::MAX),
+            (1 << 63, !(1 << 63))
+        );
+    }
+    #[test]
+    fn execute_amoand_d() {
+        assert_eq!(
+            run_amo(super::execute_amoand_d, 1 << 63, u64::MAX),
+            (1 << 63, 1 << 63)
+        );
+    }
+    #[test]
+    fn execute_amoor_d() {
+        assert_eq!(
+            run_amo(super::execute_amoor_d, 1 << 63, 1),
+            (1 << 63, (1 << 63) | 1)
+        );
+    }
+    #[test]
+    fn execute_amomin_d() {
+        assert_eq!(run_amo(super::execute_amomin_d, 1, u64::MAX), (1, u64::MAX));
+    }
+    #[test]
+    fn execute_amomax_d() {
+        assert_eq!(run_amo(super::execute_amomax_d, 1, u64::MAX), (1, 1));
+    }
+    #[test]
+    fn execute_amominu_d() {
+        assert_eq!(run_amo(super::execute_amominu_d, 1, u64::MAX), (1, 1));
+    }
+    #[test]
+    fn execute_amomaxu_d() {
+        assert_eq!(
+            run_amo(super::execute_amomaxu_d, 1, u64::MAX),
+            (1, u64::MAX)
+        );
+    }
+
+    // A CPU with single or double precision values in f1 and f2
+    fn float_cpu(format: float::Format, f1: u64, f2: u64) -> Cpu {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_float(FRegister::F1, format, f1);
+        cpu.write_float(FRegister::F2, format, f2);
+        cpu
+    }
+    // f0 and the accrued exception flags
+    fn float_result(cpu: &Cpu, format: float::Format) -> (u64, u64) {
+        (
+            cpu.read_float(FRegister::F0, format),
+            cpu.csr.read(csr::FFLAGS),
+        )
+    }
+    // Run an arithmetic executor on f1 and f2 with a rounding mode
+    fn run_arithmetic(
+        execute: fn(FRegister, FRegister, FRegister, u32, &mut Cpu) -> Result<(), Exception>,
+        format: float::Format,
+        f1: u64,
+        f2: u64,
+        rm: u32,
+    ) -> (u64, u64) {
+        let mut cpu = float_cpu(format, f1, f2);
+        execute(FRegister::F0, FRegister::F1, FRegister::F2, rm, &mut cpu).unwrap();
+        float_result(&cpu, format)
+    }
+    // Run a sign injection, min or max executor on f1 and f2
+    fn run_binary(
+        execute: fn(FRegister, FRegister, FRegister, &mut Cpu) -> Result<(), Exception>,
+        format: float::Format,
+        f1: u64,
+        f2: u64,
+    ) -> (u64, u64) {
+        let mut cpu = float_cpu(format, f1, f2);
+        execute(FRegister::F0, FRegister::F1, FRegister::F2, &mut cpu).unwrap();
+        float_result(&cpu, format)
+    }
+    // Run a comparison of f1 and f2 and return a0 and the flags
+    fn run_compare(
+        execute: fn(Register, FRegister, FRegister, &mut Cpu) -> Result<(), Exception>,
+        format: float::Format,
+        f1: u64,
+        f2: u64,
+    ) -> (u64, u64) {
+        let mut cpu = float_cpu(format, f1, f2);
+        execute(
+            AbiRegister::A0.into(),
+            FRegister::F1,
+            FRegister::F2,
+            &mut cpu,
+        )
+        .unwrap();
+        (
+            cpu.read_register(AbiRegister::A0.into()),
+            cpu.csr.read(csr::FFLAGS),
+        )
+    }
+    // Convert f1 to an integer in a0
+    fn run_to_integer(
+        execute: fn(Register, FRegister, u32, &mut Cpu) -> Result<(), Exception>,
+        format: float::Format,
+        f1: u64,
+        rm: u32,
+    ) -> (u64, u64) {
+        let mut cpu = float_cpu(format, f1, 0);
+        execute(AbiRegister::A0.into(), FRegister::F1, rm, &mut cpu).unwrap();
+        (
+            cpu.read_register(AbiRegister::A0.into()),
+            cpu.csr.read(csr::FFLAGS),
+        )
+    }
+    // Convert the integer in a1 to f0
+    fn run_from_integer(
+        execute: fn(FRegister, Register, u32, &mut Cpu) -> Result<(), Exception>,
+        format: float::Format,
+        a1: u64,
+        rm: u32,
+    ) -> (u64, u64) {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_register(AbiRegister::A1.into(), a1);
+        execute(FRegister::F0, AbiRegister::A1.into(), rm, &mut cpu).unwrap();
+        float_result(&cpu, format)
+    }
+    fn single(value: f32) -> u64 {
+        u64::from(value.to_bits())
+    }
+    fn double(value: f64) -> u64 {
+        value.to_bits()
+    }
+    const INEXACT: u64 = float::INEXACT as u64;
+    const INVALID: u64 = float::INVALID as u64;
+    #[test]
+    fn execute_fadd_s() {
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fadd_s,
+                float::SINGLE,
+                single(1.5),
+                single(2.25),
+                0
+            ),
+            (single(3.75), 0)
+        );
+        // 1 + 2^-24 is halfway between 1 and the next single
+        let tiny = single(2f32.powi(-24));
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fadd_s,
+                float::SINGLE,
+                single(1.0),
+                tiny,
+                0b000
+            ),
+            (single(1.0), INEXACT)
+        );
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fadd_s,
+                float::SINGLE,
+                single(1.0),
+                tiny,
+                0b011
+            ),
+            (single(1.0) + 1, INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fsub_s() {
+        // Exact cancellation is -0 only when rounding down
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fsub_s,
+                float::SINGLE,
+                single(1.0),
+                single(1.0),
+                0
+            ),
+            (single(0.0), 0)
+        );
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fsub_s,
+                float::SINGLE,
+                single(1.0),
+                single(1.0),
+                0b010
+            ),
+            (single(-0.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fmul_s() {
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fmul_s,
+                float::SINGLE,
+                single(f32::MAX),
+                single(2.0),
+                0
+            ),
+            (single(f32::INFINITY), u64::from(float::OVERFLOW) | INEXACT)
+        );
+        // Overflow rounds to the largest finite number toward zero
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fmul_s,
+                float::SINGLE,
+                single(f32::MAX),
+                single(2.0),
+                0b001
+            ),
+            (single(f32::MAX), u64::from(float::OVERFLOW) | INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fdiv_s() {
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fdiv_s,
+                float::SINGLE,
+                single(1.0),
+                single(0.0),
+                0
+            ),
+            (single(f32::INFINITY), u64::from(float::DIVIDE_BY_ZERO))
+        );
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fdiv_s,
+                float::SINGLE,
+                single(0.0),
+                single(0.0),
+                0
+            ),
+            (float::SINGLE.canonical_nan(), INVALID)
+        );
+    }
+    #[test]
+    fn execute_fsqrt_s() {
+        let mut cpu = float_cpu(float::SINGLE, single(2.0), 0);
+        super::execute_fsqrt_s(FRegister::F0, FRegister::F1, 0b011, &mut cpu).unwrap();
+        // Rounded up, where round to nearest gives the value below
+        assert_eq!(
+            float_result(&cpu, float::SINGLE),
+            (single(2f32.sqrt()) + 1, INEXACT)
+        );
+        cpu.write_float(FRegister::F1, float::SINGLE, single(-1.0));
+        super::execute_fsqrt_s(FRegister::F0, FRegister::F1, 0b000, &mut cpu).unwrap();
+        assert_eq!(
+            float_result(&cpu, float::SINGLE),
+            (float::SINGLE.canonical_nan(), INVALID | INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fsgnj_s() {
+        assert_eq!(
+            run_binary(
+                super::execute_fsgnj_s,
+                float::SINGLE,
+                single(1.0),
+                single(-2.0)
+            ),
+            (single(-1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fsgnjn_s() {
+        assert_eq!(
+            run_binary(
+                super::execute_fsgnjn_s,
+                float::SINGLE,
+                single(1.0),
+                single(-2.0)
+            ),
+            (single(1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fsgnjx_s() {
+        assert_eq!(
+            run_binary(
+                super::execute_fsgnjx_s,
+                float::SINGLE,
+                single(-1.0),
+                single(-2.0)
+            ),
+            (single(1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fmin_s() {
+        assert_eq!(
+            run_binary(
+                super::execute_fmin_s,
+                float::SINGLE,
+                single(0.0),
+                single(-0.0)
+            ),
+            (single(-0.0), 0)
+        );
+        // A quiet NaN operand is ignored
+        assert_eq!(
+            run_binary(
+                super::execute_fmin_s,
+                float::SINGLE,
+                single(f32::NAN),
+                single(3.0)
+            ),
+            (single(3.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fmax_s() {
+        assert_eq!(
+            run_binary(
+                super::execute_fmax_s,
+                float::SINGLE,
+                single(-0.0),
+                single(0.0)
+            ),
+            (single(0.0), 0)
+        );
+        // Signaling NaNs still raise invalid
+        assert_eq!(
+            run_binary(
+                super::execute_fmax_s,
+                float::SINGLE,
+                0x7f80_0001,
+                single(-3.0)
+            ),
+            (single(-3.0), INVALID)
+        );
+    }
+    #[test]
+    fn execute_feq_s() {
+        assert_eq!(
+            run_compare(
+                super::execute_feq_s,
+                float::SINGLE,
+                single(0.0),
+                single(-0.0)
+            ),
+            (1, 0)
+        );
+        // Quiet comparisons don't raise invalid for quiet NaNs
+        assert_eq!(
+            run_compare(
+                super::execute_feq_s,
+                float::SINGLE,
+                single(f32::NAN),
+                single(0.0)
+            ),
+            (0, 0)
+        );
+    }
+    #[test]
+    fn execute_flt_s() {
+        assert_eq!(
+            run_compare(
+                super::execute_flt_s,
+                float::SINGLE,
+                single(-1.0),
+                single(0.0)
+            ),
+            (1, 0)
+        );
+        assert_eq!(
+            run_compare(
+                super::execute_flt_s,
+                float::SINGLE,
+                single(f32::NAN),
+                single(0.0)
+            ),
+            (0, INVALID)
+        );
+    }
+    #[test]
+    fn execute_fle_s() {
+        assert_eq!(
+            run_compare(
+                super::execute_fle_s,
+                float::SINGLE,
+                single(2.0),
+                single(2.0)
+            ),
+            (1, 0)
+        );
+    }
+    #[test]
+    fn execute_fclass_s() {
+        let mut cpu = float_cpu(float::SINGLE, single(-0.0), 0);
+        super::execute_fclass_s(AbiRegister::A0.into(), FRegister::F1, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 1 << 3);
+        // Improperly NaN-boxed values are the canonical NaN
+        cpu.fregisters[1] = single(1.0);
+        super::execute_fclass_s(AbiRegister::A0.into(), FRegister::F1, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 1 << 9);
+    }
+    #[test]
+    fn execute_fcvt_w_s() {
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_w_s, float::SINGLE, single(-2.5), 0b000),
+            (-2i64 as u64, INEXACT)
+        );
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_w_s, float::SINGLE, single(-2.5), 0b100),
+            (-3i64 as u64, INEXACT)
+        );
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_w_s, float::SINGLE, single(f32::NAN), 0),
+            (i32::MAX as u64, INVALID)
+        );
+    }
+    #[test]
+    fn execute_fcvt_wu_s() {
+        // 32-bit results are sign extended
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_wu_s, float::SINGLE, single(4e9), 0),
+            (4_000_000_000u32 as i32 as u64, 0)
+        );
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_wu_s, float::SINGLE, single(-1.0), 0),
+            (0, INVALID)
+        );
+    }
+    #[test]
+    fn execute_fcvt_l_s() {
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_l_s, float::SINGLE, single(-1e19), 0),
+            (i64::MIN as u64, INVALID)
+        );
+    }
+    #[test]
+    fn execute_fcvt_lu_s() {
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_lu_s, float::SINGLE, single(1e19), 0),
+            (9_999_999_980_506_447_872, 0)
+        );
+    }
+    #[test]
+    fn execute_fcvt_s_w() {
+        assert_eq!(
+            run_from_integer(super::execute_fcvt_s_w, float::SINGLE, -3i64 as u64, 0),
+            (single(-3.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fcvt_s_wu() {
+        // Only the low 32 bits are converted
+        assert_eq!(
+            run_from_integer(super::execute_fcvt_s_wu, float::SINGLE, u64::MAX, 0b001),
+            (single(4294967040.0), INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fcvt_s_l() {
+        assert_eq!(
+            run_from_integer(super::execute_fcvt_s_l, float::SINGLE, i64::MIN as u64, 0),
+            (single(-9.223372e18), 0)
+        );
+    }
+    #[test]
+    fn execute_fcvt_s_lu() {
+        assert_eq!(
+            run_from_integer(
+                super::execute_fcvt_s_lu,
+                float::SINGLE,
+                (1 << 24) + 1,
+                0b011
+            ),
+            (single(16777218.0), INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fmv_x_w() {
+        let mut cpu = float_cpu(float::SINGLE, single(-1.0), 0);
+        super::execute_fmv_x_w(AbiRegister::A0.into(), FRegister::F1, &mut cpu).unwrap();
+        assert_eq!(
+            cpu.read_register(AbiRegister::A0.into()),
+            0xffff_ffff_bf80_0000
+        );
+    }
+    #[test]
+    fn execute_fmv_w_x() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_register(AbiRegister::A1.into(), 0x1234_5678_3f80_0000);
+        super::execute_fmv_w_x(FRegister::F0, AbiRegister::A1.into(), &mut cpu).unwrap();
+        assert_eq!(cpu.fregisters[0], 0xffff_ffff_3f80_0000);
+    }
+    #[test]
+    fn execute_fadd_d() {
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fadd_d,
+                float::DOUBLE,
+                double(0.1),
+                double(0.2),
+                0
+            ),
+            (double(0.1 + 0.2), INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fsub_d() {
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fsub_d,
+                float::DOUBLE,
+                double(f64::INFINITY),
+                double(f64::INFINITY),
+                0
+            ),
+            (float::DOUBLE.canonical_nan(), INVALID)
+        );
+    }
+    #[test]
+    fn execute_fmul_d() {
+        // Halving the smallest odd normal number is a tie, which rounds to
+        // the even subnormal
+        let min = double(f64::MIN_POSITIVE) | 1;
+        assert_eq!(
+            run_arithmetic(super::execute_fmul_d, float::DOUBLE, min, double(0.5), 0),
+            (
+                double(f64::MIN_POSITIVE / 2.0),
+                u64::from(float::UNDERFLOW) | INEXACT
+            )
+        );
+    }
+    #[test]
+    fn execute_fdiv_d() {
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fdiv_d,
+                float::DOUBLE,
+                double(1.0),
+                double(3.0),
+                0b010
+            ),
+            (double(1.0 / 3.0), INEXACT)
+        );
+        assert_eq!(
+            run_arithmetic(
+                super::execute_fdiv_d,
+                float::DOUBLE,
+                double(1.0),
+                double(3.0),
+                0b011
+            ),
+            (double(1.0 / 3.0) + 1, INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fsqrt_d() {
+        let mut cpu = float_cpu(float::DOUBLE, double(6.25), 0);
+        super::execute_fsqrt_d(FRegister::F0, FRegister::F1, 0, &mut cpu).unwrap();
+        assert_eq!(float_result(&cpu, float::DOUBLE), (double(2.5), 0));
+    }
+    #[test]
+    fn execute_fsgnj_d() {
+        assert_eq!(
+            run_binary(
+                super::execute_fsgnj_d,
+                float::DOUBLE,
+                double(-1.0),
+                double(2.0)
+            ),
+            (double(1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fsgnjn_d() {
+        assert_eq!(
+            run_binary(
+                super::execute_fsgnjn_d,
+                float::DOUBLE,
+                double(1.0),
+                double(2.0)
+            ),
+            (double(-1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fsgnjx_d() {
+        assert_eq!(
+            run_binary(
+                super::execute_fsgnjx_d,
+                float::DOUBLE,
+                double(1.0),
+                double(-2.0)
+            ),
+            (double(-1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fmin_d() {
+        assert_eq!(
+            run_binary(
+                super::execute_fmin_d,
+                float::DOUBLE,
+                double(f64::NAN),
+                double(f64::NAN)
+            ),
+            (float::DOUBLE.canonical_nan(), 0)
+        );
+    }
+    #[test]
+    fn execute_fmax_d() {
+        assert_eq!(
+            run_binary(
+                super::execute_fmax_d,
+                float::DOUBLE,
+                double(-1.0),
+                double(-2.0)
+            ),
+            (double(-1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fcvt_s_d() {
+        let mut cpu = float_cpu(float::DOUBLE, double(0.1), 0);
+        super::execute_fcvt_s_d(FRegister::F0, FRegister::F1, 0b001, &mut cpu).unwrap();
+        assert_eq!(
+            float_result(&cpu, float::SINGLE),
+            (single(0.1) - 1, INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fcvt_d_s() {
+        let mut cpu = float_cpu(float::SINGLE, 0x7f80_0001, 0);
+        super::execute_fcvt_d_s(FRegister::F0, FRegister::F1, 0, &mut cpu).unwrap();
+        assert_eq!(
+            float_result(&cpu, float::DOUBLE),
+            (float::DOUBLE.canonical_nan(), INVALID)
+        );
+    }
+    #[test]
+    fn execute_feq_d() {
+        assert_eq!(
+            run_compare(
+                super::execute_feq_d,
+                float::DOUBLE,
+                double(1.0),
+                double(2.0)
+            ),
+            (0, 0)
+        );
+    }
+    #[test]
+    fn execute_flt_d() {
+        assert_eq!(
+            run_compare(
+                super::execute_flt_d,
+                float::DOUBLE,
+                double(-0.0),
+                double(0.0)
+            ),
+            (0, 0)
+        );
+    }
+    #[test]
+    fn execute_fle_d() {
+        assert_eq!(
+            run_compare(
+                super::execute_fle_d,
+                float::DOUBLE,
+                double(-0.0),
+                double(0.0)
+            ),
+            (1, 0)
+        );
+    }
+    #[test]
+    fn execute_fclass_d() {
+        let mut cpu = float_cpu(float::DOUBLE, double(f64::NEG_INFINITY), 1);
+        super::execute_fclass_d(AbiRegister::A0.into(), FRegister::F1, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 1 << 0);
+        super::execute_fclass_d(AbiRegister::A0.into(), FRegister::F2, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 1 << 5);
+    }
+    #[test]
+    fn execute_fcvt_w_d() {
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_w_d, float::DOUBLE, double(-1.5), 0b010),
+            (-2i64 as u64, INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fcvt_wu_d() {
+        assert_eq!(
+            run_to_integer(super::execute_fcvt_wu_d, float::DOUBLE, double(-0.5), 0b001),
+            (0, INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fcvt_l_d() {
+        assert_eq!(
+            run_to_integer(
+                super::execute_fcvt_l_d,
+                float::DOUBLE,
+                double(-(2f64.powi(62))),
+                0
+            ),
+            (-(1i64 << 62) as u64, 0)
+        );
+    }
+    #[test]
+    fn execute_fcvt_lu_d() {
+        assert_eq!(
+            run_to_integer(
+                super::execute_fcvt_lu_d,
+                float::DOUBLE,
+                double(f64::INFINITY),
+                0
+            ),
+            (u64::MAX, INVALID)
+        );
+    }
+    #[test]
+    fn execute_fcvt_d_w() {
+        assert_eq!(
+            run_from_integer(super::execute_fcvt_d_w, float::DOUBLE, 0xffff_ffff, 0),
+            (double(-1.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fcvt_d_wu() {
+        assert_eq!(
+            run_from_integer(super::execute_fcvt_d_wu, float::DOUBLE, 0xffff_ffff, 0),
+            (double(4294967295.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fcvt_d_l() {
+        assert_eq!(
+            run_from_integer(
+                super::execute_fcvt_d_l,
+                float::DOUBLE,
+                i64::MAX as u64,
+                0b001
+            ),
+            (double(i64::MAX as f64) - 1, INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fcvt_d_lu() {
+        assert_eq!(
+            run_from_integer(super::execute_fcvt_d_lu, float::DOUBLE, u64::MAX, 0),
+            (double(u64::MAX as f64), INEXACT)
+        );
+    }
+    #[test]
+    fn execute_fmv_x_d() {
+        let mut cpu = float_cpu(float::DOUBLE, double(-2.0), 0);
+        super::execute_fmv_x_d(AbiRegister::A0.into(), FRegister::F1, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), double(-2.0));
+    }
+    #[test]
+    fn execute_fmv_d_x() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_register(AbiRegister::A1.into(), double(3.0));
+        super::execute_fmv_d_x(FRegister::F0, AbiRegister::A1.into(), &mut cpu).unwrap();
+        assert_eq!(cpu.fregisters[0], double(3.0));
+    }
+    #[test]
+    fn dynamic_rounding_mode() {
+        let mut cpu = float_cpu(float::DOUBLE, double(1.0), double(3.0));
+        cpu.csr.write(csr::FRM, 0b011);
+        super::execute_fdiv_d(FRegister::F0, FRegister::F1, FRegister::F2, 0b111, &mut cpu)
+            .unwrap();
+        assert_eq!(cpu.fregisters[0], double(1.0 / 3.0) + 1);
+        // Reserved rounding modes are illegal, whether static or in frm
+        assert_eq!(
+            super::execute_fdiv_d(FRegister::F0, FRegister::F1, FRegister::F2, 0b101, &mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+        cpu.csr.write(csr::FRM, 0b110);
+        assert_eq!(
+            super::execute_fdiv_d(FRegister::F0, FRegister::F1, FRegister::F2, 0b111, &mut cpu),
+            Err(Exception::IllegalInstruction(0))
+        );
+    }
+}
@@ -0,0 +1,222 @@
+use crate::riscv::cpu::Cpu;
+use crate::riscv::cpu::FRegister;
+use crate::riscv::execute;
+use crate::riscv::float;
+use crate::riscv::trap::Exception;
+
+// rs1 * rs2 + rs3 with a single rounding, negating the product and the
+// addend as requested
+fn fused_multiply_add(
+    rd: FRegister,
+    [rs1, rs2, rs3]: [FRegister; 3],
+    rm: u32,
+    negate_product: bool,
+    negate_addend: bool,
+    format: float::Format,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let mode = execute::rounding_mode(rm, cpu)?;
+    let (value, flags) = float::fused_multiply_add(
+        cpu.read_float(rs1, format),
+        cpu.read_float(rs2, format),
+        cpu.read_float(rs3, format),
+        negate_product,
+        negate_addend,
+        format,
+        mode,
+    );
+    cpu.write_float(rd, format, value);
+    cpu.csr.accrue_fflags(flags);
+    Ok(())
+}
+
+// F Extension
+
+pub fn execute_fmadd_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, false, false, float::SINGLE, cpu)
+}
+pub fn execute_fmsub_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, false, true, float::SINGLE, cpu)
+}
+pub fn execute_fnmsub_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, true, false, float::SINGLE, cpu)
+}
+pub fn execute_fnmadd_s(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, true, true, float::SINGLE, cpu)
+}
+
+// D Extension
+
+pub fn execute_fmadd_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, false, false, float::DOUBLE, cpu)
+}
+pub fn execute_fmsub_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, false, true, float::DOUBLE, cpu)
+}
+pub fn execute_fnmsub_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, true, false, float::DOUBLE, cpu)
+}
+pub fn execute_fnmadd_d(
+    rd: FRegister,
+    rs1: FRegister,
+    rs2: FRegister,
+    rs3: FRegister,
+    rm: u32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    fused_multiply_add(rd, [rs1, rs2, rs3], rm, true, true, float::DOUBLE, cpu)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::Bus;
+    use crate::riscv::csr;
+
+    type FusedExecutor =
+        fn(FRegister, FRegister, FRegister, FRegister, u32, &mut Cpu) -> Result<(), Exception>;
+
+    // Run a fused executor on f1, f2 and f3 and return f0 and the flags
+    fn run(execute: FusedExecutor, format: float::Format, operands: [u64; 3]) -> (u64, u64) {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.write_float(FRegister::F1, format, operands[0]);
+        cpu.write_float(FRegister::F2, format, operands[1]);
+        cpu.write_float(FRegister::F3, format, operands[2]);
+        execute(
+            FRegister::F0,
+            FRegister::F1,
+            FRegister::F2,
+            FRegister::F3,
+            0b000,
+            &mut cpu,
+        )
+        .unwrap();
+        (
+            cpu.read_float(FRegister::F0, format),
+            cpu.csr.read(csr::FFLAGS),
+        )
+    }
+    fn single(value: f32) -> u64 {
+        u64::from(value.to_bits())
+    }
+    fn double(value: f64) -> u64 {
+        value.to_bits()
+    }
+    #[test]
+    fn execute_fmadd_s() {
+        let operands = [single(2.0), single(3.0), single(1.0)];
+        assert_eq!(
+            run(super::execute_fmadd_s, float::SINGLE, operands),
+            (single(7.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fmsub_s() {
+        let operands = [single(2.0), single(3.0), single(1.0)];
+        assert_eq!(
+            run(super::execute_fmsub_s, float::SINGLE, operands),
+            (single(5.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fnmsub_s() {
+        let operands = [single(2.0), single(3.0), single(1.0)];
+        assert_eq!(
+            run(super::execute_fnmsub_s, float::SINGLE, operands),
+            (single(-5.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fnmadd_s() {
+        let operands = [single(2.0), single(3.0), single(1.0)];
+        assert_eq!(
+            run(super::execute_fnmadd_s, float::SINGLE, operands),
+            (single(-7.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fmadd_d() {
+        // The product is not rounded before the addition
+        let x = 1.0 + f64::EPSILON;
+        let operands = [double(x), double(x), double(-1.0)];
+        assert_eq!(
+            run(super::execute_fmadd_d, float::DOUBLE, operands),
+            (double(x.mul_add(x, -1.0)), u64::from(float::INEXACT))
+        );
+    }
+    #[test]
+    fn execute_fmsub_d() {
+        // Exact, although the rounded product would be 1.0
+        let operands = [double(0.1), double(10.0), double(1.0)];
+        assert_eq!(
+            run(super::execute_fmsub_d, float::DOUBLE, operands),
+            (double(2f64.powi(-54)), 0)
+        );
+    }
+    #[test]
+    fn execute_fnmsub_d() {
+        let operands = [double(2.0), double(3.0), double(1.0)];
+        assert_eq!(
+            run(super::execute_fnmsub_d, float::DOUBLE, operands),
+            (double(-5.0), 0)
+        );
+    }
+    #[test]
+    fn execute_fnmadd_d() {
+        // Infinity times zero is invalid even with a quiet NaN addend
+        let operands = [double(f64::INFINITY), double(0.0), double(f64::NAN)];
+        assert_eq!(
+            run(super::execute_fnmadd_d, float::DOUBLE, operands),
+            (float::DOUBLE.canonical_nan(), u64::from(float::INVALID))
+        );
+    }
+}
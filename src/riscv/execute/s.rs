@@ -1,7 +1,113 @@
 use crate::riscv::cpu::Cpu;
+use crate::riscv::cpu::FRegister;
 use crate::riscv::cpu::Register;
+use crate::riscv::trap::Exception;
 
-pub fn execute_sb(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_sh(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_sw(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_sd(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) {}
+fn store(
+    rs2: Register,
+    rs1: Register,
+    imm: i32,
+    size: usize,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let address = cpu.read_register(rs1).wrapping_add(imm as i64 as u64);
+    cpu.store(address, size, cpu.read_register(rs2))
+}
+
+pub fn execute_sb(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    store(rs2, rs1, imm, 1, cpu)
+}
+pub fn execute_sh(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    store(rs2, rs1, imm, 2, cpu)
+}
+pub fn execute_sw(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    store(rs2, rs1, imm, 4, cpu)
+}
+pub fn execute_sd(rs2: Register, rs1: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    store(rs2, rs1, imm, 8, cpu)
+}
+// Floating point stores write the register bits unchanged, NaN-boxed or not
+pub fn execute_fsw(
+    rs2: FRegister,
+    rs1: Register,
+    imm: i32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let address = cpu.read_register(rs1).wrapping_add(imm as i64 as u64);
+    cpu.store(address, 4, cpu.fregisters[usize::from(rs2)])
+}
+pub fn execute_fsd(
+    rs2: FRegister,
+    rs1: Register,
+    imm: i32,
+    cpu: &mut Cpu,
+) -> Result<(), Exception> {
+    let address = cpu.read_register(rs1).wrapping_add(imm as i64 as u64);
+    cpu.store(address, 8, cpu.fregisters[usize::from(rs2)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+    use crate::riscv::cpu::AbiRegister;
+
+    // Store 0x1122334455667788 from a1 to sp - 8 and return the memory
+    fn run(execute: fn(Register, Register, i32, &mut Cpu) -> Result<(), Exception>) -> Vec<u8> {
+        let mut cpu = Cpu::new(Bus::new(16));
+        cpu.write_register(AbiRegister::Sp.into(), DRAM_BASE + 12);
+        cpu.write_register(AbiRegister::A1.into(), 0x1122_3344_5566_7788);
+        execute(AbiRegister::A1.into(), AbiRegister::Sp.into(), -8, &mut cpu).unwrap();
+        (4..12)
+            .map(|i| cpu.bus.read_byte(DRAM_BASE + i).unwrap())
+            .collect()
+    }
+    #[test]
+    fn execute_sb() {
+        assert_eq!(run(super::execute_sb), vec![0x88, 0, 0, 0, 0, 0, 0, 0]);
+    }
+    #[test]
+    fn execute_sh() {
+        assert_eq!(run(super::execute_sh), vec![0x88, 0x77, 0, 0, 0, 0, 0, 0]);
+    }
+    #[test]
+    fn execute_sw() {
+        assert_eq!(
+            run(super::execute_sw),
+            vec![0x88, 0x77, 0x66, 0x55, 0, 0, 0, 0]
+        );
+    }
+    #[test]
+    fn execute_sd() {
+        assert_eq!(
+            run(super::execute_sd),
+            vec![0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]
+        );
+    }
+    // Store 0x1122334455667788 from f1 to sp - 8 and return the memory
+    fn run_float(
+        execute: fn(FRegister, Register, i32, &mut Cpu) -> Result<(), Exception>,
+    ) -> Vec<u8> {
+        let mut cpu = Cpu::new(Bus::new(16));
+        cpu.write_register(AbiRegister::Sp.into(), DRAM_BASE + 12);
+        cpu.fregisters[1] = 0x1122_3344_5566_7788;
+        execute(FRegister::F1, AbiRegister::Sp.into(), -8, &mut cpu).unwrap();
+        (4..12)
+            .map(|i| cpu.bus.read_byte(DRAM_BASE + i).unwrap())
+            .collect()
+    }
+    #[test]
+    fn execute_fsw() {
+        assert_eq!(
+            run_float(super::execute_fsw),
+            vec![0x88, 0x77, 0x66, 0x55, 0, 0, 0, 0]
+        );
+    }
+    #[test]
+    fn execute_fsd() {
+        assert_eq!(
+            run_float(super::execute_fsd),
+            vec![0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11]
+        );
+    }
+}
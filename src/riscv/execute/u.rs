@@ -1,5 +1,47 @@
 use crate::riscv::cpu::Cpu;
 use crate::riscv::cpu::Register;
+use crate::riscv::trap::Exception;
 
-pub fn execute_auipc(rd: Register, imm: i32, cpu: &mut Cpu) {}
-pub fn execute_lui(rd: Register, imm: i32, cpu: &mut Cpu) {}
+// The decoded immediate holds bits 31:12, sign extended from bit 31
+fn upper_immediate(imm: i32) -> u64 {
+    (imm << 12) as i64 as u64
+}
+
+pub fn execute_auipc(rd: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    cpu.write_register(rd, cpu.pc.wrapping_add(upper_immediate(imm)));
+    Ok(())
+}
+pub fn execute_lui(rd: Register, imm: i32, cpu: &mut Cpu) -> Result<(), Exception> {
+    cpu.write_register(rd, upper_immediate(imm));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::Bus;
+    use crate::riscv::cpu::AbiRegister;
+
+    #[test]
+    fn execute_auipc() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        cpu.pc = 0x1000;
+        super::execute_auipc(AbiRegister::T0.into(), 0x2, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::T0.into()), 0x3000);
+        super::execute_auipc(AbiRegister::T0.into(), 0xfffff, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::T0.into()), 0);
+    }
+    #[test]
+    fn execute_lui() {
+        let mut cpu = Cpu::new(Bus::new(0));
+        super::execute_lui(AbiRegister::A0.into(), 0x10, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::A0.into()), 0x10000);
+        super::execute_lui(AbiRegister::A0.into(), 0x80000, &mut cpu).unwrap();
+        assert_eq!(
+            cpu.read_register(AbiRegister::A0.into()),
+            0xffff_ffff_8000_0000
+        );
+        super::execute_lui(AbiRegister::Zero.into(), 0x10, &mut cpu).unwrap();
+        assert_eq!(cpu.read_register(AbiRegister::Zero.into()), 0);
+    }
+}
@@ -0,0 +1,54 @@
+// Test finisher laid out as in the SiFive test device, through which bare
+// metal programs end the emulation. Writing PASS to its register exits with
+// status 0, and FAIL with the status in the upper 16 bits.
+
+pub const PASS: u64 = 0x5555;
+pub const FAIL: u64 = 0x3333;
+
+#[derive(Default)]
+pub struct Finisher {
+    status: Option<i32>,
+}
+impl Finisher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // The status written to the device, once the program has finished
+    pub fn status(&self) -> Option<i32> {
+        self.status
+    }
+    pub fn read(&self, _offset: u64, _size: usize) -> u64 {
+        0
+    }
+    // Only whole writes of the 32 bit register at offset 0 count, and
+    // values with another code in the lower 16 bits are ignored
+    pub fn write(&mut self, offset: u64, size: usize, value: u64) {
+        if offset != 0 || size < 4 {
+            return;
+        }
+        match value & 0xffff {
+            PASS => self.status = Some(0),
+            FAIL => self.status = Some(((value >> 16) & 0xffff) as i32),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pass_and_fail() {
+        let mut finisher = Finisher::new();
+        finisher.write(0, 4, 0x1234);
+        finisher.write(0, 2, PASS);
+        finisher.write(4, 4, PASS);
+        assert_eq!(finisher.status(), None);
+        finisher.write(0, 4, 3 << 16 | FAIL);
+        assert_eq!(finisher.status(), Some(3));
+        finisher.write(0, 8, PASS);
+        assert_eq!(finisher.status(), Some(0));
+        assert_eq!(finisher.read(0, 4), 0);
+    }
+}
@@ -0,0 +1,869 @@
+// IEEE 754 binary floating point in software. Host floats only round to
+// nearest and don't report exception flags, so every operation is carried
+// out on integer significands and rounded here instead. Values are passed
+// around as their bit patterns, in the low bits of a u64.
+
+// Exception flags, as laid out in fflags
+pub const INEXACT: u32 = 1 << 0;
+pub const UNDERFLOW: u32 = 1 << 1;
+pub const OVERFLOW: u32 = 1 << 2;
+pub const DIVIDE_BY_ZERO: u32 = 1 << 3;
+pub const INVALID: u32 = 1 << 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoundingMode {
+    NearestEven,
+    TowardZero,
+    Down,
+    Up,
+    NearestMaxMagnitude,
+}
+impl RoundingMode {
+    // Decode an rm or frm field. The dynamic rounding mode is resolved by
+    // the caller.
+    pub fn from_bits(bits: u32) -> Option<Self> {
+        match bits {
+            0b000 => Some(RoundingMode::NearestEven),
+            0b001 => Some(RoundingMode::TowardZero),
+            0b010 => Some(RoundingMode::Down),
+            0b011 => Some(RoundingMode::Up),
+            0b100 => Some(RoundingMode::NearestMaxMagnitude),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+    exponent_bits: u32,
+    fraction_bits: u32,
+}
+pub const SINGLE: Format = Format {
+    exponent_bits: 8,
+    fraction_bits: 23,
+};
+pub const DOUBLE: Format = Format {
+    exponent_bits: 11,
+    fraction_bits: 52,
+};
+impl Format {
+    fn bias(self) -> i32 {
+        (1 << (self.exponent_bits - 1)) - 1
+    }
+    // Biased exponent of infinities and NaNs
+    fn max_exponent(self) -> u64 {
+        (1 << self.exponent_bits) - 1
+    }
+    fn fraction_mask(self) -> u64 {
+        (1 << self.fraction_bits) - 1
+    }
+    pub fn sign_bit(self) -> u64 {
+        1 << (self.exponent_bits + self.fraction_bits)
+    }
+    fn sign(self, sign: bool) -> u64 {
+        if sign {
+            self.sign_bit()
+        } else {
+            0
+        }
+    }
+    // The quiet NaN that RISC-V returns from any operation producing a NaN
+    pub fn canonical_nan(self) -> u64 {
+        self.max_exponent() << self.fraction_bits | 1 << (self.fraction_bits - 1)
+    }
+    fn zero(self, sign: bool) -> u64 {
+        self.sign(sign)
+    }
+    fn infinity(self, sign: bool) -> u64 {
+        self.sign(sign) | self.max_exponent() << self.fraction_bits
+    }
+    fn largest(self, sign: bool) -> u64 {
+        self.infinity(sign) - 1
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Zero,
+    // significand * 2^exponent
+    Finite { exponent: i32, significand: u128 },
+    Infinity,
+    NaN { signaling: bool },
+}
+
+fn unpack(bits: u64, format: Format) -> (bool, Value) {
+    let sign = bits & format.sign_bit() != 0;
+    let biased = (bits >> format.fraction_bits) & format.max_exponent();
+    let fraction = bits & format.fraction_mask();
+    let exponent = biased as i32 - format.bias() - format.fraction_bits as i32;
+    let value = match (biased, fraction) {
+        (0, 0) => Value::Zero,
+        // Subnormals have the same exponent as the smallest normal numbers
+        (0, fraction) => Value::Finite {
+            exponent: exponent + 1,
+            significand: fraction as u128,
+        },
+        (biased, 0) if biased == format.max_exponent() => Value::Infinity,
+        (biased, fraction) if biased == format.max_exponent() => Value::NaN {
+            signaling: fraction >> (format.fraction_bits - 1) == 0,
+        },
+        (_, fraction) => Value::Finite {
+            exponent,
+            significand: (fraction | 1 << format.fraction_bits) as u128,
+        },
+    };
+    (sign, value)
+}
+
+// Result of an operation with a NaN operand: the canonical NaN, raising
+// invalid if any operand is a signaling NaN
+fn propagate_nan(operands: &[Value], format: Format) -> Option<(u64, u32)> {
+    let mut nan = None;
+    for operand in operands {
+        if let Value::NaN { signaling } = operand {
+            let flags = nan.unwrap_or(0) | if *signaling { INVALID } else { 0 };
+            nan = Some(flags);
+        }
+    }
+    nan.map(|flags| (format.canonical_nan(), flags))
+}
+
+// Shift a significand right, ORing any bits shifted out into the least
+// significant bit so that rounding can still tell the value was inexact
+fn shift_right_jam(significand: u128, shift: u32) -> u128 {
+    match shift {
+        0 => significand,
+        1..=127 => significand >> shift | (significand & ((1 << shift) - 1) != 0) as u128,
+        _ => (significand != 0) as u128,
+    }
+}
+
+// Drop the low `shift` bits of a significand, rounding the rest in the
+// given direction. Returns the rounded value and whether it is inexact.
+fn round_shift(sign: bool, significand: u128, shift: i32, mode: RoundingMode) -> (u128, bool) {
+    let (kept, remainder, half) = if shift >= 128 {
+        // Everything is shifted out, and the remainder is below half of
+        // the least significant kept bit
+        (0, significand, u128::MAX)
+    } else {
+        let half = 1 << (shift - 1);
+        (significand >> shift, significand & ((1 << shift) - 1), half)
+    };
+    let increment = match mode {
+        RoundingMode::NearestEven => remainder > half || (remainder == half && kept & 1 == 1),
+        RoundingMode::TowardZero => false,
+        RoundingMode::Down => sign && remainder != 0,
+        RoundingMode::Up => !sign && remainder != 0,
+        RoundingMode::NearestMaxMagnitude => remainder >= half,
+    };
+    (kept + increment as u128, remainder != 0)
+}
+
+// Round sign * significand * 2^exponent to `format`. The significand must
+// be below 2^126, and bits lost before rounding must have been jammed into
+// its least significant bit.
+fn round(
+    sign: bool,
+    exponent: i32,
+    significand: u128,
+    format: Format,
+    mode: RoundingMode,
+) -> (u64, u32) {
+    if significand == 0 {
+        return (format.zero(sign), 0);
+    }
+    // Normalize so that the leading bit is bit 125, leaving at least two
+    // bits below the least significant bit of any result
+    let shift = significand.leading_zeros() as i32 - 2;
+    let significand = significand << shift;
+    let exponent = exponent - shift;
+    // Unbiased exponent of the leading bit
+    let leading = exponent + 125;
+    let precision = format.fraction_bits as i32;
+    let min_exponent = 1 - format.bias();
+
+    // Subnormal results keep fewer bits, down to the fixed exponent of the
+    // least significant bit of the smallest normal number
+    let mut lsb = leading.max(min_exponent) - precision;
+    let (mut kept, inexact) = round_shift(sign, significand, lsb - exponent, mode);
+    if kept >> (precision + 1) != 0 {
+        // Rounding carried into a new leading bit
+        kept >>= 1;
+        lsb += 1;
+    }
+
+    if lsb + precision > format.bias() {
+        let to_infinity = match mode {
+            RoundingMode::NearestEven | RoundingMode::NearestMaxMagnitude => true,
+            RoundingMode::TowardZero => false,
+            RoundingMode::Down => sign,
+            RoundingMode::Up => !sign,
+        };
+        let bits = if to_infinity {
+            format.infinity(sign)
+        } else {
+            format.largest(sign)
+        };
+        return (bits, OVERFLOW | INEXACT);
+    }
+
+    let mut flags = if inexact { INEXACT } else { 0 };
+    if inexact && leading < min_exponent {
+        // Tininess is detected after rounding: the result is tiny if it
+        // would still be below the smallest normal number when rounded
+        // with an unbounded exponent range
+        let (unbounded, _) = round_shift(sign, significand, leading - precision - exponent, mode);
+        let carried = (unbounded >> (precision + 1) != 0) as i32;
+        if leading + carried < min_exponent {
+            flags |= UNDERFLOW;
+        }
+    }
+
+    let bits = if kept >> precision != 0 {
+        let biased = (lsb + precision + format.bias()) as u64;
+        biased << format.fraction_bits | (kept as u64 & format.fraction_mask())
+    } else {
+        // Subnormal
+        kept as u64
+    };
+    (format.sign(sign) | bits, flags)
+}
+
+// Move the leading bit of a nonzero significand below 2^124 to bit 123
+fn normalize(exponent: i32, significand: u128) -> (i32, u128) {
+    let shift = significand.leading_zeros() as i32 - 4;
+    (exponent - shift, significand << shift)
+}
+
+fn add_values(
+    a: (bool, Value),
+    b: (bool, Value),
+    format: Format,
+    mode: RoundingMode,
+) -> (u64, u32) {
+    let ((sign_a, a), (sign_b, b)) = (a, b);
+    if let Some(nan) = propagate_nan(&[a, b], format) {
+        return nan;
+    }
+    match (a, b) {
+        (Value::Infinity, Value::Infinity) if sign_a != sign_b => (format.canonical_nan(), INVALID),
+        (Value::Infinity, _) => (format.infinity(sign_a), 0),
+        (_, Value::Infinity) => (format.infinity(sign_b), 0),
+        // Zeros of opposite signs sum to +0, or to -0 when rounding down
+        (Value::Zero, Value::Zero) => (
+            format.zero(sign_a && sign_b || sign_a != sign_b && mode == RoundingMode::Down),
+            0,
+        ),
+        (
+            Value::Zero,
+            Value::Finite {
+                exponent,
+                significand,
+            },
+        ) => round(sign_b, exponent, significand, format, mode),
+        (
+            Value::Finite {
+                exponent,
+                significand,
+            },
+            Value::Zero,
+        ) => round(sign_a, exponent, significand, format, mode),
+        (
+            Value::Finite {
+                exponent: exponent_a,
+                significand: significand_a,
+            },
+            Value::Finite {
+                exponent: exponent_b,
+                significand: significand_b,
+            },
+        ) => {
+            let (exponent_a, significand_a) = normalize(exponent_a, significand_a);
+            let (exponent_b, significand_b) = normalize(exponent_b, significand_b);
+            // Align the smaller operand with the larger one
+            let (exponent, significand_a, significand_b) = if exponent_a >= exponent_b {
+                let shift = (exponent_a - exponent_b) as u32;
+                (
+                    exponent_a,
+                    significand_a,
+                    shift_right_jam(significand_b, shift),
+                )
+            } else {
+                let shift = (exponent_b - exponent_a) as u32;
+                (
+                    exponent_b,
+                    shift_right_jam(significand_a, shift),
+                    significand_b,
+                )
+            };
+            if sign_a == sign_b {
+                round(
+                    sign_a,
+                    exponent,
+                    significand_a + significand_b,
+                    format,
+                    mode,
+                )
+            } else if significand_a > significand_b {
+                round(
+                    sign_a,
+                    exponent,
+                    significand_a - significand_b,
+                    format,
+                    mode,
+                )
+            } else if significand_b > significand_a {
+                round(
+                    sign_b,
+                    exponent,
+                    significand_b - significand_a,
+                    format,
+                    mode,
+                )
+            } else {
+                // An exact zero difference is +0, or -0 when rounding down
+                (format.zero(mode == RoundingMode::Down), 0)
+            }
+        }
+        // NaNs were handled above
+        _ => unreachable!(),
+    }
+}
+
+pub fn add(a: u64, b: u64, format: Format, mode: RoundingMode) -> (u64, u32) {
+    add_values(unpack(a, format), unpack(b, format), format, mode)
+}
+pub fn sub(a: u64, b: u64, format: Format, mode: RoundingMode) -> (u64, u32) {
+    add(a, b ^ format.sign_bit(), format, mode)
+}
+
+// Exact product of two values, which is only rounded once it is used
+fn multiply_values(a: Value, b: Value) -> Value {
+    match (a, b) {
+        (Value::Infinity, _) | (_, Value::Infinity) => Value::Infinity,
+        (Value::Zero, _) | (_, Value::Zero) => Value::Zero,
+        (
+            Value::Finite {
+                exponent: exponent_a,
+                significand: significand_a,
+            },
+            Value::Finite {
+                exponent: exponent_b,
+                significand: significand_b,
+            },
+        ) => Value::Finite {
+            exponent: exponent_a + exponent_b,
+            significand: significand_a * significand_b,
+        },
+        // NaNs are handled by the callers
+        _ => unreachable!(),
+    }
+}
+
+fn is_invalid_product(a: Value, b: Value) -> bool {
+    matches!(
+        (a, b),
+        (Value::Infinity, Value::Zero) | (Value::Zero, Value::Infinity)
+    )
+}
+
+pub fn mul(a: u64, b: u64, format: Format, mode: RoundingMode) -> (u64, u32) {
+    let (sign_a, a) = unpack(a, format);
+    let (sign_b, b) = unpack(b, format);
+    let sign = sign_a != sign_b;
+    if let Some(nan) = propagate_nan(&[a, b], format) {
+        return nan;
+    }
+    if is_invalid_product(a, b) {
+        return (format.canonical_nan(), INVALID);
+    }
+    match multiply_values(a, b) {
+        Value::Infinity => (format.infinity(sign), 0),
+        Value::Finite {
+            exponent,
+            significand,
+        } => round(sign, exponent, significand, format, mode),
+        _ => (format.zero(sign), 0),
+    }
+}
+
+// a * b + c with a single rounding. The negate flags flip the sign of the
+// product and of the addend, giving the FMSUB, FNMSUB and FNMADD variants.
+pub fn fused_multiply_add(
+    a: u64,
+    b: u64,
+    c: u64,
+    negate_product: bool,
+    negate_addend: bool,
+    format: Format,
+    mode: RoundingMode,
+) -> (u64, u32) {
+    let (sign_a, a) = unpack(a, format);
+    let (sign_b, b) = unpack(b, format);
+    let (sign_c, c) = unpack(c, format);
+    // Infinity times zero is invalid even when the addend is a quiet NaN
+    if is_invalid_product(a, b) {
+        return (format.canonical_nan(), INVALID);
+    }
+    if let Some(nan) = propagate_nan(&[a, b, c], format) {
+        return nan;
+    }
+    let product = (sign_a != sign_b) != negate_product;
+    add_values(
+        (product, multiply_values(a, b)),
+        (sign_c != negate_addend, c),
+        format,
+        mode,
+    )
+}
+
+pub fn div(a: u64, b: u64, format: Format, mode: RoundingMode) -> (u64, u32) {
+    let (sign_a, a) = unpack(a, format);
+    let (sign_b, b) = unpack(b, format);
+    let sign = sign_a != sign_b;
+    if let Some(nan) = propagate_nan(&[a, b], format) {
+        return nan;
+    }
+    match (a, b) {
+        (Value::Infinity, Value::Infinity) | (Value::Zero, Value::Zero) => {
+            (format.canonical_nan(), INVALID)
+        }
+        (Value::Infinity, _) => (format.infinity(sign), 0),
+        (_, Value::Infinity) | (Value::Zero, _) => (format.zero(sign), 0),
+        (_, Value::Zero) => (format.infinity(sign), DIVIDE_BY_ZERO),
+        (
+            Value::Finite {
+                exponent: exponent_a,
+                significand: significand_a,
+            },
+            Value::Finite {
+                exponent: exponent_b,
+                significand: significand_b,
+            },
+        ) => {
+            // Scale the dividend up so the quotient has more than enough
+            // bits, and jam the remainder into it
+            let shift = significand_a.leading_zeros() as i32 - 2;
+            let significand_a = significand_a << shift;
+            let quotient = significand_a / significand_b;
+            let inexact = quotient * significand_b != significand_a;
+            let exponent = exponent_a - shift - exponent_b;
+            round(sign, exponent, quotient | inexact as u128, format, mode)
+        }
+        _ => unreachable!(),
+    }
+}
+
+// Integer square root, computed a bit at a time
+fn isqrt(value: u128) -> u128 {
+    let mut remainder = value;
+    let mut root = 0;
+    let mut bit = 1 << 126;
+    while bit > value {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+pub fn sqrt(a: u64, format: Format, mode: RoundingMode) -> (u64, u32) {
+    let (sign, a) = unpack(a, format);
+    if let Some(nan) = propagate_nan(&[a], format) {
+        return nan;
+    }
+    match a {
+        Value::Zero => (format.zero(sign), 0),
+        _ if sign => (format.canonical_nan(), INVALID),
+        Value::Infinity => (format.infinity(false), 0),
+        Value::Finite {
+            exponent,
+            significand,
+        } => {
+            // Scale the significand up by an even power of two so the
+            // exponent can be halved exactly
+            let mut shift = significand.leading_zeros() as i32 - 2;
+            if (exponent - shift) & 1 != 0 {
+                shift -= 1;
+            }
+            let significand = significand << shift;
+            let root = isqrt(significand);
+            let inexact = root * root != significand;
+            let exponent = (exponent - shift) / 2;
+            round(false, exponent, root | inexact as u128, format, mode)
+        }
+        _ => unreachable!(),
+    }
+}
+
+// Convert between single and double precision
+pub fn convert(a: u64, from: Format, to: Format, mode: RoundingMode) -> (u64, u32) {
+    let (sign, a) = unpack(a, from);
+    match a {
+        Value::NaN { signaling } => (to.canonical_nan(), if signaling { INVALID } else { 0 }),
+        Value::Infinity => (to.infinity(sign), 0),
+        Value::Zero => (to.zero(sign), 0),
+        Value::Finite {
+            exponent,
+            significand,
+        } => round(sign, exponent, significand, to, mode),
+    }
+}
+
+// Convert to a `width` bit signed or unsigned integer. Out of range values
+// and NaNs saturate and raise invalid. 32-bit results are sign extended.
+pub fn to_integer(
+    a: u64,
+    format: Format,
+    signed: bool,
+    width: u32,
+    mode: RoundingMode,
+) -> (u64, u32) {
+    let (min, max): (i128, i128) = if signed {
+        (-(1 << (width - 1)), (1 << (width - 1)) - 1)
+    } else {
+        (0, (1 << width) - 1)
+    };
+    let (sign, a) = unpack(a, format);
+    let rounded = match a {
+        Value::NaN { .. } => None,
+        Value::Infinity => None,
+        Value::Zero => Some((0, false)),
+        // Anything this large is out of range of every integer type
+        Value::Finite { exponent, .. } if exponent > 64 => None,
+        Value::Finite {
+            exponent,
+            significand,
+        } => {
+            let (magnitude, inexact) = if exponent >= 0 {
+                (significand << exponent, false)
+            } else {
+                round_shift(sign, significand, -exponent, mode)
+            };
+            let value = if sign {
+                -(magnitude as i128)
+            } else {
+                magnitude as i128
+            };
+            Some((value, inexact))
+        }
+    };
+    let (value, flags) = match rounded {
+        Some((value, inexact)) if (min..=max).contains(&value) => {
+            (value, if inexact { INEXACT } else { 0 })
+        }
+        // NaNs convert to the largest integer
+        _ if sign && !matches!(a, Value::NaN { .. }) => (min, INVALID),
+        _ => (max, INVALID),
+    };
+    let bits = if width == 32 {
+        value as u32 as i32 as u64
+    } else {
+        value as u64
+    };
+    (bits, flags)
+}
+
+// Convert a `width` bit signed or unsigned integer held in the low bits of
+// `value`
+pub fn from_integer(
+    value: u64,
+    signed: bool,
+    width: u32,
+    format: Format,
+    mode: RoundingMode,
+) -> (u64, u32) {
+    let value = match (width, signed) {
+        (32, true) => value as i32 as u64,
+        (32, false) => value as u32 as u64,
+        _ => value,
+    };
+    let (sign, magnitude) = if signed && (value as i64) < 0 {
+        (true, (value as i64).unsigned_abs())
+    } else {
+        (false, value)
+    };
+    round(sign, 0, magnitude as u128, format, mode)
+}
+
+fn is_nan(a: u64, format: Format) -> bool {
+    matches!(unpack(a, format).1, Value::NaN { .. })
+}
+fn is_signaling(a: u64, format: Format) -> bool {
+    unpack(a, format).1 == Value::NaN { signaling: true }
+}
+// Map a non-NaN value to an integer with the same ordering, where both
+// zeros are equal
+fn ordered(a: u64, format: Format) -> i128 {
+    let magnitude = (a & !format.sign_bit()) as i128;
+    if a & format.sign_bit() != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+// Quiet equality, which only raises invalid for signaling NaNs
+pub fn eq(a: u64, b: u64, format: Format) -> (bool, u32) {
+    if is_nan(a, format) || is_nan(b, format) {
+        let signaling = is_signaling(a, format) || is_signaling(b, format);
+        return (false, if signaling { INVALID } else { 0 });
+    }
+    (ordered(a, format) == ordered(b, format), 0)
+}
+// Signaling comparisons, which raise invalid for any NaN
+pub fn lt(a: u64, b: u64, format: Format) -> (bool, u32) {
+    if is_nan(a, format) || is_nan(b, format) {
+        return (false, INVALID);
+    }
+    (ordered(a, format) < ordered(b, format), 0)
+}
+pub fn le(a: u64, b: u64, format: Format) -> (bool, u32) {
+    if is_nan(a, format) || is_nan(b, format) {
+        return (false, INVALID);
+    }
+    (ordered(a, format) <= ordered(b, format), 0)
+}
+
+// IEEE 754-2019 minimumNumber and maximumNumber: a NaN operand is ignored
+// in favour of the other one, and -0 is below +0
+fn min_max(a: u64, b: u64, format: Format, min: bool) -> (u64, u32) {
+    let flags = if is_signaling(a, format) || is_signaling(b, format) {
+        INVALID
+    } else {
+        0
+    };
+    let key = |x: u64| {
+        let magnitude = (x & !format.sign_bit()) as i128;
+        if x & format.sign_bit() != 0 {
+            -magnitude - 1
+        } else {
+            magnitude
+        }
+    };
+    let result = match (is_nan(a, format), is_nan(b, format)) {
+        (true, true) => format.canonical_nan(),
+        (true, false) => b,
+        (false, true) => a,
+        _ if (key(a) < key(b)) == min => a,
+        _ => b,
+    };
+    (result, flags)
+}
+pub fn min(a: u64, b: u64, format: Format) -> (u64, u32) {
+    min_max(a, b, format, true)
+}
+pub fn max(a: u64, b: u64, format: Format) -> (u64, u32) {
+    min_max(a, b, format, false)
+}
+
+// The FCLASS mask, with one bit set for the class of `a`
+pub fn classify(a: u64, format: Format) -> u64 {
+    let (sign, value) = unpack(a, format);
+    let subnormal = (a >> format.fraction_bits) & format.max_exponent() == 0;
+    let class = match value {
+        Value::Infinity => 0,
+        Value::Finite { .. } if !subnormal => 1,
+        Value::Finite { .. } => 2,
+        Value::Zero => 3,
+        Value::NaN { signaling } => return 1 << if signaling { 8 } else { 9 },
+    };
+    // Positive classes mirror the negative ones
+    1 << if sign { class } else { 7 - class }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn single(value: f32) -> u64 {
+        u64::from(value.to_bits())
+    }
+    fn double(value: f64) -> u64 {
+        value.to_bits()
+    }
+    #[test]
+    fn rounding_modes() {
+        // 1 + 2^-25 lies a quarter of the way from 1 to the next single
+        let a = single(1.0);
+        let b = single(2f32.powi(-25));
+        let up = single(1.0) + 1;
+        assert_eq!(add(a, b, SINGLE, RoundingMode::NearestEven), (a, INEXACT));
+        assert_eq!(add(a, b, SINGLE, RoundingMode::TowardZero), (a, INEXACT));
+        assert_eq!(add(a, b, SINGLE, RoundingMode::Down), (a, INEXACT));
+        assert_eq!(add(a, b, SINGLE, RoundingMode::Up), (up, INEXACT));
+        // 1 + 2^-24 is a tie, broken away from zero
+        let tie = single(2f32.powi(-24));
+        assert_eq!(
+            add(a, tie, SINGLE, RoundingMode::NearestMaxMagnitude),
+            (up, INEXACT)
+        );
+        assert_eq!(add(a, tie, SINGLE, RoundingMode::NearestEven), (a, INEXACT));
+        // Negative values round the other way
+        let (negative, _) = sub(single(-1.0), b, SINGLE, RoundingMode::Down);
+        assert_eq!(negative, single(-1.0) + 1);
+    }
+    #[test]
+    fn overflow() {
+        let max = double(f64::MAX);
+        let flags = OVERFLOW | INEXACT;
+        assert_eq!(
+            add(max, max, DOUBLE, RoundingMode::NearestEven),
+            (double(f64::INFINITY), flags)
+        );
+        assert_eq!(add(max, max, DOUBLE, RoundingMode::Down), (max, flags));
+        assert_eq!(
+            mul(double(-f64::MAX), double(2.0), DOUBLE, RoundingMode::Up),
+            (double(-f64::MAX), flags)
+        );
+    }
+    #[test]
+    fn subnormals() {
+        let smallest = double(f64::from_bits(1));
+        // Exact subnormal results don't raise underflow
+        assert_eq!(
+            sub(
+                double(f64::MIN_POSITIVE),
+                smallest,
+                DOUBLE,
+                RoundingMode::NearestEven
+            ),
+            (double(f64::MIN_POSITIVE) - 1, 0)
+        );
+        // Half the smallest subnormal is a tie with zero
+        assert_eq!(
+            mul(smallest, double(0.5), DOUBLE, RoundingMode::NearestEven),
+            (0, UNDERFLOW | INEXACT)
+        );
+        assert_eq!(
+            mul(smallest, double(0.5), DOUBLE, RoundingMode::Up),
+            (smallest, UNDERFLOW | INEXACT)
+        );
+        // Subnormal inputs are normalized
+        assert_eq!(
+            mul(
+                smallest,
+                double(2f64.powi(1000)),
+                DOUBLE,
+                RoundingMode::NearestEven
+            ),
+            (double(2f64.powi(-74)), 0)
+        );
+    }
+    #[test]
+    fn nan_propagation() {
+        let signaling = 0x7f80_0001;
+        assert_eq!(
+            add(signaling, single(1.0), SINGLE, RoundingMode::NearestEven),
+            (SINGLE.canonical_nan(), INVALID)
+        );
+        assert_eq!(
+            mul(
+                single(f32::NAN),
+                single(1.0),
+                SINGLE,
+                RoundingMode::NearestEven
+            ),
+            (SINGLE.canonical_nan(), 0)
+        );
+        assert_eq!(
+            div(
+                single(f32::INFINITY),
+                single(f32::INFINITY),
+                SINGLE,
+                RoundingMode::NearestEven
+            ),
+            (SINGLE.canonical_nan(), INVALID)
+        );
+    }
+    #[test]
+    fn sqrt_exact() {
+        assert_eq!(
+            sqrt(double(0.25), DOUBLE, RoundingMode::NearestEven),
+            (double(0.5), 0)
+        );
+        assert_eq!(
+            sqrt(double(-0.0), DOUBLE, RoundingMode::NearestEven),
+            (double(-0.0), 0)
+        );
+    }
+    #[test]
+    fn integer_conversions() {
+        // -0.5 rounds up to zero without raising invalid
+        assert_eq!(
+            to_integer(double(-0.5), DOUBLE, false, 64, RoundingMode::Up),
+            (0, INEXACT)
+        );
+        assert_eq!(
+            to_integer(
+                double(2f64.powi(63)),
+                DOUBLE,
+                true,
+                64,
+                RoundingMode::NearestEven
+            ),
+            (i64::MAX as u64, INVALID)
+        );
+        assert_eq!(
+            to_integer(
+                double(-2f64.powi(63)),
+                DOUBLE,
+                true,
+                64,
+                RoundingMode::NearestEven
+            ),
+            (i64::MIN as u64, 0)
+        );
+        assert_eq!(
+            to_integer(
+                double(2f64.powi(32)),
+                DOUBLE,
+                false,
+                32,
+                RoundingMode::NearestEven
+            ),
+            (u64::MAX, INVALID)
+        );
+        assert_eq!(
+            from_integer(u64::MAX, false, 64, DOUBLE, RoundingMode::TowardZero),
+            (double(u64::MAX as f64) - 1, INEXACT)
+        );
+        assert_eq!(
+            from_integer(0, true, 64, DOUBLE, RoundingMode::Down),
+            (double(0.0), 0)
+        );
+    }
+    #[test]
+    fn comparisons() {
+        assert_eq!(eq(single(0.0), single(-0.0), SINGLE), (true, 0));
+        assert_eq!(eq(0x7f80_0001, single(0.0), SINGLE), (false, INVALID));
+        assert_eq!(lt(single(-0.0), single(0.0), SINGLE), (false, 0));
+        assert_eq!(le(single(f32::NAN), single(0.0), SINGLE), (false, INVALID));
+        assert_eq!(min(single(0.0), single(-0.0), SINGLE), (single(-0.0), 0));
+        assert_eq!(max(single(-0.0), single(0.0), SINGLE), (single(0.0), 0));
+    }
+    #[test]
+    fn classify_values() {
+        let cases = [
+            (double(f64::NEG_INFINITY), 0),
+            (double(-1.0), 1),
+            (double(-f64::from_bits(1)), 2),
+            (double(-0.0), 3),
+            (double(0.0), 4),
+            (double(f64::from_bits(1)), 5),
+            (double(1.0), 6),
+            (double(f64::INFINITY), 7),
+            (0x7ff0_0000_0000_0001, 8),
+            (DOUBLE.canonical_nan(), 9),
+        ];
+        for (value, bit) in cases.iter() {
+            assert_eq!(classify(*value, DOUBLE), 1 << bit);
+        }
+    }
+}
@@ -0,0 +1,671 @@
+// GDB remote serial protocol stub. GDB connects over TCP and drives the
+// emulator with packets that read and write registers and memory, set
+// breakpoints, and step or continue the program. Registers are numbered as
+// GDB numbers them for RISC-V, and described to it in a target description
+// that includes the floating point registers and the CSRs.
+use crate::riscv::cpu::{self, Register};
+use crate::riscv::csr;
+use crate::riscv::mmu;
+use crate::riscv::trap::Exception;
+use crate::riscv::{Emulator, EmulatorError};
+use std::collections::HashSet;
+use std::convert::{TryFrom, TryInto};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// Register numbers
+const PC: usize = 32;
+const FIRST_FLOAT: usize = 33;
+const FIRST_CSR: usize = 65;
+
+// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 7;
+const SIGSEGV: u8 = 11;
+
+// Ctrl-C from GDB, sent outside of any packet
+const INTERRUPT: u8 = 0x03;
+
+// How many instructions a running program executes between checks for an
+// interrupt from GDB, a power of two
+const POLL_INTERVAL: u64 = 4096;
+
+const PACKET_SIZE: usize = 0x4000;
+
+// Exit status of a program GDB kills, as a shell reports one killed by
+// SIGKILL, so that it can't be taken for a clean exit
+pub const KILLED: i32 = 128 + 9;
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, u8::wrapping_add)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() & 1 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+fn parse_number(hex: &str) -> Option<u64> {
+    u64::from_str_radix(hex, 16).ok()
+}
+// Split "address,length" as used by memory and breakpoint packets
+fn parse_pair(text: &str) -> Option<(u64, u64)> {
+    let (first, second) = text.split_once(',')?;
+    Some((parse_number(first)?, parse_number(second)?))
+}
+
+// Describe the registers, giving each the number GDB uses for it
+fn target_description() -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+        r#"<target version="1.0"><architecture>riscv:rv64</architecture>"#,
+        r#"<feature name="org.gnu.gdb.riscv.cpu">"#
+    ));
+    let reg = |name: &str, kind: &str, number: usize| {
+        format!(
+            r#"<reg name="{}" bitsize="64" type="{}" regnum="{}"/>"#,
+            name, kind, number
+        )
+    };
+    for (number, name) in cpu::ABI_NAMES.iter().enumerate() {
+        let kind = match number {
+            1 => "code_ptr",
+            2..=4 | 8 => "data_ptr",
+            _ => "int",
+        };
+        xml += &reg(name, kind, number);
+    }
+    xml += &reg("pc", "code_ptr", PC);
+    xml += r#"</feature><feature name="org.gnu.gdb.riscv.fpu">"#;
+    for (number, name) in cpu::FLOAT_ABI_NAMES.iter().enumerate() {
+        xml += &reg(name, "ieee_double", FIRST_FLOAT + number);
+    }
+    let (float, other): (Vec<_>, Vec<_>) = csr::NAMES
+        .iter()
+        .partition(|(address, _)| *address <= csr::FCSR);
+    for (address, name) in float {
+        xml += &reg(name, "int", FIRST_CSR + address);
+    }
+    xml += r#"</feature><feature name="org.gnu.gdb.riscv.csr">"#;
+    for (address, name) in other {
+        xml += &reg(name, "int", FIRST_CSR + address);
+    }
+    xml += "</feature></target>";
+    xml
+}
+
+// The signal GDB is told about when an exception stops the program
+fn signal(exception: Exception) -> u8 {
+    match exception {
+        Exception::IllegalInstruction(_) => SIGILL,
+        Exception::InstructionAddressMisaligned(_)
+        | Exception::LoadAddressMisaligned(_)
+        | Exception::StoreAddressMisaligned(_) => SIGBUS,
+        Exception::InstructionAccessFault(_)
+        | Exception::LoadAccessFault(_)
+        | Exception::StoreAccessFault(_)
+        | Exception::InstructionPageFault(_)
+        | Exception::LoadPageFault(_)
+        | Exception::StorePageFault(_) => SIGSEGV,
+        Exception::Breakpoint(_)
+        | Exception::EnvironmentCallFromUMode
+        | Exception::EnvironmentCallFromSMode
+        | Exception::EnvironmentCallFromMMode => SIGTRAP,
+    }
+}
+
+// Why a resumed program stopped
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stop {
+    Signal(u8),
+    SoftwareBreakpoint,
+    HardwareBreakpoint,
+    Exited(i32),
+}
+impl Stop {
+    fn reply(&self) -> String {
+        match self {
+            Stop::Signal(signal) => format!("S{:02x}", signal),
+            Stop::SoftwareBreakpoint => format!("T{:02x}swbreak:;", SIGTRAP),
+            Stop::HardwareBreakpoint => format!("T{:02x}hwbreak:;", SIGTRAP),
+            Stop::Exited(status) => format!("W{:02x}", *status as u8),
+        }
+    }
+}
+
+// What the stub does about a packet
+#[derive(Debug, PartialEq)]
+enum Response {
+    Reply(String),
+    Resume { step: bool },
+    Kill,
+    Detach,
+}
+
+struct Stub<'a> {
+    emulator: &'a mut Emulator,
+    // Breakpoints are checked against the pc before each instruction
+    // rather than written into guest memory
+    software_breakpoints: HashSet<u64>,
+    hardware_breakpoints: HashSet<u64>,
+    last_stop: Stop,
+}
+impl<'a> Stub<'a> {
+    fn new(emulator: &'a mut Emulator) -> Self {
+        Self {
+            emulator,
+            software_breakpoints: HashSet::new(),
+            hardware_breakpoints: HashSet::new(),
+            last_stop: Stop::Signal(SIGTRAP),
+        }
+    }
+    fn read_register(&self, number: usize) -> Option<u64> {
+        let cpu = &self.emulator.cpu;
+        if let Ok(register) = Register::try_from(number) {
+            return Some(cpu.read_register(register));
+        }
+        match number {
+            PC => Some(cpu.pc),
+            33..=64 => Some(cpu.fregisters[number - FIRST_FLOAT]),
+            _ => {
+                let address = number.checked_sub(FIRST_CSR)?;
+                csr::name(address).map(|_| cpu.read_csr(address))
+            }
+        }
+    }
+    fn write_register(&mut self, number: usize, value: u64) -> bool {
+        let cpu = &mut self.emulator.cpu;
+        if let Ok(register) = Register::try_from(number) {
+            cpu.write_register(register, value);
+            return true;
+        }
+        match number {
+            PC => cpu.pc = value,
+            33..=64 => cpu.fregisters[number - FIRST_FLOAT] = value,
+            _ => match number.checked_sub(FIRST_CSR) {
+                Some(address) if csr::name(address).is_some() => {
+                    cpu.csr.write(address, value);
+                    if address == csr::SATP {
+                        cpu.tlb.flush_all();
+                    }
+                }
+                _ => return false,
+            },
+        }
+        true
+    }
+    // Memory is accessed at the hart's privilege level, through its
+    // address translation. Only DRAM can be read, so that looking at memory
+    // doesn't claim an interrupt or take input from a device.
+    fn read_memory(&mut self, address: u64, length: u64) -> Option<Vec<u8>> {
+        let cpu = &mut self.emulator.cpu;
+        (0..length)
+            .map(|i| {
+                let physical = mmu::translate(address.wrapping_add(i), mmu::Access::Load, cpu);
+                cpu.bus.peek(physical.ok()?)
+            })
+            .collect()
+    }
+    fn write_memory(&mut self, address: u64, bytes: &[u8]) -> bool {
+        let cpu = &mut self.emulator.cpu;
+        bytes.iter().enumerate().all(|(i, &byte)| {
+            cpu.store(address.wrapping_add(i as u64), 1, u64::from(byte))
+                .is_ok()
+        })
+    }
+    fn breakpoints(&mut self, kind: &str) -> Option<&mut HashSet<u64>> {
+        match kind {
+            "0" => Some(&mut self.software_breakpoints),
+            "1" => Some(&mut self.hardware_breakpoints),
+            _ => None,
+        }
+    }
+    fn command(&mut self, packet: &str) -> Response {
+        let reply = |reply: &str| Response::Reply(reply.to_string());
+        let error = reply("E01");
+        let mut chars = packet.chars();
+        let kind = chars.next().unwrap_or_default();
+        let arguments = chars.as_str();
+        match kind {
+            '?' => Response::Reply(self.last_stop.reply()),
+            'g' => Response::Reply(
+                (0..=PC)
+                    .filter_map(|number| self.read_register(number))
+                    .map(|value| to_hex(&value.to_le_bytes()))
+                    .collect(),
+            ),
+            'G' => {
+                let bytes = match from_hex(arguments) {
+                    Some(bytes) => bytes,
+                    None => return error,
+                };
+                for (number, value) in bytes.chunks_exact(8).enumerate().take(PC + 1) {
+                    let value = u64::from_le_bytes(value.try_into().unwrap());
+                    self.write_register(number, value);
+                }
+                reply("OK")
+            }
+            'p' => {
+                let value =
+                    parse_number(arguments).and_then(|number| self.read_register(number as usize));
+                match value {
+                    Some(value) => Response::Reply(to_hex(&value.to_le_bytes())),
+                    None => error,
+                }
+            }
+            'P' => {
+                let written = arguments.split_once('=').and_then(|(number, value)| {
+                    let number = parse_number(number)? as usize;
+                    let mut bytes = [0; 8];
+                    let value = from_hex(value)?;
+                    bytes.get_mut(..value.len())?.copy_from_slice(&value);
+                    Some(self.write_register(number, u64::from_le_bytes(bytes)))
+                });
+                match written {
+                    Some(true) => reply("OK"),
+                    _ => error,
+                }
+            }
+            // Replies can be shorter than asked for, and are kept within the
+            // packet size
+            'm' => {
+                let bytes = parse_pair(arguments).and_then(|(address, length)| {
+                    self.read_memory(address, length.min(PACKET_SIZE as u64 / 2))
+                });
+                match bytes {
+                    Some(bytes) => Response::Reply(to_hex(&bytes)),
+                    None => reply("E14"),
+                }
+            }
+            'M' => {
+                let written = arguments.split_once(':').and_then(|(range, data)| {
+                    let (address, length) = parse_pair(range)?;
+                    let bytes = from_hex(data).filter(|bytes| bytes.len() as u64 == length)?;
+                    Some(self.write_memory(address, &bytes))
+                });
+                match written {
+                    Some(true) => reply("OK"),
+                    Some(false) => reply("E14"),
+                    None => error,
+                }
+            }
+            'c' | 's' => {
+                if !arguments.is_empty() {
+                    match parse_number(arguments) {
+                        Some(address) => self.emulator.cpu.pc = address,
+                        None => return error,
+                    }
+                }
+                Response::Resume { step: kind == 's' }
+            }
+            'Z' | 'z' => {
+                let mut fields = arguments.splitn(3, ',');
+                let (breakpoint, address) = (fields.next(), fields.next());
+                let address = match address.and_then(parse_number) {
+                    Some(address) => address,
+                    None => return error,
+                };
+                // Watchpoints aren't supported
+                let breakpoints = match self.breakpoints(breakpoint.unwrap_or_default()) {
+                    Some(breakpoints) => breakpoints,
+                    None => return reply(""),
+                };
+                if kind == 'Z' {
+                    breakpoints.insert(address);
+                } else {
+                    breakpoints.remove(&address);
+                }
+                reply("OK")
+            }
+            'H' | 'T' => reply("OK"),
+            'k' => Response::Kill,
+            'D' => Response::Detach,
+            'q' => self.query(arguments),
+            _ => reply(""),
+        }
+    }
+    fn query(&self, query: &str) -> Response {
+        let reply = |reply: &str| Response::Reply(reply.to_string());
+        if query.starts_with("Supported") {
+            return Response::Reply(format!(
+                "PacketSize={:x};qXfer:features:read+;swbreak+;hwbreak+",
+                PACKET_SIZE
+            ));
+        }
+        if let Some(request) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let (offset, length) = match parse_pair(request) {
+                Some(range) => range,
+                None => return reply("E01"),
+            };
+            let xml = target_description();
+            let start = (offset as usize).min(xml.len());
+            let end = start.saturating_add(length as usize).min(xml.len());
+            let more = if end < xml.len() { "m" } else { "l" };
+            return Response::Reply(format!("{}{}", more, &xml[start..end]));
+        }
+        // A single thread, which GDB calls 1
+        match query {
+            "Attached" => reply("1"),
+            "C" => reply("QC1"),
+            "fThreadInfo" => reply("m1"),
+            "sThreadInfo" => reply("l"),
+            _ => reply(""),
+        }
+    }
+    // Run until the program stops. A step always runs one instruction,
+    // even from a breakpoint.
+    fn resume(&mut self, step: bool, mut interrupted: impl FnMut() -> bool) -> Stop {
+        let mut executed = 0u64;
+        let stop = loop {
+            match self.emulator.step() {
+                Ok(Some(status)) => break Stop::Exited(status),
+                Ok(None) => (),
+                Err(EmulatorError::Halted { exception, .. }) => {
+                    break Stop::Signal(signal(exception))
+                }
+                Err(_) => break Stop::Signal(SIGTRAP),
+            }
+            if step {
+                break Stop::Signal(SIGTRAP);
+            }
+            let pc = self.emulator.cpu.pc;
+            if self.software_breakpoints.contains(&pc) {
+                break Stop::SoftwareBreakpoint;
+            }
+            if self.hardware_breakpoints.contains(&pc) {
+                break Stop::HardwareBreakpoint;
+            }
+            executed += 1;
+            if executed & (POLL_INTERVAL - 1) == 0 && interrupted() {
+                break Stop::Signal(SIGINT);
+            }
+        };
+        self.last_stop = stop;
+        stop
+    }
+}
+
+// A packet from GDB
+enum Packet {
+    Command(String),
+    Interrupt,
+}
+
+struct Connection {
+    stream: TcpStream,
+}
+impl Connection {
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+    // Receive the next packet, acknowledging it
+    fn receive(&mut self) -> io::Result<Packet> {
+        loop {
+            // Acknowledgements of our own packets have been seen already
+            match self.read_byte()? {
+                b'$' => (),
+                INTERRUPT => return Ok(Packet::Interrupt),
+                _ => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    b'#' => break,
+                    byte => data.push(byte),
+                }
+            }
+            let sum = [self.read_byte()?, self.read_byte()?];
+            let data = String::from_utf8_lossy(&data).into_owned();
+            let valid = std::str::from_utf8(&sum)
+                .ok()
+                .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+                == Some(checksum(&data));
+            if valid {
+                self.stream.write_all(b"+")?;
+                return Ok(Packet::Command(data));
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+    // Send a packet, again until GDB acknowledges it
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", data, checksum(data));
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            loop {
+                match self.read_byte()? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => (),
+                }
+            }
+        }
+    }
+    // Whether GDB sent Ctrl-C, without waiting if it sent nothing
+    fn interrupted(&mut self) -> bool {
+        let mut byte = [0];
+        let read = self
+            .stream
+            .set_nonblocking(true)
+            .and_then(|_| self.stream.read(&mut byte));
+        let _ = self.stream.set_nonblocking(false);
+        matches!(read, Ok(1) if byte[0] == INTERRUPT)
+    }
+}
+
+// Wait for GDB to connect on `port` of localhost and let it debug the
+// program. Returns the exit status of the program, once it exits or GDB
+// kills it with KILLED, or after GDB detaches and the program runs to the
+// end.
+pub fn serve(emulator: &mut Emulator, port: u16) -> Result<i32, EmulatorError> {
+    let listener = TcpListener::bind(("127.0.0.1", port)).map_err(EmulatorError::Gdb)?;
+    eprintln!("Waiting for GDB on localhost:{}", port);
+    let (stream, _) = listener.accept().map_err(EmulatorError::Gdb)?;
+    stream.set_nodelay(true).map_err(EmulatorError::Gdb)?;
+    let mut connection = Connection { stream };
+    let mut stub = Stub::new(emulator);
+    loop {
+        let packet = match connection.receive().map_err(EmulatorError::Gdb)? {
+            Packet::Command(packet) => packet,
+            // The program is already stopped
+            Packet::Interrupt => continue,
+        };
+        match stub.command(&packet) {
+            Response::Reply(reply) => connection.send(&reply).map_err(EmulatorError::Gdb)?,
+            Response::Resume { step } => {
+                let stop = stub.resume(step, || connection.interrupted());
+                connection.send(&stop.reply()).map_err(EmulatorError::Gdb)?;
+                if let Stop::Exited(status) = stop {
+                    return Ok(status);
+                }
+            }
+            Response::Kill => return Ok(KILLED),
+            Response::Detach => {
+                connection.send("OK").map_err(EmulatorError::Gdb)?;
+                return stub.emulator.run();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::{bus, elf, plic};
+
+    // addi a0, a0, 1 four times, then off the end of the program
+    const PROGRAM: [u32; 4] = [0x0015_0513; 4];
+
+    fn emulator(program: &[u32]) -> Emulator {
+        let mut cpu = cpu::Cpu::new(bus::Bus::new(0x1_0000));
+        let bytes: Vec<u8> = program.iter().flat_map(|word| word.to_le_bytes()).collect();
+        cpu.bus.load(bus::DRAM_BASE, &bytes).unwrap();
+        cpu.pc = bus::DRAM_BASE;
+        Emulator {
+            cpu,
+            text: vec![elf::Segment {
+                address: bus::DRAM_BASE,
+                size: bytes.len() as u64,
+                flags: elf::PF_R | elf::PF_X,
+            }],
+            process: None,
+            symbols: elf::SymbolTable::default(),
+            trace: false,
+            blocks: true,
+            exit_outside_text: true,
+        }
+    }
+    fn reply(stub: &mut Stub, packet: &str) -> String {
+        match stub.command(packet) {
+            Response::Reply(reply) => reply,
+            response => panic!("{} got {:?}", packet, response),
+        }
+    }
+    #[test]
+    fn packets() {
+        assert_eq!(checksum("OK"), 0x9a);
+        assert_eq!(checksum(""), 0);
+        assert_eq!(to_hex(&[0x00, 0xab, 0x10]), "00ab10");
+        assert_eq!(from_hex("00ab10"), Some(vec![0x00, 0xab, 0x10]));
+        assert_eq!(from_hex("0"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(parse_pair("80000000,4"), Some((0x8000_0000, 4)));
+        assert_eq!(parse_pair("80000000"), None);
+    }
+    #[test]
+    fn registers() {
+        let mut emulator = emulator(&PROGRAM);
+        let mut stub = Stub::new(&mut emulator);
+        assert_eq!(reply(&mut stub, "P0a=2a00000000000000"), "OK");
+        assert_eq!(reply(&mut stub, "p0a"), "2a00000000000000");
+        assert_eq!(reply(&mut stub, "p20"), "0000008000000000");
+        let registers = reply(&mut stub, "g");
+        assert_eq!(registers.len(), 33 * 16);
+        assert_eq!(&registers[10 * 16..11 * 16], "2a00000000000000");
+        // x0 stays zero
+        assert_eq!(reply(&mut stub, "P0=ff"), "OK");
+        assert_eq!(reply(&mut stub, "p0"), "0000000000000000");
+        // Floating point registers and CSRs
+        assert_eq!(reply(&mut stub, "P21=0000000000000040"), "OK");
+        assert_eq!(stub.emulator.cpu.fregisters[0], 0x4000_0000_0000_0000);
+        let mscratch = format!("p{:x}", FIRST_CSR + 0x340);
+        assert_eq!(
+            reply(
+                &mut stub,
+                &format!("P{:x}=0700000000000000", FIRST_CSR + 0x340)
+            ),
+            "OK"
+        );
+        assert_eq!(reply(&mut stub, &mscratch), "0700000000000000");
+        assert_eq!(
+            reply(&mut stub, &format!("p{:x}", FIRST_CSR + 0x7ff)),
+            "E01"
+        );
+        assert_eq!(reply(&mut stub, "pffffffffffffffff"), "E01");
+        assert_eq!(reply(&mut stub, "Pffffffffffffffff=00"), "E01");
+        let mut registers = registers;
+        registers.replace_range(16..32, "1000000000000000");
+        assert_eq!(reply(&mut stub, &format!("G{}", registers)), "OK");
+        assert_eq!(stub.emulator.cpu.read_register(Register::X1), 0x10);
+    }
+    #[test]
+    fn memory() {
+        let mut emulator = emulator(&PROGRAM);
+        let mut stub = Stub::new(&mut emulator);
+        assert_eq!(reply(&mut stub, "m80000000,4"), "13051500");
+        assert_eq!(reply(&mut stub, "M80000100,2:beef"), "OK");
+        assert_eq!(reply(&mut stub, "m80000100,3"), "beef00");
+        assert_eq!(reply(&mut stub, "M80000100,2:be"), "E01");
+        assert_eq!(reply(&mut stub, "m0,4"), "E14");
+        // Devices are left alone
+        let claim = bus::PLIC_BASE + plic::CLAIM;
+        assert_eq!(reply(&mut stub, &format!("m{:x},4", claim)), "E14");
+        assert_eq!(reply(&mut stub, &format!("m{:x},1", bus::UART_BASE)), "E14");
+        assert_eq!(reply(&mut stub, "M0,1:00"), "E14");
+    }
+    #[test]
+    fn queries() {
+        let mut emulator = emulator(&PROGRAM);
+        let mut stub = Stub::new(&mut emulator);
+        assert!(reply(&mut stub, "qSupported:swbreak+").contains("qXfer:features:read+"));
+        assert_eq!(reply(&mut stub, "?"), "S05");
+        assert_eq!(reply(&mut stub, "qAttached"), "1");
+        assert_eq!(reply(&mut stub, "vCont?"), "");
+        // The target description is read in pieces
+        let mut xml = String::new();
+        loop {
+            let chunk = reply(
+                &mut stub,
+                &format!("qXfer:features:read:target.xml:{:x},100", xml.len()),
+            );
+            xml += &chunk[1..];
+            if chunk.starts_with('l') {
+                break;
+            }
+        }
+        assert_eq!(xml, target_description());
+        assert!(xml.contains(r#"<reg name="pc" bitsize="64" type="code_ptr" regnum="32"/>"#));
+        assert!(xml.contains(r#"<reg name="fa0" bitsize="64" type="ieee_double" regnum="43"/>"#));
+        assert!(xml.contains(r#"<reg name="fcsr" bitsize="64" type="int" regnum="68"/>"#));
+        assert!(xml.contains(&format!(
+            r#"<reg name="mstatus" bitsize="64" type="int" regnum="{}"/>"#,
+            FIRST_CSR + 0x300
+        )));
+    }
+    #[test]
+    fn breakpoints_and_stepping() {
+        let mut emulator = emulator(&PROGRAM);
+        let mut stub = Stub::new(&mut emulator);
+        assert_eq!(reply(&mut stub, "Z0,80000008,4"), "OK");
+        assert_eq!(reply(&mut stub, "Z1,8000000c,4"), "OK");
+        // Watchpoints aren't supported
+        assert_eq!(reply(&mut stub, "Z2,80000100,4"), "");
+        assert_eq!(stub.command("c"), Response::Resume { step: false });
+        assert_eq!(stub.resume(false, || false), Stop::SoftwareBreakpoint);
+        assert_eq!(stub.emulator.cpu.pc, 0x8000_0008);
+        assert_eq!(reply(&mut stub, "?"), "T05swbreak:;");
+        // Stepping runs the instruction at the breakpoint
+        assert_eq!(stub.command("s"), Response::Resume { step: true });
+        assert_eq!(stub.resume(true, || false), Stop::Signal(SIGTRAP));
+        assert_eq!(stub.emulator.cpu.pc, 0x8000_000c);
+        assert_eq!(reply(&mut stub, "z1,8000000c,4"), "OK");
+        assert_eq!(stub.resume(false, || false), Stop::Exited(0));
+        assert_eq!(stub.emulator.cpu.read_register(Register::X10), 4);
+        assert_eq!(Stop::Exited(0).reply(), "W00");
+        // Continuing from an address
+        assert_eq!(stub.command("c80000004"), Response::Resume { step: false });
+        assert_eq!(stub.emulator.cpu.pc, 0x8000_0004);
+    }
+    #[test]
+    fn interrupt() {
+        // j .
+        let mut emulator = emulator(&[0x0000_006f]);
+        let mut stub = Stub::new(&mut emulator);
+        let mut polls = 0;
+        let stop = stub.resume(false, || {
+            polls += 1;
+            polls == 3
+        });
+        assert_eq!(stop, Stop::Signal(SIGINT));
+        assert_eq!(stop.reply(), "S02");
+    }
+    #[test]
+    fn exceptions() {
+        // An illegal instruction with no trap handler
+        let mut emulator = emulator(&[0]);
+        let mut stub = Stub::new(&mut emulator);
+        assert_eq!(stub.resume(false, || false), Stop::Signal(SIGILL));
+        assert_eq!(stub.emulator.cpu.pc, bus::DRAM_BASE);
+    }
+}
@@ -1,6 +1,20 @@
+use crate::riscv::compressed;
 use crate::riscv::cpu;
-// Decode a RISC-V 64 instruction and return the instruction
+// Length in bytes of the instruction whose low bits are in `instruction`.
+// Compressed instructions are the ones whose two lowest bits aren't 0b11.
+pub fn length(instruction: u32) -> u64 {
+    if instruction & 0b11 == 0b11 {
+        4
+    } else {
+        2
+    }
+}
+// Decode a RISC-V 64 instruction and return the instruction. Compressed
+// instructions are read from the low 16 bits.
 pub fn decode(instruction: u32) -> Instruction {
+    if length(instruction) == 2 {
+        return compressed::decode(instruction as u16);
+    }
     let opcode = instruction & 0b1111111;
     if let Some(instruction_format) = &ENCODING_TABLE[opcode as usize] {
         instruction_format.decode(instruction)
@@ -9,7 +23,7 @@ pub fn decode(instruction: u32) -> Instruction {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Instruction {
     Undefined,
 
@@ -89,6 +103,11 @@ pub enum Instruction {
         pred: u32,
         fm: u32,
     },
+    FenceI {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        imm: i32,
+    },
 
     Addi {
         rd: cpu::Register,
@@ -164,6 +183,44 @@ pub enum Instruction {
 
     Ebreak,
     Ecall,
+    Mret,
+    Sret,
+    Wfi,
+    SfenceVma {
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+    },
+
+    Csrrw {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        csr: u32,
+    },
+    Csrrs {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        csr: u32,
+    },
+    Csrrc {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        csr: u32,
+    },
+    Csrrwi {
+        rd: cpu::Register,
+        uimm: u32,
+        csr: u32,
+    },
+    Csrrsi {
+        rd: cpu::Register,
+        uimm: u32,
+        csr: u32,
+    },
+    Csrrci {
+        rd: cpu::Register,
+        uimm: u32,
+        csr: u32,
+    },
 
     // J-Type
     Jal {
@@ -248,94 +305,758 @@ pub enum Instruction {
         rs2: cpu::Register,
     },
 
-    // S-Type
-    Sb {
+    // M Extension
+    Mul {
+        rd: cpu::Register,
+        rs1: cpu::Register,
         rs2: cpu::Register,
+    },
+    Mulh {
+        rd: cpu::Register,
         rs1: cpu::Register,
-        imm: i32,
+        rs2: cpu::Register,
     },
-    Sh {
+    Mulhsu {
+        rd: cpu::Register,
+        rs1: cpu::Register,
         rs2: cpu::Register,
+    },
+    Mulhu {
+        rd: cpu::Register,
         rs1: cpu::Register,
-        imm: i32,
+        rs2: cpu::Register,
     },
-    Sw {
+    Div {
+        rd: cpu::Register,
+        rs1: cpu::Register,
         rs2: cpu::Register,
+    },
+    Divu {
+        rd: cpu::Register,
         rs1: cpu::Register,
-        imm: i32,
+        rs2: cpu::Register,
     },
-    Sd {
+    Rem {
+        rd: cpu::Register,
+        rs1: cpu::Register,
         rs2: cpu::Register,
+    },
+    Remu {
+        rd: cpu::Register,
         rs1: cpu::Register,
-        imm: i32,
+        rs2: cpu::Register,
     },
-
-    // U-Type
-    Auipc {
+    Mulw {
         rd: cpu::Register,
-        imm: i32,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
     },
-    Lui {
+    Divw {
         rd: cpu::Register,
-        imm: i32,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+    },
+    Divuw {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+    },
+    Remw {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+    },
+    Remuw {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
     },
-}
-enum InstructionFormat {
-    R,
-    I,
-    S,
-    B,
-    U,
-    J,
-}
-
-impl InstructionFormat {
-    fn decode(&self, instruction: u32) -> Instruction {
-        let opcode = instruction & 0b1111111;
-        match self {
-            InstructionFormat::B => {
-                // Decode fields
-                let imm12 = (instruction >> 31) & 0b1;
-                let imm105 = (instruction >> 25) & 0b111111;
-                let rs2 = (((instruction >> 20) & 0b11111) as usize).into();
-                let rs1 = (((instruction >> 15) & 0b11111) as usize).into();
-                let funct3 = (instruction >> 12) & 0b111;
-                let imm41 = (instruction >> 8) & 0b1111;
-                let imm11 = (instruction >> 7) & 0b1;
-
-                // Merge and sign extend the immediate
-                let imm = (imm12 << 12) | (imm11 << 11) | (imm105 << 5) | (imm41 << 1);
-                let imm = ((imm as i32) << 19) >> 19;
-
-                match funct3 {
-                    0b000 => Instruction::Beq { rs1, rs2, imm },
-                    0b001 => Instruction::Bne { rs1, rs2, imm },
-                    0b100 => Instruction::Blt { rs1, rs2, imm },
-                    0b101 => Instruction::Bge { rs1, rs2, imm },
-                    0b110 => Instruction::Bltu { rs1, rs2, imm },
-                    0b111 => Instruction::Bgeu { rs1, rs2, imm },
-                    _ => Instruction::Undefined,
-                }
-            }
 
-            InstructionFormat::I => {
-                // Decode fields
-                let imm = (instruction >> 20) & 0b1111_1111_1111;
-                let rs1 = (((instruction >> 15) & 0b11111) as usize).into();
-                let funct3 = (instruction >> 12) & 0b111;
-                let rd = (((instruction >> 7) & 0b11111) as usize).into();
+    // A Extension
+    LrW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    ScW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoswapW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoaddW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoxorW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoandW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoorW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmominW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmomaxW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmominuW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmomaxuW {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    LrD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    ScD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoswapD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoaddD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoxorD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoandD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmoorD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmominD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmomaxD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmominuD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
+    AmomaxuD {
+        rd: cpu::Register,
+        rs1: cpu::Register,
+        rs2: cpu::Register,
+        aq: bool,
+        rl: bool,
+    },
 
-                let fm = (imm >> 8) & 0b1111;
-                let pred = (imm >> 4) & 0b1111;
-                let succ = imm & 0b1111;
+    // F Extension
+    Flw {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+    Fsw {
+        rs2: cpu::FRegister,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+    FmaddS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FmsubS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FnmsubS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FnmaddS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FaddS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FsubS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FmulS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FdivS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FsqrtS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FsgnjS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FsgnjnS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FsgnjxS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FminS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FmaxS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FeqS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FltS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FleS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FclassS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+    },
+    FcvtWS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtWuS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtLS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtLuS {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtSW {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FcvtSWu {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FcvtSL {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FcvtSLu {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FmvXW {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+    },
+    FmvWX {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+    },
 
-                // Shifts are encoded as a specialization of the I-type format
+    // D Extension
+    Fld {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+    Fsd {
+        rs2: cpu::FRegister,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+    FmaddD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FmsubD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FnmsubD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FnmaddD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rs3: cpu::FRegister,
+        rm: u32,
+    },
+    FaddD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FsubD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FmulD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FdivD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+        rm: u32,
+    },
+    FsqrtD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FsgnjD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FsgnjnD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FsgnjxD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FminD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FmaxD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FcvtSD {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtDS {
+        rd: cpu::FRegister,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FeqD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FltD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FleD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rs2: cpu::FRegister,
+    },
+    FclassD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+    },
+    FcvtWD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtWuD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtLD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtLuD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+        rm: u32,
+    },
+    FcvtDW {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FcvtDWu {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FcvtDL {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FcvtDLu {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+        rm: u32,
+    },
+    FmvXD {
+        rd: cpu::Register,
+        rs1: cpu::FRegister,
+    },
+    FmvDX {
+        rd: cpu::FRegister,
+        rs1: cpu::Register,
+    },
+
+    // S-Type
+    Sb {
+        rs2: cpu::Register,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+    Sh {
+        rs2: cpu::Register,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+    Sw {
+        rs2: cpu::Register,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+    Sd {
+        rs2: cpu::Register,
+        rs1: cpu::Register,
+        imm: i32,
+    },
+
+    // U-Type
+    Auipc {
+        rd: cpu::Register,
+        imm: i32,
+    },
+    Lui {
+        rd: cpu::Register,
+        imm: i32,
+    },
+}
+impl Instruction {
+    // Letter of the extension that defines the instruction, as in misa
+    pub fn extension(&self) -> u8 {
+        match self {
+            Instruction::Mul { .. }
+            | Instruction::Mulh { .. }
+            | Instruction::Mulhsu { .. }
+            | Instruction::Mulhu { .. }
+            | Instruction::Div { .. }
+            | Instruction::Divu { .. }
+            | Instruction::Rem { .. }
+            | Instruction::Remu { .. }
+            | Instruction::Mulw { .. }
+            | Instruction::Divw { .. }
+            | Instruction::Divuw { .. }
+            | Instruction::Remw { .. }
+            | Instruction::Remuw { .. } => b'M',
+            Instruction::LrW { .. }
+            | Instruction::ScW { .. }
+            | Instruction::AmoswapW { .. }
+            | Instruction::AmoaddW { .. }
+            | Instruction::AmoxorW { .. }
+            | Instruction::AmoandW { .. }
+            | Instruction::AmoorW { .. }
+            | Instruction::AmominW { .. }
+            | Instruction::AmomaxW { .. }
+            | Instruction::AmominuW { .. }
+            | Instruction::AmomaxuW { .. }
+            | Instruction::LrD { .. }
+            | Instruction::ScD { .. }
+            | Instruction::AmoswapD { .. }
+            | Instruction::AmoaddD { .. }
+            | Instruction::AmoxorD { .. }
+            | Instruction::AmoandD { .. }
+            | Instruction::AmoorD { .. }
+            | Instruction::AmominD { .. }
+            | Instruction::AmomaxD { .. }
+            | Instruction::AmominuD { .. }
+            | Instruction::AmomaxuD { .. } => b'A',
+            Instruction::Flw { .. }
+            | Instruction::Fsw { .. }
+            | Instruction::FmaddS { .. }
+            | Instruction::FmsubS { .. }
+            | Instruction::FnmsubS { .. }
+            | Instruction::FnmaddS { .. }
+            | Instruction::FaddS { .. }
+            | Instruction::FsubS { .. }
+            | Instruction::FmulS { .. }
+            | Instruction::FdivS { .. }
+            | Instruction::FsqrtS { .. }
+            | Instruction::FsgnjS { .. }
+            | Instruction::FsgnjnS { .. }
+            | Instruction::FsgnjxS { .. }
+            | Instruction::FminS { .. }
+            | Instruction::FmaxS { .. }
+            | Instruction::FeqS { .. }
+            | Instruction::FltS { .. }
+            | Instruction::FleS { .. }
+            | Instruction::FclassS { .. }
+            | Instruction::FcvtWS { .. }
+            | Instruction::FcvtWuS { .. }
+            | Instruction::FcvtLS { .. }
+            | Instruction::FcvtLuS { .. }
+            | Instruction::FcvtSW { .. }
+            | Instruction::FcvtSWu { .. }
+            | Instruction::FcvtSL { .. }
+            | Instruction::FcvtSLu { .. }
+            | Instruction::FmvXW { .. }
+            | Instruction::FmvWX { .. } => b'F',
+            Instruction::Fld { .. }
+            | Instruction::Fsd { .. }
+            | Instruction::FmaddD { .. }
+            | Instruction::FmsubD { .. }
+            | Instruction::FnmsubD { .. }
+            | Instruction::FnmaddD { .. }
+            | Instruction::FaddD { .. }
+            | Instruction::FsubD { .. }
+            | Instruction::FmulD { .. }
+            | Instruction::FdivD { .. }
+            | Instruction::FsqrtD { .. }
+            | Instruction::FsgnjD { .. }
+            | Instruction::FsgnjnD { .. }
+            | Instruction::FsgnjxD { .. }
+            | Instruction::FminD { .. }
+            | Instruction::FmaxD { .. }
+            | Instruction::FcvtSD { .. }
+            | Instruction::FcvtDS { .. }
+            | Instruction::FeqD { .. }
+            | Instruction::FltD { .. }
+            | Instruction::FleD { .. }
+            | Instruction::FclassD { .. }
+            | Instruction::FcvtWD { .. }
+            | Instruction::FcvtWuD { .. }
+            | Instruction::FcvtLD { .. }
+            | Instruction::FcvtLuD { .. }
+            | Instruction::FcvtDW { .. }
+            | Instruction::FcvtDWu { .. }
+            | Instruction::FcvtDL { .. }
+            | Instruction::FcvtDLu { .. }
+            | Instruction::FmvXD { .. }
+            | Instruction::FmvDX { .. } => b'D',
+            _ => b'I',
+        }
+    }
+}
+enum InstructionFormat {
+    R,
+    R4,
+    I,
+    S,
+    B,
+    U,
+    J,
+}
+
+impl InstructionFormat {
+    fn decode(&self, instruction: u32) -> Instruction {
+        let opcode = instruction & 0b1111111;
+        match self {
+            InstructionFormat::B => {
+                // Decode fields
+                let imm12 = (instruction >> 31) & 0b1;
+                let imm105 = (instruction >> 25) & 0b111111;
+                let rs2 = cpu::register_field((instruction >> 20) & 0b11111);
+                let rs1 = cpu::register_field((instruction >> 15) & 0b11111);
+                let funct3 = (instruction >> 12) & 0b111;
+                let imm41 = (instruction >> 8) & 0b1111;
+                let imm11 = (instruction >> 7) & 0b1;
+
+                // Merge and sign extend the immediate
+                let imm = (imm12 << 12) | (imm11 << 11) | (imm105 << 5) | (imm41 << 1);
+                let imm = ((imm as i32) << 19) >> 19;
+
+                match funct3 {
+                    0b000 => Instruction::Beq { rs1, rs2, imm },
+                    0b001 => Instruction::Bne { rs1, rs2, imm },
+                    0b100 => Instruction::Blt { rs1, rs2, imm },
+                    0b101 => Instruction::Bge { rs1, rs2, imm },
+                    0b110 => Instruction::Bltu { rs1, rs2, imm },
+                    0b111 => Instruction::Bgeu { rs1, rs2, imm },
+                    _ => Instruction::Undefined,
+                }
+            }
+
+            InstructionFormat::I => {
+                // Decode fields
+                let imm = (instruction >> 20) & 0b1111_1111_1111;
+                let rs1 = cpu::register_field((instruction >> 15) & 0b11111);
+                let funct3 = (instruction >> 12) & 0b111;
+                let rd = cpu::register_field((instruction >> 7) & 0b11111);
+
+                let fm = (imm >> 8) & 0b1111;
+                let pred = (imm >> 4) & 0b1111;
+                let succ = imm & 0b1111;
+
+                // Shifts are encoded as a specialization of the I-type format
                 // Shift amount field for Slli, Srli and Srai
-                let shamt = (imm & 0b111111) as u32;
+                let shamt = imm & 0b111111;
                 // Shift amount field for Slliw, Srliw and Sraiw
-                let shamtw = (imm & 0b11111) as u32;
+                let shamtw = imm & 0b11111;
                 // 30th bit contains right shift type
-                let rshift_type = ((imm >> 10) & 0b11) as u32;
+                let rshift_type = (imm >> 10) & 0b11;
+
+                // CSR instructions hold the CSR address in the immediate
+                // and a zero extended immediate in the rs1 field
+                let csr = imm;
+                let uimm = (instruction >> 15) & 0b11111;
 
                 // Sign extend the immediate
                 let imm = ((imm as i32) << 20) >> 20;
@@ -351,6 +1072,15 @@ impl InstructionFormat {
                         0b011 => Instruction::Ld { rd, rs1, imm },
                         _ => Instruction::Undefined,
                     },
+                    0b0000111 => {
+                        // Floating point loads write a floating point register
+                        let rd = cpu::register_field((instruction >> 7) & 0b11111);
+                        match funct3 {
+                            0b010 => Instruction::Flw { rd, rs1, imm },
+                            0b011 => Instruction::Fld { rd, rs1, imm },
+                            _ => Instruction::Undefined,
+                        }
+                    }
                     0b0001111 => match funct3 {
                         0b000 => Instruction::Fence {
                             rd,
@@ -359,6 +1089,7 @@ impl InstructionFormat {
                             pred,
                             fm,
                         },
+                        0b001 => Instruction::FenceI { rd, rs1, imm },
                         _ => Instruction::Undefined,
                     },
                     0b0010011 => match funct3 {
@@ -407,6 +1138,41 @@ impl InstructionFormat {
                         {
                             Instruction::Ebreak
                         }
+                        0b000
+                            if imm == 0x302
+                                && rs1 == cpu::Register::X0
+                                && rd == cpu::Register::X0 =>
+                        {
+                            Instruction::Mret
+                        }
+                        0b000
+                            if imm == 0x102
+                                && rs1 == cpu::Register::X0
+                                && rd == cpu::Register::X0 =>
+                        {
+                            Instruction::Sret
+                        }
+                        0b000
+                            if imm == 0x105
+                                && rs1 == cpu::Register::X0
+                                && rd == cpu::Register::X0 =>
+                        {
+                            Instruction::Wfi
+                        }
+                        // SFENCE.VMA is R-type, with rs2 in the low bits of the
+                        // immediate
+                        0b000 if imm >> 5 == 0b0001001 && rd == cpu::Register::X0 => {
+                            Instruction::SfenceVma {
+                                rs1,
+                                rs2: cpu::register_field((imm & 0b11111) as u32),
+                            }
+                        }
+                        0b001 => Instruction::Csrrw { rd, rs1, csr },
+                        0b010 => Instruction::Csrrs { rd, rs1, csr },
+                        0b011 => Instruction::Csrrc { rd, rs1, csr },
+                        0b101 => Instruction::Csrrwi { rd, uimm, csr },
+                        0b110 => Instruction::Csrrsi { rd, uimm, csr },
+                        0b111 => Instruction::Csrrci { rd, uimm, csr },
                         _ => Instruction::Undefined,
                     },
                     _ => Instruction::Undefined,
@@ -416,17 +1182,17 @@ impl InstructionFormat {
             InstructionFormat::J => {
                 // Decode fields
                 let imm = ((instruction & 0xfffff000) as i32) >> 12;
-                let rd = (((instruction >> 7) & 0b11111) as usize).into();
+                let rd = cpu::register_field((instruction >> 7) & 0b11111);
 
                 // Split the immediate
                 let imm20 = (imm >> 19) & 1;
-                let imm101 = (imm >> 9) & 0b11111111111;
+                let imm101 = (imm >> 9) & 0b1111111111;
                 let imm11 = (imm >> 8) & 1;
-                let imm1912 = (imm >> 0) & 0b00000000;
+                let imm1912 = imm & 0b11111111;
 
                 // Merge and sign extend the immediate
                 let imm = (imm20 << 20) | (imm1912 << 12) | (imm11 << 11) | (imm101 << 1);
-                let imm = ((imm as i32) << 11) >> 11;
+                let imm = (imm << 11) >> 11;
 
                 match opcode {
                     0b1101111 => Instruction::Jal { rd, imm },
@@ -437,10 +1203,10 @@ impl InstructionFormat {
             InstructionFormat::R => {
                 // Decode fields
                 let funct7 = (instruction >> 25) & 0b1111111;
-                let rs2 = (((instruction >> 20) & 0b11111) as usize).into();
-                let rs1 = (((instruction >> 15) & 0b11111) as usize).into();
+                let rs2 = cpu::register_field((instruction >> 20) & 0b11111);
+                let rs1 = cpu::register_field((instruction >> 15) & 0b11111);
                 let funct3 = (instruction >> 12) & 0b111;
-                let rd = (((instruction >> 7) & 0b11111) as usize).into();
+                let rd = cpu::register_field((instruction >> 7) & 0b11111);
 
                 match opcode {
                     0b0110011 => match (funct7, funct3) {
@@ -454,6 +1220,14 @@ impl InstructionFormat {
                         (0b0100000, 0b101) => Instruction::Sra { rd, rs1, rs2 },
                         (0b0000000, 0b110) => Instruction::Or { rd, rs1, rs2 },
                         (0b0000000, 0b111) => Instruction::And { rd, rs1, rs2 },
+                        (0b0000001, 0b000) => Instruction::Mul { rd, rs1, rs2 },
+                        (0b0000001, 0b001) => Instruction::Mulh { rd, rs1, rs2 },
+                        (0b0000001, 0b010) => Instruction::Mulhsu { rd, rs1, rs2 },
+                        (0b0000001, 0b011) => Instruction::Mulhu { rd, rs1, rs2 },
+                        (0b0000001, 0b100) => Instruction::Div { rd, rs1, rs2 },
+                        (0b0000001, 0b101) => Instruction::Divu { rd, rs1, rs2 },
+                        (0b0000001, 0b110) => Instruction::Rem { rd, rs1, rs2 },
+                        (0b0000001, 0b111) => Instruction::Remu { rd, rs1, rs2 },
                         _ => Instruction::Undefined,
                     },
                     0b0111011 => match (funct7, funct3) {
@@ -462,8 +1236,424 @@ impl InstructionFormat {
                         (0b0000000, 0b001) => Instruction::Sllw { rd, rs1, rs2 },
                         (0b0000000, 0b101) => Instruction::Srlw { rd, rs1, rs2 },
                         (0b0100000, 0b101) => Instruction::Sraw { rd, rs1, rs2 },
+                        (0b0000001, 0b000) => Instruction::Mulw { rd, rs1, rs2 },
+                        (0b0000001, 0b100) => Instruction::Divw { rd, rs1, rs2 },
+                        (0b0000001, 0b101) => Instruction::Divuw { rd, rs1, rs2 },
+                        (0b0000001, 0b110) => Instruction::Remw { rd, rs1, rs2 },
+                        (0b0000001, 0b111) => Instruction::Remuw { rd, rs1, rs2 },
                         _ => Instruction::Undefined,
                     },
+                    0b0101111 => {
+                        // Atomics split funct7 into funct5 and the acquire
+                        // and release ordering bits
+                        let funct5 = funct7 >> 2;
+                        let aq = (funct7 >> 1) & 1 == 1;
+                        let rl = funct7 & 1 == 1;
+                        match (funct5, funct3) {
+                            (0b00010, 0b010) if rs2 == cpu::Register::X0 => {
+                                Instruction::LrW { rd, rs1, aq, rl }
+                            }
+                            (0b00011, 0b010) => Instruction::ScW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b00001, 0b010) => Instruction::AmoswapW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b00000, 0b010) => Instruction::AmoaddW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b00100, 0b010) => Instruction::AmoxorW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b01100, 0b010) => Instruction::AmoandW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b01000, 0b010) => Instruction::AmoorW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b10000, 0b010) => Instruction::AmominW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b10100, 0b010) => Instruction::AmomaxW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b11000, 0b010) => Instruction::AmominuW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b11100, 0b010) => Instruction::AmomaxuW {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b00010, 0b011) if rs2 == cpu::Register::X0 => {
+                                Instruction::LrD { rd, rs1, aq, rl }
+                            }
+                            (0b00011, 0b011) => Instruction::ScD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b00001, 0b011) => Instruction::AmoswapD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b00000, 0b011) => Instruction::AmoaddD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b00100, 0b011) => Instruction::AmoxorD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b01100, 0b011) => Instruction::AmoandD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b01000, 0b011) => Instruction::AmoorD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b10000, 0b011) => Instruction::AmominD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b10100, 0b011) => Instruction::AmomaxD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b11000, 0b011) => Instruction::AmominuD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            (0b11100, 0b011) => Instruction::AmomaxuD {
+                                rd,
+                                rs1,
+                                rs2,
+                                aq,
+                                rl,
+                            },
+                            _ => Instruction::Undefined,
+                        }
+                    }
+                    0b1010011 => {
+                        // Operands are floating point or integer registers
+                        // depending on the instruction. The rs2 field
+                        // selects the integer type of conversions, and
+                        // funct3 holds the rounding mode or selects between
+                        // related operations.
+                        let frd = cpu::register_field((instruction >> 7) & 0b11111);
+                        let frs1 = cpu::register_field((instruction >> 15) & 0b11111);
+                        let frs2 = cpu::register_field((instruction >> 20) & 0b11111);
+                        let rs2 = (instruction >> 20) & 0b11111;
+                        let rm = funct3;
+                        match (funct7, rs2, funct3) {
+                            (0b0000000, _, _) => Instruction::FaddS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0000100, _, _) => Instruction::FsubS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0001000, _, _) => Instruction::FmulS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0001100, _, _) => Instruction::FdivS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0101100, 0b00000, _) => Instruction::FsqrtS {
+                                rd: frd,
+                                rs1: frs1,
+                                rm,
+                            },
+                            (0b0010000, _, 0b000) => Instruction::FsgnjS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010000, _, 0b001) => Instruction::FsgnjnS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010000, _, 0b010) => Instruction::FsgnjxS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010100, _, 0b000) => Instruction::FminS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010100, _, 0b001) => Instruction::FmaxS {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b1010000, _, 0b010) => Instruction::FeqS {
+                                rd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b1010000, _, 0b001) => Instruction::FltS {
+                                rd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b1010000, _, 0b000) => Instruction::FleS {
+                                rd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b1110000, 0b00000, 0b001) => Instruction::FclassS { rd, rs1: frs1 },
+                            (0b1100000, 0b00000, _) => Instruction::FcvtWS { rd, rs1: frs1, rm },
+                            (0b1100000, 0b00001, _) => Instruction::FcvtWuS { rd, rs1: frs1, rm },
+                            (0b1100000, 0b00010, _) => Instruction::FcvtLS { rd, rs1: frs1, rm },
+                            (0b1100000, 0b00011, _) => Instruction::FcvtLuS { rd, rs1: frs1, rm },
+                            (0b1101000, 0b00000, _) => Instruction::FcvtSW { rd: frd, rs1, rm },
+                            (0b1101000, 0b00001, _) => Instruction::FcvtSWu { rd: frd, rs1, rm },
+                            (0b1101000, 0b00010, _) => Instruction::FcvtSL { rd: frd, rs1, rm },
+                            (0b1101000, 0b00011, _) => Instruction::FcvtSLu { rd: frd, rs1, rm },
+                            (0b1110000, 0b00000, 0b000) => Instruction::FmvXW { rd, rs1: frs1 },
+                            (0b1111000, 0b00000, 0b000) => Instruction::FmvWX { rd: frd, rs1 },
+                            (0b0000001, _, _) => Instruction::FaddD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0000101, _, _) => Instruction::FsubD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0001001, _, _) => Instruction::FmulD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0001101, _, _) => Instruction::FdivD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                                rm,
+                            },
+                            (0b0101101, 0b00000, _) => Instruction::FsqrtD {
+                                rd: frd,
+                                rs1: frs1,
+                                rm,
+                            },
+                            (0b0010001, _, 0b000) => Instruction::FsgnjD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010001, _, 0b001) => Instruction::FsgnjnD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010001, _, 0b010) => Instruction::FsgnjxD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010101, _, 0b000) => Instruction::FminD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0010101, _, 0b001) => Instruction::FmaxD {
+                                rd: frd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b0100000, 0b00001, _) => Instruction::FcvtSD {
+                                rd: frd,
+                                rs1: frs1,
+                                rm,
+                            },
+                            (0b0100001, 0b00000, _) => Instruction::FcvtDS {
+                                rd: frd,
+                                rs1: frs1,
+                                rm,
+                            },
+                            (0b1010001, _, 0b010) => Instruction::FeqD {
+                                rd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b1010001, _, 0b001) => Instruction::FltD {
+                                rd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b1010001, _, 0b000) => Instruction::FleD {
+                                rd,
+                                rs1: frs1,
+                                rs2: frs2,
+                            },
+                            (0b1110001, 0b00000, 0b001) => Instruction::FclassD { rd, rs1: frs1 },
+                            (0b1100001, 0b00000, _) => Instruction::FcvtWD { rd, rs1: frs1, rm },
+                            (0b1100001, 0b00001, _) => Instruction::FcvtWuD { rd, rs1: frs1, rm },
+                            (0b1100001, 0b00010, _) => Instruction::FcvtLD { rd, rs1: frs1, rm },
+                            (0b1100001, 0b00011, _) => Instruction::FcvtLuD { rd, rs1: frs1, rm },
+                            (0b1101001, 0b00000, _) => Instruction::FcvtDW { rd: frd, rs1, rm },
+                            (0b1101001, 0b00001, _) => Instruction::FcvtDWu { rd: frd, rs1, rm },
+                            (0b1101001, 0b00010, _) => Instruction::FcvtDL { rd: frd, rs1, rm },
+                            (0b1101001, 0b00011, _) => Instruction::FcvtDLu { rd: frd, rs1, rm },
+                            (0b1110001, 0b00000, 0b000) => Instruction::FmvXD { rd, rs1: frs1 },
+                            (0b1111001, 0b00000, 0b000) => Instruction::FmvDX { rd: frd, rs1 },
+                            _ => Instruction::Undefined,
+                        }
+                    }
+                    _ => Instruction::Undefined,
+                }
+            }
+
+            InstructionFormat::R4 => {
+                // Decode fields
+                let rs3 = cpu::register_field((instruction >> 27) & 0b11111);
+                let fmt = (instruction >> 25) & 0b11;
+                let rs2 = cpu::register_field((instruction >> 20) & 0b11111);
+                let rs1 = cpu::register_field((instruction >> 15) & 0b11111);
+                let rm = (instruction >> 12) & 0b111;
+                let rd = cpu::register_field((instruction >> 7) & 0b11111);
+
+                match (opcode, fmt) {
+                    (0b1000011, 0b00) => Instruction::FmaddS {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
+                    (0b1000111, 0b00) => Instruction::FmsubS {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
+                    (0b1001011, 0b00) => Instruction::FnmsubS {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
+                    (0b1001111, 0b00) => Instruction::FnmaddS {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
+                    (0b1000011, 0b01) => Instruction::FmaddD {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
+                    (0b1000111, 0b01) => Instruction::FmsubD {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
+                    (0b1001011, 0b01) => Instruction::FnmsubD {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
+                    (0b1001111, 0b01) => Instruction::FnmaddD {
+                        rd,
+                        rs1,
+                        rs2,
+                        rs3,
+                        rm,
+                    },
                     _ => Instruction::Undefined,
                 }
             }
@@ -471,8 +1661,8 @@ impl InstructionFormat {
             InstructionFormat::S => {
                 // Decode fields
                 let imm115 = (instruction >> 25) & 0b1111111;
-                let rs2 = (((instruction >> 20) & 0b11111) as usize).into();
-                let rs1 = (((instruction >> 15) & 0b11111) as usize).into();
+                let rs2 = cpu::register_field((instruction >> 20) & 0b11111);
+                let rs1 = cpu::register_field((instruction >> 15) & 0b11111);
                 let funct3 = (instruction >> 12) & 0b111;
                 let imm40 = (instruction >> 7) & 0b11111;
 
@@ -488,6 +1678,15 @@ impl InstructionFormat {
                         0b011 => Instruction::Sd { rs1, rs2, imm },
                         _ => Instruction::Undefined,
                     },
+                    0b0100111 => {
+                        // Floating point stores read a floating point register
+                        let rs2 = cpu::register_field((instruction >> 20) & 0b11111);
+                        match funct3 {
+                            0b010 => Instruction::Fsw { rs1, rs2, imm },
+                            0b011 => Instruction::Fsd { rs1, rs2, imm },
+                            _ => Instruction::Undefined,
+                        }
+                    }
                     _ => Instruction::Undefined,
                 }
             }
@@ -495,7 +1694,7 @@ impl InstructionFormat {
             InstructionFormat::U => {
                 // Decode fields
                 let imm = (instruction >> 12) as i32;
-                let rd = (((instruction >> 7) & 0b11111) as usize).into();
+                let rd = cpu::register_field((instruction >> 7) & 0b11111);
 
                 match opcode {
                     0b0010111 => Instruction::Auipc { rd, imm },
@@ -514,7 +1713,7 @@ const ENCODING_TABLE: [Option<InstructionFormat>; 128] = [
     /* 0b0000100 */ None,
     /* 0b0000101 */ None,
     /* 0b0000110 */ None,
-    /* 0b0000111 */ None,
+    /* 0b0000111 */ Some(InstructionFormat::I),
     /* 0b0001000 */ None,
     /* 0b0001001 */ None,
     /* 0b0001010 */ None,
@@ -546,7 +1745,7 @@ const ENCODING_TABLE: [Option<InstructionFormat>; 128] = [
     /* 0b0100100 */ None,
     /* 0b0100101 */ None,
     /* 0b0100110 */ None,
-    /* 0b0100111 */ None,
+    /* 0b0100111 */ Some(InstructionFormat::S),
     /* 0b0101000 */ None,
     /* 0b0101001 */ None,
     /* 0b0101010 */ None,
@@ -554,7 +1753,7 @@ const ENCODING_TABLE: [Option<InstructionFormat>; 128] = [
     /* 0b0101100 */ None,
     /* 0b0101101 */ None,
     /* 0b0101110 */ None,
-    /* 0b0101111 */ None,
+    /* 0b0101111 */ Some(InstructionFormat::R),
     /* 0b0110000 */ None,
     /* 0b0110001 */ None,
     /* 0b0110010 */ None,
@@ -574,23 +1773,23 @@ const ENCODING_TABLE: [Option<InstructionFormat>; 128] = [
     /* 0b1000000 */ None,
     /* 0b1000001 */ None,
     /* 0b1000010 */ None,
-    /* 0b1000011 */ None,
+    /* 0b1000011 */ Some(InstructionFormat::R4),
     /* 0b1000100 */ None,
     /* 0b1000101 */ None,
     /* 0b1000110 */ None,
-    /* 0b1000111 */ None,
+    /* 0b1000111 */ Some(InstructionFormat::R4),
     /* 0b1001000 */ None,
     /* 0b1001001 */ None,
     /* 0b1001010 */ None,
-    /* 0b1001011 */ None,
+    /* 0b1001011 */ Some(InstructionFormat::R4),
     /* 0b1001100 */ None,
     /* 0b1001101 */ None,
     /* 0b1001110 */ None,
-    /* 0b1001111 */ None,
+    /* 0b1001111 */ Some(InstructionFormat::R4),
     /* 0b1010000 */ None,
     /* 0b1010001 */ None,
     /* 0b1010010 */ None,
-    /* 0b1010011 */ None,
+    /* 0b1010011 */ Some(InstructionFormat::R),
     /* 0b1010100 */ None,
     /* 0b1010101 */ None,
     /* 0b1010110 */ None,
@@ -661,7 +1860,22 @@ mod tests {
         );
     }
     #[test]
-    fn decode_jal() {}
+    fn decode_jal() {
+        assert_eq!(
+            decode(0x8b4fe0ef),
+            Instruction::Jal {
+                rd: (crate::riscv::cpu::AbiRegister::Ra).into(),
+                imm: -0x1f4c
+            }
+        );
+        assert_eq!(
+            decode(0x7fe7f0ef),
+            Instruction::Jal {
+                rd: (crate::riscv::cpu::AbiRegister::Ra).into(),
+                imm: 0x7f7fe
+            }
+        );
+    }
     #[test]
     fn decode_jalr() {
         assert_eq!(
@@ -1017,6 +2231,17 @@ mod tests {
         );
     }
     #[test]
+    fn decode_fence_i() {
+        assert_eq!(
+            decode(0x0000100f),
+            Instruction::FenceI {
+                rd: crate::riscv::cpu::Register::X0,
+                rs1: crate::riscv::cpu::Register::X0,
+                imm: 0
+            }
+        );
+    }
+    #[test]
     fn decode_ecall() {
         assert_eq!(decode(0x00000073), Instruction::Ecall);
     }
@@ -1025,6 +2250,94 @@ mod tests {
         assert_eq!(decode(0x00100073), Instruction::Ebreak);
     }
     #[test]
+    fn decode_mret() {
+        assert_eq!(decode(0x30200073), Instruction::Mret);
+    }
+    #[test]
+    fn decode_sret() {
+        assert_eq!(decode(0x10200073), Instruction::Sret);
+    }
+    #[test]
+    fn decode_wfi() {
+        assert_eq!(decode(0x10500073), Instruction::Wfi);
+    }
+    #[test]
+    fn decode_sfence_vma() {
+        assert_eq!(
+            decode(0x12b50073),
+            Instruction::SfenceVma {
+                rs1: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_csrrw() {
+        assert_eq!(
+            decode(0x30559073),
+            Instruction::Csrrw {
+                rd: (crate::riscv::cpu::AbiRegister::Zero).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A1).into(),
+                csr: 0x305
+            }
+        );
+    }
+    #[test]
+    fn decode_csrrs() {
+        assert_eq!(
+            decode(0xf1402573),
+            Instruction::Csrrs {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::Zero).into(),
+                csr: 0xf14
+            }
+        );
+    }
+    #[test]
+    fn decode_csrrc() {
+        assert_eq!(
+            decode(0x3002b373),
+            Instruction::Csrrc {
+                rd: (crate::riscv::cpu::AbiRegister::T1).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::T0).into(),
+                csr: 0x300
+            }
+        );
+    }
+    #[test]
+    fn decode_csrrwi() {
+        assert_eq!(
+            decode(0x340fd573),
+            Instruction::Csrrwi {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                uimm: 0x1f,
+                csr: 0x340
+            }
+        );
+    }
+    #[test]
+    fn decode_csrrsi() {
+        assert_eq!(
+            decode(0x30046073),
+            Instruction::Csrrsi {
+                rd: (crate::riscv::cpu::AbiRegister::Zero).into(),
+                uimm: 8,
+                csr: 0x300
+            }
+        );
+    }
+    #[test]
+    fn decode_csrrci() {
+        assert_eq!(
+            decode(0x30047073),
+            Instruction::Csrrci {
+                rd: (crate::riscv::cpu::AbiRegister::Zero).into(),
+                uimm: 8,
+                csr: 0x300
+            }
+        );
+    }
+    #[test]
     fn decode_lwu() {
         assert_eq!(
             decode(0x0000e903),
@@ -1189,4 +2502,1131 @@ mod tests {
             }
         );
     }
+    #[test]
+    fn decode_mul() {
+        assert_eq!(
+            decode(0x02c58533),
+            Instruction::Mul {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A1).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A2).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_mulh() {
+        assert_eq!(
+            decode(0x026294b3),
+            Instruction::Mulh {
+                rd: (crate::riscv::cpu::AbiRegister::S1).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::T0).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::T1).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_mulhsu() {
+        assert_eq!(
+            decode(0x02d727b3),
+            Instruction::Mulhsu {
+                rd: (crate::riscv::cpu::AbiRegister::A5).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A4).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A3).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_mulhu() {
+        assert_eq!(
+            decode(0x03eebe33),
+            Instruction::Mulhu {
+                rd: (crate::riscv::cpu::AbiRegister::T3).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::T4).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::T5).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_div() {
+        assert_eq!(
+            decode(0x02b54533),
+            Instruction::Div {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_divu() {
+        assert_eq!(
+            decode(0x0349d933),
+            Instruction::Divu {
+                rd: (crate::riscv::cpu::AbiRegister::S2).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::S3).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::S4).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_rem() {
+        assert_eq!(
+            decode(0x02f868b3),
+            Instruction::Rem {
+                rd: (crate::riscv::cpu::AbiRegister::A7).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A6).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A5).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_remu() {
+        assert_eq!(
+            decode(0x027372b3),
+            Instruction::Remu {
+                rd: (crate::riscv::cpu::AbiRegister::T0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::T1).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::T2).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_mulw() {
+        assert_eq!(
+            decode(0x02c5853b),
+            Instruction::Mulw {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A1).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A2).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_divw() {
+        assert_eq!(
+            decode(0x02a4c43b),
+            Instruction::Divw {
+                rd: (crate::riscv::cpu::AbiRegister::S0Fp).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::S1).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A0).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_divuw() {
+        assert_eq!(
+            decode(0x02f756bb),
+            Instruction::Divuw {
+                rd: (crate::riscv::cpu::AbiRegister::A3).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A4).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A5).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_remw() {
+        assert_eq!(
+            decode(0x03df6fbb),
+            Instruction::Remw {
+                rd: (crate::riscv::cpu::AbiRegister::T6).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::T5).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::T4).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_remuw() {
+        assert_eq!(
+            decode(0x02d675bb),
+            Instruction::Remuw {
+                rd: (crate::riscv::cpu::AbiRegister::A1).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A3).into(),
+            }
+        );
+    }
+    #[test]
+    fn decode_lr_w() {
+        assert_eq!(
+            decode(0x1005a52f),
+            Instruction::LrW {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_lr_d() {
+        assert_eq!(
+            decode(0x140132af),
+            Instruction::LrD {
+                rd: (crate::riscv::cpu::AbiRegister::T0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::Sp).into(),
+                aq: true,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_sc_w() {
+        assert_eq!(
+            decode(0x18d7262f),
+            Instruction::ScW {
+                rd: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A4).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A3).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_sc_d() {
+        assert_eq!(
+            decode(0x1ab6352f),
+            Instruction::ScD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: true,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoswap_w() {
+        assert_eq!(
+            decode(0x0eb6252f),
+            Instruction::AmoswapW {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: true,
+                rl: true,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoadd_w() {
+        assert_eq!(
+            decode(0x0053202f),
+            Instruction::AmoaddW {
+                rd: (crate::riscv::cpu::AbiRegister::Zero).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::T1).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::T0).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoxor_w() {
+        assert_eq!(
+            decode(0x20e6a7af),
+            Instruction::AmoxorW {
+                rd: (crate::riscv::cpu::AbiRegister::A5).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A3).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A4).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoand_w() {
+        assert_eq!(
+            decode(0x6099242f),
+            Instruction::AmoandW {
+                rd: (crate::riscv::cpu::AbiRegister::S0Fp).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::S2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::S1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoor_w() {
+        assert_eq!(
+            decode(0x44b6252f),
+            Instruction::AmoorW {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: true,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amomin_w() {
+        assert_eq!(
+            decode(0x80b6252f),
+            Instruction::AmominW {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amomax_w() {
+        assert_eq!(
+            decode(0xa0b6252f),
+            Instruction::AmomaxW {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amominu_w() {
+        assert_eq!(
+            decode(0xc0b6252f),
+            Instruction::AmominuW {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amomaxu_w() {
+        assert_eq!(
+            decode(0xe2b6252f),
+            Instruction::AmomaxuW {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: true,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoswap_d() {
+        assert_eq!(
+            decode(0x08b6352f),
+            Instruction::AmoswapD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoadd_d() {
+        assert_eq!(
+            decode(0x05df3e2f),
+            Instruction::AmoaddD {
+                rd: (crate::riscv::cpu::AbiRegister::T3).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::T5).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::T4).into(),
+                aq: true,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoxor_d() {
+        assert_eq!(
+            decode(0x20b6352f),
+            Instruction::AmoxorD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoand_d() {
+        assert_eq!(
+            decode(0x60b6352f),
+            Instruction::AmoandD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amoor_d() {
+        assert_eq!(
+            decode(0x40b6352f),
+            Instruction::AmoorD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amomin_d() {
+        assert_eq!(
+            decode(0x80b6352f),
+            Instruction::AmominD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amomax_d() {
+        assert_eq!(
+            decode(0xa6b6352f),
+            Instruction::AmomaxD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: true,
+                rl: true,
+            }
+        );
+    }
+    #[test]
+    fn decode_amominu_d() {
+        assert_eq!(
+            decode(0xc0b6352f),
+            Instruction::AmominuD {
+                rd: (crate::riscv::cpu::AbiRegister::A0).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::A1).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_amomaxu_d() {
+        assert_eq!(
+            decode(0xe14ab9af),
+            Instruction::AmomaxuD {
+                rd: (crate::riscv::cpu::AbiRegister::S3).into(),
+                rs1: (crate::riscv::cpu::AbiRegister::S5).into(),
+                rs2: (crate::riscv::cpu::AbiRegister::S4).into(),
+                aq: false,
+                rl: false,
+            }
+        );
+    }
+    #[test]
+    fn decode_flw() {
+        assert_eq!(
+            decode(0x010a2e87),
+            Instruction::Flw {
+                rd: crate::riscv::cpu::FRegister::F29,
+                rs1: (crate::riscv::cpu::AbiRegister::S4).into(),
+                imm: 16
+            }
+        );
+    }
+    #[test]
+    fn decode_fsw() {
+        assert_eq!(
+            decode(0xff12ac27),
+            Instruction::Fsw {
+                rs2: crate::riscv::cpu::FRegister::F17,
+                rs1: (crate::riscv::cpu::AbiRegister::T0).into(),
+                imm: -8
+            }
+        );
+    }
+    #[test]
+    fn decode_fmadd_s() {
+        assert_eq!(
+            decode(0x29daa043),
+            Instruction::FmaddS {
+                rd: crate::riscv::cpu::FRegister::F0,
+                rs1: crate::riscv::cpu::FRegister::F21,
+                rs2: crate::riscv::cpu::FRegister::F29,
+                rs3: crate::riscv::cpu::FRegister::F5,
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fmsub_s() {
+        assert_eq!(
+            decode(0xe0ac7147),
+            Instruction::FmsubS {
+                rd: crate::riscv::cpu::FRegister::F2,
+                rs1: crate::riscv::cpu::FRegister::F24,
+                rs2: crate::riscv::cpu::FRegister::F10,
+                rs3: crate::riscv::cpu::FRegister::F28,
+                rm: 0b111
+            }
+        );
+    }
+    #[test]
+    fn decode_fnmsub_s() {
+        assert_eq!(
+            decode(0x78a50dcb),
+            Instruction::FnmsubS {
+                rd: crate::riscv::cpu::FRegister::F27,
+                rs1: crate::riscv::cpu::FRegister::F10,
+                rs2: crate::riscv::cpu::FRegister::F10,
+                rs3: crate::riscv::cpu::FRegister::F15,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fnmadd_s() {
+        assert_eq!(
+            decode(0xc04473cf),
+            Instruction::FnmaddS {
+                rd: crate::riscv::cpu::FRegister::F7,
+                rs1: crate::riscv::cpu::FRegister::F8,
+                rs2: crate::riscv::cpu::FRegister::F4,
+                rs3: crate::riscv::cpu::FRegister::F24,
+                rm: 0b111
+            }
+        );
+    }
+    #[test]
+    fn decode_fadd_s() {
+        assert_eq!(
+            decode(0x00d97353),
+            Instruction::FaddS {
+                rd: crate::riscv::cpu::FRegister::F6,
+                rs1: crate::riscv::cpu::FRegister::F18,
+                rs2: crate::riscv::cpu::FRegister::F13,
+                rm: 0b111
+            }
+        );
+    }
+    #[test]
+    fn decode_fsub_s() {
+        assert_eq!(
+            decode(0x085d2753),
+            Instruction::FsubS {
+                rd: crate::riscv::cpu::FRegister::F14,
+                rs1: crate::riscv::cpu::FRegister::F26,
+                rs2: crate::riscv::cpu::FRegister::F5,
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fmul_s() {
+        assert_eq!(
+            decode(0x111ca6d3),
+            Instruction::FmulS {
+                rd: crate::riscv::cpu::FRegister::F13,
+                rs1: crate::riscv::cpu::FRegister::F25,
+                rs2: crate::riscv::cpu::FRegister::F17,
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fdiv_s() {
+        assert_eq!(
+            decode(0x18063153),
+            Instruction::FdivS {
+                rd: crate::riscv::cpu::FRegister::F2,
+                rs1: crate::riscv::cpu::FRegister::F12,
+                rs2: crate::riscv::cpu::FRegister::F0,
+                rm: 0b011
+            }
+        );
+    }
+    #[test]
+    fn decode_fsqrt_s() {
+        assert_eq!(
+            decode(0x580c31d3),
+            Instruction::FsqrtS {
+                rd: crate::riscv::cpu::FRegister::F3,
+                rs1: crate::riscv::cpu::FRegister::F24,
+                rm: 0b011
+            }
+        );
+    }
+    #[test]
+    fn decode_fsgnj_s() {
+        assert_eq!(
+            decode(0x20f08453),
+            Instruction::FsgnjS {
+                rd: crate::riscv::cpu::FRegister::F8,
+                rs1: crate::riscv::cpu::FRegister::F1,
+                rs2: crate::riscv::cpu::FRegister::F15
+            }
+        );
+    }
+    #[test]
+    fn decode_fsgnjn_s() {
+        assert_eq!(
+            decode(0x20039dd3),
+            Instruction::FsgnjnS {
+                rd: crate::riscv::cpu::FRegister::F27,
+                rs1: crate::riscv::cpu::FRegister::F7,
+                rs2: crate::riscv::cpu::FRegister::F0
+            }
+        );
+    }
+    #[test]
+    fn decode_fsgnjx_s() {
+        assert_eq!(
+            decode(0x20c623d3),
+            Instruction::FsgnjxS {
+                rd: crate::riscv::cpu::FRegister::F7,
+                rs1: crate::riscv::cpu::FRegister::F12,
+                rs2: crate::riscv::cpu::FRegister::F12
+            }
+        );
+    }
+    #[test]
+    fn decode_fmin_s() {
+        assert_eq!(
+            decode(0x28500ad3),
+            Instruction::FminS {
+                rd: crate::riscv::cpu::FRegister::F21,
+                rs1: crate::riscv::cpu::FRegister::F0,
+                rs2: crate::riscv::cpu::FRegister::F5
+            }
+        );
+    }
+    #[test]
+    fn decode_fmax_s() {
+        assert_eq!(
+            decode(0x28509453),
+            Instruction::FmaxS {
+                rd: crate::riscv::cpu::FRegister::F8,
+                rs1: crate::riscv::cpu::FRegister::F1,
+                rs2: crate::riscv::cpu::FRegister::F5
+            }
+        );
+    }
+    #[test]
+    fn decode_feq_s() {
+        assert_eq!(
+            decode(0xa0cfa9d3),
+            Instruction::FeqS {
+                rd: (crate::riscv::cpu::AbiRegister::S3).into(),
+                rs1: crate::riscv::cpu::FRegister::F31,
+                rs2: crate::riscv::cpu::FRegister::F12
+            }
+        );
+    }
+    #[test]
+    fn decode_flt_s() {
+        assert_eq!(
+            decode(0xa1921753),
+            Instruction::FltS {
+                rd: (crate::riscv::cpu::AbiRegister::A4).into(),
+                rs1: crate::riscv::cpu::FRegister::F4,
+                rs2: crate::riscv::cpu::FRegister::F25
+            }
+        );
+    }
+    #[test]
+    fn decode_fle_s() {
+        assert_eq!(
+            decode(0xa09283d3),
+            Instruction::FleS {
+                rd: (crate::riscv::cpu::AbiRegister::T2).into(),
+                rs1: crate::riscv::cpu::FRegister::F5,
+                rs2: crate::riscv::cpu::FRegister::F9
+            }
+        );
+    }
+    #[test]
+    fn decode_fclass_s() {
+        assert_eq!(
+            decode(0xe0011353),
+            Instruction::FclassS {
+                rd: (crate::riscv::cpu::AbiRegister::T1).into(),
+                rs1: crate::riscv::cpu::FRegister::F2
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_w_s() {
+        assert_eq!(
+            decode(0xc008c153),
+            Instruction::FcvtWS {
+                rd: (crate::riscv::cpu::AbiRegister::Sp).into(),
+                rs1: crate::riscv::cpu::FRegister::F17,
+                rm: 0b100
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_wu_s() {
+        assert_eq!(
+            decode(0xc014fb53),
+            Instruction::FcvtWuS {
+                rd: (crate::riscv::cpu::AbiRegister::S6).into(),
+                rs1: crate::riscv::cpu::FRegister::F9,
+                rm: 0b111
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_l_s() {
+        assert_eq!(
+            decode(0xc02104d3),
+            Instruction::FcvtLS {
+                rd: (crate::riscv::cpu::AbiRegister::S1).into(),
+                rs1: crate::riscv::cpu::FRegister::F2,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_lu_s() {
+        assert_eq!(
+            decode(0xc03c9e53),
+            Instruction::FcvtLuS {
+                rd: (crate::riscv::cpu::AbiRegister::T3).into(),
+                rs1: crate::riscv::cpu::FRegister::F25,
+                rm: 0b001
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_s_w() {
+        assert_eq!(
+            decode(0xd00a4553),
+            Instruction::FcvtSW {
+                rd: crate::riscv::cpu::FRegister::F10,
+                rs1: (crate::riscv::cpu::AbiRegister::S4).into(),
+                rm: 0b100
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_s_wu() {
+        assert_eq!(
+            decode(0xd01c2153),
+            Instruction::FcvtSWu {
+                rd: crate::riscv::cpu::FRegister::F2,
+                rs1: (crate::riscv::cpu::AbiRegister::S8).into(),
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_s_l() {
+        assert_eq!(
+            decode(0xd02e22d3),
+            Instruction::FcvtSL {
+                rd: crate::riscv::cpu::FRegister::F5,
+                rs1: (crate::riscv::cpu::AbiRegister::T3).into(),
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_s_lu() {
+        assert_eq!(
+            decode(0xd03e43d3),
+            Instruction::FcvtSLu {
+                rd: crate::riscv::cpu::FRegister::F7,
+                rs1: (crate::riscv::cpu::AbiRegister::T3).into(),
+                rm: 0b100
+            }
+        );
+    }
+    #[test]
+    fn decode_fmv_x_w() {
+        assert_eq!(
+            decode(0xe00e0653),
+            Instruction::FmvXW {
+                rd: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs1: crate::riscv::cpu::FRegister::F28
+            }
+        );
+    }
+    #[test]
+    fn decode_fmv_w_x() {
+        assert_eq!(
+            decode(0xf00686d3),
+            Instruction::FmvWX {
+                rd: crate::riscv::cpu::FRegister::F13,
+                rs1: (crate::riscv::cpu::AbiRegister::A3).into()
+            }
+        );
+    }
+    #[test]
+    fn decode_fld() {
+        assert_eq!(
+            decode(0x3e8fb607),
+            Instruction::Fld {
+                rd: crate::riscv::cpu::FRegister::F12,
+                rs1: (crate::riscv::cpu::AbiRegister::T6).into(),
+                imm: 1000
+            }
+        );
+    }
+    #[test]
+    fn decode_fsd() {
+        assert_eq!(
+            decode(0x3e16b427),
+            Instruction::Fsd {
+                rs2: crate::riscv::cpu::FRegister::F1,
+                rs1: (crate::riscv::cpu::AbiRegister::A3).into(),
+                imm: 1000
+            }
+        );
+    }
+    #[test]
+    fn decode_fmadd_d() {
+        assert_eq!(
+            decode(0x3bb01ac3),
+            Instruction::FmaddD {
+                rd: crate::riscv::cpu::FRegister::F21,
+                rs1: crate::riscv::cpu::FRegister::F0,
+                rs2: crate::riscv::cpu::FRegister::F27,
+                rs3: crate::riscv::cpu::FRegister::F7,
+                rm: 0b001
+            }
+        );
+    }
+    #[test]
+    fn decode_fmsub_d() {
+        assert_eq!(
+            decode(0xa31e0747),
+            Instruction::FmsubD {
+                rd: crate::riscv::cpu::FRegister::F14,
+                rs1: crate::riscv::cpu::FRegister::F28,
+                rs2: crate::riscv::cpu::FRegister::F17,
+                rs3: crate::riscv::cpu::FRegister::F20,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fnmsub_d() {
+        assert_eq!(
+            decode(0x1a6909cb),
+            Instruction::FnmsubD {
+                rd: crate::riscv::cpu::FRegister::F19,
+                rs1: crate::riscv::cpu::FRegister::F18,
+                rs2: crate::riscv::cpu::FRegister::F6,
+                rs3: crate::riscv::cpu::FRegister::F3,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fnmadd_d() {
+        assert_eq!(
+            decode(0xb3fdfc4f),
+            Instruction::FnmaddD {
+                rd: crate::riscv::cpu::FRegister::F24,
+                rs1: crate::riscv::cpu::FRegister::F27,
+                rs2: crate::riscv::cpu::FRegister::F31,
+                rs3: crate::riscv::cpu::FRegister::F22,
+                rm: 0b111
+            }
+        );
+    }
+    #[test]
+    fn decode_fadd_d() {
+        assert_eq!(
+            decode(0x03b277d3),
+            Instruction::FaddD {
+                rd: crate::riscv::cpu::FRegister::F15,
+                rs1: crate::riscv::cpu::FRegister::F4,
+                rs2: crate::riscv::cpu::FRegister::F27,
+                rm: 0b111
+            }
+        );
+    }
+    #[test]
+    fn decode_fsub_d() {
+        assert_eq!(
+            decode(0x0b934753),
+            Instruction::FsubD {
+                rd: crate::riscv::cpu::FRegister::F14,
+                rs1: crate::riscv::cpu::FRegister::F6,
+                rs2: crate::riscv::cpu::FRegister::F25,
+                rm: 0b100
+            }
+        );
+    }
+    #[test]
+    fn decode_fmul_d() {
+        assert_eq!(
+            decode(0x128bfbd3),
+            Instruction::FmulD {
+                rd: crate::riscv::cpu::FRegister::F23,
+                rs1: crate::riscv::cpu::FRegister::F23,
+                rs2: crate::riscv::cpu::FRegister::F8,
+                rm: 0b111
+            }
+        );
+    }
+    #[test]
+    fn decode_fdiv_d() {
+        assert_eq!(
+            decode(0x1b050953),
+            Instruction::FdivD {
+                rd: crate::riscv::cpu::FRegister::F18,
+                rs1: crate::riscv::cpu::FRegister::F10,
+                rs2: crate::riscv::cpu::FRegister::F16,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fsqrt_d() {
+        assert_eq!(
+            decode(0x5a05a1d3),
+            Instruction::FsqrtD {
+                rd: crate::riscv::cpu::FRegister::F3,
+                rs1: crate::riscv::cpu::FRegister::F11,
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fsgnj_d() {
+        assert_eq!(
+            decode(0x22938553),
+            Instruction::FsgnjD {
+                rd: crate::riscv::cpu::FRegister::F10,
+                rs1: crate::riscv::cpu::FRegister::F7,
+                rs2: crate::riscv::cpu::FRegister::F9
+            }
+        );
+    }
+    #[test]
+    fn decode_fsgnjn_d() {
+        assert_eq!(
+            decode(0x22319053),
+            Instruction::FsgnjnD {
+                rd: crate::riscv::cpu::FRegister::F0,
+                rs1: crate::riscv::cpu::FRegister::F3,
+                rs2: crate::riscv::cpu::FRegister::F3
+            }
+        );
+    }
+    #[test]
+    fn decode_fsgnjx_d() {
+        assert_eq!(
+            decode(0x2386ae53),
+            Instruction::FsgnjxD {
+                rd: crate::riscv::cpu::FRegister::F28,
+                rs1: crate::riscv::cpu::FRegister::F13,
+                rs2: crate::riscv::cpu::FRegister::F24
+            }
+        );
+    }
+    #[test]
+    fn decode_fmin_d() {
+        assert_eq!(
+            decode(0x2b600953),
+            Instruction::FminD {
+                rd: crate::riscv::cpu::FRegister::F18,
+                rs1: crate::riscv::cpu::FRegister::F0,
+                rs2: crate::riscv::cpu::FRegister::F22
+            }
+        );
+    }
+    #[test]
+    fn decode_fmax_d() {
+        assert_eq!(
+            decode(0x2be49953),
+            Instruction::FmaxD {
+                rd: crate::riscv::cpu::FRegister::F18,
+                rs1: crate::riscv::cpu::FRegister::F9,
+                rs2: crate::riscv::cpu::FRegister::F30
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_s_d() {
+        assert_eq!(
+            decode(0x401408d3),
+            Instruction::FcvtSD {
+                rd: crate::riscv::cpu::FRegister::F17,
+                rs1: crate::riscv::cpu::FRegister::F8,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_d_s() {
+        assert_eq!(
+            decode(0x42038953),
+            Instruction::FcvtDS {
+                rd: crate::riscv::cpu::FRegister::F18,
+                rs1: crate::riscv::cpu::FRegister::F7,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_feq_d() {
+        assert_eq!(
+            decode(0xa29e2753),
+            Instruction::FeqD {
+                rd: (crate::riscv::cpu::AbiRegister::A4).into(),
+                rs1: crate::riscv::cpu::FRegister::F28,
+                rs2: crate::riscv::cpu::FRegister::F9
+            }
+        );
+    }
+    #[test]
+    fn decode_flt_d() {
+        assert_eq!(
+            decode(0xa2ac1bd3),
+            Instruction::FltD {
+                rd: (crate::riscv::cpu::AbiRegister::S7).into(),
+                rs1: crate::riscv::cpu::FRegister::F24,
+                rs2: crate::riscv::cpu::FRegister::F10
+            }
+        );
+    }
+    #[test]
+    fn decode_fle_d() {
+        assert_eq!(
+            decode(0xa3f88b53),
+            Instruction::FleD {
+                rd: (crate::riscv::cpu::AbiRegister::S6).into(),
+                rs1: crate::riscv::cpu::FRegister::F17,
+                rs2: crate::riscv::cpu::FRegister::F31
+            }
+        );
+    }
+    #[test]
+    fn decode_fclass_d() {
+        assert_eq!(
+            decode(0xe2031653),
+            Instruction::FclassD {
+                rd: (crate::riscv::cpu::AbiRegister::A2).into(),
+                rs1: crate::riscv::cpu::FRegister::F6
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_w_d() {
+        assert_eq!(
+            decode(0xc201af53),
+            Instruction::FcvtWD {
+                rd: (crate::riscv::cpu::AbiRegister::T5).into(),
+                rs1: crate::riscv::cpu::FRegister::F3,
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_wu_d() {
+        assert_eq!(
+            decode(0xc21418d3),
+            Instruction::FcvtWuD {
+                rd: (crate::riscv::cpu::AbiRegister::A7).into(),
+                rs1: crate::riscv::cpu::FRegister::F8,
+                rm: 0b001
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_l_d() {
+        assert_eq!(
+            decode(0xc2229d53),
+            Instruction::FcvtLD {
+                rd: (crate::riscv::cpu::AbiRegister::S10).into(),
+                rs1: crate::riscv::cpu::FRegister::F5,
+                rm: 0b001
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_lu_d() {
+        assert_eq!(
+            decode(0xc2370cd3),
+            Instruction::FcvtLuD {
+                rd: (crate::riscv::cpu::AbiRegister::S9).into(),
+                rs1: crate::riscv::cpu::FRegister::F14,
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_d_w() {
+        assert_eq!(
+            decode(0xd2030653),
+            Instruction::FcvtDW {
+                rd: crate::riscv::cpu::FRegister::F12,
+                rs1: (crate::riscv::cpu::AbiRegister::T1).into(),
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_d_wu() {
+        assert_eq!(
+            decode(0xd2198ed3),
+            Instruction::FcvtDWu {
+                rd: crate::riscv::cpu::FRegister::F29,
+                rs1: (crate::riscv::cpu::AbiRegister::S3).into(),
+                rm: 0b000
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_d_l() {
+        assert_eq!(
+            decode(0xd2219c53),
+            Instruction::FcvtDL {
+                rd: crate::riscv::cpu::FRegister::F24,
+                rs1: (crate::riscv::cpu::AbiRegister::Gp).into(),
+                rm: 0b001
+            }
+        );
+    }
+    #[test]
+    fn decode_fcvt_d_lu() {
+        assert_eq!(
+            decode(0xd239af53),
+            Instruction::FcvtDLu {
+                rd: crate::riscv::cpu::FRegister::F30,
+                rs1: (crate::riscv::cpu::AbiRegister::S3).into(),
+                rm: 0b010
+            }
+        );
+    }
+    #[test]
+    fn decode_fmv_x_d() {
+        assert_eq!(
+            decode(0xe2020953),
+            Instruction::FmvXD {
+                rd: (crate::riscv::cpu::AbiRegister::S2).into(),
+                rs1: crate::riscv::cpu::FRegister::F4
+            }
+        );
+    }
+    #[test]
+    fn decode_fmv_d_x() {
+        assert_eq!(
+            decode(0xf20980d3),
+            Instruction::FmvDX {
+                rd: crate::riscv::cpu::FRegister::F1,
+                rs1: (crate::riscv::cpu::AbiRegister::S3).into()
+            }
+        );
+    }
 }
@@ -0,0 +1,61 @@
+// Byte addressable little endian storage backing a region of the address space
+use std::convert::TryFrom;
+
+pub struct Memory {
+    data: Vec<u8>,
+}
+impl Memory {
+    pub fn new(size: u64) -> Self {
+        Self {
+            data: vec![0; size as usize],
+        }
+    }
+    // None if the host can't spare `size` bytes, where allocating them
+    // would abort the process. The reservation is only a check, and vec!
+    // then takes zeroed pages without touching them.
+    pub fn try_new(size: u64) -> Option<Self> {
+        let size = usize::try_from(size).ok()?;
+        Vec::<u8>::new().try_reserve_exact(size).ok()?;
+        Some(Self {
+            data: vec![0; size],
+        })
+    }
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+    // Read `size` bytes starting at `offset` from the start of the region
+    pub fn read(&self, offset: u64, size: usize) -> u64 {
+        let index = offset as usize;
+        self.data[index..index + size]
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| (value << 8) | byte as u64)
+    }
+    // Write the low `size` bytes of `value` starting at `offset`
+    pub fn write(&mut self, offset: u64, size: usize, value: u64) {
+        let index = offset as usize;
+        for (i, byte) in self.data[index..index + size].iter_mut().enumerate() {
+            *byte = (value >> (i * 8)) as u8;
+        }
+    }
+    pub fn write_bytes(&mut self, offset: u64, bytes: &[u8]) {
+        let index = offset as usize;
+        self.data[index..index + bytes.len()].copy_from_slice(bytes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn read_write() {
+        let mut memory = Memory::new(16);
+        memory.write(4, 4, 0x1122_3344_5566_7788);
+        assert_eq!(memory.read(4, 8), 0x5566_7788);
+        assert_eq!(memory.read(5, 2), 0x6677);
+        memory.write_bytes(8, &[0xaa, 0xbb]);
+        assert_eq!(memory.read(7, 4), 0xbbaa55);
+        assert_eq!(Memory::try_new(16).map(|memory| memory.size()), Some(16));
+        assert!(Memory::try_new(1 << 62).is_none());
+    }
+}
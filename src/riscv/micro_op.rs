@@ -0,0 +1,410 @@
+// Instructions of basic blocks, translated ahead of time into the function
+// that runs them and the operands it needs. Running one skips the match over
+// every instruction and the misa checks, which are made once when it is
+// translated. The common integer instructions read and write the registers
+// directly, and the rest run through execute as step runs them.
+use crate::riscv::cpu::{Cpu, Register};
+use crate::riscv::csr::Csr;
+use crate::riscv::execute;
+use crate::riscv::instruction::{self, Instruction};
+use crate::riscv::trap::Exception;
+
+type Run = fn(&mut Cpu, &Op) -> Result<(), Exception>;
+
+#[derive(Clone, Copy)]
+pub struct Op {
+    run: Run,
+    // Kept for the value of an illegal instruction exception
+    pub encoded: u32,
+    pub instruction: Instruction,
+    pub length: u64,
+    rd: usize,
+    rs1: usize,
+    rs2: usize,
+    // The immediate sign extended, or the shift amount
+    imm: u64,
+}
+impl Op {
+    // Run the instruction at pc, with next_pc already pointing past it
+    pub fn run(&self, cpu: &mut Cpu) -> Result<(), Exception> {
+        (self.run)(cpu, self)
+    }
+}
+
+// Translate an instruction for the extensions in misa. Compressed
+// instructions without C, and instructions of extensions that misa leaves
+// out, are illegal.
+pub fn translate(encoded: u32, instruction: Instruction, csr: &Csr) -> Op {
+    let length = instruction::length(encoded);
+    let instruction = if (length == 2 && !csr.has_extension(b'C'))
+        || !csr.has_extension(instruction.extension())
+    {
+        Instruction::Undefined
+    } else {
+        instruction
+    };
+    let op = |run: Run, rd: Register, rs1: Register, rs2: Register, imm: i64| Op {
+        run,
+        encoded,
+        instruction,
+        length,
+        rd: rd.into(),
+        rs1: rs1.into(),
+        rs2: rs2.into(),
+        imm: imm as u64,
+    };
+    let x0 = Register::X0;
+    let upper = |imm: i32| i64::from(imm << 12);
+    match instruction {
+        Instruction::Lui { rd, imm } => op(lui, rd, x0, x0, upper(imm)),
+        Instruction::Auipc { rd, imm } => op(auipc, rd, x0, x0, upper(imm)),
+        Instruction::Jal { rd, imm } => op(jal, rd, x0, x0, imm.into()),
+        Instruction::Jalr { rd, rs1, imm } => op(jalr, rd, rs1, x0, imm.into()),
+        Instruction::Beq { rs1, rs2, imm } => op(beq, x0, rs1, rs2, imm.into()),
+        Instruction::Bne { rs1, rs2, imm } => op(bne, x0, rs1, rs2, imm.into()),
+        Instruction::Blt { rs1, rs2, imm } => op(blt, x0, rs1, rs2, imm.into()),
+        Instruction::Bge { rs1, rs2, imm } => op(bge, x0, rs1, rs2, imm.into()),
+        Instruction::Bltu { rs1, rs2, imm } => op(bltu, x0, rs1, rs2, imm.into()),
+        Instruction::Bgeu { rs1, rs2, imm } => op(bgeu, x0, rs1, rs2, imm.into()),
+        Instruction::Lb { rd, rs1, imm } => op(lb, rd, rs1, x0, imm.into()),
+        Instruction::Lh { rd, rs1, imm } => op(lh, rd, rs1, x0, imm.into()),
+        Instruction::Lw { rd, rs1, imm } => op(lw, rd, rs1, x0, imm.into()),
+        Instruction::Ld { rd, rs1, imm } => op(ld, rd, rs1, x0, imm.into()),
+        Instruction::Lbu { rd, rs1, imm } => op(lbu, rd, rs1, x0, imm.into()),
+        Instruction::Lhu { rd, rs1, imm } => op(lhu, rd, rs1, x0, imm.into()),
+        Instruction::Lwu { rd, rs1, imm } => op(lwu, rd, rs1, x0, imm.into()),
+        Instruction::Sb { rs2, rs1, imm } => op(sb, x0, rs1, rs2, imm.into()),
+        Instruction::Sh { rs2, rs1, imm } => op(sh, x0, rs1, rs2, imm.into()),
+        Instruction::Sw { rs2, rs1, imm } => op(sw, x0, rs1, rs2, imm.into()),
+        Instruction::Sd { rs2, rs1, imm } => op(sd, x0, rs1, rs2, imm.into()),
+        Instruction::Addi { rd, rs1, imm } => op(addi, rd, rs1, x0, imm.into()),
+        Instruction::Slti { rd, rs1, imm } => op(slti, rd, rs1, x0, imm.into()),
+        Instruction::Sltiu { rd, rs1, imm } => op(sltiu, rd, rs1, x0, imm.into()),
+        Instruction::Xori { rd, rs1, imm } => op(xori, rd, rs1, x0, imm.into()),
+        Instruction::Ori { rd, rs1, imm } => op(ori, rd, rs1, x0, imm.into()),
+        Instruction::Andi { rd, rs1, imm } => op(andi, rd, rs1, x0, imm.into()),
+        Instruction::Slli { rd, rs1, shamt } => op(slli, rd, rs1, x0, shamt.into()),
+        Instruction::Srli { rd, rs1, shamt } => op(srli, rd, rs1, x0, shamt.into()),
+        Instruction::Srai { rd, rs1, shamt } => op(srai, rd, rs1, x0, shamt.into()),
+        Instruction::Addiw { rd, rs1, imm } => op(addiw, rd, rs1, x0, imm.into()),
+        Instruction::Slliw { rd, rs1, shamt } => op(slliw, rd, rs1, x0, shamt.into()),
+        Instruction::Srliw { rd, rs1, shamt } => op(srliw, rd, rs1, x0, shamt.into()),
+        Instruction::Sraiw { rd, rs1, shamt } => op(sraiw, rd, rs1, x0, shamt.into()),
+        Instruction::Add { rd, rs1, rs2 } => op(add, rd, rs1, rs2, 0),
+        Instruction::Sub { rd, rs1, rs2 } => op(sub, rd, rs1, rs2, 0),
+        Instruction::Sll { rd, rs1, rs2 } => op(sll, rd, rs1, rs2, 0),
+        Instruction::Slt { rd, rs1, rs2 } => op(slt, rd, rs1, rs2, 0),
+        Instruction::Sltu { rd, rs1, rs2 } => op(sltu, rd, rs1, rs2, 0),
+        Instruction::Xor { rd, rs1, rs2 } => op(xor, rd, rs1, rs2, 0),
+        Instruction::Srl { rd, rs1, rs2 } => op(srl, rd, rs1, rs2, 0),
+        Instruction::Sra { rd, rs1, rs2 } => op(sra, rd, rs1, rs2, 0),
+        Instruction::Or { rd, rs1, rs2 } => op(or, rd, rs1, rs2, 0),
+        Instruction::And { rd, rs1, rs2 } => op(and, rd, rs1, rs2, 0),
+        Instruction::Addw { rd, rs1, rs2 } => op(addw, rd, rs1, rs2, 0),
+        Instruction::Subw { rd, rs1, rs2 } => op(subw, rd, rs1, rs2, 0),
+        Instruction::Sllw { rd, rs1, rs2 } => op(sllw, rd, rs1, rs2, 0),
+        Instruction::Srlw { rd, rs1, rs2 } => op(srlw, rd, rs1, rs2, 0),
+        Instruction::Sraw { rd, rs1, rs2 } => op(sraw, rd, rs1, rs2, 0),
+        Instruction::Mul { rd, rs1, rs2 } => op(mul, rd, rs1, rs2, 0),
+        _ => op(execute, x0, x0, x0, 0),
+    }
+}
+
+// Instructions without a translation of their own
+fn execute(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    cpu.execute(op.instruction)
+        .map_err(|exception| match exception {
+            Exception::IllegalInstruction(_) => Exception::IllegalInstruction(op.encoded as u64),
+            exception => exception,
+        })
+}
+
+// Registers are read straight from the register file, where x0 stays zero
+// because writes to it are discarded
+fn x(cpu: &Cpu, register: usize) -> u64 {
+    cpu.registers[register]
+}
+fn set(cpu: &mut Cpu, rd: usize, value: u64) {
+    if rd != 0 {
+        cpu.registers[rd] = value;
+    }
+}
+fn address(cpu: &Cpu, op: &Op) -> u64 {
+    x(cpu, op.rs1).wrapping_add(op.imm)
+}
+
+fn lui(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, op.imm);
+    Ok(())
+}
+fn auipc(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, cpu.pc.wrapping_add(op.imm));
+    Ok(())
+}
+
+// The link is written after the jump, which can fault, and the target is
+// computed before it since rd may equal rs1
+fn jal(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let link = cpu.next_pc;
+    execute::jump(cpu.pc.wrapping_add(op.imm), cpu)?;
+    set(cpu, op.rd, link);
+    Ok(())
+}
+fn jalr(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let link = cpu.next_pc;
+    execute::jump(address(cpu, op) & !1, cpu)?;
+    set(cpu, op.rd, link);
+    Ok(())
+}
+
+fn branch(condition: bool, cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    if condition {
+        execute::jump(cpu.pc.wrapping_add(op.imm), cpu)?;
+    }
+    Ok(())
+}
+fn beq(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    branch(x(cpu, op.rs1) == x(cpu, op.rs2), cpu, op)
+}
+fn bne(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    branch(x(cpu, op.rs1) != x(cpu, op.rs2), cpu, op)
+}
+fn blt(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    branch((x(cpu, op.rs1) as i64) < (x(cpu, op.rs2) as i64), cpu, op)
+}
+fn bge(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    branch((x(cpu, op.rs1) as i64) >= (x(cpu, op.rs2) as i64), cpu, op)
+}
+fn bltu(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    branch(x(cpu, op.rs1) < x(cpu, op.rs2), cpu, op)
+}
+fn bgeu(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    branch(x(cpu, op.rs1) >= x(cpu, op.rs2), cpu, op)
+}
+
+fn lb(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = cpu.load(address(cpu, op), 1)? as i8;
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+fn lh(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = cpu.load(address(cpu, op), 2)? as i16;
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+fn lw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = cpu.load(address(cpu, op), 4)? as i32;
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+fn ld(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = cpu.load(address(cpu, op), 8)?;
+    set(cpu, op.rd, value);
+    Ok(())
+}
+fn lbu(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = cpu.load(address(cpu, op), 1)?;
+    set(cpu, op.rd, value);
+    Ok(())
+}
+fn lhu(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = cpu.load(address(cpu, op), 2)?;
+    set(cpu, op.rd, value);
+    Ok(())
+}
+fn lwu(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = cpu.load(address(cpu, op), 4)?;
+    set(cpu, op.rd, value);
+    Ok(())
+}
+
+fn sb(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    cpu.store(address(cpu, op), 1, x(cpu, op.rs2))
+}
+fn sh(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    cpu.store(address(cpu, op), 2, x(cpu, op.rs2))
+}
+fn sw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    cpu.store(address(cpu, op), 4, x(cpu, op.rs2))
+}
+fn sd(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    cpu.store(address(cpu, op), 8, x(cpu, op.rs2))
+}
+
+fn addi(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1).wrapping_add(op.imm));
+    Ok(())
+}
+fn slti(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, ((x(cpu, op.rs1) as i64) < op.imm as i64) as u64);
+    Ok(())
+}
+fn sltiu(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, (x(cpu, op.rs1) < op.imm) as u64);
+    Ok(())
+}
+fn xori(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) ^ op.imm);
+    Ok(())
+}
+fn ori(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) | op.imm);
+    Ok(())
+}
+fn andi(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) & op.imm);
+    Ok(())
+}
+fn slli(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) << op.imm);
+    Ok(())
+}
+fn srli(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) >> op.imm);
+    Ok(())
+}
+fn srai(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, ((x(cpu, op.rs1) as i64) >> op.imm) as u64);
+    Ok(())
+}
+fn addiw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as i32).wrapping_add(op.imm as i32);
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+fn slliw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as u32) << op.imm;
+    set(cpu, op.rd, value as i32 as i64 as u64);
+    Ok(())
+}
+fn srliw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as u32) >> op.imm;
+    set(cpu, op.rd, value as i32 as i64 as u64);
+    Ok(())
+}
+fn sraiw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as i32) >> op.imm;
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+
+fn add(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1).wrapping_add(x(cpu, op.rs2)));
+    Ok(())
+}
+fn sub(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1).wrapping_sub(x(cpu, op.rs2)));
+    Ok(())
+}
+fn sll(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) << (x(cpu, op.rs2) & 0b111111));
+    Ok(())
+}
+fn slt(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as i64) < (x(cpu, op.rs2) as i64);
+    set(cpu, op.rd, value as u64);
+    Ok(())
+}
+fn sltu(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, (x(cpu, op.rs1) < x(cpu, op.rs2)) as u64);
+    Ok(())
+}
+fn xor(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) ^ x(cpu, op.rs2));
+    Ok(())
+}
+fn srl(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) >> (x(cpu, op.rs2) & 0b111111));
+    Ok(())
+}
+fn sra(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as i64) >> (x(cpu, op.rs2) & 0b111111);
+    set(cpu, op.rd, value as u64);
+    Ok(())
+}
+fn or(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) | x(cpu, op.rs2));
+    Ok(())
+}
+fn and(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1) & x(cpu, op.rs2));
+    Ok(())
+}
+fn addw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as i32).wrapping_add(x(cpu, op.rs2) as i32);
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+fn subw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as i32).wrapping_sub(x(cpu, op.rs2) as i32);
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+fn sllw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as u32) << (x(cpu, op.rs2) & 0b11111);
+    set(cpu, op.rd, value as i32 as i64 as u64);
+    Ok(())
+}
+fn srlw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as u32) >> (x(cpu, op.rs2) & 0b11111);
+    set(cpu, op.rd, value as i32 as i64 as u64);
+    Ok(())
+}
+fn sraw(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    let value = (x(cpu, op.rs1) as i32) >> (x(cpu, op.rs2) & 0b11111);
+    set(cpu, op.rd, value as i64 as u64);
+    Ok(())
+}
+fn mul(cpu: &mut Cpu, op: &Op) -> Result<(), Exception> {
+    set(cpu, op.rd, x(cpu, op.rs1).wrapping_mul(x(cpu, op.rs2)));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+    use crate::riscv::csr;
+    use crate::riscv::instruction::decode;
+
+    fn run(cpu: &mut Cpu, encoded: u32) -> Result<(), Exception> {
+        let op = translate(encoded, decode(encoded), &cpu.csr);
+        cpu.next_pc = cpu.pc.wrapping_add(op.length);
+        op.run(cpu)?;
+        cpu.pc = cpu.next_pc;
+        Ok(())
+    }
+
+    #[test]
+    fn translated_instructions() {
+        let mut cpu = Cpu::new(Bus::new(0x100));
+        cpu.pc = DRAM_BASE;
+        // addi a0, zero, -1; srli a1, a0, 60; sd a1, 8(zero) faults
+        run(&mut cpu, 0xfff0_0513).unwrap();
+        run(&mut cpu, 0x03c5_5593).unwrap();
+        assert_eq!(cpu.registers[10], u64::MAX);
+        assert_eq!(cpu.registers[11], 0xf);
+        assert_eq!(
+            run(&mut cpu, 0x00b0_3423),
+            Err(Exception::StoreAccessFault(8))
+        );
+        // Writes to x0 are discarded: addi zero, a0, 1
+        run(&mut cpu, 0x0015_0013).unwrap();
+        assert_eq!(cpu.registers[0], 0);
+        // jalr ra, 0(a0) jumps to the target with bit 0 cleared
+        cpu.registers[10] = DRAM_BASE + 0x41;
+        run(&mut cpu, 0x0005_00e7).unwrap();
+        assert_eq!(cpu.pc, DRAM_BASE + 0x40);
+        assert_eq!(cpu.registers[1], DRAM_BASE + 0x10);
+
+        // Instructions run through execute keep their encoding in illegal
+        // instruction exceptions, as do compressed ones without C
+        cpu.csr
+            .write(csr::MISA, cpu.csr.read(csr::MISA) & !csr::extension(b'C'));
+        assert_eq!(
+            run(&mut cpu, 0x0505),
+            Err(Exception::IllegalInstruction(0x0505))
+        );
+        assert_eq!(run(&mut cpu, 0), Err(Exception::IllegalInstruction(0)));
+    }
+}
@@ -0,0 +1,452 @@
+// Translation of virtual addresses by walking Sv39, Sv48 and Sv57 page
+// tables. Every instruction fetch, load and store goes through here before
+// it reaches the bus.
+use crate::riscv::cpu::{Cpu, Privilege};
+use crate::riscv::csr;
+use crate::riscv::pmp;
+use crate::riscv::tlb;
+use crate::riscv::trap::Exception;
+
+pub const PAGE_SIZE: u64 = 4096;
+
+// Page table entry fields
+const PTE_V: u64 = 1 << 0;
+const PTE_R: u64 = 1 << 1;
+const PTE_W: u64 = 1 << 2;
+const PTE_X: u64 = 1 << 3;
+const PTE_U: u64 = 1 << 4;
+const PTE_G: u64 = 1 << 5;
+const PTE_A: u64 = 1 << 6;
+const PTE_D: u64 = 1 << 7;
+// Bits 63:54 are reserved for extensions that aren't implemented
+const PTE_RESERVED: u64 = 0x3ff << 54;
+const PTE_PPN_MASK: u64 = (1 << 44) - 1;
+const PTE_SIZE: u64 = 8;
+
+// satp modes
+pub const SATP_BARE: u64 = 0;
+pub const SATP_SV39: u64 = 8;
+pub const SATP_SV48: u64 = 9;
+pub const SATP_SV57: u64 = 10;
+const SATP_PPN_MASK: u64 = (1 << 44) - 1;
+const ASID_MASK: u64 = 0xffff;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Instruction,
+    Load,
+    Store,
+}
+impl Access {
+    pub fn page_fault(self, address: u64) -> Exception {
+        match self {
+            Access::Instruction => Exception::InstructionPageFault(address),
+            Access::Load => Exception::LoadPageFault(address),
+            Access::Store => Exception::StorePageFault(address),
+        }
+    }
+    pub fn access_fault(self, address: u64) -> Exception {
+        match self {
+            Access::Instruction => Exception::InstructionAccessFault(address),
+            Access::Load => Exception::LoadAccessFault(address),
+            Access::Store => Exception::StoreAccessFault(address),
+        }
+    }
+}
+
+// Number of page table levels in a satp mode, or None without translation
+fn levels(mode: u64) -> Option<u32> {
+    match mode {
+        SATP_SV39 => Some(3),
+        SATP_SV48 => Some(4),
+        SATP_SV57 => Some(5),
+        _ => None,
+    }
+}
+
+// Whether a `size` byte access at `address` spans two pages
+pub fn crosses_page(address: u64, size: usize) -> bool {
+    (address % PAGE_SIZE) + size as u64 > PAGE_SIZE
+}
+
+// Privilege level that loads and stores are checked against. MPRV makes
+// machine mode accesses use the privilege level in MPP.
+pub fn effective_privilege(access: Access, cpu: &Cpu) -> Privilege {
+    let mstatus = cpu.csr.read(csr::MSTATUS);
+    if access != Access::Instruction
+        && cpu.privilege == Privilege::Machine
+        && mstatus & csr::MSTATUS_MPRV != 0
+    {
+        Privilege::from((mstatus & csr::MSTATUS_MPP) >> 11)
+    } else {
+        cpu.privilege
+    }
+}
+
+// Whether a leaf entry grants `access` at `privilege`
+fn permitted(pte: u64, access: Access, privilege: Privilege, mstatus: u64) -> bool {
+    let allowed = match access {
+        Access::Instruction => pte & PTE_X != 0,
+        // MXR makes executable pages readable
+        Access::Load => pte & PTE_R != 0 || (mstatus & csr::MSTATUS_MXR != 0 && pte & PTE_X != 0),
+        Access::Store => pte & PTE_W != 0,
+    };
+    // Supervisor mode can only read and write user pages when SUM is set,
+    // and never execute them
+    let user = pte & PTE_U != 0;
+    let privileged = match privilege {
+        Privilege::User => user,
+        Privilege::Supervisor => {
+            !user || (access != Access::Instruction && mstatus & csr::MSTATUS_SUM != 0)
+        }
+        Privilege::Machine => true,
+    };
+    allowed && privileged
+}
+
+// Walk the page tables for `access` to the page holding `address`. The
+// accessed and dirty bits of the leaf entry are set on the way.
+fn walk(
+    address: u64,
+    access: Access,
+    privilege: Privilege,
+    levels: u32,
+    cpu: &mut Cpu,
+) -> Result<tlb::Entry, Exception> {
+    let satp = cpu.csr.read(csr::SATP);
+    let mstatus = cpu.csr.read(csr::MSTATUS);
+    let mut table = (satp & SATP_PPN_MASK) * PAGE_SIZE;
+    // The global bit of a pointer applies to everything below it
+    let mut global = false;
+    for level in (0..levels).rev() {
+        let vpn = (address >> (12 + 9 * level)) & 0x1ff;
+        let pte_address = table + vpn * PTE_SIZE;
+        // Page tables are read and written as supervisor mode data
+        let allowed = pmp::check(
+            pte_address,
+            PTE_SIZE,
+            Access::Load,
+            Privilege::Supervisor,
+            &cpu.csr,
+        );
+        let mut pte = match cpu.bus.read_double(pte_address) {
+            Ok(pte) if allowed => pte,
+            _ => return Err(access.access_fault(address)),
+        };
+        let reserved = pte & PTE_W != 0 && pte & PTE_R == 0;
+        if pte & PTE_V == 0 || reserved || pte & PTE_RESERVED != 0 {
+            return Err(access.page_fault(address));
+        }
+        global |= pte & PTE_G != 0;
+        let ppn = (pte >> 10) & PTE_PPN_MASK;
+        // A pointer to the next level of the table
+        if pte & (PTE_R | PTE_X) == 0 {
+            table = ppn * PAGE_SIZE;
+            continue;
+        }
+
+        if !permitted(pte, access, privilege, mstatus) {
+            return Err(access.page_fault(address));
+        }
+        // A superpage must be aligned to its size
+        let offset_mask = (1 << (12 + 9 * level)) - 1;
+        if (ppn * PAGE_SIZE) & offset_mask != 0 {
+            return Err(access.page_fault(address));
+        }
+        // Set the accessed bit, and the dirty bit when writing
+        let flags = match access {
+            Access::Store => PTE_A | PTE_D,
+            _ => PTE_A,
+        };
+        if pte & flags != flags {
+            pte |= flags;
+            let allowed = pmp::check(
+                pte_address,
+                PTE_SIZE,
+                Access::Store,
+                Privilege::Supervisor,
+                &cpu.csr,
+            );
+            if !allowed || cpu.bus.write_double(pte_address, pte).is_err() {
+                return Err(access.access_fault(address));
+            }
+        }
+        return Ok(tlb::Entry {
+            vpn: address >> 12,
+            asid: (satp >> 44) & ASID_MASK,
+            global,
+            flags: pte & 0xff,
+            frame: ((ppn * PAGE_SIZE) | (address & offset_mask)) & !(PAGE_SIZE - 1),
+        });
+    }
+    // The last level held another pointer
+    Err(access.page_fault(address))
+}
+
+// Translate a virtual address to a physical address for `access`, raising a
+// page fault if the page tables don't allow it. Translations are looked up
+// in the TLB before walking the page tables.
+pub fn translate(address: u64, access: Access, cpu: &mut Cpu) -> Result<u64, Exception> {
+    let satp = cpu.csr.read(csr::SATP);
+    let privilege = effective_privilege(access, cpu);
+    let levels = match levels(satp >> 60) {
+        Some(levels) if privilege != Privilege::Machine => levels,
+        _ => return Ok(address),
+    };
+
+    // The address must be sign extended from its highest virtual bit
+    let bits = 12 + 9 * levels;
+    if ((address as i64) << (64 - bits)) >> (64 - bits) != address as i64 {
+        return Err(access.page_fault(address));
+    }
+
+    // The privilege level, SUM and MXR can change without a flush, so
+    // permissions are checked again on every hit. A store to a page that
+    // isn't dirty yet walks the tables again to set D.
+    let mstatus = cpu.csr.read(csr::MSTATUS);
+    let asid = (satp >> 44) & ASID_MASK;
+    if let Some(entry) = cpu.tlb.lookup(address >> 12, asid) {
+        let dirty = access != Access::Store || entry.flags & PTE_D != 0;
+        if dirty && permitted(entry.flags, access, privilege, mstatus) {
+            return Ok(entry.frame | (address & (PAGE_SIZE - 1)));
+        }
+    }
+    let entry = walk(address, access, privilege, levels, cpu)?;
+    cpu.tlb.insert(entry);
+    Ok(entry.frame | (address & (PAGE_SIZE - 1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+
+    const ROOT: u64 = DRAM_BASE;
+    const LEVEL1: u64 = DRAM_BASE + 0x1000;
+    const LEVEL0: u64 = DRAM_BASE + 0x2000;
+    const FRAME: u64 = DRAM_BASE + 0x5000;
+
+    fn pointer(table: u64) -> u64 {
+        (table / PAGE_SIZE) << 10 | PTE_V
+    }
+    fn leaf(frame: u64, flags: u64) -> u64 {
+        (frame / PAGE_SIZE) << 10 | flags | PTE_V
+    }
+    // A supervisor mode hart with Sv39 translation, mapping virtual page 0x1
+    // with `flags` through a three level table
+    fn setup(flags: u64) -> Cpu {
+        let mut cpu = Cpu::new(Bus::new(0x10000));
+        cpu.bus.write_double(ROOT, pointer(LEVEL1)).unwrap();
+        cpu.bus.write_double(LEVEL1, pointer(LEVEL0)).unwrap();
+        cpu.bus
+            .write_double(LEVEL0 + 8, leaf(FRAME, flags))
+            .unwrap();
+        cpu.csr
+            .write(csr::SATP, (SATP_SV39 << 60) | (ROOT / PAGE_SIZE));
+        pmp::allow_all(&mut cpu.csr);
+        cpu.privilege = Privilege::Supervisor;
+        cpu
+    }
+    #[test]
+    fn bare() {
+        let mut cpu = setup(PTE_R);
+        cpu.csr.write(csr::SATP, 0);
+        assert_eq!(translate(0x1234, Access::Load, &mut cpu), Ok(0x1234));
+    }
+    #[test]
+    fn machine_mode() {
+        let mut cpu = setup(PTE_R);
+        cpu.privilege = Privilege::Machine;
+        assert_eq!(translate(0x1234, Access::Load, &mut cpu), Ok(0x1234));
+        // MPRV translates loads and stores at the privilege level in MPP
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_MPRV | 0b01 << 11);
+        assert_eq!(translate(0x1234, Access::Load, &mut cpu), Ok(FRAME + 0x234));
+        assert_eq!(translate(0x1234, Access::Instruction, &mut cpu), Ok(0x1234));
+    }
+    #[test]
+    fn sv39() {
+        let mut cpu = setup(PTE_R | PTE_W | PTE_X);
+        assert_eq!(translate(0x1234, Access::Load, &mut cpu), Ok(FRAME + 0x234));
+        assert_eq!(
+            translate(0x1ffc, Access::Store, &mut cpu),
+            Ok(FRAME + 0xffc)
+        );
+        assert_eq!(
+            translate(0x2000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x2000))
+        );
+    }
+    #[test]
+    fn sv48_and_sv57() {
+        let mut cpu = setup(PTE_R);
+        // One more level of pointers in front of the Sv39 root
+        let top = DRAM_BASE + 0x3000;
+        cpu.bus.write_double(top, pointer(ROOT)).unwrap();
+        cpu.csr
+            .write(csr::SATP, (SATP_SV48 << 60) | (top / PAGE_SIZE));
+        assert_eq!(translate(0x1234, Access::Load, &mut cpu), Ok(FRAME + 0x234));
+        let top57 = DRAM_BASE + 0x4000;
+        cpu.bus.write_double(top57, pointer(top)).unwrap();
+        cpu.csr
+            .write(csr::SATP, (SATP_SV57 << 60) | (top57 / PAGE_SIZE));
+        assert_eq!(translate(0x1234, Access::Load, &mut cpu), Ok(FRAME + 0x234));
+    }
+    #[test]
+    fn non_canonical_address() {
+        let mut cpu = setup(PTE_R);
+        let address = 1 << 39 | 0x1234;
+        assert_eq!(
+            translate(address, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(address))
+        );
+    }
+    #[test]
+    fn superpage() {
+        let mut cpu = setup(PTE_R);
+        // A 1 GiB page covering DRAM
+        cpu.bus
+            .write_double(ROOT + 2 * 8, leaf(DRAM_BASE, PTE_R | PTE_X))
+            .unwrap();
+        let address = DRAM_BASE + 0x12_3456;
+        assert_eq!(translate(address, Access::Load, &mut cpu), Ok(address));
+        // A misaligned superpage faults once the old one is flushed
+        cpu.bus
+            .write_double(ROOT + 2 * 8, leaf(DRAM_BASE + PAGE_SIZE, PTE_R))
+            .unwrap();
+        cpu.tlb.flush_all();
+        assert_eq!(
+            translate(address, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(address))
+        );
+    }
+    #[test]
+    fn permissions() {
+        let mut cpu = setup(PTE_R);
+        assert_eq!(
+            translate(0x1000, Access::Store, &mut cpu),
+            Err(Exception::StorePageFault(0x1000))
+        );
+        assert_eq!(
+            translate(0x1000, Access::Instruction, &mut cpu),
+            Err(Exception::InstructionPageFault(0x1000))
+        );
+        // Write without read is reserved
+        let mut cpu = setup(PTE_W);
+        assert_eq!(
+            translate(0x1000, Access::Store, &mut cpu),
+            Err(Exception::StorePageFault(0x1000))
+        );
+    }
+    #[test]
+    fn mxr() {
+        let mut cpu = setup(PTE_X);
+        assert_eq!(
+            translate(0x1000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x1000))
+        );
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_MXR);
+        assert_eq!(translate(0x1000, Access::Load, &mut cpu), Ok(FRAME));
+    }
+    #[test]
+    fn user_pages() {
+        let mut cpu = setup(PTE_R | PTE_X | PTE_U);
+        // Supervisor mode needs SUM to read user pages and never runs them
+        assert_eq!(
+            translate(0x1000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x1000))
+        );
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_SUM);
+        assert_eq!(translate(0x1000, Access::Load, &mut cpu), Ok(FRAME));
+        assert_eq!(
+            translate(0x1000, Access::Instruction, &mut cpu),
+            Err(Exception::InstructionPageFault(0x1000))
+        );
+        cpu.privilege = Privilege::User;
+        assert_eq!(translate(0x1000, Access::Instruction, &mut cpu), Ok(FRAME));
+        // User mode can't access supervisor pages
+        let mut cpu = setup(PTE_R);
+        cpu.privilege = Privilege::User;
+        assert_eq!(
+            translate(0x1000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x1000))
+        );
+    }
+    #[test]
+    fn accessed_and_dirty() {
+        let mut cpu = setup(PTE_R | PTE_W);
+        translate(0x1000, Access::Load, &mut cpu).unwrap();
+        assert_eq!(
+            cpu.bus.read_double(LEVEL0 + 8),
+            Ok(leaf(FRAME, PTE_R | PTE_W | PTE_A))
+        );
+        translate(0x1000, Access::Store, &mut cpu).unwrap();
+        assert_eq!(
+            cpu.bus.read_double(LEVEL0 + 8),
+            Ok(leaf(FRAME, PTE_R | PTE_W | PTE_A | PTE_D))
+        );
+    }
+    #[test]
+    fn invalid_entries() {
+        let mut cpu = setup(PTE_R);
+        cpu.bus.write_double(LEVEL0 + 8, 0).unwrap();
+        assert_eq!(
+            translate(0x1000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x1000))
+        );
+        // A pointer at the last level
+        cpu.bus.write_double(LEVEL0 + 8, pointer(LEVEL0)).unwrap();
+        assert_eq!(
+            translate(0x1000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x1000))
+        );
+        // Tables outside of memory raise access faults
+        cpu.csr.write(csr::SATP, SATP_SV39 << 60);
+        assert_eq!(
+            translate(0x1000, Access::Store, &mut cpu),
+            Err(Exception::StoreAccessFault(0x1000))
+        );
+    }
+    #[test]
+    fn tlb() {
+        let mut cpu = setup(PTE_R | PTE_W);
+        translate(0x1000, Access::Load, &mut cpu).unwrap();
+        // Later accesses to the page don't read the tables
+        cpu.bus.write_double(LEVEL0 + 8, 0).unwrap();
+        assert_eq!(translate(0x1008, Access::Load, &mut cpu), Ok(FRAME + 8));
+        assert_eq!((cpu.tlb.hits, cpu.tlb.misses), (1, 1));
+        // Cached permissions are still checked at the current privilege
+        cpu.privilege = Privilege::User;
+        assert_eq!(
+            translate(0x1000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x1000))
+        );
+        // Address spaces are kept apart by ASID
+        let mut cpu = setup(PTE_R);
+        translate(0x1000, Access::Load, &mut cpu).unwrap();
+        cpu.bus.write_double(LEVEL0 + 8, 0).unwrap();
+        let satp = cpu.csr.read(csr::SATP);
+        cpu.csr.write(csr::SATP, satp | 1 << 44);
+        assert_eq!(
+            translate(0x1000, Access::Load, &mut cpu),
+            Err(Exception::LoadPageFault(0x1000))
+        );
+    }
+    #[test]
+    fn tlb_dirty() {
+        let mut cpu = setup(PTE_R | PTE_W);
+        translate(0x1000, Access::Load, &mut cpu).unwrap();
+        // The first store walks the tables again to set the dirty bit
+        translate(0x1000, Access::Store, &mut cpu).unwrap();
+        assert_eq!(
+            cpu.bus.read_double(LEVEL0 + 8),
+            Ok(leaf(FRAME, PTE_R | PTE_W | PTE_A | PTE_D))
+        );
+        translate(0x1000, Access::Store, &mut cpu).unwrap();
+        assert_eq!((cpu.tlb.hits, cpu.tlb.misses), (2, 1));
+    }
+    #[test]
+    fn crosses_page() {
+        assert!(!super::crosses_page(0xff8, 8));
+        assert!(super::crosses_page(0xffa, 8));
+    }
+}
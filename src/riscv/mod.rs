@@ -1,13 +1,488 @@
+pub mod assembler;
+pub mod block_cache;
+pub mod builder;
+pub mod bus;
+pub mod clint;
+pub mod compressed;
 pub mod cpu;
+pub mod csr;
+pub mod decode_cache;
+pub mod disassembler;
+pub mod elf;
+pub mod encoder;
 pub mod execute;
+pub mod finisher;
+pub mod float;
+pub mod gdb;
 pub mod instruction;
+pub mod memory;
+pub mod micro_op;
+pub mod mmu;
+pub mod plic;
+pub mod pmp;
+pub mod syscall;
+pub mod tlb;
+pub mod trap;
+pub mod uart;
+use std::fmt;
 
-pub fn emulate(encoded_instructions: Vec<u8>) {
-    let mut cpu = cpu::Cpu::new(encoded_instructions);
-    while cpu.pc < cpu.encoded_instructions.len() as u64 {
-        let encoded_instruction = cpu.fetch();
-        let instruction = instruction::decode(encoded_instruction);
-        cpu.pc += 4;
-        cpu.execute(instruction);
+// Everything that can go wrong in loading, stepping and running a program,
+// none of which brings down the host
+#[derive(Debug)]
+pub enum EmulatorError {
+    // The program couldn't be loaded
+    Elf(elf::ElfError),
+    Assembler(assembler::AssemblerError),
+    Bus(bus::BusError),
+    // The builder was given settings it can't make a machine from
+    Config(String),
+    // No CSR is implemented at the address
+    Csr(usize),
+    // A register number past the 32 registers of a file
+    Register(usize),
+    // The guest raised an exception with no trap handler to take it, and
+    // is stopped at the instruction that raised it
+    Halted { exception: trap::Exception, pc: u64 },
+    Gdb(std::io::Error),
+}
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::Elf(error) => write!(f, "invalid ELF executable: {}", error),
+            EmulatorError::Bus(error) => write!(f, "bus error: {}", error),
+            EmulatorError::Halted { exception, pc } => {
+                write!(f, "unhandled exception {:?} at {:#x}", exception, pc)
+            }
+            EmulatorError::Gdb(error) => write!(f, "GDB connection failed: {}", error),
+            EmulatorError::Assembler(error) => write!(f, "assembly failed at {}", error),
+            EmulatorError::Config(message) => write!(f, "invalid configuration: {}", message),
+            EmulatorError::Csr(address) => write!(f, "no CSR at {:#x}", address),
+            EmulatorError::Register(number) => write!(f, "no register numbered {}", number),
+        }
+    }
+}
+impl std::error::Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmulatorError::Elf(error) => Some(error),
+            EmulatorError::Assembler(error) => Some(error),
+            EmulatorError::Bus(error) => Some(error),
+            EmulatorError::Gdb(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+impl From<elf::ElfError> for EmulatorError {
+    fn from(error: elf::ElfError) -> EmulatorError {
+        EmulatorError::Elf(error)
+    }
+}
+impl From<assembler::AssemblerError> for EmulatorError {
+    fn from(error: assembler::AssemblerError) -> EmulatorError {
+        EmulatorError::Assembler(error)
+    }
+}
+impl From<bus::BusError> for EmulatorError {
+    fn from(error: bus::BusError) -> EmulatorError {
+        EmulatorError::Bus(error)
+    }
+}
+impl From<cpu::InvalidRegister> for EmulatorError {
+    fn from(error: cpu::InvalidRegister) -> EmulatorError {
+        EmulatorError::Register(error.0)
+    }
+}
+
+// Why run_until returned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    // The program finished with an exit status
+    Exited(i32),
+    // The program reached an EBREAK that no trap handler takes, and pc is
+    // left at it
+    Breakpoint,
+    // The limit on the number of steps was reached
+    Limit,
+}
+
+// A program loaded into a fresh hart, either as a bare metal image or
+// firmware in system mode or as a Linux executable in user mode. They are
+// made with a builder::Builder.
+pub struct Emulator {
+    pub cpu: cpu::Cpu,
+    // Segments holding code
+    text: Vec<elf::Segment>,
+    // The process running in user mode
+    process: Option<syscall::Process>,
+    symbols: elf::SymbolTable,
+    // Print each instruction to stderr before running it
+    trace: bool,
+    // Run whole basic blocks at a time when not tracing
+    blocks: bool,
+    // In system mode, end the program once pc leaves the text segments
+    exit_outside_text: bool,
+}
+impl Emulator {
+    pub fn builder() -> builder::Builder {
+        builder::Builder::new()
+    }
+    // The status the program exited with, once it has finished. A system
+    // mode program finishes by writing the finisher, or by leaving its code
+    // if the builder asked for that.
+    pub fn exit_status(&self) -> Option<i32> {
+        match &self.process {
+            Some(process) => process.exit_status,
+            None => self.cpu.bus.finisher.status().or_else(|| {
+                let outside = !self
+                    .text
+                    .iter()
+                    .any(|segment| segment.contains(self.cpu.pc));
+                if self.exit_outside_text && outside {
+                    Some(0)
+                } else {
+                    None
+                }
+            }),
+        }
+    }
+    // Run one instruction, or enter the handler of an interrupt that became
+    // pending. An exception without a handler to take it leaves the hart
+    // at the instruction that raised it.
+    pub fn step(&mut self) -> Result<Option<i32>, EmulatorError> {
+        if let Some(status) = self.exit_status() {
+            return Ok(Some(status));
+        }
+        self.advance(1)?;
+        Ok(self.exit_status())
+    }
+    // Run up to `limit` instructions of the program, which hasn't finished,
+    // and return how many steps were taken. Stepping a basic block at a
+    // time, interrupts are only taken between blocks.
+    fn advance(&mut self, limit: u64) -> Result<u64, EmulatorError> {
+        // A program that ends when pc leaves its code must stop once it
+        // leaves the segment it is in
+        let within = match self.process {
+            None if self.exit_outside_text => self
+                .text
+                .iter()
+                .find(|segment| segment.contains(self.cpu.pc))
+                .map_or(0..u64::MAX, |segment| {
+                    segment.address..segment.address + segment.size
+                }),
+            _ => 0..u64::MAX,
+        };
+        let cpu = &mut self.cpu;
+        let trace = if self.trace {
+            Some(&self.symbols)
+        } else {
+            None
+        };
+        let blocks = self.blocks && trace.is_none();
+        let run = |cpu: &mut cpu::Cpu| {
+            if blocks {
+                cpu.run_block(limit, within.clone())
+            } else {
+                (1, step_traced(cpu, trace))
+            }
+        };
+        match &mut self.process {
+            Some(process) => match run(cpu) {
+                (steps, Ok(())) => Ok(steps),
+                (steps, Err(trap::Exception::EnvironmentCallFromUMode)) => {
+                    process.syscall(cpu);
+                    // Carry on after the ECALL
+                    if process.exit_status.is_none() {
+                        cpu.pc = cpu.pc.wrapping_add(4);
+                    }
+                    Ok(steps)
+                }
+                (_, Err(exception)) => Err(EmulatorError::Halted {
+                    exception,
+                    pc: cpu.pc,
+                }),
+            },
+            None => {
+                // Interrupts are taken between instructions, or blocks
+                trap::update_pending(cpu);
+                if let Some(interrupt) = trap::pending_interrupt(cpu) {
+                    trap::take_interrupt(interrupt, cpu);
+                    return Ok(1);
+                }
+                let (steps, result) = run(cpu);
+                if let Err(exception) = result {
+                    if trap::trap_vector(exception, cpu) == 0 {
+                        return Err(EmulatorError::Halted {
+                            exception,
+                            pc: cpu.pc,
+                        });
+                    }
+                    trap::take_trap(exception, cpu);
+                }
+                Ok(steps)
+            }
+        }
+    }
+    // Run the program to the end and return its exit status
+    pub fn run(&mut self) -> Result<i32, EmulatorError> {
+        loop {
+            if let Some(status) = self.exit_status() {
+                return Ok(status);
+            }
+            self.advance(u64::MAX)?;
+        }
+    }
+    // Run at most `limit` steps, stopping early when the program exits or
+    // reaches a breakpoint
+    pub fn run_until(&mut self, limit: u64) -> Result<StopReason, EmulatorError> {
+        let mut steps = 0;
+        while steps < limit {
+            if let Some(status) = self.exit_status() {
+                return Ok(StopReason::Exited(status));
+            }
+            match self.advance(limit - steps) {
+                Ok(taken) => steps += taken,
+                Err(EmulatorError::Halted {
+                    exception: trap::Exception::Breakpoint(_),
+                    ..
+                }) => return Ok(StopReason::Breakpoint),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(self
+            .exit_status()
+            .map_or(StopReason::Limit, StopReason::Exited))
+    }
+    pub fn pc(&self) -> u64 {
+        self.cpu.pc
+    }
+    pub fn set_pc(&mut self, pc: u64) {
+        self.cpu.pc = pc;
+    }
+    pub fn register(&self, register: cpu::Register) -> u64 {
+        self.cpu.read_register(register)
+    }
+    pub fn set_register(&mut self, register: cpu::Register, value: u64) {
+        match register {
+            // Writing pc through the hart sets the next pc of the
+            // instruction running
+            cpu::Register::PC => self.cpu.pc = value,
+            register => self.cpu.write_register(register, value),
+        }
+    }
+    // The raw bits of a floating point register, with single precision
+    // values NaN-boxed
+    pub fn fregister(&self, register: cpu::FRegister) -> u64 {
+        self.cpu.fregisters[usize::from(register)]
+    }
+    pub fn set_fregister(&mut self, register: cpu::FRegister, value: u64) {
+        self.cpu.fregisters[usize::from(register)] = value;
+    }
+    // Read and write the physical address space, devices included
+    pub fn read_memory(&mut self, address: u64, bytes: &mut [u8]) -> Result<(), EmulatorError> {
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = self.cpu.bus.read_byte(address.wrapping_add(i as u64))?;
+        }
+        Ok(())
+    }
+    pub fn write_memory(&mut self, address: u64, bytes: &[u8]) -> Result<(), EmulatorError> {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.cpu
+                .bus
+                .write_byte(address.wrapping_add(i as u64), byte)?;
+        }
+        Ok(())
+    }
+    // Read and write CSRs as the hart sees them, ignoring privilege
+    pub fn csr(&self, address: usize) -> Result<u64, EmulatorError> {
+        csr::name(address).ok_or(EmulatorError::Csr(address))?;
+        Ok(self.cpu.read_csr(address))
+    }
+    pub fn set_csr(&mut self, address: usize, value: u64) -> Result<(), EmulatorError> {
+        csr::name(address).ok_or(EmulatorError::Csr(address))?;
+        self.cpu.csr.write(address, value);
+        Ok(())
+    }
+    // The address of a symbol in the program
+    pub fn symbol(&self, name: &str) -> Option<u64> {
+        self.symbols.lookup(name).map(|symbol| symbol.address)
+    }
+}
+
+// Step the hart, first printing the instruction at pc when tracing
+fn step_traced(
+    cpu: &mut cpu::Cpu,
+    trace: Option<&elf::SymbolTable>,
+) -> Result<(), trap::Exception> {
+    if let Some(symbols) = trace {
+        let pc = cpu.pc;
+        if let Ok(encoded) = cpu.fetch() {
+            let instruction = instruction::decode(encoded);
+            eprintln!("{:8x}: {}", pc, instruction.disassemble(pc, Some(symbols)));
+        }
+    }
+    cpu.step()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::cpu::AbiRegister;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn run_until() {
+        let mut emulator = Emulator::builder()
+            .memory_size(0x1000)
+            .assembly(
+                "
+                loop:
+                    addi a0, a0, 1
+                    blt a0, a1, loop
+                    la t0, value
+                    sd a0, (t0)
+                    ebreak
+                    li t0, 0x100000
+                    li t1, 0x33333
+                    sw t1, (t0)
+                value: .dword 0
+                ",
+            )
+            .unwrap();
+        emulator.set_register(AbiRegister::A1.into(), 5);
+        assert_eq!(emulator.run_until(4).unwrap(), StopReason::Limit);
+        assert_eq!(emulator.register(AbiRegister::A0.into()), 2);
+        assert_eq!(emulator.run_until(100).unwrap(), StopReason::Breakpoint);
+        let value = emulator.symbol("value").unwrap();
+        let mut bytes = [0; 8];
+        emulator.read_memory(value, &mut bytes).unwrap();
+        assert_eq!(u64::from_le_bytes(bytes), 5);
+
+        // Carry on past the ebreak to fail with status 3 through the
+        // finisher
+        emulator.set_pc(emulator.pc() + 4);
+        assert_eq!(emulator.run_until(100).unwrap(), StopReason::Exited(3));
+        assert_eq!(emulator.run_until(100).unwrap(), StopReason::Exited(3));
+
+        assert_eq!(emulator.csr(csr::MSCRATCH).unwrap(), 0);
+        emulator.set_csr(csr::MSCRATCH, 7).unwrap();
+        assert_eq!(emulator.csr(csr::MSCRATCH).unwrap(), 7);
+        assert!(matches!(
+            emulator.csr(0x7ff),
+            Err(EmulatorError::Csr(0x7ff))
+        ));
+    }
+
+    #[test]
+    fn error_messages() {
+        use std::error::Error;
+        let error = EmulatorError::from(elf::ElfError::Unmapped(bus::BusError::Unmapped(0x1000)));
+        assert_eq!(
+            error.to_string(),
+            "invalid ELF executable: a segment doesn't fit in memory: nothing is mapped at 0x1000"
+        );
+        // The errors it came from are chained through source
+        let elf_error = error.source().unwrap();
+        assert!(elf_error.to_string().starts_with("a segment"));
+        assert_eq!(
+            elf_error.source().unwrap().to_string(),
+            "nothing is mapped at 0x1000"
+        );
+        assert_eq!(
+            EmulatorError::from(elf::ElfError::WrongMachine(62)).to_string(),
+            "invalid ELF executable: built for machine 62, not RISC-V"
+        );
+        assert!(EmulatorError::Csr(0x7ff).source().is_none());
+    }
+    #[test]
+    fn register_numbers() {
+        let mut emulator = Emulator::builder()
+            .memory_size(0x1000)
+            .assembly("nop")
+            .unwrap();
+        let set = |emulator: &mut Emulator, number: usize| -> Result<(), EmulatorError> {
+            emulator.set_register(cpu::Register::try_from(number)?, 7);
+            Ok(())
+        };
+        set(&mut emulator, 10).unwrap();
+        assert_eq!(emulator.register(AbiRegister::A0.into()), 7);
+        // Numbers past the register file don't wrap around to x10
+        assert!(matches!(
+            set(&mut emulator, 32 + 10),
+            Err(EmulatorError::Register(42))
+        ));
+        assert_eq!(emulator.register(AbiRegister::A0.into()), 7);
+        assert_eq!(
+            cpu::FRegister::try_from(usize::MAX),
+            Err(cpu::InvalidRegister(usize::MAX))
+        );
+    }
+    #[test]
+    fn exit_outside_text() {
+        let source = "addi a0, a0, 1";
+        // Running off the end of the code is no way to exit unless asked for
+        let mut emulator = Emulator::builder()
+            .memory_size(0x1000)
+            .assembly(source)
+            .unwrap();
+        assert!(matches!(
+            emulator.run_until(100),
+            Err(EmulatorError::Halted {
+                exception: trap::Exception::IllegalInstruction(0),
+                pc: 0x8000_0004,
+            })
+        ));
+        assert_eq!(emulator.exit_status(), None);
+        for blocks in [false, true] {
+            let mut emulator = Emulator::builder()
+                .memory_size(0x1000)
+                .block_cache(blocks)
+                .exit_outside_text(true)
+                .assembly(source)
+                .unwrap();
+            assert_eq!(emulator.run().unwrap(), 0);
+            assert_eq!(emulator.pc(), 0x8000_0004);
+        }
+    }
+    #[test]
+    fn self_modifying_code() {
+        // The first store replaces the li that already ran, and the loop
+        // only ends once the replacement runs. The second replaces the next
+        // instruction, in the same basic block.
+        let loop_source = "
+            again:
+                li a0, 0
+                la t0, again
+                la t1, patch
+                lw t1, (t1)
+                sw t1, (t0)
+                beqz a0, again
+                fence.i
+                ebreak
+            patch:
+                li a0, 7
+            ";
+        let block_source = "
+                la t0, next
+                la t1, patch
+                lw t1, (t1)
+                sw t1, (t0)
+            next:
+                li a0, 0
+                ebreak
+            patch:
+                li a0, 7
+            ";
+        for &source in &[loop_source, block_source] {
+            for &(decode_cache, block_cache) in &[(true, true), (true, false), (false, false)] {
+                let mut emulator = Emulator::builder()
+                    .memory_size(0x1000)
+                    .decode_cache(decode_cache)
+                    .block_cache(block_cache)
+                    .assembly(source)
+                    .unwrap();
+                assert_eq!(emulator.run_until(100).unwrap(), StopReason::Breakpoint);
+                assert_eq!(emulator.register(AbiRegister::A0.into()), 7);
+            }
+        }
     }
 }
@@ -0,0 +1,276 @@
+// Platform-level interrupt controller. Devices raise interrupt sources, and
+// each hart has a machine and a supervisor context that sees the enabled
+// sources above its threshold through MEIP and SEIP and claims them one at
+// a time. The register layout is that of the SiFive PLIC.
+use crate::riscv::cpu::Privilege;
+
+// Source 0 is reserved to mean no interrupt
+pub const SOURCES: usize = 1024;
+const WORDS: usize = SOURCES / 32;
+
+// Register offsets
+pub const PRIORITY: u64 = 0x0;
+pub const PENDING: u64 = 0x1000;
+pub const ENABLE: u64 = 0x2000;
+pub const ENABLE_STRIDE: u64 = 0x80;
+pub const THRESHOLD: u64 = 0x20_0000;
+pub const CLAIM: u64 = 0x20_0004;
+pub const CONTEXT_STRIDE: u64 = 0x1000;
+
+// Priorities and thresholds are 3 bits wide
+const MAX_PRIORITY: u32 = 7;
+
+// The context of a hart that interrupts at `privilege`
+pub fn context(hart: usize, privilege: Privilege) -> usize {
+    match privilege {
+        Privilege::Supervisor => 2 * hart + 1,
+        _ => 2 * hart,
+    }
+}
+
+fn bit(bits: &[u32; WORDS], source: usize) -> bool {
+    bits[source / 32] & (1 << (source % 32)) != 0
+}
+fn set_bit(bits: &mut [u32; WORDS], source: usize, value: bool) {
+    if value {
+        bits[source / 32] |= 1 << (source % 32);
+    } else {
+        bits[source / 32] &= !(1 << (source % 32));
+    }
+}
+
+pub struct Plic {
+    priority: Vec<u32>,
+    pending: [u32; WORDS],
+    // Sources claimed by a context that hasn't signalled completion yet.
+    // They stay out of pending until then, even if the device still
+    // raises them.
+    claimed: [u32; WORDS],
+    enable: Vec<[u32; WORDS]>,
+    threshold: Vec<u32>,
+}
+impl Plic {
+    pub fn new(harts: usize) -> Self {
+        Self {
+            priority: vec![0; SOURCES],
+            pending: [0; WORDS],
+            claimed: [0; WORDS],
+            enable: vec![[0; WORDS]; 2 * harts],
+            threshold: vec![0; 2 * harts],
+        }
+    }
+    // Set the level of a device's interrupt line. A raised source becomes
+    // pending unless it is being serviced, and a lowered one stops pending.
+    pub fn set_level(&mut self, source: usize, level: bool) {
+        if source == 0 || source >= SOURCES {
+            return;
+        }
+        let pending = level && !bit(&self.claimed, source);
+        set_bit(&mut self.pending, source, pending);
+    }
+    // The pending source a context would claim: the enabled one with the
+    // highest priority above the threshold, the lowest numbered on a tie
+    fn best(&self, context: usize) -> Option<usize> {
+        let mut best = None;
+        let mut best_priority = self.threshold[context];
+        // This runs each time the hart checks for interrupts, which is
+        // between blocks, or before every instruction when blocks are off or
+        // tracing, so words with nothing both pending and enabled are
+        // skipped whole
+        for (word, (&pending, &enable)) in
+            self.pending.iter().zip(&self.enable[context]).enumerate()
+        {
+            let mut bits = pending & enable;
+            while bits != 0 {
+                let source = word * 32 + bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                if source != 0 && self.priority[source] > best_priority {
+                    best = Some(source);
+                    best_priority = self.priority[source];
+                }
+            }
+        }
+        best
+    }
+    // Whether a context has an interrupt to claim
+    pub fn interrupting(&self, context: usize) -> bool {
+        self.best(context).is_some()
+    }
+    fn claim(&mut self, context: usize) -> u32 {
+        match self.best(context) {
+            Some(source) => {
+                set_bit(&mut self.pending, source, false);
+                set_bit(&mut self.claimed, source, true);
+                source as u32
+            }
+            None => 0,
+        }
+    }
+    // Completions for sources the context hasn't enabled are ignored
+    fn complete(&mut self, context: usize, source: usize) {
+        if source < SOURCES && bit(&self.enable[context], source) {
+            set_bit(&mut self.claimed, source, false);
+        }
+    }
+    // The context of a per-context register and the offset of the same
+    // register for context 0
+    fn context_register(&self, offset: u64) -> Option<(usize, u64)> {
+        let context = ((offset - THRESHOLD) / CONTEXT_STRIDE) as usize;
+        if context < self.threshold.len() {
+            Some((context, THRESHOLD + (offset - THRESHOLD) % CONTEXT_STRIDE))
+        } else {
+            None
+        }
+    }
+    fn enable_word(&self, offset: u64) -> Option<(usize, usize)> {
+        let context = ((offset - ENABLE) / ENABLE_STRIDE) as usize;
+        let word = ((offset - ENABLE) % ENABLE_STRIDE / 4) as usize;
+        if context < self.enable.len() {
+            Some((context, word))
+        } else {
+            None
+        }
+    }
+    // Registers are 32 bits wide. Other accesses and reserved offsets read
+    // as zero. Reading a claim register claims the interrupt.
+    pub fn read(&mut self, offset: u64, size: usize) -> u64 {
+        if size != 4 || offset & 0b11 != 0 {
+            return 0;
+        }
+        let value = match offset {
+            PRIORITY..PENDING => self.priority[(offset / 4) as usize],
+            PENDING..ENABLE => match ((offset - PENDING) / 4) as usize {
+                word if word < WORDS => self.pending[word],
+                _ => 0,
+            },
+            ENABLE..THRESHOLD => match self.enable_word(offset) {
+                Some((context, word)) => self.enable[context][word],
+                None => 0,
+            },
+            _ => match self.context_register(offset) {
+                Some((context, THRESHOLD)) => self.threshold[context],
+                Some((context, CLAIM)) => self.claim(context),
+                _ => 0,
+            },
+        };
+        u64::from(value)
+    }
+    // Writing a claim register signals that the handler is done with the
+    // source written
+    pub fn write(&mut self, offset: u64, size: usize, value: u64) {
+        if size != 4 || offset & 0b11 != 0 {
+            return;
+        }
+        let value = value as u32;
+        match offset {
+            PRIORITY..PENDING => {
+                // Source 0 doesn't exist
+                if offset != PRIORITY {
+                    self.priority[(offset / 4) as usize] = value & MAX_PRIORITY;
+                }
+            }
+            // Pending bits are read only
+            PENDING..ENABLE => (),
+            ENABLE..THRESHOLD => {
+                if let Some((context, word)) = self.enable_word(offset) {
+                    let value = if word == 0 { value & !1 } else { value };
+                    self.enable[context][word] = value;
+                }
+            }
+            _ => match self.context_register(offset) {
+                Some((context, THRESHOLD)) => self.threshold[context] = value & MAX_PRIORITY,
+                Some((context, CLAIM)) => self.complete(context, value as usize),
+                _ => (),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MACHINE: usize = 0;
+    const SUPERVISOR: usize = 1;
+
+    fn enable(plic: &mut Plic, context: usize, source: usize, priority: u64) {
+        plic.write(PRIORITY + 4 * source as u64, 4, priority);
+        let word = ENABLE + ENABLE_STRIDE * context as u64 + 4 * (source / 32) as u64;
+        let old = plic.read(word, 4);
+        plic.write(word, 4, old | 1 << (source % 32));
+    }
+    fn claim_register(context: usize) -> u64 {
+        CLAIM + CONTEXT_STRIDE * context as u64
+    }
+    #[test]
+    fn contexts() {
+        assert_eq!(context(0, Privilege::Machine), 0);
+        assert_eq!(context(0, Privilege::Supervisor), 1);
+        assert_eq!(context(2, Privilege::Supervisor), 5);
+    }
+    #[test]
+    fn registers() {
+        let mut plic = Plic::new(1);
+        plic.write(PRIORITY + 4, 4, 0xff);
+        assert_eq!(plic.read(PRIORITY + 4, 4), 7);
+        plic.write(PRIORITY, 4, 1);
+        assert_eq!(plic.read(PRIORITY, 4), 0);
+        // Source 0 can't be enabled
+        plic.write(ENABLE + ENABLE_STRIDE, 4, u64::MAX);
+        assert_eq!(plic.read(ENABLE + ENABLE_STRIDE, 4), 0xffff_fffe);
+        plic.write(THRESHOLD + CONTEXT_STRIDE, 4, 3);
+        assert_eq!(plic.read(THRESHOLD + CONTEXT_STRIDE, 4), 3);
+        // Contexts of harts that don't exist
+        plic.write(THRESHOLD + 2 * CONTEXT_STRIDE, 4, 3);
+        assert_eq!(plic.read(THRESHOLD + 2 * CONTEXT_STRIDE, 4), 0);
+        assert_eq!(plic.read(ENABLE + 2 * ENABLE_STRIDE, 4), 0);
+        // Only whole registers can be accessed
+        assert_eq!(plic.read(PRIORITY + 4, 8), 0);
+        assert_eq!(plic.read(PRIORITY + 6, 4), 0);
+    }
+    #[test]
+    fn claim_and_complete() {
+        let mut plic = Plic::new(1);
+        enable(&mut plic, MACHINE, 10, 1);
+        assert!(!plic.interrupting(MACHINE));
+        plic.set_level(10, true);
+        assert_eq!(plic.read(PENDING, 4), 1 << 10);
+        assert!(plic.interrupting(MACHINE));
+        assert!(!plic.interrupting(SUPERVISOR));
+        assert_eq!(plic.read(claim_register(MACHINE), 4), 10);
+        assert_eq!(plic.read(PENDING, 4), 0);
+        // The source stays quiet while it is being serviced
+        plic.set_level(10, true);
+        assert!(!plic.interrupting(MACHINE));
+        assert_eq!(plic.read(claim_register(MACHINE), 4), 0);
+        plic.write(claim_register(MACHINE), 4, 10);
+        plic.set_level(10, true);
+        assert!(plic.interrupting(MACHINE));
+        // Lowering the line withdraws the interrupt
+        plic.set_level(10, false);
+        assert!(!plic.interrupting(MACHINE));
+    }
+    #[test]
+    fn priority_and_threshold() {
+        let mut plic = Plic::new(1);
+        enable(&mut plic, SUPERVISOR, 3, 2);
+        enable(&mut plic, SUPERVISOR, 40, 5);
+        enable(&mut plic, SUPERVISOR, 41, 5);
+        for source in [3, 40, 41] {
+            plic.set_level(source, true);
+        }
+        assert_eq!(plic.read(PENDING + 4, 4), 0b11 << 8);
+        // Highest priority first, then the lowest source number
+        assert_eq!(plic.read(claim_register(SUPERVISOR), 4), 40);
+        plic.write(THRESHOLD + CONTEXT_STRIDE, 4, 2);
+        assert_eq!(plic.read(claim_register(SUPERVISOR), 4), 41);
+        // Source 3 is at the threshold, which masks it
+        assert!(!plic.interrupting(SUPERVISOR));
+        plic.write(THRESHOLD + CONTEXT_STRIDE, 4, 1);
+        assert!(plic.interrupting(SUPERVISOR));
+        // A priority of zero never interrupts
+        plic.write(PRIORITY + 12, 4, 0);
+        plic.write(THRESHOLD + CONTEXT_STRIDE, 4, 0);
+        assert!(!plic.interrupting(SUPERVISOR));
+    }
+}
@@ -0,0 +1,262 @@
+// Physical memory protection. Machine mode firmware describes regions of the
+// physical address space in pmpcfg and pmpaddr, and accesses from supervisor
+// and user mode (and from machine mode, for locked entries) are checked
+// against them after address translation.
+use crate::riscv::cpu::Privilege;
+use crate::riscv::csr::{self, Csr};
+use crate::riscv::mmu::Access;
+
+pub const ENTRIES: usize = 64;
+
+// pmpcfg fields, one byte per entry
+pub const PMP_R: u64 = 1 << 0;
+pub const PMP_W: u64 = 1 << 1;
+pub const PMP_X: u64 = 1 << 2;
+pub const PMP_A: u64 = 0b11 << 3;
+pub const PMP_L: u64 = 1 << 7;
+
+// Address matching modes in the A field, where zero turns an entry off
+pub const TOR: u64 = 1;
+pub const NA4: u64 = 2;
+pub const NAPOT: u64 = 3;
+
+// pmpaddr holds bits 55:2 of an address
+pub const ADDRESS_MASK: u64 = (1 << 54) - 1;
+
+// Apply a write to a pmpcfg register. Locked entries keep their
+// configuration, and write without read is reserved so W is dropped.
+pub fn write_config(old: u64, value: u64) -> u64 {
+    (0..8).fold(0, |result, byte| {
+        let old = (old >> (8 * byte)) & 0xff;
+        let new = (value >> (8 * byte)) & (PMP_L | PMP_A | PMP_X | PMP_W | PMP_R);
+        let config = if old & PMP_L != 0 {
+            old
+        } else if new & PMP_R == 0 {
+            new & !PMP_W
+        } else {
+            new
+        };
+        result | config << (8 * byte)
+    })
+}
+
+// Whether writes to pmpaddr of `entry` are ignored. Locking an entry locks
+// its address, and a locked TOR entry also locks the address below it.
+pub fn address_locked(entry: usize, csr: &Csr) -> bool {
+    let locked = csr.pmp_config(entry) & PMP_L != 0;
+    let next = entry + 1;
+    let top_locked = next < ENTRIES && {
+        let config = csr.pmp_config(next);
+        config & PMP_L != 0 && (config & PMP_A) >> 3 == TOR
+    };
+    locked || top_locked
+}
+
+// Addresses [start, end) covered by an entry, or None when it is off
+fn region(entry: usize, csr: &Csr) -> Option<(u128, u128)> {
+    let address = csr.read(csr::PMPADDR0 + entry);
+    match (csr.pmp_config(entry) & PMP_A) >> 3 {
+        TOR => {
+            let bottom = match entry {
+                0 => 0,
+                _ => csr.read(csr::PMPADDR0 + entry - 1),
+            };
+            Some((u128::from(bottom) << 2, u128::from(address) << 2))
+        }
+        NA4 => {
+            let start = u128::from(address) << 2;
+            Some((start, start + 4))
+        }
+        NAPOT => {
+            // The number of trailing ones gives the size, from 8 bytes up
+            let ones = address.trailing_ones();
+            let start = u128::from(address & !((1 << ones) - 1)) << 2;
+            Some((start, start + (1 << (ones + 3))))
+        }
+        _ => None,
+    }
+}
+
+// Whether a `size` byte access to the physical address `address` is
+// allowed at `privilege`. The lowest numbered entry that covers any of the
+// bytes decides, and it must cover all of them. Accesses that no entry
+// covers are only allowed from machine mode, so until firmware enables an
+// entry supervisor and user mode can't access anything.
+pub fn check(address: u64, size: u64, access: Access, privilege: Privilege, csr: &Csr) -> bool {
+    // Every fetch is checked, so the entries are only walked once one is on
+    if !enabled(csr) {
+        return privilege == Privilege::Machine;
+    }
+    let start = u128::from(address);
+    let end = start + u128::from(size);
+    for entry in 0..ENTRIES {
+        let (bottom, top) = match region(entry, csr) {
+            Some(region) => region,
+            None => continue,
+        };
+        if end <= bottom || start >= top {
+            continue;
+        }
+        if start < bottom || end > top {
+            return false;
+        }
+        let config = csr.pmp_config(entry);
+        if privilege == Privilege::Machine && config & PMP_L == 0 {
+            return true;
+        }
+        let permission = match access {
+            Access::Instruction => PMP_X,
+            Access::Load => PMP_R,
+            Access::Store => PMP_W,
+        };
+        return config & permission != 0;
+    }
+    privilege == Privilege::Machine
+}
+
+// Whether any entry is on, checking the A fields of eight entries at a time
+fn enabled(csr: &Csr) -> bool {
+    let a_fields = PMP_A * 0x0101_0101_0101_0101;
+    (0..ENTRIES / 8).any(|register| csr.read(csr::PMPCFG0 + 2 * register) & a_fields != 0)
+}
+
+// Let supervisor and user mode access the whole address space through the
+// first entry, as firmware does before starting an operating system
+pub fn allow_all(csr: &mut Csr) {
+    csr.write(csr::PMPADDR0, ADDRESS_MASK);
+    csr.write(csr::PMPCFG0, NAPOT << 3 | PMP_X | PMP_W | PMP_R);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::DRAM_BASE;
+
+    fn set_config(csr: &mut Csr, entry: usize, config: u64) {
+        let register = csr::PMPCFG0 + entry / 8 * 2;
+        let shift = 8 * (entry % 8);
+        let value = (csr.read(register) & !(0xff << shift)) | config << shift;
+        csr.write(register, value);
+    }
+    #[test]
+    fn write_config() {
+        let mut csr = Csr::new();
+        // W without R is reserved and the reserved bits read as zero
+        csr.write(csr::PMPCFG0, 0xff_7e);
+        assert_eq!(csr.read(csr::PMPCFG0), 0x9f_1c);
+        // Locked entries can't be changed
+        csr.write(csr::PMPCFG0, 0);
+        assert_eq!(csr.read(csr::PMPCFG0), 0x9f_00);
+        csr.write(csr::PMPCFG0 + 14, 0xff << 56);
+        assert_eq!(csr.pmp_config(63), 0x9f);
+    }
+    #[test]
+    fn locked_addresses() {
+        let mut csr = Csr::new();
+        csr.write(csr::PMPADDR0 + 1, u64::MAX);
+        assert_eq!(csr.read(csr::PMPADDR0 + 1), ADDRESS_MASK);
+        set_config(&mut csr, 1, PMP_L | NA4 << 3);
+        csr.write(csr::PMPADDR0 + 1, 0);
+        assert_eq!(csr.read(csr::PMPADDR0 + 1), ADDRESS_MASK);
+        // A locked TOR entry locks the bottom of its range too
+        set_config(&mut csr, 3, PMP_L | TOR << 3);
+        csr.write(csr::PMPADDR0 + 2, 0x10);
+        assert_eq!(csr.read(csr::PMPADDR0 + 2), 0);
+        csr.write(csr::PMPADDR0 + 4, 0x10);
+        assert_eq!(csr.read(csr::PMPADDR0 + 4), 0x10);
+    }
+    #[test]
+    fn unconfigured() {
+        let mut csr = Csr::new();
+        // Only machine mode gets in before an entry is on
+        assert!(check(DRAM_BASE, 8, Access::Store, Privilege::Machine, &csr));
+        assert!(!check(DRAM_BASE, 8, Access::Store, Privilege::User, &csr));
+        assert!(!check(
+            DRAM_BASE,
+            4,
+            Access::Instruction,
+            Privilege::Supervisor,
+            &csr
+        ));
+        allow_all(&mut csr);
+        assert!(check(DRAM_BASE, 8, Access::Store, Privilege::User, &csr));
+    }
+    #[test]
+    fn tor() {
+        let mut csr = Csr::new();
+        csr.write(csr::PMPADDR0, DRAM_BASE >> 2);
+        csr.write(csr::PMPADDR0 + 1, (DRAM_BASE + 0x1000) >> 2);
+        set_config(&mut csr, 1, TOR << 3 | PMP_R);
+        let user = Privilege::User;
+        assert!(check(DRAM_BASE, 8, Access::Load, user, &csr));
+        assert!(check(DRAM_BASE + 0xff8, 8, Access::Load, user, &csr));
+        assert!(!check(DRAM_BASE, 8, Access::Store, user, &csr));
+        // Nothing matches outside of the region
+        assert!(!check(DRAM_BASE + 0x1000, 8, Access::Load, user, &csr));
+        assert!(check(
+            DRAM_BASE + 0x1000,
+            8,
+            Access::Load,
+            Privilege::Machine,
+            &csr
+        ));
+        // Accesses must be entirely inside the region
+        assert!(!check(DRAM_BASE + 0xffc, 8, Access::Load, user, &csr));
+        // The first entry's range starts at zero
+        set_config(&mut csr, 0, TOR << 3 | PMP_X);
+        assert!(check(0x1000, 4, Access::Instruction, user, &csr));
+    }
+    #[test]
+    fn na4() {
+        let mut csr = Csr::new();
+        csr.write(csr::PMPADDR0, DRAM_BASE >> 2);
+        set_config(&mut csr, 0, NA4 << 3 | PMP_W | PMP_R);
+        let supervisor = Privilege::Supervisor;
+        assert!(check(DRAM_BASE, 4, Access::Store, supervisor, &csr));
+        assert!(!check(DRAM_BASE, 8, Access::Store, supervisor, &csr));
+        assert!(!check(DRAM_BASE + 4, 4, Access::Load, supervisor, &csr));
+    }
+    #[test]
+    fn napot() {
+        let mut csr = Csr::new();
+        // 64 KiB at DRAM_BASE
+        csr.write(csr::PMPADDR0, (DRAM_BASE >> 2) | 0x1fff);
+        set_config(&mut csr, 0, NAPOT << 3 | PMP_X | PMP_R);
+        let user = Privilege::User;
+        assert!(check(DRAM_BASE, 4, Access::Instruction, user, &csr));
+        assert!(check(DRAM_BASE + 0xfff8, 8, Access::Load, user, &csr));
+        assert!(!check(DRAM_BASE + 0x1_0000, 8, Access::Load, user, &csr));
+        assert!(!check(DRAM_BASE, 8, Access::Store, user, &csr));
+        // All ones covers the whole physical address space
+        csr.write(csr::PMPADDR0 + 1, u64::MAX);
+        set_config(&mut csr, 1, NAPOT << 3 | PMP_W | PMP_R);
+        assert!(check((1 << 56) - 8, 8, Access::Store, user, &csr));
+    }
+    #[test]
+    fn priority() {
+        let mut csr = Csr::new();
+        // A read-only page inside a read-write region
+        csr.write(csr::PMPADDR0, (DRAM_BASE >> 2) | 0x1ff);
+        set_config(&mut csr, 0, NAPOT << 3 | PMP_R);
+        csr.write(csr::PMPADDR0 + 1, (DRAM_BASE >> 2) | 0x1fff);
+        set_config(&mut csr, 1, NAPOT << 3 | PMP_W | PMP_R);
+        let user = Privilege::User;
+        assert!(!check(DRAM_BASE + 0x100, 8, Access::Store, user, &csr));
+        assert!(check(DRAM_BASE + 0x1000, 8, Access::Store, user, &csr));
+        // Partially matching the first entry fails even though the second
+        // entry covers the whole access
+        assert!(!check(DRAM_BASE + 0xffc, 8, Access::Load, user, &csr));
+    }
+    #[test]
+    fn lock() {
+        let mut csr = Csr::new();
+        csr.write(csr::PMPADDR0, (DRAM_BASE >> 2) | 0x1ff);
+        set_config(&mut csr, 0, NAPOT << 3 | PMP_R);
+        let machine = Privilege::Machine;
+        // Machine mode ignores unlocked entries
+        assert!(check(DRAM_BASE, 8, Access::Store, machine, &csr));
+        set_config(&mut csr, 0, PMP_L | NAPOT << 3 | PMP_R);
+        assert!(!check(DRAM_BASE, 8, Access::Store, machine, &csr));
+        assert!(check(DRAM_BASE, 8, Access::Load, machine, &csr));
+    }
+}
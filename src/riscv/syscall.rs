@@ -0,0 +1,810 @@
+// Linux system calls for running user programs without a kernel, as
+// qemu-user does. ECALL from user mode passes the call number in a7 and the
+// arguments in a0-a5, and gets the result or a negated errno back in a0.
+// Files are the host's, while memory management happens in guest memory.
+use crate::riscv::cpu::{AbiRegister, Cpu};
+use crate::riscv::csr;
+use crate::riscv::elf::Elf;
+use crate::riscv::mmu::PAGE_SIZE;
+use crate::riscv::trap::Exception;
+use crate::riscv::EmulatorError;
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::fd::AsFd;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+// riscv64 system call numbers, from asm-generic/unistd.h
+pub const IOCTL: u64 = 29;
+pub const OPENAT: u64 = 56;
+pub const CLOSE: u64 = 57;
+pub const LSEEK: u64 = 62;
+pub const READ: u64 = 63;
+pub const WRITE: u64 = 64;
+pub const READV: u64 = 65;
+pub const WRITEV: u64 = 66;
+pub const NEWFSTATAT: u64 = 79;
+pub const FSTAT: u64 = 80;
+pub const EXIT: u64 = 93;
+pub const EXIT_GROUP: u64 = 94;
+pub const SET_TID_ADDRESS: u64 = 96;
+pub const SET_ROBUST_LIST: u64 = 99;
+pub const CLOCK_GETTIME: u64 = 113;
+pub const RT_SIGACTION: u64 = 134;
+pub const RT_SIGPROCMASK: u64 = 135;
+pub const UNAME: u64 = 160;
+pub const GETPID: u64 = 172;
+pub const GETTID: u64 = 178;
+pub const BRK: u64 = 214;
+pub const MUNMAP: u64 = 215;
+pub const MMAP: u64 = 222;
+pub const MPROTECT: u64 = 226;
+pub const GETRANDOM: u64 = 278;
+
+// errno values
+pub const ENOENT: i64 = 2;
+pub const EBADF: i64 = 9;
+pub const ENOMEM: i64 = 12;
+pub const EFAULT: i64 = 14;
+pub const ENODEV: i64 = 19;
+pub const EINVAL: i64 = 22;
+pub const ENOTTY: i64 = 25;
+pub const ENOSYS: i64 = 38;
+const EIO: i64 = 5;
+
+const AT_FDCWD: i64 = -100;
+const AT_SYMLINK_NOFOLLOW: u64 = 0x100;
+const AT_EMPTY_PATH: u64 = 0x1000;
+
+// Open flags
+const O_ACCMODE: u64 = 0b11;
+const O_WRONLY: u64 = 1;
+const O_RDWR: u64 = 2;
+const O_CREAT: u64 = 0o100;
+const O_EXCL: u64 = 0o200;
+const O_TRUNC: u64 = 0o1000;
+const O_APPEND: u64 = 0o2000;
+
+const MAP_FIXED: u64 = 0x10;
+const MAP_ANONYMOUS: u64 = 0x20;
+
+const CLOCK_REALTIME: u64 = 0;
+const CLOCK_REALTIME_COARSE: u64 = 5;
+// The highest clock id; the ones after it aren't supported
+const CLOCK_BOOTTIME: u64 = 7;
+
+// Auxiliary vector entry types
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_HWCAP: u64 = 16;
+pub const AT_CLKTCK: u64 = 17;
+pub const AT_RANDOM: u64 = 25;
+pub const AT_EXECFN: u64 = 31;
+
+// Clock ticks per second reported for times()
+const CLOCK_TICKS: u64 = 100;
+
+// Size of struct stat and of each field of struct utsname
+const STAT_SIZE: usize = 128;
+const UTSNAME_FIELD: usize = 65;
+
+// Guest memory for user programs starts at address zero, and the stack
+// takes up the top of it
+pub const MEMORY_SIZE: u64 = 1 << 30;
+pub const STACK_SIZE: u64 = 8 * 1024 * 1024;
+
+// Longest path or string read from guest memory
+const PATH_MAX: usize = 4096;
+// Longest single read or write, which may then come up short
+const MAX_TRANSFER: u64 = 1 << 20;
+// Most iovecs readv and writev take
+const IOV_MAX: u64 = 1024;
+
+type SyscallResult = Result<u64, i64>;
+
+fn round_up(value: u64) -> u64 {
+    (value + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)
+}
+
+// errno for a failed host operation
+fn errno(error: io::Error) -> i64 {
+    error.raw_os_error().map_or(EIO, i64::from)
+}
+
+// Copy between guest memory and the host. Addresses are guest virtual
+// addresses, so a bad pointer turns into EFAULT rather than an exception.
+pub fn read_memory(cpu: &mut Cpu, address: u64, size: u64) -> Result<Vec<u8>, i64> {
+    (0..size)
+        .map(|i| {
+            cpu.load(address.wrapping_add(i), 1)
+                .map(|byte| byte as u8)
+                .map_err(|_| EFAULT)
+        })
+        .collect()
+}
+// Check that `size` bytes of guest memory can be written, by writing back
+// what they hold, so that a bad buffer is found before input is consumed
+fn check_writable(cpu: &mut Cpu, address: u64, size: u64) -> Result<(), i64> {
+    let bytes = read_memory(cpu, address, size)?;
+    write_memory(cpu, address, &bytes)
+}
+pub fn write_memory(cpu: &mut Cpu, address: u64, bytes: &[u8]) -> Result<(), i64> {
+    for (i, &byte) in bytes.iter().enumerate() {
+        cpu.store(address.wrapping_add(i as u64), 1, u64::from(byte))
+            .map_err(|_| EFAULT)?;
+    }
+    Ok(())
+}
+// Read a NUL terminated string, without the NUL
+fn read_string(cpu: &mut Cpu, address: u64) -> Result<String, i64> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = cpu
+            .load(address.wrapping_add(bytes.len() as u64), 1)
+            .map_err(|_| EFAULT)? as u8;
+        if byte == 0 {
+            return String::from_utf8(bytes).map_err(|_| ENOENT);
+        }
+        if bytes.len() == PATH_MAX {
+            return Err(EINVAL);
+        }
+        bytes.push(byte);
+    }
+}
+// Read an array of struct iovec, as base and length pairs
+fn read_iovecs(cpu: &mut Cpu, address: u64, count: u64) -> Result<Vec<(u64, u64)>, i64> {
+    if count > IOV_MAX {
+        return Err(EINVAL);
+    }
+    (0..count)
+        .map(|i| {
+            let iovec = address.wrapping_add(16 * i);
+            let base = cpu.load(iovec, 8).map_err(|_| EFAULT)?;
+            let length = cpu.load(iovec.wrapping_add(8), 8).map_err(|_| EFAULT)?;
+            Ok((base, length))
+        })
+        .collect()
+}
+
+fn random_bytes(count: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0; count];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn store_bytes(cpu: &mut Cpu, address: u64, bytes: &[u8]) -> Result<(), Exception> {
+    for (i, &byte) in bytes.iter().enumerate() {
+        let address = address
+            .checked_add(i as u64)
+            .ok_or(Exception::StoreAccessFault(address))?;
+        cpu.store(address, 1, u64::from(byte))?;
+    }
+    Ok(())
+}
+
+// Lay out the stack a process starts with below `top` and return the stack
+// pointer. From the stack pointer up come argc, the argv and envp pointer
+// arrays each ending in a null pointer and the auxiliary vector, and above
+// them the strings they point to and the bytes AT_RANDOM points to.
+pub fn setup_stack(
+    cpu: &mut Cpu,
+    top: u64,
+    args: &[String],
+    env: &[String],
+    elf: &Elf,
+) -> Result<u64, EmulatorError> {
+    let full = || EmulatorError::Config("the initial stack doesn't fit in memory".to_string());
+    let mut address = top;
+    let mut pointers = Vec::new();
+    for string in args.iter().chain(env) {
+        let mut bytes = string.as_bytes().to_vec();
+        bytes.push(0);
+        address = address.checked_sub(bytes.len() as u64).ok_or_else(full)?;
+        store_bytes(cpu, address, &bytes).map_err(|_| full())?;
+        pointers.push(address);
+    }
+    let (arg_pointers, env_pointers) = pointers.split_at(args.len());
+    // Seeds for the C library's stack protector and pointer mangling
+    address = address.checked_sub(16).ok_or_else(full)? & !0xf;
+    let random = address;
+    store_bytes(
+        cpu,
+        random,
+        &random_bytes(16).unwrap_or_else(|_| vec![0; 16]),
+    )
+    .map_err(|_| full())?;
+
+    // The extensions in misa, with the same bit for each letter
+    let hwcap = cpu.csr.read(csr::MISA) & ((1 << 26) - 1);
+    let mut auxv = Vec::new();
+    if let Some(program_headers) = elf.program_headers {
+        auxv.push((AT_PHDR, program_headers));
+    }
+    auxv.extend([
+        (AT_PHENT, elf.program_header_size),
+        (AT_PHNUM, elf.program_header_count),
+        (AT_PAGESZ, PAGE_SIZE),
+        (AT_ENTRY, elf.entry),
+        (AT_HWCAP, hwcap),
+        (AT_CLKTCK, CLOCK_TICKS),
+        (AT_RANDOM, random),
+    ]);
+    if let Some(&filename) = arg_pointers.first() {
+        auxv.push((AT_EXECFN, filename));
+    }
+    auxv.push((AT_NULL, 0));
+
+    let mut words = vec![args.len() as u64];
+    words.extend(arg_pointers);
+    words.push(0);
+    words.extend(env_pointers);
+    words.push(0);
+    for (kind, value) in auxv {
+        words.extend([kind, value]);
+    }
+    let sp = address
+        .checked_sub(8 * words.len() as u64)
+        .ok_or_else(full)?
+        & !0xf;
+    for (i, &word) in words.iter().enumerate() {
+        cpu.store(sp + 8 * i as u64, 8, word).map_err(|_| full())?;
+    }
+    Ok(sp)
+}
+
+// State the kernel keeps for a process
+pub struct Process {
+    // Open files indexed by file descriptor, starting with the host's
+    // stdin, stdout and stderr
+    files: Vec<Option<File>>,
+    // The heap grows from the end of the program up to the lowest mapping
+    brk_start: u64,
+    brk: u64,
+    // mmap hands out memory downward from the bottom of the stack
+    mmap_bottom: u64,
+    started: Instant,
+    // Status passed to exit, once the program has exited
+    pub exit_status: Option<i32>,
+}
+impl Process {
+    // A process whose program ends at `program_end` in guest memory of
+    // `memory_size` bytes
+    pub fn new(program_end: u64, memory_size: u64) -> Self {
+        let stdio = [
+            io::stdin().as_fd().try_clone_to_owned(),
+            io::stdout().as_fd().try_clone_to_owned(),
+            io::stderr().as_fd().try_clone_to_owned(),
+        ];
+        Self {
+            files: Vec::from(stdio.map(|fd| fd.ok().map(File::from))),
+            brk_start: round_up(program_end),
+            brk: round_up(program_end),
+            mmap_bottom: memory_size.saturating_sub(STACK_SIZE),
+            started: Instant::now(),
+            exit_status: None,
+        }
+    }
+    fn file(&mut self, fd: u64) -> Result<&mut File, i64> {
+        self.files
+            .get_mut(fd as usize)
+            .and_then(Option::as_mut)
+            .ok_or(EBADF)
+    }
+    // Put a file in the lowest free descriptor
+    fn add_file(&mut self, file: File) -> u64 {
+        let fd = match self.files.iter().position(Option::is_none) {
+            Some(fd) => fd,
+            None => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+        };
+        self.files[fd] = Some(file);
+        fd as u64
+    }
+    // Handle the ECALL the hart just raised and put the result in a0
+    pub fn syscall(&mut self, cpu: &mut Cpu) {
+        let number = cpu.read_register(AbiRegister::A7.into());
+        let args = [
+            AbiRegister::A0,
+            AbiRegister::A1,
+            AbiRegister::A2,
+            AbiRegister::A3,
+            AbiRegister::A4,
+            AbiRegister::A5,
+        ]
+        .map(|register| cpu.read_register(register.into()));
+        let result = match number {
+            READ => self.read(args[0], args[1], args[2], cpu),
+            WRITE => self.write(args[0], args[1], args[2], cpu),
+            READV => self.readv(args[0], args[1], args[2], cpu),
+            WRITEV => self.writev(args[0], args[1], args[2], cpu),
+            OPENAT => self.openat(args[0], args[1], args[2], args[3], cpu),
+            CLOSE => self.close(args[0]),
+            LSEEK => self.lseek(args[0], args[1], args[2]),
+            FSTAT => self.fstat(args[0], args[1], cpu),
+            NEWFSTATAT => self.newfstatat(args[0], args[1], args[2], args[3], cpu),
+            BRK => self.brk(args[0], cpu),
+            MMAP => self.mmap(args[0], args[1], args[3], args[4], cpu),
+            MUNMAP => Ok(0),
+            MPROTECT => Ok(0),
+            EXIT | EXIT_GROUP => {
+                self.exit_status = Some(args[0] as u8 as i32);
+                Ok(0)
+            }
+            CLOCK_GETTIME => self.clock_gettime(args[0], args[1], cpu),
+            GETRANDOM => getrandom(args[0], args[1], cpu),
+            UNAME => uname(args[0], cpu),
+            GETPID | GETTID | SET_TID_ADDRESS => Ok(u64::from(std::process::id())),
+            // Signals are never delivered and there are no other threads
+            RT_SIGACTION | RT_SIGPROCMASK | SET_ROBUST_LIST => Ok(0),
+            // No file is a terminal, so stdio is fully buffered
+            IOCTL => Err(ENOTTY),
+            _ => Err(ENOSYS),
+        };
+        let value = match result {
+            Ok(value) => value,
+            Err(errno) => -errno as u64,
+        };
+        cpu.write_register(AbiRegister::A0.into(), value);
+    }
+    fn read(&mut self, fd: u64, buffer: u64, count: u64, cpu: &mut Cpu) -> SyscallResult {
+        let count = count.min(MAX_TRANSFER);
+        self.file(fd)?;
+        check_writable(cpu, buffer, count)?;
+        let mut bytes = vec![0; count as usize];
+        let read = self.file(fd)?.read(&mut bytes).map_err(errno)?;
+        write_memory(cpu, buffer, &bytes[..read])?;
+        Ok(read as u64)
+    }
+    fn write(&mut self, fd: u64, buffer: u64, count: u64, cpu: &mut Cpu) -> SyscallResult {
+        let file = self.file(fd)?;
+        let bytes = read_memory(cpu, buffer, count.min(MAX_TRANSFER))?;
+        file.write(&bytes)
+            .map(|written| written as u64)
+            .map_err(errno)
+    }
+    fn readv(&mut self, fd: u64, iov: u64, count: u64, cpu: &mut Cpu) -> SyscallResult {
+        self.file(fd)?;
+        let mut total = 0;
+        // Once some bytes are read, an error only cuts the total short
+        for (base, length) in read_iovecs(cpu, iov, count)? {
+            let read = match self.read(fd, base, length, cpu) {
+                Ok(read) => read,
+                Err(_) if total > 0 => break,
+                Err(error) => return Err(error),
+            };
+            total += read;
+            if read < length {
+                break;
+            }
+        }
+        Ok(total)
+    }
+    fn writev(&mut self, fd: u64, iov: u64, count: u64, cpu: &mut Cpu) -> SyscallResult {
+        self.file(fd)?;
+        let mut total = 0;
+        for (base, length) in read_iovecs(cpu, iov, count)? {
+            let bytes = read_memory(cpu, base, length.min(MAX_TRANSFER))?;
+            self.file(fd)?.write_all(&bytes).map_err(errno)?;
+            total += bytes.len() as u64;
+        }
+        Ok(total)
+    }
+    fn openat(
+        &mut self,
+        dirfd: u64,
+        path: u64,
+        flags: u64,
+        mode: u64,
+        cpu: &mut Cpu,
+    ) -> SyscallResult {
+        let path = read_string(cpu, path)?;
+        // Relative paths are only resolved against the working directory
+        if dirfd as i64 != AT_FDCWD && !path.starts_with('/') {
+            return Err(EBADF);
+        }
+        let access = flags & O_ACCMODE;
+        let file = OpenOptions::new()
+            .read(access != O_WRONLY)
+            .write(access == O_WRONLY || access == O_RDWR)
+            .append(flags & O_APPEND != 0)
+            .truncate(flags & O_TRUNC != 0)
+            .create(flags & O_CREAT != 0 && flags & O_EXCL == 0)
+            .create_new(flags & O_CREAT != 0 && flags & O_EXCL != 0)
+            .mode(mode as u32)
+            .open(path)
+            .map_err(errno)?;
+        Ok(self.add_file(file))
+    }
+    fn close(&mut self, fd: u64) -> SyscallResult {
+        self.file(fd)?;
+        self.files[fd as usize] = None;
+        Ok(0)
+    }
+    fn lseek(&mut self, fd: u64, offset: u64, whence: u64) -> SyscallResult {
+        let position = match whence {
+            0 => SeekFrom::Start(offset),
+            1 => SeekFrom::Current(offset as i64),
+            2 => SeekFrom::End(offset as i64),
+            _ => return Err(EINVAL),
+        };
+        self.file(fd)?.seek(position).map_err(errno)
+    }
+    fn fstat(&mut self, fd: u64, buffer: u64, cpu: &mut Cpu) -> SyscallResult {
+        let metadata = self.file(fd)?.metadata().map_err(errno)?;
+        write_memory(cpu, buffer, &stat(&metadata))?;
+        Ok(0)
+    }
+    fn newfstatat(
+        &mut self,
+        dirfd: u64,
+        path: u64,
+        buffer: u64,
+        flags: u64,
+        cpu: &mut Cpu,
+    ) -> SyscallResult {
+        let path = read_string(cpu, path)?;
+        if path.is_empty() && flags & AT_EMPTY_PATH != 0 {
+            return self.fstat(dirfd, buffer, cpu);
+        }
+        if dirfd as i64 != AT_FDCWD && !path.starts_with('/') {
+            return Err(EBADF);
+        }
+        let metadata = if flags & AT_SYMLINK_NOFOLLOW != 0 {
+            fs::symlink_metadata(path)
+        } else {
+            fs::metadata(path)
+        };
+        write_memory(cpu, buffer, &stat(&metadata.map_err(errno)?))?;
+        Ok(0)
+    }
+    // Move the end of the heap. Memory given back is cleared so that it
+    // reads as zero when the heap grows over it again.
+    fn brk(&mut self, address: u64, cpu: &mut Cpu) -> SyscallResult {
+        if address >= self.brk_start && address <= self.mmap_bottom {
+            if address < self.brk {
+                write_memory(cpu, address, &vec![0; (self.brk - address) as usize])?;
+            }
+            self.brk = address;
+        }
+        Ok(self.brk)
+    }
+    // Map anonymous memory. Mappings are never reused, so munmap has
+    // nothing to do.
+    fn mmap(
+        &mut self,
+        address: u64,
+        length: u64,
+        flags: u64,
+        fd: u64,
+        cpu: &mut Cpu,
+    ) -> SyscallResult {
+        if flags & MAP_ANONYMOUS == 0 {
+            return Err(if fd as i64 == -1 { EBADF } else { ENODEV });
+        }
+        if length == 0 {
+            return Err(EINVAL);
+        }
+        if length > cpu.bus.dram.size() {
+            return Err(ENOMEM);
+        }
+        let length = round_up(length);
+        if flags & MAP_FIXED != 0 {
+            if address & (PAGE_SIZE - 1) != 0 {
+                return Err(EINVAL);
+            }
+            write_memory(cpu, address, &vec![0; length as usize]).map_err(|_| ENOMEM)?;
+            return Ok(address);
+        }
+        let bottom = self.mmap_bottom.checked_sub(length).ok_or(ENOMEM)?;
+        if bottom < self.brk {
+            return Err(ENOMEM);
+        }
+        self.mmap_bottom = bottom;
+        Ok(bottom)
+    }
+    // The real time clocks are the host's, and every other clock counts
+    // from the start of the process
+    fn clock_gettime(&self, clock: u64, buffer: u64, cpu: &mut Cpu) -> SyscallResult {
+        let time = match clock {
+            CLOCK_REALTIME | CLOCK_REALTIME_COARSE => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            _ if clock <= CLOCK_BOOTTIME => self.started.elapsed(),
+            _ => return Err(EINVAL),
+        };
+        let mut timespec = time.as_secs().to_le_bytes().to_vec();
+        timespec.extend(u64::from(time.subsec_nanos()).to_le_bytes());
+        write_memory(cpu, buffer, &timespec)?;
+        Ok(0)
+    }
+}
+
+// struct stat as laid out by the asm-generic ABI
+fn stat(metadata: &Metadata) -> [u8; STAT_SIZE] {
+    let mut buffer = [0; STAT_SIZE];
+    let mut put = |offset: usize, bytes: &[u8]| {
+        buffer[offset..offset + bytes.len()].copy_from_slice(bytes);
+    };
+    put(0, &metadata.dev().to_le_bytes());
+    put(8, &metadata.ino().to_le_bytes());
+    put(16, &metadata.mode().to_le_bytes());
+    put(20, &(metadata.nlink() as u32).to_le_bytes());
+    put(24, &metadata.uid().to_le_bytes());
+    put(28, &metadata.gid().to_le_bytes());
+    put(32, &metadata.rdev().to_le_bytes());
+    put(48, &metadata.size().to_le_bytes());
+    put(56, &(metadata.blksize() as u32).to_le_bytes());
+    put(64, &metadata.blocks().to_le_bytes());
+    put(72, &metadata.atime().to_le_bytes());
+    put(80, &metadata.atime_nsec().to_le_bytes());
+    put(88, &metadata.mtime().to_le_bytes());
+    put(96, &metadata.mtime_nsec().to_le_bytes());
+    put(104, &metadata.ctime().to_le_bytes());
+    put(112, &metadata.ctime_nsec().to_le_bytes());
+    buffer
+}
+
+fn getrandom(buffer: u64, count: u64, cpu: &mut Cpu) -> SyscallResult {
+    let bytes = random_bytes(count.min(MAX_TRANSFER) as usize).map_err(errno)?;
+    write_memory(cpu, buffer, &bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+fn uname(buffer: u64, cpu: &mut Cpu) -> SyscallResult {
+    let fields = ["Linux", "rv64", "6.1.0", "#1", "riscv64", "(none)"];
+    let mut utsname = vec![0; fields.len() * UTSNAME_FIELD];
+    for (i, field) in fields.iter().enumerate() {
+        let start = i * UTSNAME_FIELD;
+        utsname[start..start + field.len()].copy_from_slice(field.as_bytes());
+    }
+    write_memory(cpu, buffer, &utsname)?;
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::Bus;
+    use crate::riscv::cpu::{Privilege, Register};
+    use crate::riscv::pmp;
+    use std::convert::TryFrom;
+
+    const MEMORY: u64 = 0x100_0000;
+    const BUFFER: u64 = 0x1000;
+
+    fn setup() -> (Process, Cpu) {
+        let mut bus = Bus::new(MEMORY);
+        bus.dram_base = 0;
+        let mut cpu = Cpu::new(bus);
+        pmp::allow_all(&mut cpu.csr);
+        cpu.privilege = Privilege::User;
+        (Process::new(0x2345, MEMORY), cpu)
+    }
+    fn syscall(process: &mut Process, cpu: &mut Cpu, number: u64, args: &[u64]) -> i64 {
+        cpu.write_register(AbiRegister::A7.into(), number);
+        // The arguments go in a0 (x10) onward
+        for (i, &arg) in args.iter().enumerate() {
+            cpu.write_register(Register::try_from(10 + i).unwrap(), arg);
+        }
+        process.syscall(cpu);
+        cpu.read_register(AbiRegister::A0.into()) as i64
+    }
+    fn put_string(cpu: &mut Cpu, address: u64, string: &str) {
+        write_memory(cpu, address, string.as_bytes()).unwrap();
+        write_memory(cpu, address + string.len() as u64, &[0]).unwrap();
+    }
+    #[test]
+    fn files() {
+        let (mut process, mut cpu) = setup();
+        let path = std::env::temp_dir().join(format!("syscall-{}", std::process::id()));
+        put_string(&mut cpu, 0x100, path.to_str().unwrap());
+        let flags = O_RDWR | O_CREAT | O_TRUNC;
+        let fd = syscall(
+            &mut process,
+            &mut cpu,
+            OPENAT,
+            &[AT_FDCWD as u64, 0x100, flags, 0o600],
+        );
+        assert_eq!(fd, 3);
+        write_memory(&mut cpu, BUFFER, b"hello").unwrap();
+        let args = [fd as u64, BUFFER, 5];
+        assert_eq!(syscall(&mut process, &mut cpu, WRITE, &args), 5);
+        assert_eq!(
+            syscall(&mut process, &mut cpu, LSEEK, &[fd as u64, 1, 0]),
+            1
+        );
+        let args = [fd as u64, BUFFER + 0x10, 0x10];
+        assert_eq!(syscall(&mut process, &mut cpu, READ, &args), 4);
+        assert_eq!(
+            read_memory(&mut cpu, BUFFER + 0x10, 4),
+            Ok(b"ello".to_vec())
+        );
+        // st_size
+        let args = [fd as u64, BUFFER];
+        assert_eq!(syscall(&mut process, &mut cpu, FSTAT, &args), 0);
+        assert_eq!(cpu.load(BUFFER + 48, 8), Ok(5));
+        let args = [AT_FDCWD as u64, 0x100, BUFFER, 0];
+        assert_eq!(syscall(&mut process, &mut cpu, NEWFSTATAT, &args), 0);
+        assert_eq!(cpu.load(BUFFER + 48, 8), Ok(5));
+        assert_eq!(syscall(&mut process, &mut cpu, CLOSE, &[fd as u64]), 0);
+        assert_eq!(syscall(&mut process, &mut cpu, CLOSE, &[fd as u64]), -EBADF);
+        fs::remove_file(&path).unwrap();
+        let args = [AT_FDCWD as u64, 0x100, 0, 0];
+        assert_eq!(syscall(&mut process, &mut cpu, OPENAT, &args), -ENOENT);
+    }
+    #[test]
+    fn writev() {
+        let (mut process, mut cpu) = setup();
+        let path = std::env::temp_dir().join(format!("syscall-writev-{}", std::process::id()));
+        process.add_file(File::create(&path).unwrap());
+        write_memory(&mut cpu, BUFFER, b"abcdef").unwrap();
+        for (i, (base, length)) in [(BUFFER, 2), (BUFFER + 4, 2)].iter().enumerate() {
+            cpu.store(0x100 + 16 * i as u64, 8, *base).unwrap();
+            cpu.store(0x108 + 16 * i as u64, 8, *length).unwrap();
+        }
+        assert_eq!(syscall(&mut process, &mut cpu, WRITEV, &[3, 0x100, 2]), 4);
+        assert_eq!(fs::read(&path).unwrap(), b"abef");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            syscall(&mut process, &mut cpu, WRITEV, &[9, 0x100, 2]),
+            -EBADF
+        );
+    }
+    #[test]
+    fn read_into_bad_buffer() {
+        let (mut process, mut cpu) = setup();
+        let path = std::env::temp_dir().join(format!("syscall-read-{}", std::process::id()));
+        fs::write(&path, b"abcdef").unwrap();
+        process.add_file(File::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        // Nothing is consumed when the buffer runs off the end of memory
+        let args = [3, MEMORY - 2, 4];
+        assert_eq!(syscall(&mut process, &mut cpu, READ, &args), -EFAULT);
+        assert_eq!(syscall(&mut process, &mut cpu, READ, &[3, BUFFER, 1]), 1);
+        assert_eq!(cpu.load(BUFFER, 1), Ok(u64::from(b'a')));
+
+        // readv keeps what it read before a bad iovec
+        for (i, (base, length)) in [(BUFFER, 2), (MEMORY, 2)].iter().enumerate() {
+            cpu.store(0x100 + 16 * i as u64, 8, *base).unwrap();
+            cpu.store(0x108 + 16 * i as u64, 8, *length).unwrap();
+        }
+        assert_eq!(syscall(&mut process, &mut cpu, READV, &[3, 0x100, 2]), 2);
+        assert_eq!(read_memory(&mut cpu, BUFFER, 2), Ok(b"bc".to_vec()));
+        assert_eq!(
+            syscall(&mut process, &mut cpu, READV, &[3, 0x110, 1]),
+            -EFAULT
+        );
+        assert_eq!(syscall(&mut process, &mut cpu, READ, &[3, BUFFER, 8]), 3);
+    }
+    #[test]
+    fn bad_pointer() {
+        let (mut process, mut cpu) = setup();
+        assert_eq!(syscall(&mut process, &mut cpu, UNAME, &[MEMORY]), -EFAULT);
+    }
+    #[test]
+    fn brk() {
+        let (mut process, mut cpu) = setup();
+        assert_eq!(syscall(&mut process, &mut cpu, BRK, &[0]), 0x3000);
+        assert_eq!(syscall(&mut process, &mut cpu, BRK, &[0x5000]), 0x5000);
+        cpu.store(0x4000, 8, u64::MAX).unwrap();
+        // The heap can't shrink below its start
+        assert_eq!(syscall(&mut process, &mut cpu, BRK, &[0x1000]), 0x5000);
+        assert_eq!(syscall(&mut process, &mut cpu, BRK, &[0x3000]), 0x3000);
+        assert_eq!(syscall(&mut process, &mut cpu, BRK, &[0x5000]), 0x5000);
+        assert_eq!(cpu.load(0x4000, 8), Ok(0));
+    }
+    #[test]
+    fn mmap() {
+        let (mut process, mut cpu) = setup();
+        let anonymous = MAP_ANONYMOUS | 0x2;
+        let args = [0, 0x1800, 3, anonymous, u64::MAX, 0];
+        let first = syscall(&mut process, &mut cpu, MMAP, &args) as u64;
+        assert_eq!(first, MEMORY - STACK_SIZE - 0x2000);
+        let second = syscall(&mut process, &mut cpu, MMAP, &args) as u64;
+        assert_eq!(second, first - 0x2000);
+        cpu.store(second, 8, 1).unwrap();
+        let args = [second, 0x1000, 3, anonymous | MAP_FIXED, u64::MAX, 0];
+        assert_eq!(syscall(&mut process, &mut cpu, MMAP, &args) as u64, second);
+        assert_eq!(cpu.load(second, 8), Ok(0));
+        // Only anonymous mappings are supported
+        let args = [0, 0x1000, 1, 0x2, 3, 0];
+        assert_eq!(syscall(&mut process, &mut cpu, MMAP, &args), -ENODEV);
+        // Mappings can't run into the heap
+        let args = [0, MEMORY, 3, anonymous, u64::MAX, 0];
+        assert_eq!(syscall(&mut process, &mut cpu, MMAP, &args), -ENOMEM);
+    }
+    #[test]
+    fn exit() {
+        let (mut process, mut cpu) = setup();
+        assert_eq!(process.exit_status, None);
+        syscall(&mut process, &mut cpu, EXIT_GROUP, &[0x103]);
+        assert_eq!(process.exit_status, Some(3));
+    }
+    #[test]
+    fn clock_gettime() {
+        let (mut process, mut cpu) = setup();
+        let args = [CLOCK_REALTIME, BUFFER];
+        assert_eq!(syscall(&mut process, &mut cpu, CLOCK_GETTIME, &args), 0);
+        // Some time after 2020
+        assert!(cpu.load(BUFFER, 8).unwrap() > 1_577_836_800);
+        assert!(cpu.load(BUFFER + 8, 8).unwrap() < 1_000_000_000);
+        let args = [CLOCK_BOOTTIME + 1, BUFFER];
+        assert_eq!(
+            syscall(&mut process, &mut cpu, CLOCK_GETTIME, &args),
+            -EINVAL
+        );
+    }
+    #[test]
+    fn uname_and_getrandom() {
+        let (mut process, mut cpu) = setup();
+        assert_eq!(syscall(&mut process, &mut cpu, UNAME, &[BUFFER]), 0);
+        assert_eq!(read_string(&mut cpu, BUFFER), Ok(String::from("Linux")));
+        let machine = BUFFER + 4 * UTSNAME_FIELD as u64;
+        assert_eq!(read_string(&mut cpu, machine), Ok(String::from("riscv64")));
+        let args = [BUFFER, 16, 0];
+        assert_eq!(syscall(&mut process, &mut cpu, GETRANDOM, &args), 16);
+    }
+    #[test]
+    fn initial_stack() {
+        let (_, mut cpu) = setup();
+        let elf = Elf {
+            entry: 0x10078,
+            segments: Vec::new(),
+            program_headers: Some(0x10040),
+            program_header_count: 3,
+            program_header_size: 56,
+            symbols: Default::default(),
+        };
+        let args = [String::from("/bin/true"), String::from("-x")];
+        let env = [String::from("HOME=/")];
+        let sp = setup_stack(&mut cpu, MEMORY, &args, &env, &elf).unwrap();
+        assert_eq!(sp & 0xf, 0);
+        let words: Vec<u64> = (0..32).map(|i| cpu.load(sp + 8 * i, 8).unwrap()).collect();
+        assert_eq!(words[0], 2);
+        assert_eq!(words[3], 0);
+        assert_eq!(words[5], 0);
+        let mut strings = Vec::new();
+        for &pointer in [words[1], words[2], words[4]].iter() {
+            strings.push(read_string(&mut cpu, pointer).unwrap());
+        }
+        assert_eq!(strings, ["/bin/true", "-x", "HOME=/"]);
+        let auxv: Vec<_> = words[6..]
+            .chunks(2)
+            .map(|entry| (entry[0], entry[1]))
+            .take_while(|entry| entry.0 != AT_NULL)
+            .collect();
+        let find = |kind| auxv.iter().find(|entry| entry.0 == kind).unwrap().1;
+        assert_eq!(find(AT_PHDR), 0x10040);
+        assert_eq!(find(AT_PHNUM), 3);
+        assert_eq!(find(AT_PAGESZ), PAGE_SIZE);
+        assert_eq!(find(AT_ENTRY), 0x10078);
+        assert_eq!(find(AT_HWCAP) & csr::extension(b'C'), csr::extension(b'C'));
+        let random = find(AT_RANDOM);
+        assert!(random > sp && random + 16 <= MEMORY);
+        let execfn = find(AT_EXECFN);
+        assert_eq!(read_string(&mut cpu, execfn), Ok(args[0].clone()));
+
+        // Stacks that would wrap below address zero don't fit
+        for top in [0x20, 0x40] {
+            assert!(matches!(
+                setup_stack(&mut cpu, top, &args, &env, &elf),
+                Err(EmulatorError::Config(_))
+            ));
+        }
+    }
+    #[test]
+    fn unknown() {
+        let (mut process, mut cpu) = setup();
+        assert_eq!(syscall(&mut process, &mut cpu, 0xfff, &[]), -ENOSYS);
+        assert_eq!(
+            syscall(&mut process, &mut cpu, IOCTL, &[1, 0x5413]),
+            -ENOTTY
+        );
+    }
+}
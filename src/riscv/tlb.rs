@@ -0,0 +1,130 @@
+// Translations cached from earlier page table walks. Entries are kept per
+// 4 KiB page, so a superpage fills one entry for every page used in it.
+
+pub const DEFAULT_ENTRIES: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Entry {
+    // Virtual page number, the virtual address shifted right by 12
+    pub vpn: u64,
+    pub asid: u64,
+    // Global mappings exist in every address space
+    pub global: bool,
+    // Permission, user and accessed/dirty bits of the leaf entry
+    pub flags: u64,
+    // Physical address of the page
+    pub frame: u64,
+}
+
+// A direct mapped TLB shared by instruction fetches and data accesses
+pub struct Tlb {
+    entries: Vec<Option<Entry>>,
+    pub hits: u64,
+    pub misses: u64,
+}
+impl Tlb {
+    pub fn new(entries: usize) -> Self {
+        assert!(entries > 0, "a TLB needs at least one entry");
+        Self {
+            entries: vec![None; entries],
+            hits: 0,
+            misses: 0,
+        }
+    }
+    fn index(&self, vpn: u64) -> usize {
+        (vpn % self.entries.len() as u64) as usize
+    }
+    // Find the entry for a page in an address space, counting the hit or
+    // miss
+    pub fn lookup(&mut self, vpn: u64, asid: u64) -> Option<Entry> {
+        let index = self.index(vpn);
+        match self.entries[index] {
+            Some(entry) if entry.vpn == vpn && (entry.global || entry.asid == asid) => {
+                self.hits += 1;
+                Some(entry)
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+    pub fn insert(&mut self, entry: Entry) {
+        let index = self.index(entry.vpn);
+        self.entries[index] = Some(entry);
+    }
+    // Invalidate the entries SFENCE.VMA selects: those for one page when
+    // `vpn` is given and those in one address space when `asid` is given.
+    // Global entries are kept when only an address space is flushed.
+    pub fn flush(&mut self, vpn: Option<u64>, asid: Option<u64>) {
+        for slot in self.entries.iter_mut() {
+            let selected = match *slot {
+                Some(entry) => {
+                    vpn.is_none_or(|vpn| entry.vpn == vpn)
+                        && asid.is_none_or(|asid| !entry.global && entry.asid == asid)
+                }
+                None => false,
+            };
+            if selected {
+                *slot = None;
+            }
+        }
+    }
+    pub fn flush_all(&mut self) {
+        self.flush(None, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(vpn: u64, asid: u64, global: bool) -> Entry {
+        Entry {
+            vpn,
+            asid,
+            global,
+            flags: 0,
+            frame: vpn << 12,
+        }
+    }
+    #[test]
+    fn lookup() {
+        let mut tlb = Tlb::new(4);
+        assert_eq!(tlb.lookup(1, 0), None);
+        tlb.insert(entry(1, 0, false));
+        assert_eq!(tlb.lookup(1, 0), Some(entry(1, 0, false)));
+        // Other address spaces only see global entries
+        assert_eq!(tlb.lookup(1, 1), None);
+        tlb.insert(entry(2, 0, true));
+        assert_eq!(tlb.lookup(2, 1), Some(entry(2, 0, true)));
+        // Pages that share an index replace each other
+        tlb.insert(entry(5, 0, false));
+        assert_eq!(tlb.lookup(1, 0), None);
+        assert_eq!((tlb.hits, tlb.misses), (2, 3));
+    }
+    #[test]
+    fn flush() {
+        let mut tlb = Tlb::new(8);
+        let fill = |tlb: &mut Tlb| {
+            tlb.insert(entry(1, 1, false));
+            tlb.insert(entry(2, 2, false));
+            tlb.insert(entry(3, 2, true));
+        };
+        fill(&mut tlb);
+        tlb.flush(Some(1), None);
+        assert_eq!(tlb.lookup(1, 1), None);
+        assert!(tlb.lookup(2, 2).is_some());
+        tlb.flush(None, Some(2));
+        assert_eq!(tlb.lookup(2, 2), None);
+        assert!(tlb.lookup(3, 2).is_some());
+        fill(&mut tlb);
+        tlb.flush(Some(3), Some(2));
+        assert!(tlb.lookup(3, 2).is_some());
+        tlb.flush(Some(2), Some(2));
+        assert_eq!(tlb.lookup(2, 2), None);
+        tlb.flush_all();
+        assert_eq!(tlb.lookup(1, 1), None);
+        assert_eq!(tlb.lookup(3, 2), None);
+    }
+}
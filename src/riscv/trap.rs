@@ -0,0 +1,462 @@
+use crate::riscv::cpu::{Cpu, Privilege};
+use crate::riscv::csr;
+use crate::riscv::plic;
+
+// Synchronous exceptions, carrying the value written to mtval
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exception {
+    InstructionAddressMisaligned(u64),
+    InstructionAccessFault(u64),
+    IllegalInstruction(u64),
+    Breakpoint(u64),
+    LoadAddressMisaligned(u64),
+    LoadAccessFault(u64),
+    StoreAddressMisaligned(u64),
+    StoreAccessFault(u64),
+    EnvironmentCallFromUMode,
+    EnvironmentCallFromSMode,
+    EnvironmentCallFromMMode,
+    InstructionPageFault(u64),
+    LoadPageFault(u64),
+    StorePageFault(u64),
+}
+impl Exception {
+    // Exception code written to mcause
+    pub fn code(&self) -> u64 {
+        match self {
+            Exception::InstructionAddressMisaligned(_) => 0,
+            Exception::InstructionAccessFault(_) => 1,
+            Exception::IllegalInstruction(_) => 2,
+            Exception::Breakpoint(_) => 3,
+            Exception::LoadAddressMisaligned(_) => 4,
+            Exception::LoadAccessFault(_) => 5,
+            Exception::StoreAddressMisaligned(_) => 6,
+            Exception::StoreAccessFault(_) => 7,
+            Exception::EnvironmentCallFromUMode => 8,
+            Exception::EnvironmentCallFromSMode => 9,
+            Exception::EnvironmentCallFromMMode => 11,
+            Exception::InstructionPageFault(_) => 12,
+            Exception::LoadPageFault(_) => 13,
+            Exception::StorePageFault(_) => 15,
+        }
+    }
+    // Faulting address or instruction bits written to mtval
+    pub fn value(&self) -> u64 {
+        match *self {
+            Exception::InstructionAddressMisaligned(value)
+            | Exception::InstructionAccessFault(value)
+            | Exception::IllegalInstruction(value)
+            | Exception::Breakpoint(value)
+            | Exception::LoadAddressMisaligned(value)
+            | Exception::LoadAccessFault(value)
+            | Exception::StoreAddressMisaligned(value)
+            | Exception::StoreAccessFault(value)
+            | Exception::InstructionPageFault(value)
+            | Exception::LoadPageFault(value)
+            | Exception::StorePageFault(value) => value,
+            Exception::EnvironmentCallFromUMode
+            | Exception::EnvironmentCallFromSMode
+            | Exception::EnvironmentCallFromMMode => 0,
+        }
+    }
+}
+
+// Asynchronous interrupts, in the order of their bits in mip and mie
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    SupervisorSoftware,
+    MachineSoftware,
+    SupervisorTimer,
+    MachineTimer,
+    SupervisorExternal,
+    MachineExternal,
+}
+impl Interrupt {
+    // Exception code written to mcause, with the interrupt bit clear
+    pub fn code(&self) -> u64 {
+        match self {
+            Interrupt::SupervisorSoftware => 1,
+            Interrupt::MachineSoftware => 3,
+            Interrupt::SupervisorTimer => 5,
+            Interrupt::MachineTimer => 7,
+            Interrupt::SupervisorExternal => 9,
+            Interrupt::MachineExternal => 11,
+        }
+    }
+}
+
+// Simultaneous interrupts are taken in this order
+const PRIORITY: [Interrupt; 6] = [
+    Interrupt::MachineExternal,
+    Interrupt::MachineSoftware,
+    Interrupt::MachineTimer,
+    Interrupt::SupervisorExternal,
+    Interrupt::SupervisorSoftware,
+    Interrupt::SupervisorTimer,
+];
+
+// Copy the interrupt lines of the devices on the bus into mip
+pub fn update_pending(cpu: &mut Cpu) {
+    cpu.bus.update_interrupts();
+    let software = cpu.bus.clint.software_interrupt();
+    let timer = cpu.bus.clint.timer_interrupt();
+    cpu.csr.set_pending(csr::MSIP, software);
+    cpu.csr.set_pending(csr::MTIP, timer);
+    let hart = cpu.csr.read(csr::MHARTID) as usize;
+    let machine = cpu
+        .bus
+        .plic
+        .interrupting(plic::context(hart, Privilege::Machine));
+    let supervisor = cpu
+        .bus
+        .plic
+        .interrupting(plic::context(hart, Privilege::Supervisor));
+    cpu.csr.set_pending(csr::MEIP, machine);
+    cpu.csr.set_pending(csr::SEIP, supervisor);
+}
+
+// The interrupt to take before the next instruction, if any. Interrupts
+// for a higher privilege level are always enabled, those for the current
+// level only when its global enable in mstatus is set, and those for a
+// lower level never.
+pub fn pending_interrupt(cpu: &Cpu) -> Option<Interrupt> {
+    let pending = cpu.csr.read(csr::MIP) & cpu.csr.read(csr::MIE);
+    let delegated = cpu.csr.read(csr::MIDELEG);
+    let mstatus = cpu.csr.read(csr::MSTATUS);
+    let machine = match cpu.privilege {
+        Privilege::Machine => mstatus & csr::MSTATUS_MIE != 0,
+        _ => true,
+    };
+    let supervisor = match cpu.privilege {
+        Privilege::Machine => false,
+        Privilege::Supervisor => mstatus & csr::MSTATUS_SIE != 0,
+        Privilege::User => true,
+    };
+    let mut enabled = 0;
+    if machine {
+        enabled |= pending & !delegated;
+    }
+    if supervisor {
+        enabled |= pending & delegated;
+    }
+    PRIORITY
+        .iter()
+        .copied()
+        .find(|interrupt| enabled & (1 << interrupt.code()) != 0)
+}
+
+// Whether an exception is handled in supervisor mode. Exceptions raised in
+// machine mode never trap to a lower privilege level.
+fn delegated(exception: Exception, cpu: &Cpu) -> bool {
+    cpu.privilege != Privilege::Machine && cpu.csr.read(csr::MEDELEG) & (1 << exception.code()) != 0
+}
+
+// Address of the handler an exception traps to. Exceptions always use the
+// base address, even in vectored mode.
+pub fn trap_vector(exception: Exception, cpu: &Cpu) -> u64 {
+    let tvec = if delegated(exception, cpu) {
+        csr::STVEC
+    } else {
+        csr::MTVEC
+    };
+    cpu.csr.read(tvec) & !0b11
+}
+
+// Enter the trap handler for an exception raised by the instruction at pc,
+// in supervisor mode if medeleg delegates it and in machine mode otherwise
+pub fn take_trap(exception: Exception, cpu: &mut Cpu) {
+    let vector = trap_vector(exception, cpu);
+    let supervisor = delegated(exception, cpu);
+    enter_handler(cpu, supervisor, exception.code(), exception.value(), vector);
+}
+
+// Enter the trap handler for an interrupt, before the instruction at pc. In
+// vectored mode each interrupt has its own entry after the base address.
+pub fn take_interrupt(interrupt: Interrupt, cpu: &mut Cpu) {
+    let supervisor = cpu.privilege != Privilege::Machine
+        && cpu.csr.read(csr::MIDELEG) & (1 << interrupt.code()) != 0;
+    let tvec = cpu
+        .csr
+        .read(if supervisor { csr::STVEC } else { csr::MTVEC });
+    let vector = match tvec & 0b11 {
+        0b01 => (tvec & !0b11) + 4 * interrupt.code(),
+        _ => tvec & !0b11,
+    };
+    let cause = 1 << 63 | interrupt.code();
+    enter_handler(cpu, supervisor, cause, 0, vector);
+}
+
+// Record the cause of a trap, save the interrupted state and jump to the
+// handler at `vector`
+fn enter_handler(cpu: &mut Cpu, supervisor: bool, cause: u64, value: u64, vector: u64) {
+    let mstatus = cpu.csr.read(csr::MSTATUS);
+    if supervisor {
+        cpu.csr.write(csr::SEPC, cpu.pc);
+        cpu.csr.write(csr::SCAUSE, cause);
+        cpu.csr.write(csr::STVAL, value);
+
+        // Save the interrupt enable in SPIE, disable interrupts and record
+        // the previous privilege in SPP
+        let spie = if mstatus & csr::MSTATUS_SIE != 0 {
+            csr::MSTATUS_SPIE
+        } else {
+            0
+        };
+        let spp = if cpu.privilege == Privilege::Supervisor {
+            csr::MSTATUS_SPP
+        } else {
+            0
+        };
+        let fields = csr::MSTATUS_SIE | csr::MSTATUS_SPIE | csr::MSTATUS_SPP;
+        cpu.csr
+            .write(csr::MSTATUS, (mstatus & !fields) | spie | spp);
+        cpu.privilege = Privilege::Supervisor;
+    } else {
+        cpu.csr.write(csr::MEPC, cpu.pc);
+        cpu.csr.write(csr::MCAUSE, cause);
+        cpu.csr.write(csr::MTVAL, value);
+
+        // Save the interrupt enable in MPIE, disable interrupts and record
+        // the previous privilege in MPP
+        let mpie = if mstatus & csr::MSTATUS_MIE != 0 {
+            csr::MSTATUS_MPIE
+        } else {
+            0
+        };
+        let mpp = (cpu.privilege as u64) << 11;
+        let fields = csr::MSTATUS_MIE | csr::MSTATUS_MPIE | csr::MSTATUS_MPP;
+        cpu.csr
+            .write(csr::MSTATUS, (mstatus & !fields) | mpie | mpp);
+        cpu.privilege = Privilege::Machine;
+    }
+
+    // Trapping gives up any reservation so that an SC in the interrupted
+    // code cannot succeed after the handler ran
+    cpu.reservation = None;
+
+    cpu.pc = vector;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::riscv::bus::{Bus, DRAM_BASE};
+    use crate::riscv::clint;
+    use crate::riscv::pmp;
+
+    // Load the instructions at the start of DRAM and point the hart at them
+    fn setup(program: &[u32]) -> Cpu {
+        let mut cpu = Cpu::new(Bus::new(0x100));
+        for (i, instruction) in program.iter().enumerate() {
+            cpu.bus
+                .write_word(DRAM_BASE + 4 * i as u64, *instruction)
+                .unwrap();
+        }
+        pmp::allow_all(&mut cpu.csr);
+        cpu.pc = DRAM_BASE;
+        cpu
+    }
+    #[test]
+    fn take_trap() {
+        let mut cpu = setup(&[]);
+        cpu.pc = DRAM_BASE + 8;
+        cpu.csr.write(csr::MTVEC, DRAM_BASE + 0x41);
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_MIE);
+        super::take_trap(Exception::LoadAccessFault(0x10), &mut cpu);
+        assert_eq!(cpu.pc, DRAM_BASE + 0x40);
+        assert_eq!(cpu.csr.read(csr::MEPC), DRAM_BASE + 8);
+        assert_eq!(cpu.csr.read(csr::MCAUSE), 5);
+        assert_eq!(cpu.csr.read(csr::MTVAL), 0x10);
+        let fields = csr::MSTATUS_MIE | csr::MSTATUS_MPIE | csr::MSTATUS_MPP;
+        assert_eq!(
+            cpu.csr.read(csr::MSTATUS) & fields,
+            csr::MSTATUS_MPIE | csr::MSTATUS_MPP
+        );
+    }
+    #[test]
+    fn delegated_trap() {
+        let mut cpu = setup(&[]);
+        cpu.pc = DRAM_BASE + 8;
+        cpu.privilege = Privilege::User;
+        cpu.csr.write(csr::STVEC, DRAM_BASE + 0x80);
+        cpu.csr.write(csr::MEDELEG, 1 << 8);
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_SIE);
+        super::take_trap(Exception::EnvironmentCallFromUMode, &mut cpu);
+        assert_eq!(cpu.privilege, Privilege::Supervisor);
+        assert_eq!(cpu.pc, DRAM_BASE + 0x80);
+        assert_eq!(cpu.csr.read(csr::SEPC), DRAM_BASE + 8);
+        assert_eq!(cpu.csr.read(csr::SCAUSE), 8);
+        assert_eq!(cpu.csr.read(csr::MCAUSE), 0);
+        let fields = csr::MSTATUS_SIE | csr::MSTATUS_SPIE | csr::MSTATUS_SPP;
+        assert_eq!(cpu.csr.read(csr::MSTATUS) & fields, csr::MSTATUS_SPIE);
+    }
+    #[test]
+    fn undelegated_trap() {
+        let mut cpu = setup(&[]);
+        cpu.privilege = Privilege::Supervisor;
+        cpu.csr.write(csr::MTVEC, DRAM_BASE + 0x40);
+        cpu.csr.write(csr::MEDELEG, 1 << 8);
+        super::take_trap(Exception::EnvironmentCallFromSMode, &mut cpu);
+        assert_eq!(cpu.privilege, Privilege::Machine);
+        assert_eq!(cpu.pc, DRAM_BASE + 0x40);
+        assert_eq!(cpu.csr.read(csr::MCAUSE), 9);
+        assert_eq!(cpu.csr.read(csr::MSTATUS) & csr::MSTATUS_MPP, 0b01 << 11);
+        // Delegation doesn't apply to exceptions raised in machine mode
+        cpu.csr.write(csr::MEDELEG, 1 << 2);
+        super::take_trap(Exception::IllegalInstruction(0), &mut cpu);
+        assert_eq!(cpu.csr.read(csr::MCAUSE), 2);
+        assert_eq!(cpu.csr.read(csr::SCAUSE), 0);
+    }
+    #[test]
+    fn round_trip_through_user_mode() {
+        // mret; ecall
+        let mut cpu = setup(&[0x30200073, 0x00000073]);
+        cpu.csr.write(csr::MEPC, DRAM_BASE + 4);
+        cpu.csr.write(csr::MTVEC, DRAM_BASE + 0x40);
+        cpu.step().unwrap();
+        assert_eq!(cpu.privilege, Privilege::User);
+        assert_eq!(cpu.pc, DRAM_BASE + 4);
+        let exception = cpu.step().unwrap_err();
+        assert_eq!(exception, Exception::EnvironmentCallFromUMode);
+        super::take_trap(exception, &mut cpu);
+        assert_eq!(cpu.privilege, Privilege::Machine);
+        assert_eq!(cpu.csr.read(csr::MSTATUS) & csr::MSTATUS_MPP, 0);
+        assert_eq!(cpu.csr.read(csr::MEPC), DRAM_BASE + 4);
+    }
+    #[test]
+    fn timer_interrupt() {
+        let mut cpu = setup(&[]);
+        cpu.pc = DRAM_BASE + 8;
+        cpu.csr.write(csr::MTVEC, DRAM_BASE + 0x40);
+        cpu.bus.clint.write(clint::MTIMECMP, 8, 0);
+        update_pending(&mut cpu);
+        assert_eq!(cpu.csr.read(csr::MIP), csr::MTIP);
+        // Masked until both mie and mstatus.MIE enable it
+        assert_eq!(pending_interrupt(&cpu), None);
+        cpu.csr.write(csr::MIE, csr::MTIP);
+        assert_eq!(pending_interrupt(&cpu), None);
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_MIE);
+        assert_eq!(pending_interrupt(&cpu), Some(Interrupt::MachineTimer));
+        take_interrupt(Interrupt::MachineTimer, &mut cpu);
+        assert_eq!(cpu.pc, DRAM_BASE + 0x40);
+        assert_eq!(cpu.csr.read(csr::MEPC), DRAM_BASE + 8);
+        assert_eq!(cpu.csr.read(csr::MCAUSE), 1 << 63 | 7);
+        assert_eq!(cpu.csr.read(csr::MSTATUS) & csr::MSTATUS_MIE, 0);
+        assert_eq!(pending_interrupt(&cpu), None);
+        // Moving mtimecmp into the future clears the interrupt
+        cpu.bus.clint.write(clint::MTIMECMP, 8, u64::MAX);
+        update_pending(&mut cpu);
+        assert_eq!(cpu.csr.read(csr::MIP), 0);
+    }
+    #[test]
+    fn vectored_interrupt() {
+        let mut cpu = setup(&[]);
+        cpu.privilege = Privilege::User;
+        cpu.csr.write(csr::MTVEC, DRAM_BASE + 0x41);
+        cpu.csr.write(csr::MIE, csr::MSIP);
+        cpu.bus.clint.write(clint::MSIP, 4, 1);
+        update_pending(&mut cpu);
+        // Machine interrupts are enabled below machine mode regardless of
+        // mstatus.MIE
+        assert_eq!(pending_interrupt(&cpu), Some(Interrupt::MachineSoftware));
+        take_interrupt(Interrupt::MachineSoftware, &mut cpu);
+        assert_eq!(cpu.pc, DRAM_BASE + 0x40 + 12);
+        assert_eq!(cpu.privilege, Privilege::Machine);
+        assert_eq!(cpu.csr.read(csr::MTVAL), 0);
+    }
+    #[test]
+    fn interrupt_priority_and_delegation() {
+        let mut cpu = setup(&[]);
+        cpu.privilege = Privilege::Supervisor;
+        cpu.csr.write(csr::STVEC, DRAM_BASE + 0x80);
+        cpu.csr.write(csr::MIE, u64::MAX);
+        cpu.csr.write(csr::MIDELEG, csr::STIP);
+        cpu.csr.write(csr::MIP, csr::STIP | csr::SSIP);
+        // Supervisor interrupts that aren't delegated go to machine mode
+        assert_eq!(pending_interrupt(&cpu), Some(Interrupt::SupervisorSoftware));
+        cpu.csr.write(csr::MIP, csr::STIP);
+        // Delegated ones wait for sstatus.SIE in supervisor mode
+        assert_eq!(pending_interrupt(&cpu), None);
+        cpu.csr.write(csr::MSTATUS, csr::MSTATUS_SIE);
+        assert_eq!(pending_interrupt(&cpu), Some(Interrupt::SupervisorTimer));
+        cpu.csr.set_pending(csr::MTIP, true);
+        assert_eq!(pending_interrupt(&cpu), Some(Interrupt::MachineTimer));
+        cpu.csr.set_pending(csr::MTIP, false);
+        take_interrupt(Interrupt::SupervisorTimer, &mut cpu);
+        assert_eq!(cpu.pc, DRAM_BASE + 0x80);
+        assert_eq!(cpu.csr.read(csr::SCAUSE), 1 << 63 | 5);
+        assert_eq!(cpu.csr.read(csr::MCAUSE), 0);
+        // and are never taken in machine mode
+        cpu.privilege = Privilege::Machine;
+        cpu.csr
+            .write(csr::MSTATUS, csr::MSTATUS_MIE | csr::MSTATUS_SIE);
+        assert_eq!(pending_interrupt(&cpu), None);
+    }
+    #[test]
+    fn external_interrupt() {
+        let mut cpu = setup(&[]);
+        cpu.privilege = Privilege::User;
+        cpu.csr.write(csr::MIE, csr::SEIP);
+        cpu.csr.write(csr::MIDELEG, csr::SEIP);
+        // Source 1 at priority 1, enabled for the supervisor context
+        cpu.bus.plic.write(plic::PRIORITY + 4, 4, 1);
+        cpu.bus
+            .plic
+            .write(plic::ENABLE + plic::ENABLE_STRIDE, 4, 1 << 1);
+        cpu.bus.plic.set_level(1, true);
+        update_pending(&mut cpu);
+        assert_eq!(cpu.csr.read(csr::MIP), csr::SEIP);
+        assert_eq!(pending_interrupt(&cpu), Some(Interrupt::SupervisorExternal));
+        // Claiming the source lowers SEIP
+        let claim = plic::CLAIM + plic::CONTEXT_STRIDE;
+        assert_eq!(cpu.bus.plic.read(claim, 4), 1);
+        update_pending(&mut cpu);
+        assert_eq!(cpu.csr.read(csr::MIP), 0);
+    }
+    #[test]
+    fn illegal_instruction() {
+        let mut cpu = setup(&[0xffff_ffff]);
+        assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(0xffff_ffff)));
+        assert_eq!(cpu.pc, DRAM_BASE);
+    }
+    #[test]
+    fn ecall_and_ebreak() {
+        let mut cpu = setup(&[0x00000073, 0x00100073]);
+        assert_eq!(cpu.step(), Err(Exception::EnvironmentCallFromMMode));
+        cpu.pc += 4;
+        assert_eq!(cpu.step(), Err(Exception::Breakpoint(DRAM_BASE + 4)));
+    }
+    #[test]
+    fn instruction_access_fault() {
+        let mut cpu = setup(&[]);
+        cpu.pc = 0x1000;
+        assert_eq!(cpu.step(), Err(Exception::InstructionAccessFault(0x1000)));
+    }
+    #[test]
+    fn load_and_store_access_faults() {
+        // ld a0, 0(zero); sd a0, 0(zero)
+        let mut cpu = setup(&[0x00003503, 0x00a03023]);
+        assert_eq!(cpu.step(), Err(Exception::LoadAccessFault(0)));
+        cpu.pc += 4;
+        assert_eq!(cpu.step(), Err(Exception::StoreAccessFault(0)));
+    }
+    #[test]
+    fn misaligned_jump_target() {
+        // jal zero, 6
+        let mut cpu = setup(&[0x0060006f]);
+        // Without the C extension instructions must be 4-byte aligned
+        cpu.csr.write(csr::MISA, 0);
+        assert_eq!(
+            cpu.step(),
+            Err(Exception::InstructionAddressMisaligned(DRAM_BASE + 6))
+        );
+        assert_eq!(cpu.pc, DRAM_BASE);
+    }
+    #[test]
+    fn compressed_without_c_extension() {
+        // c.nop
+        let mut cpu = setup(&[0x0001]);
+        cpu.csr.write(csr::MISA, 0);
+        assert_eq!(cpu.step(), Err(Exception::IllegalInstruction(0x0001)));
+    }
+}
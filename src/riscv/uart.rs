@@ -0,0 +1,273 @@
+// 16550A compatible UART. Transmitted bytes go straight to the output, so
+// the transmitter is always ready, and received bytes come from the input
+// and wait in a FIFO until the guest reads them.
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Register offsets. DLL and DLM take the place of RBR/THR and IER while
+// the divisor latch access bit in LCR is set.
+pub const RBR: u64 = 0;
+pub const THR: u64 = 0;
+pub const IER: u64 = 1;
+pub const IIR: u64 = 2;
+pub const FCR: u64 = 2;
+pub const LCR: u64 = 3;
+pub const MCR: u64 = 4;
+pub const LSR: u64 = 5;
+pub const MSR: u64 = 6;
+pub const SCR: u64 = 7;
+
+// Interrupt enables
+pub const IER_RDI: u8 = 1 << 0;
+pub const IER_THRI: u8 = 1 << 1;
+
+// Interrupt identification, with bit 0 set when nothing is pending
+pub const IIR_NO_INTERRUPT: u8 = 0x01;
+pub const IIR_THRI: u8 = 0x02;
+pub const IIR_RDI: u8 = 0x04;
+const IIR_FIFO_ENABLED: u8 = 0xc0;
+
+const FCR_FIFO_ENABLE: u8 = 1 << 0;
+const FCR_CLEAR_RECEIVE: u8 = 1 << 1;
+
+const LCR_DLAB: u8 = 1 << 7;
+
+// Line status: data ready, and the transmitter holding register and shift
+// register empty
+pub const LSR_DR: u8 = 1 << 0;
+pub const LSR_THRE: u8 = 1 << 5;
+pub const LSR_TEMT: u8 = 1 << 6;
+
+// Carrier detect, data set ready and clear to send
+const MSR_CONNECTED: u8 = 0xb0;
+
+pub struct Uart {
+    input: Option<Receiver<u8>>,
+    output: Box<dyn Write>,
+    receive: VecDeque<u8>,
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+    // The transmitter empty interrupt is raised by emptying the holding
+    // register or enabling it, and cleared when the guest writes THR or
+    // reads IIR while it is the interrupt reported there
+    transmit_interrupt: bool,
+}
+impl Uart {
+    pub fn new(input: Option<Receiver<u8>>, output: Box<dyn Write>) -> Self {
+        Self {
+            input,
+            output,
+            receive: VecDeque::new(),
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            transmit_interrupt: false,
+        }
+    }
+    // A UART with nothing attached, which never receives and drops what it
+    // transmits
+    pub fn disconnected() -> Self {
+        Self::new(None, Box::new(io::sink()))
+    }
+    // A UART connected to the host's stdin and stdout. stdin is read on
+    // its own thread so that the guest never blocks waiting for input.
+    pub fn console() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 256];
+            while let Ok(count @ 1..) = io::stdin().read(&mut buffer) {
+                if buffer[..count]
+                    .iter()
+                    .any(|&byte| sender.send(byte).is_err())
+                {
+                    break;
+                }
+            }
+        });
+        Self::new(Some(receiver), Box::new(io::stdout()))
+    }
+    // Move bytes that arrived from the input into the receive FIFO
+    pub fn poll(&mut self) {
+        if let Some(input) = &self.input {
+            self.receive.extend(input.try_iter());
+        }
+    }
+    fn dlab(&self) -> bool {
+        self.lcr & LCR_DLAB != 0
+    }
+    // The interrupt reported in IIR, received data taking priority
+    fn interrupt(&self) -> Option<u8> {
+        if self.ier & IER_RDI != 0 && !self.receive.is_empty() {
+            Some(IIR_RDI)
+        } else if self.ier & IER_THRI != 0 && self.transmit_interrupt {
+            Some(IIR_THRI)
+        } else {
+            None
+        }
+    }
+    // Level of the interrupt line to the interrupt controller
+    pub fn interrupting(&self) -> bool {
+        self.interrupt().is_some()
+    }
+    // Registers are a byte wide, and wider accesses only use the low byte
+    pub fn read(&mut self, offset: u64, _size: usize) -> u64 {
+        let value = match offset {
+            RBR if self.dlab() => self.dll,
+            RBR => self.receive.pop_front().unwrap_or(0),
+            IER if self.dlab() => self.dlm,
+            IER => self.ier,
+            IIR => {
+                let interrupt = self.interrupt();
+                if interrupt == Some(IIR_THRI) {
+                    self.transmit_interrupt = false;
+                }
+                let fifo = if self.fcr & FCR_FIFO_ENABLE != 0 {
+                    IIR_FIFO_ENABLED
+                } else {
+                    0
+                };
+                interrupt.unwrap_or(IIR_NO_INTERRUPT) | fifo
+            }
+            LCR => self.lcr,
+            MCR => self.mcr,
+            LSR => {
+                let ready = if self.receive.is_empty() { 0 } else { LSR_DR };
+                LSR_THRE | LSR_TEMT | ready
+            }
+            MSR => MSR_CONNECTED,
+            SCR => self.scr,
+            _ => 0,
+        };
+        u64::from(value)
+    }
+    pub fn write(&mut self, offset: u64, _size: usize, value: u64) {
+        let value = value as u8;
+        match offset {
+            THR if self.dlab() => self.dll = value,
+            THR => {
+                // The host can't push back, so a failed write is dropped
+                // like a byte lost on the line
+                let _ = self
+                    .output
+                    .write_all(&[value])
+                    .and_then(|_| self.output.flush());
+                self.transmit_interrupt = true;
+            }
+            IER if self.dlab() => self.dlm = value,
+            IER => {
+                if value & IER_THRI != 0 && self.ier & IER_THRI == 0 {
+                    self.transmit_interrupt = true;
+                }
+                self.ier = value & 0x0f;
+            }
+            FCR => {
+                if value & FCR_CLEAR_RECEIVE != 0 {
+                    self.receive.clear();
+                }
+                self.fcr = value & FCR_FIFO_ENABLE;
+            }
+            LCR => self.lcr = value,
+            MCR => self.mcr = value & 0x1f,
+            SCR => self.scr = value,
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Output the test can inspect after handing it to the UART
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    #[test]
+    fn transmit() {
+        let output = Shared::default();
+        let mut uart = Uart::new(None, Box::new(output.clone()));
+        assert_eq!(uart.read(LSR, 1) as u8 & LSR_THRE, LSR_THRE);
+        for &byte in b"hi\n" {
+            uart.write(THR, 1, u64::from(byte));
+        }
+        assert_eq!(*output.0.borrow(), b"hi\n");
+    }
+    #[test]
+    fn receive() {
+        let (sender, receiver) = mpsc::channel();
+        let mut uart = Uart::new(Some(receiver), Box::new(io::sink()));
+        assert_eq!(uart.read(LSR, 1) as u8 & LSR_DR, 0);
+        sender.send(b'o').unwrap();
+        sender.send(b'k').unwrap();
+        // Nothing arrives until the UART is polled
+        assert_eq!(uart.read(LSR, 1) as u8 & LSR_DR, 0);
+        uart.poll();
+        assert_eq!(uart.read(LSR, 1) as u8 & LSR_DR, LSR_DR);
+        assert_eq!(uart.read(RBR, 1), u64::from(b'o'));
+        assert_eq!(uart.read(RBR, 1), u64::from(b'k'));
+        assert_eq!(uart.read(LSR, 1) as u8 & LSR_DR, 0);
+        // Clearing the FIFO drops what wasn't read
+        sender.send(b'x').unwrap();
+        uart.poll();
+        uart.write(FCR, 1, u64::from(FCR_FIFO_ENABLE | FCR_CLEAR_RECEIVE));
+        assert_eq!(uart.read(LSR, 1) as u8 & LSR_DR, 0);
+    }
+    #[test]
+    fn receive_interrupt() {
+        let (sender, receiver) = mpsc::channel();
+        let mut uart = Uart::new(Some(receiver), Box::new(io::sink()));
+        sender.send(b'a').unwrap();
+        uart.poll();
+        assert!(!uart.interrupting());
+        assert_eq!(uart.read(IIR, 1), u64::from(IIR_NO_INTERRUPT));
+        uart.write(IER, 1, u64::from(IER_RDI));
+        assert!(uart.interrupting());
+        uart.write(FCR, 1, u64::from(FCR_FIFO_ENABLE));
+        assert_eq!(uart.read(IIR, 1), u64::from(IIR_RDI | IIR_FIFO_ENABLED));
+        uart.read(RBR, 1);
+        assert!(!uart.interrupting());
+    }
+    #[test]
+    fn transmit_interrupt() {
+        let mut uart = Uart::disconnected();
+        uart.write(IER, 1, u64::from(IER_THRI));
+        assert!(uart.interrupting());
+        // Reading IIR acknowledges it
+        assert_eq!(uart.read(IIR, 1), u64::from(IIR_THRI));
+        assert!(!uart.interrupting());
+        uart.write(THR, 1, u64::from(b'a'));
+        assert!(uart.interrupting());
+    }
+    #[test]
+    fn divisor_latch() {
+        let mut uart = Uart::disconnected();
+        uart.write(LCR, 1, u64::from(LCR_DLAB | 0x03));
+        uart.write(RBR, 1, 0x01);
+        uart.write(IER, 1, 0x02);
+        assert_eq!(uart.read(RBR, 1), 0x01);
+        assert_eq!(uart.read(IER, 1), 0x02);
+        uart.write(LCR, 1, 0x03);
+        assert_eq!(uart.read(IER, 1), 0);
+        assert!(!uart.interrupting());
+    }
+}